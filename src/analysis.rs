@@ -1,3 +1,5 @@
+use rustfft::{FftPlanner, num_complex::Complex};
+use symphonia::core::audio::Channels;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
@@ -8,7 +10,37 @@ use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
-use crate::waveform::load_audio;
+use crate::waveform::{audio_track, load_audio, load_excerpt};
+
+#[cfg(test)]
+mod tests;
+
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+/// Size of the frames analysed by the onset detection
+const ONSET_FFT_SIZE: usize = 1024;
+/// Distance between two analysed frames
+const ONSET_HOP_SIZE: usize = 512;
+/// Frames on each side of a peak used for the adaptive threshold
+const ONSET_WINDOW: usize = 8;
+/// Ratio over the local mean of the flux needed for an onset
+const ONSET_MEAN_RATIO: f32 = 1.5;
+/// Ratio of the maximum flux under which peaks are ignored
+const ONSET_FLOOR: f32 = 0.1;
+/// Minimum time between two onsets in seconds
+const MIN_ONSET_INTERVAL: f32 = 0.05;
+/// Range of the estimated tempos
+const MIN_TEMPO: f32 = 60.;
+const MAX_TEMPO: f32 = 200.;
+/// Centre of the preference given to common tempos
+const PREFERRED_TEMPO: f32 = 120.;
+/// Shortest file with an estimated tempo in seconds
+const MIN_TEMPO_DURATION: f32 = 2.;
+/// Normalized autocorrelation under which no pulse is found
+const MIN_TEMPO_CORRELATION: f32 = 0.1;
+/// Relative distance under which the tempo is taken from the length of a loop
+const LOOP_TEMPO_TOLERANCE: f32 = 0.02;
+/// Seconds decoded from the start of a file to estimate its tempo in the file browser
+const TEMPO_EXCERPT_DURATION: f32 = 20.;
 
 #[derive(Debug)]
 pub enum AudioInfoError {
@@ -16,7 +48,7 @@ pub enum AudioInfoError {
     Symphonia(symphonia::core::errors::Error),
     MissingSampleRate,
     MissingChannels,
-    NoDefaultTrack,
+    NoAudioTrack,
 }
 
 impl From<std::io::Error> for AudioInfoError {
@@ -31,6 +63,81 @@ impl From<symphonia::core::errors::Error> for AudioInfoError {
     }
 }
 
+/// Speaker layout of a source file. Decoded data is always folded down to stereo
+/// using the matching downmix matrix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    /// Front left, front right, rear left, rear right
+    Quad,
+    /// Front left, front right, centre, LFE, surround left, surround right
+    Surround51,
+    /// Any other channel count. Channels are spread alternately on left and right.
+    Other(usize),
+}
+
+impl ChannelLayout {
+    pub fn from_channels(channels: Channels) -> Self {
+        let count = channels.count();
+        match count {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            4 if !channels.contains(Channels::FRONT_CENTRE) => ChannelLayout::Quad,
+            6 if channels.contains(Channels::LFE1) => ChannelLayout::Surround51,
+            _ => ChannelLayout::Other(count),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Quad => 4,
+            ChannelLayout::Surround51 => 6,
+            ChannelLayout::Other(count) => *count,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            ChannelLayout::Mono => "Mono".into(),
+            ChannelLayout::Stereo => "Stereo".into(),
+            ChannelLayout::Quad => "Quad".into(),
+            ChannelLayout::Surround51 => "5.1".into(),
+            ChannelLayout::Other(_) => format!("{}ch", self.count()),
+        }
+    }
+
+    pub fn is_mono(&self) -> bool {
+        *self == ChannelLayout::Mono
+    }
+
+    /// (left, right) gains applied to each source channel, in decoding order.
+    /// Surround layouts follow the ITU-R BS.775 downmix, the LFE channel is dropped.
+    pub fn downmix_matrix(&self) -> Vec<[f32; 2]> {
+        match self {
+            ChannelLayout::Mono => vec![[1., 1.]],
+            ChannelLayout::Stereo => vec![[1., 0.], [0., 1.]],
+            ChannelLayout::Quad => vec![[1., 0.], [0., 1.], [MINUS_3DB, 0.], [0., MINUS_3DB]],
+            ChannelLayout::Surround51 => vec![
+                [1., 0.],
+                [0., 1.],
+                [MINUS_3DB, MINUS_3DB],
+                [0., 0.],
+                [MINUS_3DB, 0.],
+                [0., MINUS_3DB],
+            ],
+            ChannelLayout::Other(count) => {
+                let gain = 1. / ((*count as f32 / 2.).ceil()).max(1.).sqrt();
+                (0..*count)
+                    .map(|i| if i % 2 == 0 { [gain, 0.] } else { [0., gain] })
+                    .collect()
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AudioInfo {
     pub name: String,
@@ -38,10 +145,37 @@ pub struct AudioInfo {
     pub data: Arc<RwLock<(Vec<f32>, Vec<f32>)>>,
     pub ready: Arc<RwLock<bool>>,
     pub sample_rate: u32,
-    pub channels: u16,
+    pub layout: ChannelLayout,
     pub bit_depth: Option<u32>,
     pub num_samples: Option<u64>,
     pub path: PathBuf,
+    /// Frames of the detected transients, filled once the data is loaded
+    pub transients: Arc<RwLock<Vec<usize>>>,
+    /// Estimated tempo, filled once the data is loaded
+    pub tempo: Arc<RwLock<Option<f32>>>,
+}
+
+impl AudioInfo {
+    /// Loaded stereo audio playing `samples` on both channels
+    #[cfg(test)]
+    pub fn from_samples(name: &str, samples: Vec<f32>, sample_rate: u32) -> Self {
+        let num_samples = samples.len();
+        Self {
+            name: name.into(),
+            duration: Some(Duration::from_secs_f64(
+                num_samples as f64 / sample_rate as f64,
+            )),
+            data: Arc::new(RwLock::new((samples.clone(), samples))),
+            ready: Arc::new(RwLock::new(true)),
+            sample_rate,
+            layout: ChannelLayout::Stereo,
+            bit_depth: None,
+            num_samples: Some(num_samples as u64),
+            path: name.into(),
+            transients: Arc::new(RwLock::new(Vec::new())),
+            tempo: Arc::new(RwLock::new(None)),
+        }
+    }
 }
 
 pub fn get_audio_info<P: AsRef<Path>>(path: P) -> Result<AudioInfo, AudioInfoError> {
@@ -61,9 +195,7 @@ pub fn get_audio_info<P: AsRef<Path>>(path: P) -> Result<AudioInfo, AudioInfoErr
         get_probe().format(&hint, mss, &Default::default(), &MetadataOptions::default())?;
     let format = probed.format;
 
-    let track = format
-        .default_track()
-        .ok_or(AudioInfoError::NoDefaultTrack)?;
+    let track = audio_track(format.as_ref()).ok_or(AudioInfoError::NoAudioTrack)?;
 
     let codec_params = &track.codec_params;
 
@@ -71,10 +203,11 @@ pub fn get_audio_info<P: AsRef<Path>>(path: P) -> Result<AudioInfo, AudioInfoErr
         .sample_rate
         .ok_or(AudioInfoError::MissingSampleRate)?;
 
-    let channels = codec_params
-        .channels
-        .ok_or(AudioInfoError::MissingChannels)?
-        .count() as u16;
+    let layout = ChannelLayout::from_channels(
+        codec_params
+            .channels
+            .ok_or(AudioInfoError::MissingChannels)?,
+    );
 
     let duration = codec_params
         .n_frames
@@ -84,21 +217,199 @@ pub fn get_audio_info<P: AsRef<Path>>(path: P) -> Result<AudioInfo, AudioInfoErr
     let data_ref = data.clone();
     let analyzed = Arc::new(RwLock::new(false));
     let ready_clone = analyzed.clone();
+    let transients = Arc::new(RwLock::new(Vec::new()));
+    let transients_ref = transients.clone();
+    let tempo = Arc::new(RwLock::new(None));
+    let tempo_ref = tempo.clone();
     let p = path.as_ref().to_string_lossy().to_string();
 
     std::thread::spawn(move || {
-        let _ = load_audio(p, data, analyzed);
+        if load_audio(p, data.clone(), analyzed).is_ok()
+            && let Ok(data) = data.read()
+        {
+            let detected = detect_transients(&data, sample_rate);
+            if let Ok(mut transients) = transients.write() {
+                *transients = detected;
+            }
+            let estimated = estimate_tempo(&data, sample_rate);
+            if let Ok(mut tempo) = tempo.write() {
+                *tempo = estimated;
+            }
+        }
     });
 
     Ok(AudioInfo {
         name,
         duration,
         sample_rate,
-        channels,
+        layout,
         bit_depth: codec_params.bits_per_sample,
         num_samples: codec_params.n_frames,
         ready: ready_clone,
         path: path.as_ref().to_path_buf(),
         data: data_ref,
+        transients: transients_ref,
+        tempo: tempo_ref,
     })
 }
+
+/// Positive increase of the log magnitudes of the downmixed `data`.
+/// Frames are centered on multiples of the onset hop size.
+fn spectral_flux(data: &(Vec<f32>, Vec<f32>)) -> Vec<f32> {
+    let len = data.0.len().min(data.1.len());
+    let fft = FftPlanner::new().plan_fft_forward(ONSET_FFT_SIZE);
+    let window: Vec<f32> = (0..ONSET_FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2. * std::f32::consts::PI * i as f32 / ONSET_FFT_SIZE as f32).cos())
+        .collect();
+    let bins = ONSET_FFT_SIZE / 2 + 1;
+    let mut buffer = vec![Complex::default(); ONSET_FFT_SIZE];
+    let mut previous = vec![0.; bins];
+
+    let frames = len / ONSET_HOP_SIZE + 1;
+    let mut flux = Vec::with_capacity(frames);
+    for frame in 0..frames {
+        let start = (frame * ONSET_HOP_SIZE) as isize - (ONSET_FFT_SIZE / 2) as isize;
+        for (i, (value, w)) in buffer.iter_mut().zip(window.iter()).enumerate() {
+            let index = start + i as isize;
+            let sample = if index >= 0 && (index as usize) < len {
+                (data.0[index as usize] + data.1[index as usize]) / 2.
+            } else {
+                0.
+            };
+            *value = Complex::new(sample * w, 0.);
+        }
+        fft.process(&mut buffer);
+        let mut value = 0.;
+        for (bin, previous) in buffer[..bins].iter().zip(previous.iter_mut()) {
+            let magnitude = (1. + 100. * bin.norm()).ln();
+            value += (magnitude - *previous).max(0.);
+            *previous = magnitude;
+        }
+        flux.push(value);
+    }
+    flux
+}
+
+/// Find the onsets of `data` with the spectral flux of the downmixed signal.
+/// Returns the frames of the onsets.
+pub fn detect_transients(data: &(Vec<f32>, Vec<f32>), sample_rate: u32) -> Vec<usize> {
+    let len = data.0.len().min(data.1.len());
+    if len == 0 {
+        return Vec::new();
+    }
+    let flux = spectral_flux(data);
+    let frames = flux.len();
+
+    // Peak picking over an adaptive threshold
+    let max_flux = flux.iter().copied().fold(0., f32::max);
+    if max_flux <= 0. {
+        return Vec::new();
+    }
+    let min_interval =
+        (MIN_ONSET_INTERVAL * sample_rate as f32 / ONSET_HOP_SIZE as f32).ceil() as usize;
+    let mut onsets = Vec::new();
+    let mut last: Option<usize> = None;
+    for (frame, value) in flux.iter().enumerate() {
+        let local =
+            &flux[frame.saturating_sub(ONSET_WINDOW)..(frame + ONSET_WINDOW + 1).min(frames)];
+        let mean = local.iter().sum::<f32>() / local.len() as f32;
+        if local.iter().all(|v| v <= value)
+            && *value > mean * ONSET_MEAN_RATIO
+            && *value > max_flux * ONSET_FLOOR
+            && last.is_none_or(|last| frame - last >= min_interval)
+        {
+            onsets.push((frame * ONSET_HOP_SIZE).min(len - 1));
+            last = Some(frame);
+        }
+    }
+    onsets
+}
+
+/// Estimate the tempo of the file at `path` from its first seconds only,
+/// fast enough for the file browser
+pub fn estimate_file_tempo(path: &Path) -> Option<f32> {
+    let excerpt = load_excerpt(&path.to_string_lossy(), TEMPO_EXCERPT_DURATION).ok()?;
+    let seconds = excerpt
+        .num_samples
+        .map(|frames| frames as f32 / excerpt.sample_rate as f32);
+    estimate_tempo_with_duration(&excerpt.data, excerpt.sample_rate, seconds)
+}
+
+/// Estimate the tempo of `data` from the autocorrelation of its spectral flux.
+/// When the length of the file is a whole number of bars close to the estimate, as for loops,
+/// the tempo is taken from the length. Returns `None` when no pulse is found.
+pub fn estimate_tempo(data: &(Vec<f32>, Vec<f32>), sample_rate: u32) -> Option<f32> {
+    estimate_tempo_with_duration(data, sample_rate, None)
+}
+
+/// Estimate the tempo of `data`, the start of a file lasting `duration` seconds
+/// when given, used to recognize loops
+fn estimate_tempo_with_duration(
+    data: &(Vec<f32>, Vec<f32>),
+    sample_rate: u32,
+    duration: Option<f32>,
+) -> Option<f32> {
+    let len = data.0.len().min(data.1.len());
+    let analysed = len as f32 / sample_rate as f32;
+    if analysed < MIN_TEMPO_DURATION {
+        return None;
+    }
+    let mut flux = spectral_flux(data);
+    let mean = flux.iter().sum::<f32>() / flux.len() as f32;
+    for value in flux.iter_mut() {
+        *value = (*value - mean).max(0.);
+    }
+    let energy: f32 = flux.iter().map(|v| v * v).sum();
+    if energy <= 0. {
+        return None;
+    }
+
+    // Autocorrelation weighted towards common tempos
+    let frame_rate = sample_rate as f32 / ONSET_HOP_SIZE as f32;
+    let min_lag = (60. * frame_rate / MAX_TEMPO).floor() as usize;
+    let max_lag = ((60. * frame_rate / MIN_TEMPO).ceil() as usize).min(flux.len() / 2);
+    if min_lag < 1 || max_lag <= min_lag + 1 {
+        return None;
+    }
+    let correlation: Vec<f32> = (min_lag - 1..=max_lag + 1)
+        .map(|lag| {
+            flux.iter()
+                .zip(flux[lag..].iter())
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / energy
+        })
+        .collect();
+    let weight = |lag: f32| {
+        let octaves = (60. * frame_rate / lag / PREFERRED_TEMPO).log2();
+        (-0.5 * octaves * octaves).exp()
+    };
+    let (best, value) = (1..correlation.len() - 1)
+        .map(|i| (i, correlation[i] * weight((min_lag - 1 + i) as f32)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if value < MIN_TEMPO_CORRELATION {
+        return None;
+    }
+    // Parabolic interpolation of the peak
+    let (a, b, c) = (
+        correlation[best - 1],
+        correlation[best],
+        correlation[best + 1],
+    );
+    let denominator = a - 2. * b + c;
+    let offset = if denominator.abs() > f32::EPSILON {
+        (0.5 * (a - c) / denominator).clamp(-0.5, 0.5)
+    } else {
+        0.
+    };
+    let tempo = 60. * frame_rate / ((min_lag - 1 + best) as f32 + offset);
+
+    // Whole number of bars for loops
+    let seconds = duration.unwrap_or(analysed);
+    let from_length = (1..=(seconds * MAX_TEMPO / 240.) as usize)
+        .map(|bars| bars as f32 * 240. / seconds)
+        .min_by(|a, b| (a - tempo).abs().total_cmp(&(b - tempo).abs()))
+        .filter(|from_length| (from_length - tempo).abs() / tempo < LOOP_TEMPO_TOLERANCE);
+    let tempo = from_length.unwrap_or(tempo);
+    Some((tempo * 100.).round() / 100.)
+}
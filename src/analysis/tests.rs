@@ -0,0 +1,73 @@
+use super::{ChannelLayout, detect_transients, estimate_tempo};
+
+fn downmix(layout: ChannelLayout, frame: &[f32]) -> (f32, f32) {
+    layout
+        .downmix_matrix()
+        .iter()
+        .zip(frame)
+        .fold((0., 0.), |(l, r), ([gl, gr], s)| (l + gl * s, r + gr * s))
+}
+
+#[test]
+fn test_downmix_matrices() {
+    // Mono is copied on both sides
+    assert_eq!(downmix(ChannelLayout::Mono, &[0.5]), (0.5, 0.5));
+    // Stereo is untouched
+    assert_eq!(downmix(ChannelLayout::Stereo, &[0.2, -0.3]), (0.2, -0.3));
+    // Centre goes on both sides, LFE is dropped
+    let (l, r) = downmix(ChannelLayout::Surround51, &[0., 0., 1., 1., 0., 0.]);
+    assert!((l - r).abs() < 1e-6);
+    assert!((l - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    // Rear channels stay on their side
+    let (l, r) = downmix(ChannelLayout::Quad, &[0., 0., 1., 0.]);
+    assert!(l > 0. && r == 0.);
+    assert_eq!(ChannelLayout::Other(3).downmix_matrix().len(), 3);
+}
+
+/// Decaying noise bursts starting at `onsets`
+fn bursts(onsets: &[usize], len: usize) -> Vec<f32> {
+    let mut seed: u32 = 1;
+    let mut data = vec![0.; len];
+    for onset in onsets {
+        for i in 0..8000.min(len - onset) {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (seed >> 8) as f32 / (1 << 24) as f32 * 2. - 1.;
+            data[onset + i] += noise * 0.8 * (-(i as f32) / 1500.).exp();
+        }
+    }
+    data
+}
+
+#[test]
+fn test_detect_transients() {
+    let sample_rate = 44100;
+    let onsets = [11025, 33075, 57330];
+    let data = bursts(&onsets, sample_rate * 2);
+    let detected = detect_transients(&(data.clone(), data), sample_rate as u32);
+    assert_eq!(detected.len(), onsets.len());
+    for (detected, onset) in detected.iter().zip(onsets) {
+        assert!((*detected as i64 - onset as i64).abs() <= 1024);
+    }
+}
+
+#[test]
+fn test_estimate_tempo() {
+    let sample_rate = 44100;
+    // Four bars at 128 bpm with an accent every two beats
+    let beat = 60. / 128. * sample_rate as f32;
+    let len = (beat * 16.).round() as usize;
+    let mut onsets: Vec<usize> = (0..16).map(|i| (i as f32 * beat) as usize).collect();
+    onsets.extend((0..8).map(|i| (i as f32 * 2. * beat) as usize + 10));
+    let data = bursts(&onsets, len);
+    assert_eq!(
+        estimate_tempo(&(data.clone(), data), sample_rate as u32),
+        Some(128.)
+    );
+
+    // No pulse in silence
+    let silence = vec![0.; sample_rate * 3];
+    assert_eq!(
+        estimate_tempo(&(silence.clone(), silence), sample_rate as u32),
+        None
+    );
+}
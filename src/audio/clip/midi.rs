@@ -1,22 +1,133 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::{
+    midi::{MidiClipCore, MidiControl, MidiNote},
+    tempo::TempoMap,
+};
+use midly::MidiMessage;
+
 #[derive(Debug, Clone)]
 pub struct MidiEvent {
-    pub timestamp: usize,            // time since start of clip
-    pub message: midly::MidiMessage, // or custom enum
+    pub timestamp: usize, // frames since start of clip
+    pub message: MidiMessage,
 }
 
+/// MIDI clip for the audio thread, its notes converted to events in frames
 #[derive(Debug, Clone)]
 pub struct MidiClip {
-    pub id: uuid::Uuid,
-    pub name: String,
+    pub id: String,
+    /// Position in beats
+    pub position: f32,
+    /// Length in beats
+    pub length_beats: f32,
+    notes: Vec<MidiNote>,
+    controls: Vec<MidiControl>,
+    /// Timeline frame of the position, updated by `retime`
     pub start: usize,
-    pub length: usize,          // clip length in musical time or real time
+    /// Length in frames
+    pub length: usize,
     pub events: Vec<MidiEvent>, // sorted by timestamp
 }
 
 impl MidiClip {
+    pub fn from_core(clip: &MidiClipCore, tempo: &TempoMap, sample_rate: usize) -> Self {
+        let mut midi_clip = Self {
+            id: clip.id.clone(),
+            position: clip.position,
+            length_beats: clip.length,
+            notes: clip.notes.clone(),
+            controls: clip.controls.clone(),
+            start: 0,
+            length: 0,
+            events: Vec::with_capacity(clip.notes.len() * 2 + clip.controls.len()),
+        };
+        midi_clip.retime(tempo, sample_rate);
+        midi_clip
+    }
+
+    /// Convert the position and the notes to frames with a new tempo map
+    pub fn retime(&mut self, tempo: &TempoMap, sample_rate: usize) {
+        self.start = tempo.beats_to_frames(self.position, sample_rate);
+        let end = tempo.beats_to_frames(self.position + self.length_beats, sample_rate);
+        self.length = end.saturating_sub(self.start);
+
+        self.events.clear();
+        for note in self
+            .notes
+            .iter()
+            .filter(|note| note.start >= 0. && note.start < self.length_beats)
+        {
+            let on = tempo.beats_to_frames(self.position + note.start, sample_rate);
+            let off = tempo.beats_to_frames(
+                self.position + note.end().min(self.length_beats),
+                sample_rate,
+            );
+            self.events.push(MidiEvent {
+                timestamp: on.saturating_sub(self.start),
+                message: MidiMessage::NoteOn {
+                    key: note.key.into(),
+                    vel: note.velocity.max(1).into(),
+                },
+            });
+            self.events.push(MidiEvent {
+                timestamp: off.saturating_sub(self.start),
+                message: MidiMessage::NoteOff {
+                    key: note.key.into(),
+                    vel: 0.into(),
+                },
+            });
+        }
+        for control in self
+            .controls
+            .iter()
+            .filter(|control| control.time >= 0. && control.time < self.length_beats)
+        {
+            let frame = tempo.beats_to_frames(self.position + control.time, sample_rate);
+            self.events.push(MidiEvent {
+                timestamp: frame.saturating_sub(self.start),
+                message: MidiMessage::Controller {
+                    controller: control.controller.into(),
+                    value: control.value.into(),
+                },
+            });
+        }
+        // Release before attacking at the same frame so repeated keys retrigger
+        self.events.sort_by_key(|event| {
+            (
+                event.timestamp,
+                matches!(event.message, MidiMessage::NoteOn { .. }),
+            )
+        });
+    }
+
     pub fn in_range(&self, pos: usize, num_frames: usize) -> bool {
-        self.start > pos + num_frames || self.start + self.length < pos
+        self.start < pos + num_frames && self.start + self.length >= pos
     }
 
-    pub fn render_block(&self, pos: usize, num_frames: usize) {}
+    /// Push the events played from `pos` during `num_frames`
+    /// with their offset in the block, as long as `events` has room without allocating
+    pub fn render_block(
+        &self,
+        pos: usize,
+        num_frames: usize,
+        events: &mut Vec<(usize, MidiMessage)>,
+    ) {
+        if !self.in_range(pos, num_frames) {
+            return;
+        }
+        // Events from the start of the block, clamped to the clip
+        let from = pos.saturating_sub(self.start);
+        let to = (pos + num_frames - self.start).min(self.length + 1);
+        let first = self.events.partition_point(|event| event.timestamp < from);
+        for event in self.events[first..]
+            .iter()
+            .take_while(|event| event.timestamp < to)
+        {
+            if events.len() == events.capacity() {
+                break;
+            }
+            events.push((self.start + event.timestamp - pos, event.message));
+        }
+    }
 }
@@ -0,0 +1,74 @@
+use crate::{
+    audio::clip::midi::MidiClip,
+    core::{
+        midi::{MidiClipCore, MidiNote},
+        tempo::TempoMap,
+    },
+};
+use midly::MidiMessage;
+
+const SAMPLE_RATE: usize = 48000;
+
+#[test]
+fn test_render_block_offsets() {
+    // 120 bpm, one beat is 24000 frames
+    let tempo = TempoMap::new(120.);
+    let mut core = MidiClipCore::new(1., 2.);
+    core.notes = vec![
+        MidiNote {
+            key: 60,
+            velocity: 100,
+            start: 0.5,
+            length: 0.25,
+        },
+        // Cut at the end of the clip
+        MidiNote {
+            key: 64,
+            velocity: 100,
+            start: 1.5,
+            length: 4.,
+        },
+    ];
+    let clip = MidiClip::from_core(&core, &tempo, SAMPLE_RATE);
+    assert_eq!(clip.start, 24000);
+    assert_eq!(clip.length, 48000);
+
+    let mut events = Vec::with_capacity(16);
+    clip.render_block(35000, 2048, &mut events);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, 1000);
+    assert!(matches!(events[0].1, MidiMessage::NoteOn { key, .. } if key == 60));
+
+    events.clear();
+    clip.render_block(41000, 1024, &mut events);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, 1000);
+    assert!(matches!(events[0].1, MidiMessage::NoteOff { key, .. } if key == 60));
+
+    // The last note is released at the end of the clip
+    events.clear();
+    clip.render_block(71000, 1024, &mut events);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, 1000);
+    assert!(matches!(events[0].1, MidiMessage::NoteOff { key, .. } if key == 64));
+
+    // Nothing outside the clip
+    events.clear();
+    clip.render_block(0, 1024, &mut events);
+    clip.render_block(80000, 1024, &mut events);
+    assert!(events.is_empty());
+
+    // No allocation once the events are full
+    let full = (
+        0,
+        MidiMessage::NoteOff {
+            key: 0.into(),
+            vel: 0.into(),
+        },
+    );
+    events.resize(events.capacity(), full);
+    let capacity = events.capacity();
+    clip.render_block(35000, 2048, &mut events);
+    assert_eq!(events.len(), capacity);
+    assert_eq!(events.capacity(), capacity);
+}
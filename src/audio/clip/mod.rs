@@ -1,7 +1,18 @@
 pub mod midi;
-use crate::{analysis::AudioInfo, cache::AUDIO_ANALYSIS_CACHE, core::clip::ClipCore};
+mod stretch;
+#[cfg(test)]
+mod tests;
+use crate::{
+    analysis::AudioInfo,
+    audio::clip::stretch::{StretchSource, Stretcher},
+    cache::AUDIO_ANALYSIS_CACHE,
+    core::{
+        clip::{ChannelMode, ClipCore, ClipFade, ClipWarp},
+        tempo::TempoMap,
+    },
+};
 use rubato::{Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType};
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 const RESAMPLER_CHUNK_SIZE: usize = 1024;
 
@@ -9,9 +20,24 @@ const RESAMPLER_CHUNK_SIZE: usize = 1024;
 pub struct ClipBackend {
     pub id: String,
     pub audio: AudioInfo,
+    /// Position in beats
+    pub position: f32,
+    /// Timeline frame of the position, updated by `retime`
     pub start_frame: usize,
     pub trim_start: f32,
     pub trim_end: f32,
+    pub channel_mode: ChannelMode,
+    pub fade_in: ClipFade,
+    pub fade_out: ClipFade,
+    /// Linear gain including polarity
+    pub amplitude: f32,
+    pub reverse: bool,
+    pub warp: ClipWarp,
+    /// Tempo map used to convert beats to frames
+    pub tempo: Arc<TempoMap>,
+    /// Frequency ratio of the transpose
+    pub pitch: f32,
+    pub preserve_formants: bool,
 
     resampler: SincFixedIn<f32>,
     /// Buffer used for the resampler
@@ -24,6 +50,10 @@ pub struct ClipBackend {
     playhead: usize,
     /// Current playhead in the timeline
     timeline_playhead: usize,
+    /// Time-stretcher used by warped and transposed clips
+    stretcher: Box<Stretcher>,
+    /// Output buffer of the stretcher
+    stretch_output_buffer: [Vec<f32>; 2],
 }
 
 impl ClipBackend {
@@ -35,6 +65,16 @@ impl ClipBackend {
         trim_end: f32,
     ) -> Self {
         let audio = AUDIO_ANALYSIS_CACHE.get_or_analyze(path.clone()).unwrap();
+        Self::from_audio(id, audio, start_frame, trim_start, trim_end)
+    }
+
+    pub fn from_audio(
+        id: String,
+        audio: AudioInfo,
+        start_frame: usize,
+        trim_start: f32,
+        trim_end: f32,
+    ) -> Self {
         let input_buffer = vec![Vec::new(), Vec::new()];
         let output_buffer = [Vec::new(), Vec::new()];
         let resampler = SincFixedIn::<f32>::new(
@@ -59,12 +99,24 @@ impl ClipBackend {
             start_frame,
             trim_start: trim_start,
             trim_end: trim_end,
+            channel_mode: ChannelMode::default(),
+            position: 0.,
+            fade_in: ClipFade::default(),
+            fade_out: ClipFade::default(),
+            amplitude: 1.,
+            reverse: false,
+            warp: ClipWarp::default(),
+            tempo: Arc::new(TempoMap::default()),
+            pitch: 1.,
+            preserve_formants: false,
             input_buffer,
             resampler_cache_buffer,
             playhead: 0,
             timeline_playhead: 0,
             resampler,
             resampler_output_buffer: output_buffer,
+            stretcher: Box::new(Stretcher::new()),
+            stretch_output_buffer: [Vec::new(), Vec::new()],
         }
     }
 
@@ -124,17 +176,10 @@ impl ClipBackend {
                 self.input_buffer[0].resize(chunk_size, 0.);
                 self.input_buffer[1].resize(chunk_size, 0.);
 
-                self.input_buffer[0][..input_size].copy_from_slice(
-                    &data.0[self.playhead..(self.playhead + input_size).min(data.0.len())],
-                );
-                if self.audio.channels > 1 {
-                    self.input_buffer[1][..input_size].copy_from_slice(
-                        &data.1[self.playhead..(self.playhead + input_size).min(data.0.len())],
-                    );
-                } else {
-                    self.input_buffer[1][..input_size].copy_from_slice(
-                        &data.0[self.playhead..(self.playhead + input_size).min(data.0.len())],
-                    );
+                for i in 0..input_size {
+                    let index = self.source_index(self.playhead + i);
+                    self.input_buffer[0][i] = data.0.get(index).copied().unwrap_or(0.);
+                    self.input_buffer[1][i] = data.1.get(index).copied().unwrap_or(0.);
                 }
             }
             // Process input
@@ -201,6 +246,11 @@ impl ClipBackend {
         let start = clip_start.max(playhead);
         // end position of the clip in [pos, pos + num_frames]
         let end = clip_end.min(playhead + num_frames);
+        if self.is_stretched() {
+            self.render_stretched(mix, playhead, start, end, sample_rate);
+            return;
+        }
+        let fades = self.fade_frames(clip_end, sample_rate);
         // ratio in sample rates
         let sample_rate_ratio = self.audio.sample_rate as f64 / sample_rate as f64;
         // position relative to the clip
@@ -215,26 +265,19 @@ impl ClipBackend {
 
         if sample_rate as u32 == self.audio.sample_rate {
             if let Ok(data) = self.audio.data.read() {
-                let frames = end_offset - start_offset;
-                let data_start = start_index;
-                let data_end = data_start + frames;
-
-                let left = &data.0[data_start..data_end];
-                let right = if self.audio.channels > 1 {
-                    &data.1[data_start..data_end]
-                } else {
-                    &data.0[data_start..data_end]
-                };
-
+                let data_len = data.0.len().min(data.1.len());
                 let out_slice = &mut mix[start_offset * 2..end_offset * 2];
 
-                for ((frame, &l), &r) in out_slice
-                    .chunks_exact_mut(2)
-                    .zip(left.iter())
-                    .zip(right.iter())
-                {
-                    frame[0] += l;
-                    frame[1] += r;
+                for (i, frame) in out_slice.chunks_exact_mut(2).enumerate() {
+                    let index = self.source_index(start_index + i);
+                    if index >= data_len {
+                        continue;
+                    }
+                    let gain =
+                        self.amplitude * self.fade_gain(start + i, clip_start, clip_end, fades);
+                    let (l, r) = self.channel_mode.apply(data.0[index], data.1[index]);
+                    frame[0] += l * gain;
+                    frame[1] += r * gain;
                 }
             }
             return;
@@ -244,14 +287,135 @@ impl ClipBackend {
         // Add samples to mix
         let out_slice = &mut mix[(start - playhead) * 2..(end - playhead) * 2];
 
-        for (frame, (&l, &r)) in out_slice.chunks_exact_mut(2).zip(
-            self.resampler_output_buffer[0][0..frames]
-                .iter()
-                .zip(&self.resampler_output_buffer[1][0..frames]),
-        ) {
-            frame[0] += l;
-            frame[1] += r;
+        for (i, (frame, (&l, &r))) in out_slice
+            .chunks_exact_mut(2)
+            .zip(
+                self.resampler_output_buffer[0][0..frames]
+                    .iter()
+                    .zip(&self.resampler_output_buffer[1][0..frames]),
+            )
+            .enumerate()
+        {
+            let gain = self.amplitude * self.fade_gain(start + i, clip_start, clip_end, fades);
+            let (l, r) = self.channel_mode.apply(l, r);
+            frame[0] += l * gain;
+            frame[1] += r * gain;
+        }
+    }
+
+    /// Whether the clip goes through the time-stretcher
+    fn is_stretched(&self) -> bool {
+        self.warp.enabled || self.pitch != 1.
+    }
+
+    /// Render the clip between the timeline frames `start` and `end` through the stretcher
+    fn render_stretched(
+        &mut self,
+        mix: &mut [f32],
+        playhead: usize,
+        start: usize,
+        end: usize,
+        sample_rate: usize,
+    ) {
+        let clip_start = self.start_frame;
+        let clip_end = self.end(sample_rate);
+        let Ok(data) = self.audio.data.read() else {
+            return;
+        };
+        let duration = self.audio.num_samples.unwrap() as f32 / self.audio.sample_rate as f32;
+        let anchor = if self.reverse {
+            self.trim_end
+        } else {
+            self.trim_start
+        } * duration;
+        let source = WarpedSource {
+            data: &data,
+            warp: &self.warp,
+            tempo: &self.tempo,
+            position: self.position,
+            start: self.tempo.beats_to_secs(self.position),
+            origin: if self.warp.enabled {
+                self.warp.source_to_beats(anchor)
+            } else {
+                anchor
+            },
+            range: (self.playhead_start(), self.playhead_end()),
+            source_rate: self.audio.sample_rate as f64,
+            output_rate: sample_rate as f64,
+            reverse: self.reverse,
+            pitch: self.pitch,
+            preserve_formants: self.preserve_formants,
+        };
+        let frames = end - start;
+        self.stretcher.render(
+            (start - clip_start) as i64,
+            frames,
+            &mut self.stretch_output_buffer,
+            &source,
+        );
+        drop(data);
+        let fades = self.fade_frames(clip_end, sample_rate);
+
+        let out_slice = &mut mix[(start - playhead) * 2..(end - playhead) * 2];
+        for (i, (frame, (&l, &r))) in out_slice
+            .chunks_exact_mut(2)
+            .zip(
+                self.stretch_output_buffer[0]
+                    .iter()
+                    .zip(&self.stretch_output_buffer[1]),
+            )
+            .enumerate()
+        {
+            let gain = self.amplitude * self.fade_gain(start + i, clip_start, clip_end, fades);
+            let (l, r) = self.channel_mode.apply(l, r);
+            frame[0] += l * gain;
+            frame[1] += r * gain;
+        }
+    }
+
+    /// Index in the source data of the `index`-th frame of the clip, taking reverse into account
+    #[inline]
+    fn source_index(&self, index: usize) -> usize {
+        if self.reverse {
+            (self.playhead_start() + self.playhead_end()).saturating_sub(index + 1)
+        } else {
+            index
+        }
+    }
+
+    /// Lengths in timeline frames of the fade in and the fade out
+    fn fade_frames(&self, clip_end: usize, sample_rate: usize) -> (usize, usize) {
+        let fade_in = self
+            .tempo
+            .beats_to_frames(self.position + self.fade_in.length, sample_rate)
+            .saturating_sub(self.start_frame);
+        let end = self.tempo.frames_to_beats(clip_end, sample_rate);
+        let fade_out = clip_end.saturating_sub(
+            self.tempo
+                .beats_to_frames(end - self.fade_out.length, sample_rate),
+        );
+        (fade_in, fade_out)
+    }
+
+    /// Gain of the fades at the timeline `frame`
+    #[inline]
+    fn fade_gain(
+        &self,
+        frame: usize,
+        clip_start: usize,
+        clip_end: usize,
+        (fade_in, fade_out): (usize, usize),
+    ) -> f32 {
+        let mut gain = 1.;
+        let offset = frame.saturating_sub(clip_start);
+        if offset < fade_in {
+            gain *= self.fade_in.curve.gain(offset as f32 / fade_in as f32);
+        }
+        let remaining = clip_end.saturating_sub(frame);
+        if remaining < fade_out {
+            gain *= self.fade_out.curve.gain(remaining as f32 / fade_out as f32);
         }
+        gain
     }
 
     pub fn num_frames(&self) -> usize {
@@ -267,30 +431,136 @@ impl ClipBackend {
     }
 
     pub fn end(&self, sample_rate: usize) -> usize {
+        if self.warp.enabled {
+            let duration = self.audio.num_samples.unwrap() as f32 / self.audio.sample_rate as f32;
+            let beats = self.warp.source_to_beats(self.trim_end * duration)
+                - self.warp.source_to_beats(self.trim_start * duration);
+            return self
+                .tempo
+                .beats_to_frames(self.position + beats, sample_rate)
+                .max(self.start_frame);
+        }
         self.start_frame
             + (self.num_frames() as f32 * sample_rate as f32 / self.audio.sample_rate as f32)
                 .floor() as usize
     }
 
-    pub fn from_clipcore(clip: &ClipCore, bpm: f32, sample_rate: usize) -> Self {
-        Self::new(
+    pub fn from_clipcore(clip: &ClipCore, tempo: &Arc<TempoMap>, sample_rate: usize) -> Self {
+        let mut backend = Self::new(
             clip.id.clone(),
             clip.audio.path.clone(),
-            (clip.position / bpm * 60. * sample_rate as f32).floor() as usize,
+            tempo.beats_to_frames(clip.position, sample_rate),
             clip.trim_start,
             clip.trim_end,
-        )
+        );
+        backend.update(clip, tempo, sample_rate);
+        backend
+    }
+
+    /// Update clip settings from its core counterpart
+    pub fn update(&mut self, clip: &ClipCore, tempo: &Arc<TempoMap>, sample_rate: usize) {
+        self.position = clip.position;
+        self.trim_start = clip.trim_start;
+        self.trim_end = clip.trim_end;
+        self.channel_mode = clip.channel_mode;
+        self.amplitude = clip.amplitude();
+        self.reverse = clip.reverse;
+        self.warp = clip.warp.clone();
+        self.pitch = clip.pitch_ratio();
+        self.preserve_formants = clip.preserve_formants;
+        self.fade_in = clip.fade_in;
+        self.fade_out = clip.fade_out;
+        self.retime(tempo, sample_rate);
+    }
+
+    /// Place the clip on the timeline following `tempo`, shared with the player
+    pub fn retime(&mut self, tempo: &Arc<TempoMap>, sample_rate: usize) {
+        self.tempo = tempo.clone();
+        self.start_frame = tempo.beats_to_frames(self.position, sample_rate);
     }
 }
 
 impl Clone for ClipBackend {
     fn clone(&self) -> Self {
-        Self::new(
+        let mut clone = Self::from_audio(
             self.id.clone(),
-            self.audio.path.clone(),
+            self.audio.clone(),
             self.start_frame,
             self.trim_start,
             self.trim_end,
-        )
+        );
+        clone.position = self.position;
+        clone.channel_mode = self.channel_mode;
+        clone.fade_in = self.fade_in;
+        clone.fade_out = self.fade_out;
+        clone.amplitude = self.amplitude;
+        clone.reverse = self.reverse;
+        clone.warp = self.warp.clone();
+        clone.tempo = self.tempo.clone();
+        clone.pitch = self.pitch;
+        clone.preserve_formants = self.preserve_formants;
+        clone
+    }
+}
+
+/// Source of a warped or transposed clip read by the stretcher
+struct WarpedSource<'a> {
+    data: &'a (Vec<f32>, Vec<f32>),
+    warp: &'a ClipWarp,
+    tempo: &'a TempoMap,
+    /// Position of the clip in beats
+    position: f32,
+    /// Time of the position in seconds
+    start: f64,
+    /// Beat of the source played at the start of the clip, or its time in seconds when the clip
+    /// is not warped
+    origin: f32,
+    /// Trimmed range of the data
+    range: (usize, usize),
+    source_rate: f64,
+    output_rate: f64,
+    reverse: bool,
+    pitch: f32,
+    preserve_formants: bool,
+}
+
+impl StretchSource for WarpedSource<'_> {
+    fn position(&self, frame: f64) -> f64 {
+        let secs = frame / self.output_rate;
+        if !self.warp.enabled {
+            return (self.origin as f64 + self.direction() * secs) * self.source_rate;
+        }
+        let beats = self.tempo.secs_to_beats(self.start + secs) - self.position;
+        let beats = if self.reverse {
+            self.origin - beats
+        } else {
+            self.origin + beats
+        };
+        self.warp.beats_to_source(beats) as f64 * self.source_rate
+    }
+
+    fn sample(&self, channel: usize, frame: isize) -> f32 {
+        if frame < self.range.0 as isize || frame >= self.range.1 as isize {
+            return 0.;
+        }
+        let data = if channel == 0 {
+            &self.data.0
+        } else {
+            &self.data.1
+        };
+        data.get(frame as usize).copied().unwrap_or(0.)
+    }
+
+    fn direction(&self) -> f64 {
+        if self.reverse { -1. } else { 1. }
+    }
+
+    fn frequency_ratio(&self) -> f32 {
+        (self.source_rate / self.output_rate) as f32 * self.pitch
+    }
+
+    fn formant_ratio(&self) -> Option<f32> {
+        self.preserve_formants
+            .then_some((self.source_rate / self.output_rate) as f32)
     }
 }
@@ -0,0 +1,330 @@
+use once_cell::sync::Lazy;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use std::{f32::consts::PI, sync::Arc};
+
+/// Size of the analysis and synthesis frames
+pub const STRETCH_FFT_SIZE: usize = 4096;
+/// Distance between two synthesis frames
+const HOP_SIZE: usize = STRETCH_FFT_SIZE / 4;
+/// Length reached by the overlap-add buffers when rendering at most a hop at once
+const OUTPUT_CAPACITY: usize = 2 * STRETCH_FFT_SIZE + HOP_SIZE;
+/// Sum of the squared Hann windows overlapping at 75%
+const WINDOW_GAIN: f32 = 1.5;
+/// Half width in bins of the smoothing giving the spectral envelope
+const ENVELOPE_HALF_WIDTH: usize = 24;
+/// Maximum boost of a bin when preserving formants
+const MAX_FORMANT_GAIN: f32 = 8.;
+
+/// Forward and inverse transforms
+type FftPair = (Arc<dyn Fft<f32>>, Arc<dyn Fft<f32>>);
+
+static FFT: Lazy<FftPair> = Lazy::new(|| {
+    let mut planner = FftPlanner::new();
+    (
+        planner.plan_fft_forward(STRETCH_FFT_SIZE),
+        planner.plan_fft_inverse(STRETCH_FFT_SIZE),
+    )
+});
+
+/// Audio read by the stretcher.
+pub trait StretchSource {
+    /// Source frame played at the clip relative output `frame`
+    fn position(&self, frame: f64) -> f64;
+    /// Sample of `channel` at the source `frame`, zero outside of the source
+    fn sample(&self, channel: usize, frame: isize) -> f32;
+    /// -1 when the source is played backwards
+    fn direction(&self) -> f64;
+    /// Ratio between output and source frequencies, including sample rate conversion
+    fn frequency_ratio(&self) -> f32;
+    /// Ratio between output and source frequencies of the spectral envelope,
+    /// `None` to shift the envelope with the pitch
+    fn formant_ratio(&self) -> Option<f32>;
+}
+
+struct StretchChannel {
+    current: Vec<Complex<f32>>,
+    previous: Vec<Complex<f32>>,
+    synthesis: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    phases: Vec<f32>,
+    frequencies: Vec<f32>,
+    envelope: Vec<f32>,
+    synthesis_phases: Vec<f32>,
+    next_phases: Vec<f32>,
+    /// Overlap-add buffer starting at `Stretcher::output_start`
+    output: Vec<f32>,
+}
+
+impl StretchChannel {
+    fn new() -> Self {
+        let bins = STRETCH_FFT_SIZE / 2 + 1;
+        Self {
+            current: vec![Complex::default(); STRETCH_FFT_SIZE],
+            previous: vec![Complex::default(); STRETCH_FFT_SIZE],
+            synthesis: vec![Complex::default(); STRETCH_FFT_SIZE],
+            magnitudes: vec![0.; bins],
+            phases: vec![0.; bins],
+            frequencies: vec![0.; bins],
+            envelope: vec![0.; bins],
+            synthesis_phases: vec![0.; bins],
+            next_phases: vec![0.; bins],
+            output: Vec::with_capacity(OUTPUT_CAPACITY),
+        }
+    }
+}
+
+/// Phase vocoder with identity phase locking, following any mapping between output and source
+/// frames and shifting the frequencies by the ratio of the source. The output only depends on the rendered positions and not on the block sizes, so a
+/// clip renders the same in real time and offline.
+pub struct Stretcher {
+    window: Vec<f32>,
+    scratch: Vec<Complex<f32>>,
+    peaks: Vec<usize>,
+    /// Prefix sums of the log magnitudes
+    log_sums: Vec<f32>,
+    channels: [StretchChannel; 2],
+    /// Next synthesis frame, starting at `grain * HOP_SIZE`
+    next_grain: i64,
+    /// Clip relative frame of the start of the overlap-add buffers
+    output_start: i64,
+    /// Frame following the last rendered block
+    expected: Option<i64>,
+    /// No synthesis frame computed since the last reset
+    first: bool,
+}
+
+impl Stretcher {
+    pub fn new() -> Self {
+        // Periodic Hann window
+        let window = (0..STRETCH_FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2. * PI * i as f32 / STRETCH_FFT_SIZE as f32).cos())
+            .collect();
+        Self {
+            window,
+            scratch: vec![Complex::default(); FFT.0.get_inplace_scratch_len()],
+            peaks: Vec::with_capacity(STRETCH_FFT_SIZE / 2),
+            log_sums: vec![0.; STRETCH_FFT_SIZE / 2 + 2],
+            channels: [StretchChannel::new(), StretchChannel::new()],
+            next_grain: 0,
+            output_start: 0,
+            expected: None,
+            first: true,
+        }
+    }
+
+    /// Restart the synthesis at the clip relative `position`
+    pub fn reset(&mut self, position: i64) {
+        let overlap = (STRETCH_FFT_SIZE / HOP_SIZE) as i64;
+        self.next_grain = position.div_euclid(HOP_SIZE as i64) - (overlap - 1);
+        self.output_start = self.next_grain * HOP_SIZE as i64;
+        self.expected = None;
+        self.first = true;
+        for channel in self.channels.iter_mut() {
+            channel.output.clear();
+        }
+    }
+
+    /// Render `frames` frames starting at the clip relative `position` into `output`
+    pub fn render(
+        &mut self,
+        position: i64,
+        frames: usize,
+        output: &mut [Vec<f32>; 2],
+        source: &impl StretchSource,
+    ) {
+        if self.expected != Some(position) {
+            self.reset(position);
+        }
+        for out in output.iter_mut() {
+            out.resize(frames, 0.);
+        }
+        // Rendered a hop at most at once so that the overlap-add buffers keep their capacity
+        let mut rendered = 0;
+        while rendered < frames {
+            let count = (frames - rendered).min(HOP_SIZE);
+            let start = position + rendered as i64;
+            let end = start + count as i64;
+            while self.next_grain * (HOP_SIZE as i64) < end {
+                self.synthesize(self.next_grain, source);
+                self.next_grain += 1;
+            }
+
+            let offset = (start - self.output_start) as usize;
+            let consumed = (end - self.output_start) as usize;
+            for (channel, out) in self.channels.iter_mut().zip(output.iter_mut()) {
+                out[rendered..rendered + count]
+                    .copy_from_slice(&channel.output[offset..offset + count]);
+                channel.output.drain(..consumed);
+            }
+            self.output_start = end;
+            rendered += count;
+        }
+        self.expected = Some(position + frames as i64);
+    }
+
+    /// Read a windowed frame of the source centered on `center`
+    fn read_frame(
+        window: &[f32],
+        buffer: &mut [Complex<f32>],
+        source: &impl StretchSource,
+        channel: usize,
+        center: f64,
+    ) {
+        let direction = source.direction();
+        let half = (STRETCH_FFT_SIZE / 2) as f64;
+        for (i, (value, w)) in buffer.iter_mut().zip(window).enumerate() {
+            let frame = center + direction * (i as f64 - half);
+            let index = frame.floor();
+            let fraction = (frame - index) as f32;
+            let a = source.sample(channel, index as isize);
+            let b = source.sample(channel, index as isize + 1);
+            *value = Complex::new((a + (b - a) * fraction) * w, 0.);
+        }
+    }
+
+    fn synthesize(&mut self, grain: i64, source: &impl StretchSource) {
+        let hop = HOP_SIZE as f32;
+        let bins = STRETCH_FFT_SIZE / 2 + 1;
+        let center =
+            source.position((grain * HOP_SIZE as i64) as f64 + STRETCH_FFT_SIZE as f64 / 2.);
+        let previous_center = center - source.direction() * HOP_SIZE as f64;
+        let ratio = source.frequency_ratio();
+        let formant_ratio = source.formant_ratio();
+        let normalization = 1. / (STRETCH_FFT_SIZE as f32 * WINDOW_GAIN);
+
+        for c in 0..2 {
+            let channel = &mut self.channels[c];
+            Self::read_frame(&self.window, &mut channel.current, source, c, center);
+            Self::read_frame(
+                &self.window,
+                &mut channel.previous,
+                source,
+                c,
+                previous_center,
+            );
+            FFT.0
+                .process_with_scratch(&mut channel.current, &mut self.scratch);
+            FFT.0
+                .process_with_scratch(&mut channel.previous, &mut self.scratch);
+
+            // Instantaneous frequencies in radians per output frame
+            for k in 0..bins {
+                let expected = 2. * PI * k as f32 / STRETCH_FFT_SIZE as f32;
+                let phase = channel.current[k].arg();
+                let deviation = princarg(phase - channel.previous[k].arg() - expected * hop);
+                channel.magnitudes[k] = channel.current[k].norm();
+                channel.phases[k] = phase;
+                channel.frequencies[k] = (expected + deviation / hop) * ratio;
+            }
+            if formant_ratio.is_some() {
+                spectral_envelope(
+                    &channel.magnitudes,
+                    &mut self.log_sums,
+                    &mut channel.envelope,
+                );
+            }
+
+            // Find spectral peaks
+            self.peaks.clear();
+            for k in 1..bins - 1 {
+                if channel.magnitudes[k] > channel.magnitudes[k - 1]
+                    && channel.magnitudes[k] >= channel.magnitudes[k + 1]
+                {
+                    self.peaks.push(k);
+                }
+            }
+
+            channel.synthesis.fill(Complex::default());
+            for k in 0..bins {
+                channel.next_phases[k] = channel.synthesis_phases[k]
+                    + 2. * PI * k as f32 / STRETCH_FFT_SIZE as f32 * hop;
+            }
+            // Shift each peak region and lock its phases to the peak
+            for (i, &peak) in self.peaks.iter().enumerate() {
+                let low = if i == 0 {
+                    0
+                } else {
+                    (self.peaks[i - 1] + peak).div_ceil(2)
+                };
+                let high = self
+                    .peaks
+                    .get(i + 1)
+                    .map_or(bins, |next| (peak + next).div_ceil(2));
+                let target = (channel.frequencies[peak] * STRETCH_FFT_SIZE as f32 / (2. * PI))
+                    .round() as isize;
+                if target < 0 || target >= bins as isize {
+                    continue;
+                }
+                let shift = target - peak as isize;
+                let peak_phase = if self.first {
+                    channel.phases[peak]
+                } else {
+                    channel.synthesis_phases[target as usize] + channel.frequencies[peak] * hop
+                };
+                for k in low..high {
+                    let shifted = k as isize + shift;
+                    if shifted < 0 || shifted >= bins as isize {
+                        continue;
+                    }
+                    let phase = peak_phase + channel.phases[k] - channel.phases[peak];
+                    let mut magnitude = channel.magnitudes[k];
+                    if let Some(formant_ratio) = formant_ratio {
+                        // Envelope of the source at the frequency of the shifted bin
+                        let position = (shifted as f32 / formant_ratio).min((bins - 1) as f32);
+                        let index = position.floor() as usize;
+                        let next = (index + 1).min(bins - 1);
+                        let fraction = position - index as f32;
+                        let target = channel.envelope[index]
+                            + (channel.envelope[next] - channel.envelope[index]) * fraction;
+                        magnitude *= (target / channel.envelope[k]).min(MAX_FORMANT_GAIN);
+                    }
+                    channel.synthesis[shifted as usize] += Complex::from_polar(magnitude, phase);
+                    channel.next_phases[shifted as usize] = princarg(phase);
+                }
+            }
+            std::mem::swap(&mut channel.synthesis_phases, &mut channel.next_phases);
+
+            // Hermitian spectrum for a real output
+            channel.synthesis[0].im = 0.;
+            channel.synthesis[bins - 1].im = 0.;
+            for k in 1..bins - 1 {
+                channel.synthesis[STRETCH_FFT_SIZE - k] = channel.synthesis[k].conj();
+            }
+            FFT.1
+                .process_with_scratch(&mut channel.synthesis, &mut self.scratch);
+
+            // Overlap-add
+            let offset = (grain * HOP_SIZE as i64 - self.output_start) as usize;
+            channel.output.resize(offset + STRETCH_FFT_SIZE, 0.);
+            debug_assert!(channel.output.len() <= OUTPUT_CAPACITY);
+            for ((out, value), w) in channel.output[offset..]
+                .iter_mut()
+                .zip(channel.synthesis.iter())
+                .zip(self.window.iter())
+            {
+                *out += value.re * w * normalization;
+            }
+        }
+        self.first = false;
+    }
+}
+
+/// Smooth the log `magnitudes` into `envelope`, using `sums` as scratch
+fn spectral_envelope(magnitudes: &[f32], sums: &mut [f32], envelope: &mut [f32]) {
+    sums[0] = 0.;
+    for (k, magnitude) in magnitudes.iter().enumerate() {
+        sums[k + 1] = sums[k] + (magnitude + 1e-9).ln();
+    }
+    let bins = magnitudes.len();
+    for (k, value) in envelope.iter_mut().enumerate() {
+        let low = k.saturating_sub(ENVELOPE_HALF_WIDTH);
+        let high = (k + ENVELOPE_HALF_WIDTH + 1).min(bins);
+        *value = ((sums[high] - sums[low]) / (high - low) as f32).exp();
+    }
+}
+
+/// Wrap a phase between -PI and PI
+#[inline]
+fn princarg(phase: f32) -> f32 {
+    phase - 2. * PI * (phase / (2. * PI)).round()
+}
@@ -0,0 +1,89 @@
+use crate::{
+    analysis::AudioInfo,
+    audio::clip::ClipBackend,
+    core::{
+        clip::{ClipCore, ClipWarp},
+        tempo::TempoMap,
+    },
+};
+use std::sync::Arc;
+
+const SAMPLE_RATE: usize = 44100;
+
+fn sine_audio(seconds: f32) -> AudioInfo {
+    let num_samples = (seconds * SAMPLE_RATE as f32) as usize;
+    let data: Vec<f32> = (0..num_samples)
+        .map(|i| (2. * std::f32::consts::PI * 440. * i as f32 / SAMPLE_RATE as f32).sin() * 0.5)
+        .collect();
+    AudioInfo::from_samples("sine.wav", data, SAMPLE_RATE as u32)
+}
+
+fn render(clip: &ClipCore, tempo: &TempoMap, block_size: usize, frames: usize) -> Vec<f32> {
+    let mut backend = ClipBackend::from_audio(clip.id.clone(), clip.audio.clone(), 0, 0., 1.);
+    backend.update(clip, &Arc::new(tempo.clone()), SAMPLE_RATE);
+    let mut output = Vec::new();
+    let mut mix = vec![0.; block_size * 2];
+    let mut playhead = 0;
+    while playhead < frames {
+        mix.fill(0.);
+        backend.render_block(&mut mix, playhead, block_size, SAMPLE_RATE);
+        output.extend_from_slice(&mix);
+        playhead += block_size;
+    }
+    output.truncate(frames * 2);
+    output
+}
+
+fn warped_clip() -> ClipCore {
+    let mut clip = ClipCore::new(sine_audio(1.), 0.);
+    clip.warp = ClipWarp {
+        enabled: true,
+        source_bpm: 100.,
+        markers: Vec::new(),
+    };
+    clip
+}
+
+#[test]
+fn test_warped_length_follows_tempo() {
+    let clip = warped_clip();
+    // One second at 100 bpm lasts 1.6667 beats whatever the project tempo
+    assert!((clip.length(&TempoMap::new(120.)) - 100. / 60.).abs() < 1e-4);
+    assert!((clip.length(&TempoMap::new(150.)) - 100. / 60.).abs() < 1e-4);
+
+    let mut backend = ClipBackend::from_audio(clip.id.clone(), clip.audio.clone(), 0, 0., 1.);
+    backend.update(&clip, &Arc::new(TempoMap::new(200.)), SAMPLE_RATE);
+    assert_eq!(backend.end(SAMPLE_RATE), SAMPLE_RATE / 2);
+}
+
+#[test]
+fn test_stretch_independent_of_block_size() {
+    for (transpose, preserve_formants) in [(0., false), (7.25, false), (-3., true)] {
+        let mut clip = warped_clip();
+        clip.transpose = transpose;
+        clip.preserve_formants = preserve_formants;
+        let tempo = TempoMap::new(130.);
+        let realtime = render(&clip, &tempo, 256, 20000);
+        let offline = render(&clip, &tempo, 4096, 20000);
+        assert_eq!(realtime, offline);
+        assert!(realtime.iter().any(|s| s.abs() > 0.1));
+    }
+}
+
+#[test]
+fn test_transpose_keeps_length() {
+    let mut clip = ClipCore::new(sine_audio(1.), 0.);
+    clip.transpose = 12.;
+    let tempo = TempoMap::new(120.);
+    assert!((clip.length(&tempo) - 2.).abs() < 1e-4);
+
+    let output = render(&clip, &tempo, 512, SAMPLE_RATE);
+    // Count the rising zero crossings of the left channel in the steady part
+    let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+    let steady = &left[SAMPLE_RATE / 4..SAMPLE_RATE * 3 / 4];
+    let crossings = steady
+        .windows(2)
+        .filter(|w| w[0] <= 0. && w[1] > 0.)
+        .count();
+    assert!((crossings as i32 - 440).abs() <= 4);
+}
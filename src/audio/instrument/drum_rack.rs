@@ -0,0 +1,213 @@
+use crate::{audio::instrument::Engine, core::instrument::DrumRackParams};
+use fundsp::{
+    MAX_BUFFER_SIZE,
+    hacker::{AudioUnit, BufferArray, exp2},
+    hacker32::U2,
+};
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+/// Hits of a pad ringing at the same time
+const PAD_VOICES: usize = 4;
+/// Fade out of a choked hit in seconds
+const CHOKE_FADE: f32 = 0.005;
+
+/// One hit of a pad
+#[derive(Clone, Copy, Default)]
+struct Hit {
+    /// Position in the frames of the sample
+    position: f64,
+    /// Level from the velocity
+    gain: f32,
+    /// Level of the fade out once choked, `None` while playing
+    fade: Option<f32>,
+    /// Frame of the rack when the hit started
+    started: u64,
+    active: bool,
+}
+
+/// Hits and effects of a pad
+struct Pad {
+    hits: [Hit; PAD_VOICES],
+    /// Effect chain, processed in order
+    effects: Vec<Box<dyn AudioUnit>>,
+    /// Interleaved stereo output of the pad before its effects are added to the mix
+    buffer: Vec<f32>,
+}
+
+/// Drum rack playing the sample of the pad of each key
+pub struct DrumRack {
+    params: DrumRackParams,
+    pads: Vec<Pad>,
+    sample_rate: usize,
+    /// Frames rendered since the creation of the rack
+    time: u64,
+    input: Box<BufferArray<U2>>,
+    output: Box<BufferArray<U2>>,
+}
+
+impl DrumRack {
+    pub fn new(params: DrumRackParams, sample_rate: usize) -> Self {
+        let pads = params
+            .pads
+            .iter()
+            .map(|pad| Pad {
+                hits: [Hit::default(); PAD_VOICES],
+                effects: pad
+                    .effects
+                    .iter()
+                    .map(|effect| {
+                        let mut unit = effect.get_unit();
+                        unit.set_sample_rate(sample_rate as f64);
+                        unit
+                    })
+                    .collect(),
+                buffer: Vec::with_capacity(2 * MAX_BUFFER_SIZE),
+            })
+            .collect();
+        Self {
+            params,
+            pads,
+            sample_rate,
+            time: 0,
+            input: Box::new(BufferArray::new()),
+            output: Box::new(BufferArray::new()),
+        }
+    }
+
+    /// Fade out the hits of the pads of `group` other than `except`
+    fn choke(&mut self, group: usize, except: usize) {
+        for (index, pad) in self.pads.iter_mut().enumerate() {
+            if index != except && self.params.pads[index].choke_group() == Some(group) {
+                for hit in pad.hits.iter_mut().filter(|hit| hit.active) {
+                    hit.fade.get_or_insert(1.);
+                }
+            }
+        }
+    }
+
+    /// Add the hits of the pad at `index` to its buffer of `size` frames
+    fn render_hits(&mut self, index: usize, size: usize) {
+        let params = &self.params.pads[index];
+        let pad = &mut self.pads[index];
+        pad.buffer.clear();
+        pad.buffer.resize(2 * size, 0.);
+        let Some(sample) = &params.sample else {
+            return;
+        };
+        // Skipped while the file is loading
+        let Ok(data) = sample.data.try_read() else {
+            return;
+        };
+        let (left, right) = &*data;
+        let length = left.len().min(right.len());
+        let step = exp2(params.tune.value() / 12.) as f64 * sample.sample_rate as f64
+            / self.sample_rate as f64;
+        let angle = (params.pan.value().clamp(-1., 1.) + 1.) * FRAC_PI_4;
+        let volume = params.volume.value() * self.params.volume.value();
+        let pan = [angle.cos() * SQRT_2 * volume, angle.sin() * SQRT_2 * volume];
+        let fade_step = 1. / (CHOKE_FADE * self.sample_rate as f32);
+
+        for hit in pad.hits.iter_mut().filter(|hit| hit.active) {
+            for frame in pad.buffer.chunks_mut(2) {
+                let index = hit.position as usize;
+                let fade = hit.fade.unwrap_or(1.);
+                if index + 1 >= length || fade <= 0. {
+                    hit.active = false;
+                    break;
+                }
+                let fraction = (hit.position - index as f64) as f32;
+                let gain = hit.gain * fade;
+                frame[0] +=
+                    (left[index] + (left[index + 1] - left[index]) * fraction) * gain * pan[0];
+                frame[1] +=
+                    (right[index] + (right[index + 1] - right[index]) * fraction) * gain * pan[1];
+                hit.position += step;
+                if let Some(fade) = hit.fade.as_mut() {
+                    *fade -= fade_step;
+                }
+            }
+        }
+    }
+
+    /// Process the buffer of the pad at `index` through its effects
+    fn process_effects(&mut self, index: usize, size: usize) {
+        let pad = &mut self.pads[index];
+        for effect in pad.effects.iter_mut() {
+            for i in 0..size {
+                self.input.set_f32(0, i, pad.buffer[2 * i]);
+                self.input.set_f32(1, i, pad.buffer[2 * i + 1]);
+            }
+            effect.process(
+                size,
+                &self.input.buffer_ref(),
+                &mut self.output.buffer_mut(),
+            );
+            for i in 0..size {
+                pad.buffer[2 * i] = self.output.at_f32(0, i);
+                pad.buffer[2 * i + 1] = self.output.at_f32(1, i);
+            }
+        }
+    }
+}
+
+impl Engine for DrumRack {
+    fn note_on(&mut self, key: u8, velocity: u8) {
+        let Some(index) = self.params.pad_index(key) else {
+            return;
+        };
+        if let Some(group) = self.params.pads[index].choke_group() {
+            self.choke(group, index);
+        }
+        let sensitivity = self.params.velocity.value().clamp(0., 1.);
+        // Take a free hit, else the oldest one
+        let hits = &mut self.pads[index].hits;
+        let hit = match hits.iter().position(|hit| !hit.active) {
+            Some(free) => &mut hits[free],
+            None => hits.iter_mut().min_by_key(|hit| hit.started).unwrap(),
+        };
+        *hit = Hit {
+            position: 0.,
+            gain: 1. - sensitivity + sensitivity * velocity as f32 / 127.,
+            fade: None,
+            started: self.time,
+            active: true,
+        };
+    }
+
+    /// Pads are one-shot, the whole sample is played
+    fn note_off(&mut self, _key: u8) {}
+
+    fn set_bend(&mut self, _bend: f32) {}
+
+    fn set_modulation(&mut self, _modulation: f32) {}
+
+    fn reset(&mut self) {
+        for pad in self.pads.iter_mut() {
+            pad.hits = [Hit::default(); PAD_VOICES];
+            for effect in pad.effects.iter_mut() {
+                effect.reset();
+            }
+        }
+    }
+
+    fn all_notes_off(&mut self) {}
+
+    fn render_block(&mut self, mix: &mut [f32]) {
+        for chunk in mix.chunks_mut(2 * MAX_BUFFER_SIZE) {
+            let size = chunk.len() / 2;
+            for index in 0..self.pads.len() {
+                let pad = &self.pads[index];
+                // Effects keep processing silent pads for their tails
+                if pad.effects.is_empty() && pad.hits.iter().all(|hit| !hit.active) {
+                    continue;
+                }
+                self.render_hits(index, size);
+                self.process_effects(index, size);
+                for (sample, mixed) in self.pads[index].buffer.iter().zip(chunk.iter_mut()) {
+                    *mixed += sample;
+                }
+            }
+            self.time += size as u64;
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::core::instrument::EnvelopeParams;
+use fundsp::{
+    hacker::{AudioNode, Frame},
+    hacker32::U1,
+};
+
+/// Shortest stage of an envelope in seconds, avoids clicks
+const MIN_STAGE: f32 = 0.001;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// ADSR envelope reading its times from shared parameters.
+/// Starts from the current level when retriggered so that stolen voices don't click.
+#[derive(Clone)]
+pub struct Adsr {
+    params: EnvelopeParams,
+    sample_duration: f32,
+    stage: Stage,
+    /// Gate value of the current note
+    trigger: f32,
+    /// Level at the start of the stage
+    from: f32,
+    level: f32,
+    /// Seconds since the start of the stage
+    time: f32,
+}
+
+impl Adsr {
+    pub fn new(params: &EnvelopeParams) -> Self {
+        Self {
+            params: params.clone(),
+            sample_duration: 1. / 44100.,
+            stage: Stage::Idle,
+            trigger: 0.,
+            from: 0.,
+            level: 0.,
+            time: 0.,
+        }
+    }
+
+    /// The envelope is silent until the next note
+    pub fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.from = self.level;
+        self.time = 0.;
+    }
+}
+
+impl AudioNode for Adsr {
+    const ID: u64 = 0x746f_6e69_6164_7372;
+    type Inputs = U1;
+    type Outputs = U1;
+
+    fn reset(&mut self) {
+        self.stage = Stage::Idle;
+        self.trigger = 0.;
+        self.from = 0.;
+        self.level = 0.;
+        self.time = 0.;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_duration = 1. / sample_rate as f32;
+    }
+
+    #[inline]
+    fn tick(&mut self, input: &Frame<f32, Self::Inputs>) -> Frame<f32, Self::Outputs> {
+        let gate = input[0];
+        if gate > 0. && gate != self.trigger {
+            self.trigger = gate;
+            self.enter(Stage::Attack);
+        } else if gate <= 0. && !matches!(self.stage, Stage::Idle | Stage::Release) {
+            self.trigger = 0.;
+            self.enter(Stage::Release);
+        }
+        let sustain = self.params.sustain.value().clamp(0., 1.);
+        match self.stage {
+            Stage::Idle => self.level = 0.,
+            Stage::Attack => {
+                let attack = self.params.attack.value().max(MIN_STAGE);
+                self.level = self.from + (1. - self.from) * (self.time / attack).min(1.);
+                if self.time >= attack {
+                    self.enter(Stage::Decay);
+                }
+            }
+            Stage::Decay => {
+                let decay = self.params.decay.value().max(MIN_STAGE);
+                self.level = 1. + (sustain - 1.) * (self.time / decay).min(1.);
+                if self.time >= decay {
+                    self.enter(Stage::Sustain);
+                }
+            }
+            Stage::Sustain => self.level = sustain,
+            Stage::Release => {
+                let release = self.params.release.value().max(MIN_STAGE);
+                self.level = self.from * (1. - self.time / release).max(0.);
+                if self.time >= release {
+                    self.enter(Stage::Idle);
+                }
+            }
+        }
+        self.time += self.sample_duration;
+        [self.level].into()
+    }
+}
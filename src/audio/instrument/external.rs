@@ -0,0 +1,139 @@
+use crate::core::instrument::ExternalParams;
+use midly::MidiMessage;
+use std::sync::Arc;
+
+/// Events kept for a block without allocating on the audio thread
+const EVENTS_CAPACITY: usize = 1024;
+/// Part of the events kept for the note offs, so that held notes are always released
+const RELEASE_CAPACITY: usize = 256;
+/// Controller releasing the held notes
+pub const ALL_NOTES_OFF: u8 = 123;
+
+/// Output of a MIDI track sending its events to an external instrument.
+/// The held notes are tracked to release them when the playback stops or jumps.
+#[derive(Clone)]
+pub struct ExternalOutput {
+    params: ExternalParams,
+    port: Option<Arc<str>>,
+    /// Channel of the note held on each key
+    held: [Option<u8>; 128],
+    /// Messages of the block with their offset in frames and their channel
+    events: Vec<(usize, u8, MidiMessage)>,
+    /// Channels whose note offs could not be sent, one bit each,
+    /// released with an all notes off
+    lost_releases: u16,
+}
+
+impl ExternalOutput {
+    pub fn new(params: ExternalParams) -> Self {
+        Self {
+            port: params.port.as_deref().map(Arc::from),
+            params,
+            held: [None; 128],
+            events: Vec::with_capacity(EVENTS_CAPACITY),
+            lost_releases: 0,
+        }
+    }
+
+    pub fn port(&self) -> Option<&Arc<str>> {
+        self.port.as_ref()
+    }
+
+    /// Seconds added to the time of the events
+    pub fn delay(&self) -> f32 {
+        self.params.delay.value()
+    }
+
+    /// Send `message` at `offset` frames in the block, on the channel of the output.
+    /// Releasing a key uses the channel of its note.
+    pub fn handle(&mut self, offset: usize, message: MidiMessage) {
+        let channel = self.params.channel();
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel > 0 => {
+                // Release the key first when it is held on another channel
+                if let Some(held) = self.held[key.as_int() as usize]
+                    && held != channel
+                {
+                    self.note_off(offset, key.as_int());
+                }
+                self.held[key.as_int() as usize] = Some(channel);
+                self.push(offset, channel, message);
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                self.note_off(offset, key.as_int())
+            }
+            MidiMessage::Controller { controller, .. } if controller == ALL_NOTES_OFF => {
+                self.release(offset)
+            }
+            message => self.push(offset, channel, message),
+        }
+    }
+
+    /// Select the program of the output, when the transport starts
+    pub fn start(&mut self, offset: usize) {
+        if let Some(program) = self.params.program() {
+            let channel = self.params.channel();
+            self.push(
+                offset,
+                channel,
+                MidiMessage::ProgramChange {
+                    program: program.into(),
+                },
+            );
+        }
+    }
+
+    /// Release every held note at `offset`
+    pub fn release(&mut self, offset: usize) {
+        for key in 0..self.held.len() {
+            if self.held[key].is_some() {
+                self.note_off(offset, key as u8);
+            }
+        }
+    }
+
+    fn note_off(&mut self, offset: usize, key: u8) {
+        if let Some(channel) = self.held[key as usize].take() {
+            self.push(
+                offset,
+                channel,
+                MidiMessage::NoteOff {
+                    key: key.into(),
+                    vel: 0.into(),
+                },
+            );
+        }
+    }
+
+    /// Keep `message` for the block. Note offs use the reserved capacity, once the block
+    /// is full their channel is released with an all notes off instead.
+    fn push(&mut self, offset: usize, channel: u8, message: MidiMessage) {
+        let release = matches!(message, MidiMessage::NoteOff { .. });
+        let capacity = if release {
+            EVENTS_CAPACITY
+        } else {
+            EVENTS_CAPACITY - RELEASE_CAPACITY
+        };
+        if self.events.len() < capacity {
+            self.events.push((offset, channel, message));
+        } else if release {
+            self.lose_releases(1 << (channel & 0x0F));
+        }
+    }
+
+    /// Note offs on the `channels`, one bit each, were not sent. Their notes are to be
+    /// released with an all notes off.
+    pub fn lose_releases(&mut self, channels: u16) {
+        self.lost_releases |= channels;
+    }
+
+    /// Channels to release with an all notes off, one bit each
+    pub fn take_lost_releases(&mut self) -> u16 {
+        std::mem::take(&mut self.lost_releases)
+    }
+
+    /// Take the messages of the block, in their order
+    pub fn drain_events(&mut self) -> impl Iterator<Item = (usize, u8, MidiMessage)> + '_ {
+        self.events.drain(..)
+    }
+}
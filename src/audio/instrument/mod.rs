@@ -1,19 +1,164 @@
-use fundsp::hacker::AudioUnit;
+#[cfg(test)]
+mod tests;
 
-struct Instrument {
-    unit: Box<dyn AudioUnit>,
-    voices: Vec<Box<dyn AudioUnit>>,
+mod drum_rack;
+mod envelope;
+pub mod external;
+mod sampler;
+mod synth;
+
+use crate::{
+    audio::instrument::{drum_rack::DrumRack, sampler::Sampler, synth::Synth},
+    core::instrument::{DrumRackParams, SamplerParams, SynthParams},
+};
+use fundsp::hacker::Shared;
+use midly::MidiMessage;
+
+/// Voices allocated for each instrument, the polyphony limits the ones used
+const MAX_VOICES: usize = 32;
+/// Controller of the mod wheel
+const MOD_WHEEL: u8 = 1;
+/// Controller releasing every voice at once
+const ALL_SOUND_OFF: u8 = 120;
+/// Controller releasing the held notes
+const ALL_NOTES_OFF: u8 = 123;
+
+/// Sound generation of an instrument, driven by the MIDI events
+trait Engine: Send {
+    fn note_on(&mut self, key: u8, velocity: u8);
+    fn note_off(&mut self, key: u8);
+    /// Pitch bend between -1 and 1
+    fn set_bend(&mut self, bend: f32);
+    /// Mod wheel between 0 and 1
+    fn set_modulation(&mut self, modulation: f32);
+    /// Release every voice at once and clear their state
+    fn reset(&mut self);
+    /// Release the held notes, letting them ring
+    fn all_notes_off(&mut self);
+    /// Add the voices to the interleaved stereo `mix`
+    fn render_block(&mut self, mix: &mut [f32]);
+}
+
+/// Allocation state of a voice
+#[derive(Default)]
+struct VoiceState {
+    /// Key held by the voice, `None` once released
+    key: Option<u8>,
+    /// Frame of the instrument when the note started
+    started: u64,
+    /// Frame of the instrument when the note was released
+    released: u64,
+}
+
+impl VoiceState {
+    fn start(&mut self, key: u8, time: u64) {
+        self.key = Some(key);
+        self.started = time;
+    }
+
+    fn release(&mut self, time: u64) {
+        self.key = None;
+        self.released = time;
+    }
+
+    /// Index of the voice playing `key` among the first `polyphony` ones: the voice already
+    /// holding the key, else the voice released the longest time ago, else the oldest note
+    /// which is stolen
+    fn pick<'a>(
+        voices: impl Iterator<Item = &'a VoiceState> + Clone,
+        key: u8,
+        polyphony: &Shared,
+    ) -> Option<usize> {
+        let polyphony = (polyphony.value().round() as usize).clamp(1, MAX_VOICES);
+        let voices = voices.take(polyphony);
+        voices
+            .clone()
+            .position(|voice| voice.key == Some(key))
+            .or_else(|| {
+                voices
+                    .clone()
+                    .enumerate()
+                    .filter(|(_, voice)| voice.key.is_none())
+                    .min_by_key(|(_, voice)| voice.released)
+                    .map(|(index, _)| index)
+            })
+            .or_else(|| {
+                voices
+                    .enumerate()
+                    .min_by_key(|(_, voice)| voice.started)
+                    .map(|(index, _)| index)
+            })
+    }
+}
+
+/// Parameters of the instruments generating their sound, external instruments are played
+/// by an `ExternalOutput`
+#[derive(Clone)]
+pub enum EngineParams {
+    Synth(SynthParams),
+    Sampler(SamplerParams),
+    DrumRack(DrumRackParams),
+}
+
+impl EngineParams {
+    fn engine(&self, sample_rate: usize) -> Box<dyn Engine> {
+        match self {
+            Self::Synth(synth) => Box::new(Synth::new(synth.clone(), sample_rate)),
+            Self::Sampler(sampler) => Box::new(Sampler::new(sampler.clone(), sample_rate)),
+            Self::DrumRack(rack) => Box::new(DrumRack::new(rack.clone(), sample_rate)),
+        }
+    }
+}
+
+/// Instrument playing the MIDI events of a track
+pub struct Instrument {
+    params: EngineParams,
+    sample_rate: usize,
+    engine: Box<dyn Engine>,
 }
 
 impl Instrument {
-    pub fn new(unit: Box<dyn AudioUnit>, voices: usize) -> Self {
+    pub fn new(params: EngineParams, sample_rate: usize) -> Self {
         Self {
-            unit,
-            voices: Vec::new(),
+            engine: params.engine(sample_rate),
+            params,
+            sample_rate,
+        }
+    }
+
+    /// Release the held notes, letting them ring
+    pub fn all_notes_off(&mut self) {
+        self.engine.all_notes_off();
+    }
+
+    pub fn handle(&mut self, message: MidiMessage) {
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel > 0 => {
+                self.engine.note_on(key.as_int(), vel.as_int())
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                self.engine.note_off(key.as_int())
+            }
+            MidiMessage::PitchBend { bend } => self.engine.set_bend(bend.as_f32()),
+            MidiMessage::Controller { controller, value } => match controller.as_int() {
+                MOD_WHEEL => self.engine.set_modulation(value.as_int() as f32 / 127.),
+                ALL_SOUND_OFF => self.engine.reset(),
+                ALL_NOTES_OFF => self.engine.all_notes_off(),
+                _ => {}
+            },
+            _ => {}
         }
     }
 
-    pub fn reset(&mut self) {}
+    /// Add the voices to the interleaved stereo `mix`
+    pub fn render_block(&mut self, mix: &mut [f32]) {
+        self.engine.render_block(mix);
+    }
+}
 
-    pub fn render_block(&mut self, pos: usize, num_frames: usize, sample_rate: usize) {}
+impl Clone for Instrument {
+    /// Voices hold shared controls, a clone gets its own voices
+    fn clone(&self) -> Self {
+        Self::new(self.params.clone(), self.sample_rate)
+    }
 }
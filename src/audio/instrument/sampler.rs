@@ -0,0 +1,196 @@
+use crate::{
+    audio::instrument::{Engine, MAX_VOICES, VoiceState, envelope::Adsr},
+    core::instrument::{SampleMode, SamplerParams},
+};
+use fundsp::hacker::{AudioNode, exp2};
+
+/// Shortest loop in frames, shorter loops play the sample to its end
+const MIN_LOOP_LENGTH: f64 = 16.;
+
+/// One note of the sampler
+struct Voice {
+    state: VoiceState,
+    /// Key of the note, kept during the release for the pitch bend
+    note: u8,
+    /// Velocity layer played
+    layer: usize,
+    /// Position in the frames of the sample
+    position: f64,
+    /// Level from the velocity
+    gain: f32,
+    /// Gate of the envelope, a new positive value retriggers it
+    gate: f32,
+    envelope: Adsr,
+    /// The voice is playing, until the end of the sample or of the release
+    active: bool,
+}
+
+impl Voice {
+    fn stop(&mut self, time: u64) {
+        self.active = false;
+        self.gate = 0.;
+        if self.state.key.is_some() {
+            self.state.release(time);
+        }
+    }
+}
+
+/// Sampler playing its samples pitched from a root note
+pub struct Sampler {
+    params: SamplerParams,
+    voices: Vec<Voice>,
+    sample_rate: usize,
+    /// Frames rendered since the creation of the sampler
+    time: u64,
+    /// Gate value of the last note, a new value retriggers the envelopes
+    trigger: f32,
+    /// Pitch bend between -1 and 1
+    bend: f32,
+}
+
+impl Sampler {
+    pub fn new(params: SamplerParams, sample_rate: usize) -> Self {
+        let voices = (0..MAX_VOICES)
+            .map(|_| {
+                let mut envelope = Adsr::new(&params.amp_envelope);
+                envelope.set_sample_rate(sample_rate as f64);
+                Voice {
+                    state: VoiceState::default(),
+                    note: 0,
+                    layer: 0,
+                    position: 0.,
+                    gain: 0.,
+                    gate: 0.,
+                    envelope,
+                    active: false,
+                }
+            })
+            .collect();
+        Self {
+            params,
+            voices,
+            sample_rate,
+            time: 0,
+            trigger: 0.,
+            bend: 0.,
+        }
+    }
+
+    fn mode(&self) -> SampleMode {
+        SampleMode::from_value(self.params.mode.value())
+    }
+}
+
+impl Engine for Sampler {
+    fn note_on(&mut self, key: u8, velocity: u8) {
+        let Some(layer) = self.params.layer_index(velocity) else {
+            return;
+        };
+        let states = self.voices.iter().map(|voice| &voice.state);
+        let Some(index) = VoiceState::pick(states, key, &self.params.polyphony) else {
+            return;
+        };
+        self.trigger = self.trigger % 1_000_000. + 1.;
+        let sensitivity = self.params.velocity.value().clamp(0., 1.);
+        let voice = &mut self.voices[index];
+        voice.state.start(key, self.time);
+        voice.note = key;
+        voice.layer = layer;
+        voice.position = 0.;
+        voice.gain = 1. - sensitivity + sensitivity * velocity as f32 / 127.;
+        voice.gate = self.trigger;
+        voice.active = true;
+    }
+
+    fn note_off(&mut self, key: u8) {
+        // One-shot voices play until the end of the sample
+        if self.mode() == SampleMode::OneShot {
+            return;
+        }
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.state.key == Some(key))
+        {
+            voice.state.release(self.time);
+            voice.gate = 0.;
+        }
+    }
+
+    fn set_bend(&mut self, bend: f32) {
+        self.bend = bend;
+    }
+
+    fn set_modulation(&mut self, _modulation: f32) {}
+
+    fn reset(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.stop(0);
+            voice.envelope.reset();
+        }
+        self.bend = 0.;
+    }
+
+    fn all_notes_off(&mut self) {
+        if self.mode() == SampleMode::OneShot {
+            return;
+        }
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.state.key.is_some())
+        {
+            voice.state.release(self.time);
+            voice.gate = 0.;
+        }
+    }
+
+    fn render_block(&mut self, mix: &mut [f32]) {
+        let mode = self.mode();
+        let transpose = self.params.tune.value() - self.params.root.value()
+            + self.bend * self.params.bend_range.value();
+        let volume = self.params.volume.value();
+        let loop_start = self.params.loop_start.value().clamp(0., 1.) as f64;
+        let loop_end = self.params.loop_end.value().clamp(0., 1.) as f64;
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            let Some(layer) = self.params.layers.get(voice.layer) else {
+                voice.stop(self.time);
+                continue;
+            };
+            // Skipped while the file is loading
+            let Ok(data) = layer.audio.data.try_read() else {
+                continue;
+            };
+            let (left, right) = &*data;
+            let length = left.len().min(right.len());
+            let step = exp2((voice.note as f32 + transpose) / 12.) as f64
+                * layer.audio.sample_rate as f64
+                / self.sample_rate as f64;
+            let start = loop_start * length as f64;
+            let end = loop_end * length as f64;
+            let looping = mode == SampleMode::Loop && end - start >= MIN_LOOP_LENGTH;
+
+            for (i, frame) in mix.chunks_mut(2).enumerate() {
+                if looping && voice.position >= end {
+                    voice.position = start + (voice.position - start) % (end - start);
+                }
+                let index = voice.position as usize;
+                if index + 1 >= length {
+                    voice.stop(self.time + i as u64);
+                    break;
+                }
+                let level = voice.envelope.tick(&[voice.gate].into())[0];
+                if voice.state.key.is_none() && voice.envelope.is_idle() {
+                    voice.stop(self.time + i as u64);
+                    break;
+                }
+                let fraction = (voice.position - index as f64) as f32;
+                let gain = level * voice.gain * volume;
+                frame[0] += (left[index] + (left[index + 1] - left[index]) * fraction) * gain;
+                frame[1] += (right[index] + (right[index + 1] - right[index]) * fraction) * gain;
+                voice.position += step;
+            }
+        }
+        self.time += (mix.len() / 2) as u64;
+    }
+}
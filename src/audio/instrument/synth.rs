@@ -0,0 +1,228 @@
+use crate::{
+    audio::instrument::{Engine, MAX_VOICES, VoiceState, envelope::Adsr},
+    core::instrument::{SynthParams, Waveform},
+};
+use fundsp::{
+    MAX_BUFFER_SIZE,
+    buffer::BufferRef,
+    hacker::{
+        An, AudioNode, AudioUnit, BufferArray, Frame, Shared, exp2, map, midi_hz, moog, pass, saw,
+        shared, sine, sine_hz, square, triangle, var, var_fn,
+    },
+    hacker32::U1,
+};
+
+/// Rate of the vibrato of the mod wheel in Hz
+const VIBRATO_RATE: f32 = 5.5;
+
+/// Controls of one voice set by the synth
+#[derive(Clone)]
+struct VoiceControls {
+    /// Frequency in Hz including the pitch bend
+    pitch: Shared,
+    /// Positive while the note is held, a new value retriggers the envelopes
+    gate: Shared,
+    /// Velocity between 0 and 1
+    velocity: Shared,
+    /// Mod wheel between 0 and 1, shared by the voices
+    modulation: Shared,
+}
+
+/// Oscillator playing the waveform selected by `wave`, its input being the frequency
+fn oscillator(wave: &Shared) -> An<impl AudioNode<Inputs = U1, Outputs = U1> + use<>> {
+    let weight = |waveform: Waveform| {
+        var_fn(wave, move |value| {
+            if Waveform::from_value(value) == waveform {
+                1.
+            } else {
+                0.
+            }
+        })
+    };
+    (sine() * weight(Waveform::Sine))
+        & (saw() * weight(Waveform::Saw))
+        & (square() * weight(Waveform::Square))
+        & (triangle() * weight(Waveform::Triangle))
+}
+
+/// Sound of one voice: two oscillators into a resonant lowpass with its own envelope,
+/// shaped by the amplitude envelope and the velocity
+fn voice(params: &SynthParams, controls: &VoiceControls) -> Box<dyn AudioUnit> {
+    // Vibrato from the mod wheel
+    let frequency = (var(&controls.pitch)
+        | var(&controls.modulation)
+        | var(&params.vibrato)
+        | sine_hz(VIBRATO_RATE))
+        >> map(|f: &Frame<f32, fundsp::hacker32::U4>| f[0] * exp2(f[1] * f[2] * f[3] / 12.));
+    let osc1 = oscillator(&params.osc1_wave) * var(&params.osc1_level);
+    let osc2 = (pass() * var_fn(&params.osc2_detune, |semitones| exp2(semitones / 12.)))
+        >> oscillator(&params.osc2_wave)
+        >> (pass() * var(&params.osc2_level));
+    let oscillators = frequency >> (osc1 & osc2);
+
+    let cutoff = ((var(&controls.gate) >> An(Adsr::new(&params.filter_envelope)))
+        | var(&params.cutoff)
+        | var(&params.filter_amount))
+        >> map(|f: &Frame<f32, fundsp::hacker32::U3>| {
+            (f[1] * exp2(f[0] * f[2])).clamp(20., 18000.)
+        });
+    let filter =
+        (oscillators | cutoff | var_fn(&params.resonance, |q| q.clamp(0., 0.95))) >> moog();
+
+    // Level between 1 - sensitivity and 1 with the velocity
+    let level = (var(&controls.velocity) | var(&params.velocity))
+        >> map(|f: &Frame<f32, fundsp::hacker32::U2>| 1. - f[1] + f[1] * f[0]);
+    let amplitude = var(&controls.gate) >> An(Adsr::new(&params.amp_envelope));
+
+    Box::new(filter * amplitude * level * var(&params.volume))
+}
+
+/// One note of the synth
+struct Voice {
+    state: VoiceState,
+    controls: VoiceControls,
+    unit: Box<dyn AudioUnit>,
+}
+
+impl Voice {
+    fn new(params: &SynthParams, modulation: &Shared, sample_rate: usize) -> Self {
+        let controls = VoiceControls {
+            pitch: shared(440.),
+            gate: shared(0.),
+            velocity: shared(0.),
+            modulation: modulation.clone(),
+        };
+        let mut unit = voice(params, &controls);
+        unit.set_sample_rate(sample_rate as f64);
+        Self {
+            state: VoiceState::default(),
+            controls,
+            unit,
+        }
+    }
+
+    fn release(&mut self, time: u64) {
+        self.state.release(time);
+        self.controls.gate.set_value(0.);
+    }
+}
+
+/// Polyphonic subtractive synth
+pub struct Synth {
+    params: SynthParams,
+    voices: Vec<Voice>,
+    sample_rate: usize,
+    /// Frames rendered since the creation of the synth
+    time: u64,
+    /// Gate value of the last note, a new value retriggers the envelopes
+    trigger: f32,
+    /// Pitch bend between -1 and 1
+    bend: f32,
+    /// Mod wheel between 0 and 1
+    modulation: Shared,
+    output: Box<BufferArray<U1>>,
+}
+
+impl Synth {
+    pub fn new(params: SynthParams, sample_rate: usize) -> Self {
+        let modulation = shared(0.);
+        Self {
+            voices: (0..MAX_VOICES)
+                .map(|_| Voice::new(&params, &modulation, sample_rate))
+                .collect(),
+            params,
+            sample_rate,
+            time: 0,
+            trigger: 0.,
+            bend: 0.,
+            modulation,
+            output: Box::new(BufferArray::new()),
+        }
+    }
+
+    /// Frequency of `key` with the pitch bend
+    fn frequency(&self, key: u8) -> f32 {
+        midi_hz(key as f32 + self.bend * self.params.bend_range.value())
+    }
+}
+
+impl Engine for Synth {
+    fn note_on(&mut self, key: u8, velocity: u8) {
+        let states = self.voices.iter().map(|voice| &voice.state);
+        let Some(index) = VoiceState::pick(states, key, &self.params.polyphony) else {
+            return;
+        };
+        self.trigger = self.trigger % 1_000_000. + 1.;
+        let frequency = self.frequency(key);
+        let voice = &mut self.voices[index];
+        voice.state.start(key, self.time);
+        voice.controls.pitch.set_value(frequency);
+        voice.controls.velocity.set_value(velocity as f32 / 127.);
+        voice.controls.gate.set_value(self.trigger);
+    }
+
+    fn note_off(&mut self, key: u8) {
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.state.key == Some(key))
+        {
+            voice.release(self.time);
+        }
+    }
+
+    fn set_bend(&mut self, bend: f32) {
+        self.bend = bend;
+        for voice in self.voices.iter() {
+            if let Some(key) = voice.state.key {
+                voice.controls.pitch.set_value(self.frequency(key));
+            }
+        }
+    }
+
+    fn set_modulation(&mut self, modulation: f32) {
+        self.modulation.set_value(modulation);
+    }
+
+    fn reset(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.release(0);
+            voice.unit.reset();
+        }
+        self.bend = 0.;
+        self.modulation.set_value(0.);
+    }
+
+    fn all_notes_off(&mut self) {
+        for voice in self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.state.key.is_some())
+        {
+            voice.release(self.time);
+        }
+    }
+
+    fn render_block(&mut self, mix: &mut [f32]) {
+        // Voices are skipped once their release is over
+        let release = self.params.amp_envelope.release.value().max(0.) + 0.05;
+        let release = (release * self.sample_rate as f32) as u64;
+        for chunk in mix.chunks_mut(2 * MAX_BUFFER_SIZE) {
+            let size = chunk.len() / 2;
+            for voice in self.voices.iter_mut() {
+                if voice.state.key.is_none() && self.time >= voice.state.released + release {
+                    continue;
+                }
+                voice
+                    .unit
+                    .process(size, &BufferRef::empty(), &mut self.output.buffer_mut());
+                for (i, frame) in chunk.chunks_mut(2).enumerate() {
+                    let sample = self.output.at_f32(0, i);
+                    frame[0] += sample;
+                    frame[1] += sample;
+                }
+            }
+            self.time += size as u64;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    analysis::AudioInfo,
+    audio::instrument::{EngineParams, Instrument, VoiceState, external::ExternalOutput},
+    core::instrument::{DrumRackParams, ExternalParams},
+};
+use fundsp::hacker::shared;
+use midly::MidiMessage;
+
+#[test]
+fn test_oldest_voice_is_stolen() {
+    let mut voices: Vec<VoiceState> = (0..4).map(|_| VoiceState::default()).collect();
+    let polyphony = shared(2.);
+
+    for (time, key) in [60, 64, 67].into_iter().enumerate() {
+        let index = VoiceState::pick(voices.iter(), key, &polyphony).unwrap();
+        voices[index].start(key, time as u64);
+    }
+
+    let keys: Vec<_> = voices.iter().map(|voice| voice.key).collect();
+    assert_eq!(keys, [Some(67), Some(64), None, None]);
+}
+
+/// Sample of constant `value`
+fn constant_sample(value: f32) -> AudioInfo {
+    AudioInfo::from_samples("constant.wav", vec![value; 48000], 48000)
+}
+
+#[test]
+fn test_choke_group_stops_other_pads() {
+    let mut params = DrumRackParams::default();
+    params.pads[0].sample = Some(constant_sample(1.));
+    params.pads[1].sample = Some(constant_sample(0.));
+    for pad in &params.pads[..2] {
+        pad.choke.set_value(1.);
+    }
+    let (open, closed) = (params.pads[0].key, params.pads[1].key);
+    let mut rack = Instrument::new(EngineParams::DrumRack(params), 48000);
+    let note_on = |key: u8| MidiMessage::NoteOn {
+        key: key.into(),
+        vel: 127.into(),
+    };
+
+    let mut mix = vec![0.; 2 * 512];
+    rack.handle(note_on(open));
+    rack.render_block(&mut mix);
+    assert!(mix.iter().all(|sample| *sample > 0.));
+
+    mix.fill(0.);
+    rack.handle(note_on(closed));
+    rack.render_block(&mut mix);
+    assert!(mix[2 * 256..].iter().all(|sample| *sample == 0.));
+}
+
+#[test]
+fn test_note_offs_sent_when_block_full() {
+    let mut output = ExternalOutput::new(ExternalParams::default());
+    for key in 0..4 {
+        output.handle(
+            0,
+            MidiMessage::NoteOn {
+                key: key.into(),
+                vel: 100.into(),
+            },
+        );
+    }
+    for value in 0..2000u32 {
+        output.handle(
+            0,
+            MidiMessage::Controller {
+                controller: 1.into(),
+                value: ((value % 128) as u8).into(),
+            },
+        );
+    }
+    output.release(1);
+    let note_offs = output
+        .drain_events()
+        .filter(|(_, _, message)| matches!(message, MidiMessage::NoteOff { .. }))
+        .count();
+    assert_eq!(note_offs, 4);
+}
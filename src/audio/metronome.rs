@@ -1,59 +1,177 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::{
+    meter::{Meter, TimeSignature},
+    metronome::{ClickSample, MetronomeSettings},
+    tempo::TempoMap,
+};
 use fundsp::hacker::{AudioUnit, envelope, square_hz};
+use std::sync::Arc;
+
+/// Bars counted before the transport starts
+struct CountIn {
+    /// Frames played since the start of the count-in
+    frame: usize,
+    length: usize,
+    /// Length of a beat of the signature in frames
+    beat_frames: f64,
+    /// Index of the next beat to click
+    beat: u32,
+    numerator: u32,
+}
 
 pub struct MetronomeBackend {
     pub enabled: bool,
-    pub metronome_volume: f32,
+    pub settings: MetronomeSettings,
     pub metronome_tick: Box<dyn AudioUnit>,
     pub metronome_accent: Box<dyn AudioUnit>,
+    /// Read positions in the tick and accent samples, `None` when silent
+    sample_voices: [Option<f64>; 2],
+    count_in: Option<CountIn>,
 }
 
 impl MetronomeBackend {
     pub fn new() -> Self {
         Self {
             enabled: false,
-            metronome_volume: 0.4,
+            settings: MetronomeSettings::default(),
             metronome_tick: Box::new(metronome_click(1000.0)), // regular tick
             metronome_accent: Box::new(metronome_click(1800.0)), // bar accent}
+            sample_voices: [None, None],
+            count_in: None,
+        }
+    }
+
+    /// Apply the settings, the clicks playing go on unless their sound changed
+    pub fn set_settings(&mut self, settings: MetronomeSettings) {
+        for accent in [false, true] {
+            let old = Self::sample(&self.settings, accent);
+            if !same_sample(old, Self::sample(&settings, accent)) {
+                self.sample_voices[accent as usize] = None;
+            }
+        }
+        self.settings = settings;
+    }
+
+    /// Start counting the bars of the settings before the transport, with `signature` and `bpm`
+    /// at the playhead
+    pub fn start_count_in(&mut self, signature: TimeSignature, bpm: f32, sample_rate: usize) {
+        if self.settings.count_in == 0 {
+            self.count_in = None;
+            return;
         }
+        let beat_frames = 60. / bpm as f64 * signature.beat_length() as f64 * sample_rate as f64;
+        let beats = self.settings.count_in as u32 * signature.numerator;
+        self.count_in = Some(CountIn {
+            frame: 0,
+            length: (beats as f64 * beat_frames).round() as usize,
+            beat_frames,
+            beat: 0,
+            numerator: signature.numerator,
+        });
+    }
+
+    pub fn counting_in(&self) -> bool {
+        self.count_in.is_some()
+    }
+
+    pub fn stop_count_in(&mut self) {
+        self.count_in = None;
     }
 
+    /// Render the count-in into the mono `click` buffer.
+    /// Returns the number of frames counted, less than the buffer once the count-in ends.
+    pub fn render_count_in(&mut self, click: &mut [f32], sample_rate: usize) -> usize {
+        let Some(mut count_in) = self.count_in.take() else {
+            return 0;
+        };
+        let frames = (count_in.length - count_in.frame).min(click.len());
+        for value in click[..frames].iter_mut() {
+            let beat_frame = (count_in.beat as f64 * count_in.beat_frames).round() as usize;
+            if count_in.frame == beat_frame {
+                self.trigger(count_in.beat % count_in.numerator == 0);
+                count_in.beat += 1;
+            }
+            *value += self.next_value(sample_rate);
+            count_in.frame += 1;
+        }
+        if count_in.frame < count_in.length {
+            self.count_in = Some(count_in);
+        }
+        frames
+    }
+
+    /// Render the beats of `meter` into the mono `click` buffer starting at frame `block_start`
     pub fn render(
         &mut self,
-        output: &mut [f32],
-        num_frames: usize,
+        click: &mut [f32],
         sample_rate: usize,
-        playhead: usize,
-        bpm: f32,
+        block_start: usize,
+        tempo: &TempoMap,
+        meter: &Meter,
     ) {
-        let samples_per_beat = (sample_rate as f32 * 60.0 / bpm) as usize;
-        let beats_per_bar = 4; // You could make this configurable
-
-        for i in 0..num_frames {
-            let absolute_sample = playhead + i - num_frames;
-
-            // Determine current beat
-            let beat_index = absolute_sample / samples_per_beat;
-            let sample_in_beat = absolute_sample % samples_per_beat;
+        // Next beat of the time signature at or after the start of the block
+        let start_beat = tempo.frames_to_beats(block_start, sample_rate).max(0.);
+        let (mut beat, mut is_accent) = meter.next_beat(start_beat);
+        let mut beat_frame = tempo.beats_to_frames(beat, sample_rate);
+        while beat_frame < block_start {
+            (beat, is_accent) = meter.next_beat(beat + 1e-3);
+            beat_frame = tempo.beats_to_frames(beat, sample_rate);
+        }
 
+        for (i, value) in click.iter_mut().enumerate() {
             // Only trigger at the start of each beat
-            if sample_in_beat == 0 {
-                let is_accent = beat_index % beats_per_bar == 0;
-                let click = if is_accent {
-                    &mut self.metronome_accent
-                } else {
-                    &mut self.metronome_tick
-                };
-                click.reset();
+            if block_start + i == beat_frame {
+                self.trigger(is_accent);
+                (beat, is_accent) = meter.next_beat(beat + 1e-3);
+                beat_frame = tempo.beats_to_frames(beat, sample_rate);
             }
+            *value += self.next_value(sample_rate);
+        }
+    }
 
-            // Mix click output into the buffer
-            let tick = self.metronome_tick.get_mono() * self.metronome_volume;
-            let accent = self.metronome_accent.get_mono() * self.metronome_volume;
-            let value = tick + accent;
+    /// Sample played for the accent or the tick, `None` for the synthesized click
+    fn sample(settings: &MetronomeSettings, accent: bool) -> Option<&ClickSample> {
+        if accent {
+            settings.accent.as_ref().or(settings.tick.as_ref())
+        } else {
+            settings.tick.as_ref()
+        }
+    }
 
-            output[2 * i] += value;
-            output[2 * i + 1] += value;
+    fn trigger(&mut self, accent: bool) {
+        if Self::sample(&self.settings, accent).is_some() {
+            self.sample_voices[accent as usize] = Some(0.);
+        } else if accent {
+            self.metronome_accent.reset();
+        } else {
+            self.metronome_tick.reset();
+        }
+    }
+
+    /// Next frame of the tick and the accent
+    fn next_value(&mut self, sample_rate: usize) -> f32 {
+        let mut value = 0.;
+        for accent in [false, true] {
+            match Self::sample(&self.settings, accent) {
+                Some(sample) => {
+                    let voice = &mut self.sample_voices[accent as usize];
+                    if let Some(position) = *voice {
+                        let index = position as usize;
+                        let fraction = (position - index as f64) as f32;
+                        let current = sample.data.get(index).copied().unwrap_or(0.);
+                        let next = sample.data.get(index + 1).copied().unwrap_or(0.);
+                        value += current + (next - current) * fraction;
+                        let position = position + sample.sample_rate as f64 / sample_rate as f64;
+                        *voice = (position < sample.data.len() as f64).then_some(position);
+                    }
+                }
+                None if accent => value += self.metronome_accent.get_mono(),
+                None => value += self.metronome_tick.get_mono(),
+            }
         }
+        value * self.settings.volume
     }
 }
 /// Click used
@@ -67,3 +185,12 @@ pub fn metronome_click(freq: f32) -> impl AudioUnit {
     });
     square_hz(freq) * env
 }
+
+/// Whether both are the same loaded sample, without comparing their data
+fn same_sample(a: Option<&ClickSample>, b: Option<&ClickSample>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(&a.data, &b.data) && a.sample_rate == b.sample_rate,
+        (None, None) => true,
+        _ => false,
+    }
+}
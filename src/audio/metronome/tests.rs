@@ -0,0 +1,65 @@
+use crate::{
+    audio::metronome::MetronomeBackend,
+    core::{
+        meter::TimeSignature,
+        metronome::{ClickSample, MetronomeSettings},
+    },
+};
+use std::sync::Arc;
+
+#[test]
+fn test_count_in() {
+    let mut metronome = MetronomeBackend::new();
+    metronome.set_settings(MetronomeSettings {
+        count_in: 1,
+        ..Default::default()
+    });
+    // One bar of 3/4 at 60 bpm counts 3 seconds
+    metronome.start_count_in(TimeSignature::new(3, 4), 60., 100);
+    let mut click = vec![0.; 128];
+    assert_eq!(metronome.render_count_in(&mut click, 100), 128);
+    assert!(click.iter().any(|value| value.abs() > 0.));
+    assert!(metronome.counting_in());
+    let mut click = vec![0.; 256];
+    assert_eq!(metronome.render_count_in(&mut click, 100), 172);
+    assert!(!metronome.counting_in());
+
+    // No count-in by default
+    metronome.set_settings(MetronomeSettings::default());
+    metronome.start_count_in(TimeSignature::new(3, 4), 60., 100);
+    assert!(!metronome.counting_in());
+}
+
+#[test]
+fn test_settings_keep_playing_click() {
+    let sample = ClickSample {
+        name: "click".into(),
+        data: Arc::new(vec![1.; 100]),
+        sample_rate: 100,
+    };
+    let mut metronome = MetronomeBackend::new();
+    metronome.set_settings(MetronomeSettings {
+        tick: Some(sample.clone()),
+        ..Default::default()
+    });
+    metronome.trigger(false);
+    metronome.next_value(100);
+    // Moving the volume does not cut the click
+    metronome.set_settings(MetronomeSettings {
+        volume: 1.,
+        tick: Some(sample),
+        ..Default::default()
+    });
+    assert_eq!(metronome.next_value(100), 1.);
+    // Another sample does
+    metronome.set_settings(MetronomeSettings {
+        volume: 1.,
+        tick: Some(ClickSample {
+            name: "click".into(),
+            data: Arc::new(vec![1.; 100]),
+            sample_rate: 100,
+        }),
+        ..Default::default()
+    });
+    assert_eq!(metronome.next_value(100), 0.);
+}
@@ -1,50 +1,355 @@
-use midir::{Ignore, MidiInput, MidiOutput};
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    config::Config,
+    core::message::{MidiInputEvent, MidiOutputEvent, MidiToPlayerMsg},
+};
+use midir::{Ignore, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
 use midly::live::LiveEvent;
-use rtrb::Producer;
-use std::thread;
+use rtrb::{Consumer, Producer};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Interval between two scans of the MIDI ports to follow plugged and unplugged devices
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest wait of the output thread before reading the messages of the player
+const OUTPUT_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// MIDI clients opened by the app to send and receive, their ports are not devices
+const OUTPUT_CLIENT: &str = "tonique-output";
+const INPUT_CLIENT: &str = "tonique-input";
+
+/// Output receiving the clock, with its name
+type SyncOutput = Arc<Mutex<Option<(String, MidiOutputConnection)>>>;
+
+/// MIDI port seen by the last scan
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiPort {
+    pub name: String,
+    /// Whether the input is enabled or the output receives the clock
+    pub enabled: bool,
+    /// Whether the port is connected
+    pub connected: bool,
+}
+
+enum MidiCommand {
+    /// Names of the inputs to connect
+    SetInputs(Vec<String>),
+    /// Name of the output receiving the clock, `None` to send no clock
+    SetSyncOutput(Option<String>),
+}
+
+/// Handle of the MIDI thread used by the GUI
+#[derive(Clone)]
+pub struct MidiHandle {
+    commands: Sender<MidiCommand>,
+    ports: Arc<Mutex<Vec<MidiPort>>>,
+    outputs: Arc<Mutex<Vec<MidiPort>>>,
+}
+
+impl MidiHandle {
+    /// Input ports found by the last scan
+    pub fn ports(&self) -> Vec<MidiPort> {
+        self.ports
+            .lock()
+            .map(|ports| ports.clone())
+            .unwrap_or_default()
+    }
+
+    /// Enable or disable an input and save the enabled inputs in the config
+    pub fn set_enabled(&self, name: &str, enabled: bool) {
+        let mut inputs: Vec<String> = self
+            .ports()
+            .into_iter()
+            .filter(|port| port.enabled && port.name != name)
+            .map(|port| port.name)
+            .collect();
+        if enabled {
+            inputs.push(name.into());
+        }
+        // Keep the enabled inputs which are unplugged
+        let mut config = Config::load();
+        if let Some(saved) = config.midi_inputs() {
+            let ports = self.ports();
+            inputs.extend(
+                saved
+                    .iter()
+                    .filter(|saved| !ports.iter().any(|port| port.name == **saved))
+                    .cloned(),
+            );
+        }
+        config.set_midi_inputs(inputs.clone());
+        if let Ok(mut ports) = self.ports.lock() {
+            for port in ports.iter_mut().filter(|port| port.name == name) {
+                port.enabled = enabled;
+            }
+        }
+        let _ = self.commands.send(MidiCommand::SetInputs(inputs));
+    }
+
+    /// Output ports found by the last scan, the one receiving the clock enabled
+    pub fn outputs(&self) -> Vec<MidiPort> {
+        self.outputs
+            .lock()
+            .map(|outputs| outputs.clone())
+            .unwrap_or_default()
+    }
+
+    /// Choose the output receiving the clock and save it in the config
+    pub fn set_sync_output(&self, name: Option<String>) {
+        Config::load().set_midi_sync_output(name.clone());
+        if let Ok(mut outputs) = self.outputs.lock() {
+            for output in outputs.iter_mut() {
+                output.enabled = Some(&output.name) == name.as_ref();
+            }
+        }
+        let _ = self.commands.send(MidiCommand::SetSyncOutput(name));
+    }
+}
+
+/// Connect the MIDI inputs enabled in the config and forward their events to the player,
+/// and send the messages of the player to the sync output chosen in the config and to the
+/// outputs of the external tracks.
+/// The ports are scanned regularly to connect the devices when they are plugged.
+pub fn spawn_midi_thread(
+    tx: Producer<MidiInputEvent>,
+    output_rx: Consumer<MidiOutputEvent>,
+) -> MidiHandle {
+    let (commands, commands_rx) = mpsc::channel();
+    let ports = Arc::new(Mutex::new(Vec::new()));
+    let outputs = Arc::new(Mutex::new(Vec::new()));
+    let handle = MidiHandle {
+        commands,
+        ports: ports.clone(),
+        outputs: outputs.clone(),
+    };
+    let tx = Arc::new(Mutex::new(tx));
+    let sync_output: SyncOutput = Arc::new(Mutex::new(None));
+    spawn_output_thread(output_rx, sync_output.clone());
 
-pub fn spawn_midi_thread(mut tx: Producer<Vec<u8>>) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let mut midi_in = MidiInput::new("midi-input").expect("Failed to create MidiInput");
-        let midi_out = MidiOutput::new("Hello").expect("Failed to create MidiOutput");
-
-        midi_in.ignore(Ignore::None);
-
-        let port = &midi_out.ports()[0];
-        let mut conn_out = midi_out
-            .connect(port, "Midi out")
-            .expect("Failed to connect");
-
-        let port = &midi_in.ports()[1];
-
-        let _conn_in = midi_in
-            .connect(
-                port,
-                "midir-read-input",
-                move |_, message, _| {
-                    let res = LiveEvent::parse(message);
-                    match res {
-                        Ok(event) => match event {
-                            LiveEvent::Midi {
-                                channel: _,
-                                message: _,
-                            } => todo!(),
-                            LiveEvent::Common(_) => todo!(),
-                            LiveEvent::Realtime(_) => todo!(),
-                        },
-                        Err(_) => {}
-                    };
-                    if message.len() >= 3 {
-                        let _ = tx.push(message.to_vec());
-                        let _ = conn_out.send(message);
-                    }
-                },
-                (),
-            )
-            .expect("Failed to connect to MIDI input port");
-        // Keep the thread alive to receive events
+        let mut enabled = Config::load().midi_inputs().cloned();
+        let mut selected_output = Config::load().midi_sync_output().cloned();
+        let mut connections: HashMap<String, MidiInputConnection<()>> = HashMap::new();
         loop {
-            thread::park();
+            let available = scan_ports();
+            // Drop the connections of unplugged or disabled ports
+            connections.retain(|name, _| {
+                available.contains(name) && enabled.as_ref().is_none_or(|e| e.contains(name))
+            });
+            for name in available.iter() {
+                if connections.contains_key(name)
+                    || enabled.as_ref().is_some_and(|e| !e.contains(name))
+                {
+                    continue;
+                }
+                match connect(name, tx.clone()) {
+                    Ok(connection) => {
+                        connections.insert(name.clone(), connection);
+                    }
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            if let Ok(mut ports) = ports.lock() {
+                *ports = available
+                    .into_iter()
+                    .map(|name| MidiPort {
+                        enabled: enabled.as_ref().is_none_or(|e| e.contains(&name)),
+                        connected: connections.contains_key(&name),
+                        name,
+                    })
+                    .collect();
+            }
+            let available = scan_output_ports();
+            if let Ok(mut output) = sync_output.lock() {
+                // Drop the connection of an unplugged or unselected output
+                if output.as_ref().is_some_and(|(name, _)| {
+                    selected_output.as_ref() != Some(name) || !available.contains(name)
+                }) {
+                    *output = None;
+                }
+                if output.is_none()
+                    && let Some(name) = selected_output
+                        .as_ref()
+                        .filter(|name| available.contains(name))
+                {
+                    match connect_output(name) {
+                        Ok(connection) => *output = Some((name.clone(), connection)),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+                if let Ok(mut outputs) = outputs.lock() {
+                    *outputs = available
+                        .into_iter()
+                        .map(|name| MidiPort {
+                            enabled: selected_output.as_ref() == Some(&name),
+                            connected: output
+                                .as_ref()
+                                .is_some_and(|(connected, _)| *connected == name),
+                            name,
+                        })
+                        .collect();
+                }
+            }
+
+            match commands_rx.recv_timeout(SCAN_INTERVAL) {
+                Ok(MidiCommand::SetInputs(inputs)) => enabled = Some(inputs),
+                Ok(MidiCommand::SetSyncOutput(name)) => selected_output = name,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+    handle
+}
+
+/// Send the messages of the player at their time to the sync output, or to the port of
+/// their track which is connected on its first message
+fn spawn_output_thread(mut rx: Consumer<MidiOutputEvent>, sync_output: SyncOutput) {
+    thread::spawn(move || {
+        // Messages sorted by time, the delay of the tracks can send them out of order
+        let mut pending: Vec<MidiOutputEvent> = Vec::new();
+        // Connections of the track outputs with the time they were last tried
+        let mut ports: HashMap<Arc<str>, (Option<MidiOutputConnection>, Option<Instant>)> =
+            HashMap::new();
+        while !rx.is_abandoned() {
+            while let Ok(event) = rx.pop() {
+                let index = pending.partition_point(|pending| pending.due <= event.due);
+                pending.insert(index, event);
+            }
+            let now = Instant::now();
+            let sent = pending.partition_point(|event| event.due <= now);
+            for event in pending.drain(..sent) {
+                let bytes = event.msg.bytes();
+                match event.port {
+                    None => {
+                        if let Ok(mut output) = sync_output.lock()
+                            && let Some((_, connection)) = output.as_mut()
+                        {
+                            let _ = connection.send(&bytes);
+                        }
+                    }
+                    Some(port) => {
+                        let (connection, tried) = ports.entry(port.clone()).or_default();
+                        if connection.is_none()
+                            && tried.is_none_or(|tried| now - tried > SCAN_INTERVAL)
+                        {
+                            *tried = Some(now);
+                            *connection = connect_output(&port)
+                                .inspect_err(|e| eprintln!("{}", e))
+                                .ok();
+                        }
+                        // An unplugged port is connected again on a later message
+                        if let Some(output) = connection
+                            && output.send(&bytes).is_err()
+                        {
+                            *connection = None;
+                        }
+                    }
+                }
+            }
+            let wait = pending.first().map_or(OUTPUT_POLL_INTERVAL, |event| {
+                event
+                    .due
+                    .saturating_duration_since(now)
+                    .min(OUTPUT_POLL_INTERVAL)
+            });
+            thread::sleep(wait);
         }
-    })
+    });
+}
+
+/// Names of the MIDI output ports, without the ports of the app
+fn scan_output_ports() -> Vec<String> {
+    let Ok(midi_out) = MidiOutput::new("tonique-scan") else {
+        return Vec::new();
+    };
+    midi_out
+        .ports()
+        .iter()
+        .filter_map(|port| midi_out.port_name(port).ok())
+        .filter(|name| !is_own_port(name))
+        .collect()
+}
+
+/// Connect the output port `name`
+fn connect_output(name: &str) -> Result<MidiOutputConnection, String> {
+    let midi_out = MidiOutput::new(OUTPUT_CLIENT).map_err(|e| e.to_string())?;
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| {
+            midi_out
+                .port_name(port)
+                .is_ok_and(|port_name| port_name == name)
+        })
+        .ok_or(format!("MIDI output {} not found", name))?;
+    midi_out
+        .connect(&port, "tonique-write-output")
+        .map_err(|e| format!("Failed to connect to MIDI output {}: {}", name, e))
+}
+
+/// Names of the MIDI input ports, without the ports of the app
+fn scan_ports() -> Vec<String> {
+    let Ok(midi_in) = MidiInput::new("tonique-scan") else {
+        return Vec::new();
+    };
+    midi_in
+        .ports()
+        .iter()
+        .filter_map(|port| midi_in.port_name(port).ok())
+        .filter(|name| !is_own_port(name))
+        .collect()
+}
+
+/// Whether the port `name` belongs to a client of the app, connecting it would loop the app
+/// on itself
+fn is_own_port(name: &str) -> bool {
+    [OUTPUT_CLIENT, INPUT_CLIENT]
+        .iter()
+        .any(|client| name.starts_with(client))
+}
+
+/// Connect the input port `name`, forwarding its events to `tx`
+fn connect(
+    name: &str,
+    tx: Arc<Mutex<Producer<MidiInputEvent>>>,
+) -> Result<MidiInputConnection<()>, String> {
+    let mut midi_in = MidiInput::new(INPUT_CLIENT).map_err(|e| e.to_string())?;
+    midi_in.ignore(Ignore::None);
+    let port = midi_in
+        .ports()
+        .into_iter()
+        .find(|port| {
+            midi_in
+                .port_name(port)
+                .is_ok_and(|port_name| port_name == name)
+        })
+        .ok_or(format!("MIDI input {} not found", name))?;
+    midi_in
+        .connect(
+            &port,
+            "tonique-read-input",
+            move |_, message, _| {
+                if let Ok(event) = LiveEvent::parse(message)
+                    && let Some(msg) = MidiToPlayerMsg::from_live(&event)
+                    && let Ok(mut tx) = tx.lock()
+                {
+                    let _ = tx.push(MidiInputEvent {
+                        time: Instant::now(),
+                        msg,
+                    });
+                }
+            },
+            (),
+        )
+        .map_err(|e| format!("Failed to connect to MIDI input {}: {}", name, e))
 }
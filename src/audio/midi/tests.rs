@@ -0,0 +1,137 @@
+use crate::{
+    audio::midi::{
+        connect, connect_output, is_own_port, scan_output_ports, scan_ports, spawn_output_thread,
+    },
+    core::{
+        message::{MidiOutputEvent, MidiOutputMsg, MidiToPlayerMsg},
+        sync::{ClockFollower, ClockMaster},
+    },
+};
+use midir::{
+    Ignore, MidiInput, MidiOutput,
+    os::unix::{VirtualInput, VirtualOutput},
+};
+use rtrb::RingBuffer;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Name of the port created as `name` in the scanned `ports`
+fn find_port(ports: Vec<String>, name: &str) -> String {
+    ports
+        .into_iter()
+        .find(|port| port.contains(name))
+        .expect("virtual port not found")
+}
+
+/// Wait until `done` or `timeout`, whether it is done
+fn wait_until(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !done() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    true
+}
+
+#[test]
+fn test_own_ports_are_not_devices() {
+    assert!(is_own_port("tonique-output:tonique-write-output 130:0"));
+    assert!(is_own_port("tonique-input:tonique-input 131:0"));
+    assert!(!is_own_port("Midi Through:Midi Through Port-0 14:0"));
+    assert!(!is_own_port("tonique-test-out:tonique-follow-test 132:0"));
+}
+
+/// The tests connect to virtual ports and need a MIDI sequencer,
+/// run them with `cargo test -- --ignored`
+#[test]
+#[ignore = "needs a MIDI sequencer"]
+fn test_clock_sent_to_virtual_port() {
+    let mut midi_in = MidiInput::new("tonique-test-in").expect("no MIDI sequencer");
+    midi_in.ignore(Ignore::None);
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    let _port = midi_in
+        .create_virtual(
+            "tonique-clock-test",
+            move |_, message, _| sink.lock().unwrap().push(message.to_vec()),
+            (),
+        )
+        .expect("virtual port not created");
+    let name = find_port(scan_output_ports(), "tonique-clock-test");
+    let output = Arc::new(Mutex::new(Some((
+        name.clone(),
+        connect_output(&name).unwrap(),
+    ))));
+    let (mut tx, rx) = RingBuffer::new(64);
+    spawn_output_thread(rx, output);
+
+    let mut master = ClockMaster::default();
+    let mut events = Vec::new();
+    master.block(0., 1., &mut events);
+    events.push((1., master.stop().unwrap()));
+    let start = Instant::now();
+    for (beat, msg) in events {
+        // 120 bpm
+        let due = start + Duration::from_secs_f32(beat / 2.);
+        tx.push(MidiOutputEvent {
+            due,
+            port: None,
+            msg: MidiOutputMsg::Sync(msg),
+        })
+        .unwrap();
+    }
+    let stopped = wait_until(Duration::from_secs(2), || {
+        received.lock().unwrap().last() == Some(&vec![0xFC])
+    });
+    assert!(stopped, "stop not received");
+
+    let mut expected = vec![vec![0xF2, 0, 0], vec![0xFA]];
+    expected.extend(std::iter::repeat_n(vec![0xF8], 24));
+    expected.push(vec![0xFC]);
+    assert_eq!(*received.lock().unwrap(), expected);
+}
+
+#[test]
+#[ignore = "needs a MIDI sequencer"]
+fn test_clock_followed_from_virtual_port() {
+    let midi_out = MidiOutput::new("tonique-test-out").expect("no MIDI sequencer");
+    let mut port = midi_out
+        .create_virtual("tonique-follow-test")
+        .expect("virtual port not created");
+    let name = find_port(scan_ports(), "tonique-follow-test");
+    let (tx, mut rx) = RingBuffer::new(256);
+    let _input = connect(&name, Arc::new(Mutex::new(tx))).unwrap();
+
+    // 120 bpm, a clock every 1 / 48 s
+    let start = Instant::now();
+    port.send(&[0xFA]).unwrap();
+    for i in 0..25 {
+        let due = start + Duration::from_secs_f64(i as f64 / 48.);
+        thread::sleep(due.saturating_duration_since(Instant::now()));
+        port.send(&[0xF8]).unwrap();
+    }
+    let mut expected = vec![MidiToPlayerMsg::Start];
+    expected.extend(std::iter::repeat_n(MidiToPlayerMsg::Clock, 25));
+    let mut events = Vec::new();
+    let all_received = wait_until(Duration::from_secs(1), || {
+        while let Ok(event) = rx.pop() {
+            events.push(event);
+        }
+        events.len() >= expected.len()
+    });
+    assert!(all_received, "{} events received", events.len());
+
+    let mut follower = ClockFollower::default();
+    for event in &events {
+        follower.receive(event.time, &event.msg);
+    }
+    let msgs: Vec<_> = events.iter().map(|event| event.msg).collect();
+    assert_eq!(msgs, expected);
+    assert!(follower.playing);
+    assert!((follower.bpm().unwrap() - 120.).abs() < 5.);
+}
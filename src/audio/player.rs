@@ -1,27 +1,70 @@
 use crate::{
     GuiToPlayerMsg, ProcessToGuiMsg,
     audio::{
-        clip::ClipBackend,
+        clip::{ClipBackend, midi::MidiClip},
+        instrument::external::{ALL_NOTES_OFF, ExternalOutput},
         metronome::MetronomeBackend,
         preview::PreviewBackend,
-        track::{TrackBackend, TrackKind, audio::AudioTrackData},
+        track::{
+            TrackBackend, TrackKind,
+            audio::AudioTrackData,
+            midi::{MidiDevice, MidiTrackData},
+        },
     },
     core::{
+        message::{MidiInputEvent, MidiOutputEvent, MidiOutputMsg, MidiToPlayerMsg},
+        meter::Meter,
         metrics::{AudioMetrics, GlobalMetrics},
+        recording::{PEAKS_PER_MESSAGE, TrackRecorder},
         state::PlaybackState,
+        sync::{ClockFollower, ClockMaster, SyncMsg, SyncSettings, SyncSource, TimeCodeFollower},
+        tempo::TempoMap,
     },
+    input::AudioInput,
 };
+use midly::MidiMessage;
 use rayon::prelude::*;
 use rtrb::{Consumer, Producer};
-use std::{collections::HashMap, time::Instant};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// MIDI events kept for a block without allocating on the audio thread
+const MIDI_EVENTS_CAPACITY: usize = 1024;
+/// Seconds the playhead can drift from an external transport before jumping to it
+const SYNC_MAX_DRIFT: f32 = 0.05;
 
 pub struct PlayerBackend {
     to_gui_tx: Producer<ProcessToGuiMsg>,
     from_gui_rx: Consumer<GuiToPlayerMsg>,
-    _midi_rx: Consumer<Vec<u8>>,
+    midi_rx: Consumer<MidiInputEvent>,
+    /// MIDI input events received for the current block
+    midi_events: Vec<MidiToPlayerMsg>,
+    /// Frame of each MIDI input event in the current block, from the time it was received
+    midi_offsets: Vec<usize>,
+    /// Events of the computer keyboard waiting for the next block
+    keyboard_events: Vec<MidiToPlayerMsg>,
+    /// Sync messages and events of the external tracks sent to the MIDI thread
+    midi_out_tx: Producer<MidiOutputEvent>,
+    sync: SyncSettings,
+    clock_master: ClockMaster,
+    /// Sync messages of the current block with their beat
+    sync_events: Vec<(f32, SyncMsg)>,
+    clock_follower: ClockFollower,
+    time_code: TimeCodeFollower,
+    /// Play state and tempo of the external transport last sent to the GUI
+    external: (bool, Option<f32>),
 
     channels: usize,
     sample_rate: usize,
+    input: Option<AudioInput>,
+    input_channels: usize,
+    /// Seconds between the callback and the playback of the output, measured by the device
+    pub output_latency: f32,
+    /// Input of the current block
+    input_buffer: Vec<f32>,
 
     playhead: usize,
     preview: PreviewBackend,
@@ -29,8 +72,15 @@ pub struct PlayerBackend {
     // current state
     playback_state: PlaybackState,
     preview_state: PlaybackState,
+    recording: bool,
+    recorders: Vec<TrackRecorder>,
+    /// Frame of the first recorded block
+    record_start: Option<usize>,
     tracks: HashMap<String, TrackBackend>,
-    bpm: f32,
+    tempo: Arc<TempoMap>,
+    meter: Meter,
+    /// Start and end beats of the loop
+    loop_region: Option<(f32, f32)>,
     solo_tracks: Vec<String>,
     metronome: MetronomeBackend,
 }
@@ -39,17 +89,50 @@ impl PlayerBackend {
     pub fn new(
         to_gui_tx: Producer<ProcessToGuiMsg>,
         from_gui_rx: Consumer<GuiToPlayerMsg>,
-        midi_rx: Consumer<Vec<u8>>,
+        midi_rx: Consumer<MidiInputEvent>,
+        midi_out_tx: Producer<MidiOutputEvent>,
         sample_rate: usize,
+        channels: usize,
+        input: Option<AudioInput>,
     ) -> Self {
+        let mut to_gui_tx = to_gui_tx;
+        let input_sample_rate = input.as_ref().map(|input| input.sample_rate);
+        // Audio at another rate would be recorded and monitored at the wrong pitch
+        let input = input.filter(|input| input.sample_rate == sample_rate);
+        let input_channels = input.as_ref().map_or(0, |input| input.channels);
+        let _ = to_gui_tx.push(ProcessToGuiMsg::DeviceConfig {
+            output_channels: channels,
+            input_channels,
+            sample_rate,
+            input_sample_rate,
+        });
         Self {
             to_gui_tx,
             from_gui_rx,
-            _midi_rx: midi_rx,
-            channels: 2,
+            midi_rx,
+            midi_events: Vec::with_capacity(MIDI_EVENTS_CAPACITY),
+            midi_offsets: Vec::with_capacity(MIDI_EVENTS_CAPACITY),
+            keyboard_events: Vec::with_capacity(MIDI_EVENTS_CAPACITY),
+            midi_out_tx,
+            sync: SyncSettings::default(),
+            clock_master: ClockMaster::default(),
+            sync_events: Vec::with_capacity(MIDI_EVENTS_CAPACITY),
+            clock_follower: ClockFollower::default(),
+            time_code: TimeCodeFollower::default(),
+            external: (false, None),
+            channels,
+            input,
+            input_channels,
+            input_buffer: Vec::new(),
+            output_latency: 0.,
             playhead: 0,
-            bpm: 120.,
+            tempo: Arc::new(TempoMap::default()),
+            meter: Meter::default(),
+            loop_region: None,
             playback_state: PlaybackState::Paused,
+            recording: false,
+            recorders: Vec::new(),
+            record_start: None,
             sample_rate,
             tracks: HashMap::new(),
             solo_tracks: vec![],
@@ -65,16 +148,20 @@ impl PlayerBackend {
         // Start timer
         let time_start = Instant::now();
         let _ = self.handle_messages();
+        self.send_external_tracks(0, time_start);
         let pos = self.playhead;
         let mut metrics = GlobalMetrics::new();
         let num_frames = output.len() / self.channels;
+        self.read_input(num_frames);
+        self.read_midi(time_start, num_frames);
+        self.follow_sync(time_start);
 
         // Preview
         if self.preview_state == PlaybackState::Playing {
             if let Some(data) = self.preview.read(num_frames, self.sample_rate) {
                 for ch in 0..2 {
                     for (i, sample) in data[ch].iter().enumerate() {
-                        output[2 * i + ch] = *sample;
+                        output[self.channels * i + ch] = *sample;
                     }
                 }
                 if let Some(stream) = &self.preview.stream {
@@ -85,27 +172,148 @@ impl PlayerBackend {
             }
         }
 
-        // Paused
+        // Paused, only the monitored inputs are heard
         if self.playback_state == PlaybackState::Paused {
-            metrics.latency =
-                time_start.elapsed().as_secs_f32() / (num_frames as f32 / self.sample_rate as f32);
+            if self.monitoring() {
+                let mut master_mix = vec![0.; num_frames * 2];
+                self.process_tracks(&mut master_mix, 0, &mut metrics, false);
+                self.send_external_tracks(0, time_start);
+                self.mix_master(output, &master_mix, &mut metrics);
+            }
+            self.send_idle_metrics(metrics, time_start, num_frames);
+            return;
+        }
 
-            for (track_id, _) in self.tracks.iter() {
-                metrics.tracks.insert(track_id.clone(), AudioMetrics::new());
+        // Count-in, the transport starts once counted
+        if self.metronome.counting_in() {
+            let mut click = vec![0.; num_frames];
+            let counted = self.metronome.render_count_in(&mut click, self.sample_rate);
+            if counted < num_frames {
+                // Tracks join on the next block, click the rest of this one on time
+                if self.metronome_audible() {
+                    self.metronome.render(
+                        &mut click[counted..],
+                        self.sample_rate,
+                        self.playhead,
+                        &self.tempo,
+                        &self.meter,
+                    );
+                }
+                self.playhead += num_frames - counted;
             }
-            metrics.tracks.insert("master".into(), AudioMetrics::new());
-            let _ = self.to_gui_tx.push(ProcessToGuiMsg::Metrics(metrics));
+            if self.monitoring() {
+                let mut master_mix = vec![0.; num_frames * 2];
+                self.process_tracks(&mut master_mix, 0, &mut metrics, false);
+                self.send_external_tracks(0, time_start);
+                self.mix_master(output, &master_mix, &mut metrics);
+            }
+            self.mix_click(output, &click);
+            self.send_idle_metrics(metrics, time_start, num_frames);
+            let _ = self.to_gui_tx.push(ProcessToGuiMsg::PlaybackPos(
+                self.tempo.frames_to_beats(pos, self.sample_rate),
+            ));
             return;
         }
-        let mut master_mix = vec![0.; output.len()];
+        if self.recording {
+            self.record();
+        }
+
+        let mut master_mix = vec![0.; num_frames * 2];
+        let mut click = vec![0.; num_frames];
+        let metronome_audible = self.metronome_audible();
+        // Split the block at the end of the loop
+        let mut offset = 0;
+        while offset < num_frames {
+            let loop_end = self.frames_to_loop_end(num_frames - offset);
+            let frames = loop_end.unwrap_or(num_frames - offset);
+            self.process_tracks(
+                &mut master_mix[2 * offset..2 * (offset + frames)],
+                offset,
+                &mut metrics,
+                true,
+            );
+            self.send_external_tracks(offset, time_start);
+            if self.recording {
+                self.record_midi(offset, frames);
+            }
+            if self.sync.send_clock {
+                self.send_clock(offset, frames, time_start);
+            }
+            if metronome_audible {
+                self.metronome.render(
+                    &mut click[offset..offset + frames],
+                    self.sample_rate,
+                    self.playhead,
+                    &self.tempo,
+                    &self.meter,
+                );
+            }
+            // Update playhead
+            self.playhead += frames;
+            offset += frames;
+            if loop_end.is_some()
+                && let Some((start, _)) = self.loop_region
+            {
+                self.playhead = self.tempo.beats_to_frames(start, self.sample_rate);
+            }
+        }
+
+        self.mix_master(output, &master_mix, &mut metrics);
+        if metronome_audible {
+            self.mix_click(output, &click);
+        }
 
+        // Send data
+        metrics.latency =
+            time_start.elapsed().as_secs_f32() / (num_frames as f32 / self.sample_rate as f32);
+
+        let _ = self.to_gui_tx.push(ProcessToGuiMsg::Metrics(metrics));
+        let _ = self.to_gui_tx.push(ProcessToGuiMsg::PlaybackPos(
+            self.tempo.frames_to_beats(pos, self.sample_rate),
+        ));
+    }
+
+    /// Frames before the end of the loop when it is reached within the next `frames`
+    fn frames_to_loop_end(&self, frames: usize) -> Option<usize> {
+        let (_, end) = self.loop_region?;
+        let end = self.tempo.beats_to_frames(end, self.sample_rate);
+        (self.playhead < end && self.playhead + frames >= end).then(|| end - self.playhead)
+    }
+
+    /// Mix the tracks into the stereo `master_mix`, starting `offset` frames into the block.
+    /// The clips are played from the playhead when `play_clips`, the monitored inputs always.
+    fn process_tracks(
+        &mut self,
+        master_mix: &mut [f32],
+        offset: usize,
+        metrics: &mut GlobalMetrics,
+        play_clips: bool,
+    ) {
+        let pos = play_clips.then_some(self.playhead);
+        let num_frames = master_mix.len() / 2;
+        let playing = self.playback_state == PlaybackState::Playing;
+        let recording = self.recording;
+        let input_channels = self.input_channels;
+        let input = self
+            .input_buffer
+            .get(offset * input_channels..(offset + num_frames) * input_channels)
+            .unwrap_or_default();
+        // The MIDI input is played once, at the start of the block
+        let live: &[MidiToPlayerMsg] = if offset == 0 { &self.midi_events } else { &[] };
         // Collect tracks
         let tracks: Vec<_> = self.tracks.values_mut().collect();
 
         tracks.into_par_iter().for_each(|track| {
             let mut track_metrics = AudioMetrics::new();
 
-            track.process(pos, num_frames, self.sample_rate);
+            let monitored = track.monitor.active(track.armed, playing, recording);
+            track.process(
+                pos,
+                num_frames,
+                self.sample_rate,
+                monitored.then_some((input, input_channels)),
+                if monitored { live } else { &[] },
+            );
 
             // Compute metrics
             for i in 0..track.mix.len() {
@@ -123,56 +331,319 @@ impl PlayerBackend {
                 .tracks
                 .insert(track.id.clone(), track.metrics.clone());
         }
+    }
 
-        // Assign master output buffer
-        for (i, sample) in output.iter_mut().enumerate() {
-            *sample += master_mix[i];
-            metrics
-                .master
-                .add_sample(master_mix[i], (i % 2 == 0).into());
+    /// Assign the master mix to the first output pair
+    fn mix_master(&self, output: &mut [f32], master_mix: &[f32], metrics: &mut GlobalMetrics) {
+        for (i, sample) in master_mix.iter().enumerate() {
+            output[self.channels * (i / 2) + i % 2] += sample;
+            metrics.master.add_sample(*sample, (i % 2 == 0).into());
         }
         metrics
             .tracks
             .insert("master".into(), metrics.master.clone());
+    }
 
-        // Send data
+    /// Whether a track plays its input, MIDI tracks playing the MIDI inputs
+    fn monitoring(&self) -> bool {
+        let playing = self.playback_state == PlaybackState::Playing;
+        self.tracks.values().any(|track| {
+            track.monitor.active(track.armed, playing, self.recording)
+                && (self.input.is_some() || matches!(track.kind, TrackKind::Midi(_)))
+        })
+    }
+
+    /// Send empty metrics while the tracks are not processed
+    fn send_idle_metrics(
+        &mut self,
+        mut metrics: GlobalMetrics,
+        time_start: Instant,
+        num_frames: usize,
+    ) {
         metrics.latency =
             time_start.elapsed().as_secs_f32() / (num_frames as f32 / self.sample_rate as f32);
 
+        for (track_id, _) in self.tracks.iter() {
+            metrics.tracks.insert(track_id.clone(), AudioMetrics::new());
+        }
+        metrics.tracks.insert("master".into(), AudioMetrics::new());
         let _ = self.to_gui_tx.push(ProcessToGuiMsg::Metrics(metrics));
-        let _ = self.to_gui_tx.push(ProcessToGuiMsg::PlaybackPos(
-            self.bpm * (self.playhead as f32) / (self.sample_rate as f32 * 60.),
-        ));
-        // Update playhead
-        self.playhead += num_frames;
+    }
 
-        if self.metronome.enabled && self.playback_state == PlaybackState::Playing {
-            self.metronome.render(
-                output,
-                num_frames,
-                self.sample_rate,
-                self.playhead,
-                self.bpm,
+    /// Read the input of the block, dropping the late samples when the input runs ahead
+    fn read_input(&mut self, num_frames: usize) {
+        let Some(input_rx) = self.input.as_mut().map(|input| &mut input.samples) else {
+            return;
+        };
+        let len = num_frames * self.input_channels;
+        let late = input_rx.slots().saturating_sub(4 * len);
+        let late = late - late % self.input_channels;
+        if let Ok(chunk) = input_rx.read_chunk(late) {
+            chunk.commit_all();
+        }
+        self.input_buffer.clear();
+        self.input_buffer.resize(len, 0.);
+        if let Ok(chunk) = input_rx.read_chunk(input_rx.slots().min(len)) {
+            let (first, second) = chunk.as_slices();
+            self.input_buffer[..first.len()].copy_from_slice(first);
+            self.input_buffer[first.len()..first.len() + second.len()].copy_from_slice(second);
+            chunk.commit_all();
+        }
+    }
+
+    /// Read the MIDI input events received since the last block,
+    /// sending the control changes to the GUI for the learned controls.
+    /// The events received during the last block keep their spacing in this one.
+    fn read_midi(&mut self, block_start: Instant, num_frames: usize) {
+        self.midi_events.clear();
+        self.midi_offsets.clear();
+        // The computer keyboard plays at the start of the block
+        self.midi_offsets.resize(self.keyboard_events.len(), 0);
+        self.midi_events.append(&mut self.keyboard_events);
+        let block = Duration::from_secs_f64(num_frames as f64 / self.sample_rate as f64);
+        let last_block_start = block_start.checked_sub(block).unwrap_or(block_start);
+        while self.midi_events.len() < MIDI_EVENTS_CAPACITY
+            && let Ok(event) = self.midi_rx.pop()
+        {
+            match event.msg {
+                MidiToPlayerMsg::TimeCode { piece, value } => {
+                    self.time_code.receive(event.time, piece, value)
+                }
+                msg => self.clock_follower.receive(event.time, &msg),
+            }
+            let offset = event.time.saturating_duration_since(last_block_start);
+            let offset = (offset.as_secs_f64() * self.sample_rate as f64) as usize;
+            self.midi_offsets
+                .push(offset.min(num_frames.saturating_sub(1)));
+            self.midi_events.push(event.msg);
+        }
+        for event in self.midi_events.iter() {
+            if matches!(event, MidiToPlayerMsg::ControlChange { .. }) {
+                let _ = self.to_gui_tx.push(ProcessToGuiMsg::MidiControl(*event));
+            }
+        }
+    }
+
+    /// Send the MIDI input of the `frames` played from `offset` in the block
+    /// with the playhead, which is at the start of the loop after its end
+    fn record_midi(&mut self, offset: usize, frames: usize) {
+        let beat = self.tempo.frames_to_beats(self.playhead, self.sample_rate);
+        for (event, event_offset) in self.midi_events.iter().zip(self.midi_offsets.iter()) {
+            if (offset..offset + frames).contains(event_offset) {
+                let _ = self.to_gui_tx.push(ProcessToGuiMsg::RecordedMidi {
+                    beat,
+                    offset: event_offset - offset,
+                    event: *event,
+                });
+            }
+        }
+    }
+
+    /// Write the input of the block in the files of the armed tracks
+    fn record(&mut self) {
+        if self.record_start.is_none() {
+            self.record_start = Some(self.playhead);
+            let input_latency = self.input.as_ref().map_or(0., |input| input.latency());
+            let _ = self.to_gui_tx.push(ProcessToGuiMsg::RecordingStarted(
+                self.tempo.frames_to_beats(self.playhead, self.sample_rate),
+                input_latency + self.output_latency,
+            ));
+        }
+        if self.recorders.is_empty() {
+            return;
+        }
+        for (index, recorders) in self.recorders.chunks_mut(PEAKS_PER_MESSAGE).enumerate() {
+            let mut peaks = [0.; PEAKS_PER_MESSAGE];
+            for (peak, recorder) in peaks.iter_mut().zip(recorders) {
+                *peak = recorder.write(&self.input_buffer, self.input_channels);
+            }
+            let _ = self.to_gui_tx.push(ProcessToGuiMsg::RecordingPeaks(
+                index * PEAKS_PER_MESSAGE,
+                peaks,
+            ));
+        }
+    }
+
+    /// Close the recorded files and give the recorders back to the GUI
+    fn stop_recording(&mut self) {
+        self.recording = false;
+        self.record_start = None;
+        let mut recorders = std::mem::take(&mut self.recorders);
+        for recorder in recorders.iter_mut() {
+            recorder.finish();
+        }
+        let _ = self
+            .to_gui_tx
+            .push(ProcessToGuiMsg::RecordingStopped(recorders));
+    }
+
+    fn metronome_audible(&self) -> bool {
+        self.metronome.enabled && (self.recording || !self.metronome.settings.record_only)
+    }
+
+    /// Add the mono metronome `click` to its output pair, the first pair when the device has
+    /// less channels
+    fn mix_click(&self, output: &mut [f32], click: &[f32]) {
+        let mut first = 2 * self.metronome.settings.output;
+        if first + 1 >= self.channels {
+            first = 0;
+        }
+        for (frame, value) in output.chunks_mut(self.channels).zip(click) {
+            frame[first] += value;
+            frame[first + 1] += value;
+        }
+    }
+
+    /// Schedule the clock of the `frames` played from the playhead, `offset` frames into the block
+    fn send_clock(&mut self, offset: usize, frames: usize, block_start: Instant) {
+        let start = self.tempo.frames_to_beats(self.playhead, self.sample_rate);
+        let end = self
+            .tempo
+            .frames_to_beats(self.playhead + frames, self.sample_rate);
+        self.sync_events.clear();
+        self.clock_master.block(start, end, &mut self.sync_events);
+        for &(beat, msg) in self.sync_events.iter() {
+            let frame = offset
+                + self
+                    .tempo
+                    .beats_to_frames(beat, self.sample_rate)
+                    .saturating_sub(self.playhead);
+            let delay = self.output_latency + frame as f32 / self.sample_rate as f32;
+            let _ = self.midi_out_tx.push(MidiOutputEvent {
+                due: block_start + Duration::from_secs_f32(delay),
+                port: None,
+                msg: MidiOutputMsg::Sync(msg),
+            });
+        }
+    }
+
+    /// Send the events of the external tracks for the frames from `offset` in the block
+    fn send_external_tracks(&mut self, offset: usize, block_start: Instant) {
+        let start = block_start
+            + Duration::from_secs_f32(
+                self.output_latency + offset as f32 / self.sample_rate as f32,
             );
+        for track in self.tracks.values_mut() {
+            let disabled = track.disabled(&self.solo_tracks);
+            if let TrackKind::Midi(data) = &mut track.kind
+                && let MidiDevice::External(output) = &mut data.device
+            {
+                send_external(
+                    &mut self.midi_out_tx,
+                    output,
+                    disabled,
+                    start,
+                    self.sample_rate,
+                );
+            }
+        }
+    }
+
+    /// Follow the play state, position and tempo of the external transport
+    fn follow_sync(&mut self, now: Instant) {
+        let (playing, position, bpm) = match self.sync.follow {
+            SyncSource::Internal => return,
+            SyncSource::Clock => (
+                self.clock_follower.playing,
+                self.tempo
+                    .beats_to_frames(self.clock_follower.beat(now), self.sample_rate),
+                // Rounded to not update the tempo on each clock
+                self.clock_follower
+                    .bpm()
+                    .map(|bpm| (bpm * 10.).round() / 10.),
+            ),
+            // Only stopped when the quarter frames stop, the transport stays as it is
+            // until the first complete time code
+            SyncSource::TimeCode => match self.time_code.seconds(now) {
+                Some(seconds) => (true, (seconds * self.sample_rate as f64) as usize, None),
+                None if self.time_code.playing(now) => (
+                    self.playback_state == PlaybackState::Playing,
+                    self.playhead,
+                    None,
+                ),
+                None => (false, self.playhead, None),
+            },
+        };
+        if playing {
+            if self.playback_state == PlaybackState::Paused {
+                self.playback_state = PlaybackState::Playing;
+                self.preview_state = PlaybackState::Paused;
+            }
+            let max_drift = (SYNC_MAX_DRIFT * self.sample_rate as f32) as usize;
+            if self.playhead.abs_diff(position) > max_drift {
+                self.playhead = position;
+            }
+        } else if self.playback_state == PlaybackState::Playing {
+            self.pause();
+        }
+        if (playing, bpm) != self.external {
+            self.external = (playing, bpm);
+            let _ = self
+                .to_gui_tx
+                .push(ProcessToGuiMsg::ExternalTransport { playing, bpm });
+        }
+    }
+
+    /// Stop the transport and the recording
+    fn pause(&mut self) {
+        self.playback_state = PlaybackState::Paused;
+        self.metronome.stop_count_in();
+        if self.recording {
+            self.stop_recording();
+        }
+        if let Some(msg) = self.clock_master.stop() {
+            let _ = self.midi_out_tx.push(MidiOutputEvent {
+                due: Instant::now() + Duration::from_secs_f32(self.output_latency),
+                port: None,
+                msg: MidiOutputMsg::Sync(msg),
+            });
+        }
+        for track in self.tracks.values_mut() {
+            if let TrackKind::Midi(data) = &mut track.kind {
+                data.stop();
+            }
         }
     }
 
+    /// Start the transport, counting in first when enabled
+    fn start(&mut self) {
+        if self.playback_state == PlaybackState::Playing {
+            return;
+        }
+        self.playback_state = PlaybackState::Playing;
+        self.preview_state = PlaybackState::Paused;
+        let beat = self.tempo.frames_to_beats(self.playhead, self.sample_rate);
+        self.metronome.start_count_in(
+            self.meter.bar_at(beat).signature,
+            self.tempo.bpm_at(beat),
+            self.sample_rate,
+        );
+    }
+
     fn handle_messages(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         while let Ok(msg) = self.from_gui_rx.pop() {
             if cfg!(debug_assertions) {
                 println!("\x1b[1m\x1b[34mOutput Thread: {:?}\x1b[0m", msg);
             }
             match msg {
-                GuiToPlayerMsg::Play => {
-                    self.playback_state = PlaybackState::Playing;
-                    self.preview_state = PlaybackState::Paused;
-                }
-                GuiToPlayerMsg::Pause => {
-                    self.playback_state = PlaybackState::Paused;
+                GuiToPlayerMsg::Play => self.start(),
+                GuiToPlayerMsg::Pause => self.pause(),
+                GuiToPlayerMsg::StartRecording(recorders) => {
+                    self.recording = true;
+                    self.recorders = recorders;
+                    self.start();
                 }
+                GuiToPlayerMsg::StopRecording => self.stop_recording(),
                 GuiToPlayerMsg::SeekTo(position) => {
-                    let frames = (position / self.bpm * 60. * self.sample_rate as f32) as usize;
-                    self.playhead = frames;
+                    self.playhead = self.tempo.beats_to_frames(position, self.sample_rate);
+                }
+                GuiToPlayerMsg::SetLoop(region) => self.loop_region = region,
+                GuiToPlayerMsg::SetTrackMonitor(id, monitor, armed, input) => {
+                    if let Some(track) = self.tracks.get_mut(&id) {
+                        track.monitor = monitor;
+                        track.armed = armed;
+                        track.input = input;
+                    }
                 }
                 GuiToPlayerMsg::AddTrack(id) => {
                     let track =
@@ -180,26 +651,57 @@ impl PlayerBackend {
 
                     self.tracks.insert(id, track);
                 }
-                GuiToPlayerMsg::AddClip(
-                    track_id,
-                    file_path,
-                    position,
-                    clip_id,
-                    trim_start,
-                    trim_end,
-                ) => {
-                    let track = self.tracks.get_mut(&track_id);
-                    // Find the track by ID and add a sample to it
-                    if let Some(track) = track
-                        && let TrackKind::Audio(data) = &mut track.kind
+                GuiToPlayerMsg::AddMidiTrack(id, params) => {
+                    let device = MidiDevice::new(params, self.sample_rate);
+                    let track = TrackBackend::new(
+                        id.clone(),
+                        1.0,
+                        TrackKind::Midi(Box::new(MidiTrackData::new(device))),
+                    );
+
+                    self.tracks.insert(id, track);
+                }
+                GuiToPlayerMsg::SetInstrument(track_id, params) => {
+                    if let Some(track) = self.tracks.get_mut(&track_id)
+                        && let TrackKind::Midi(data) = &mut track.kind
+                    {
+                        // The notes held on a replaced output are released first
+                        data.stop();
+                        if let MidiDevice::External(output) = &mut data.device {
+                            send_external(
+                                &mut self.midi_out_tx,
+                                output,
+                                false,
+                                Instant::now(),
+                                self.sample_rate,
+                            );
+                        }
+                        data.device = MidiDevice::new(params, self.sample_rate);
+                    }
+                }
+                GuiToPlayerMsg::PlayMidi(track_id, event) => {
+                    if let Some(track) = self.tracks.get_mut(&track_id)
+                        && let TrackKind::Midi(data) = &mut track.kind
+                        && let Some(message) = event.message()
                     {
-                        data.clips.push(ClipBackend::new(
-                            clip_id,
-                            file_path,
-                            (position / self.bpm * 60. * self.sample_rate as f32).floor() as usize,
-                            trim_start,
-                            trim_end,
-                        ));
+                        data.device.handle(message);
+                    }
+                }
+                GuiToPlayerMsg::KeyboardMidi(event) => {
+                    if self.keyboard_events.len() < MIDI_EVENTS_CAPACITY {
+                        self.keyboard_events.push(event);
+                    }
+                }
+                GuiToPlayerMsg::SetMidiClips(track_id, clips) => {
+                    if let Some(track) = self.tracks.get_mut(&track_id) {
+                        track.set_midi_clips(
+                            clips
+                                .iter()
+                                .map(|clip| {
+                                    MidiClip::from_core(clip, &self.tempo, self.sample_rate)
+                                })
+                                .collect(),
+                        );
                     }
                 }
                 GuiToPlayerMsg::AddClips(map) => {
@@ -210,7 +712,7 @@ impl PlayerBackend {
                             for clip in clips {
                                 data.clips.push(ClipBackend::from_clipcore(
                                     &clip,
-                                    self.bpm,
+                                    &self.tempo,
                                     self.sample_rate,
                                 ));
                             }
@@ -240,8 +742,8 @@ impl PlayerBackend {
                         && let Some(clip) = previous_clip.as_mut()
                         && let TrackKind::Audio(data) = &mut track.kind
                     {
-                        clip.start_frame =
-                            (position / self.bpm * 60. * self.sample_rate as f32).floor() as usize;
+                        clip.position = position;
+                        clip.retime(&self.tempo, self.sample_rate);
 
                         let clone = clip.clone();
 
@@ -266,8 +768,8 @@ impl PlayerBackend {
                         {
                             clip.trim_start = trim_start;
                             clip.trim_end = trim_end;
-                            clip.start_frame = (position / self.bpm * 60. * self.sample_rate as f32)
-                                .floor() as usize;
+                            clip.position = position;
+                            clip.retime(&self.tempo, self.sample_rate);
                             break;
                         }
                     }
@@ -276,7 +778,20 @@ impl PlayerBackend {
                     self.solo_tracks = tracks;
                 }
                 GuiToPlayerMsg::RemoveTrack(id) => {
-                    self.tracks.remove(&id);
+                    if let Some(mut track) = self.tracks.remove(&id)
+                        && let TrackKind::Midi(data) = &mut track.kind
+                    {
+                        data.stop();
+                        if let MidiDevice::External(output) = &mut data.device {
+                            send_external(
+                                &mut self.midi_out_tx,
+                                output,
+                                false,
+                                Instant::now(),
+                                self.sample_rate,
+                            );
+                        }
+                    }
                     self.solo_tracks.retain(|solo| *solo != *id);
                 }
                 GuiToPlayerMsg::PlayPreview(file) => {
@@ -290,8 +805,26 @@ impl PlayerBackend {
                     self.preview.seek(pos);
                     self.preview_state = PlaybackState::Playing
                 }
-                GuiToPlayerMsg::UpdateBPM(bpm) => {
-                    self.bpm = bpm;
+                GuiToPlayerMsg::UpdateTempo(tempo) => {
+                    self.tempo = tempo;
+                    for track in self.tracks.values_mut() {
+                        match &mut track.kind {
+                            TrackKind::Audio(data) => {
+                                for clip in data.clips.iter_mut() {
+                                    clip.retime(&self.tempo, self.sample_rate);
+                                }
+                            }
+                            TrackKind::Midi(data) => {
+                                for clip in data.clips.iter_mut() {
+                                    clip.retime(&self.tempo, self.sample_rate);
+                                }
+                            }
+                            TrackKind::Bus(_) => {}
+                        }
+                    }
+                }
+                GuiToPlayerMsg::UpdateMeter(meter) => {
+                    self.meter = meter;
                 }
                 GuiToPlayerMsg::AddNode(track_id, index, effect_id, node) => {
                     if let Some(track) = self.tracks.get_mut(&track_id) {
@@ -320,6 +853,16 @@ impl PlayerBackend {
                         }
                     }
                 }
+                GuiToPlayerMsg::UpdateClip(clip) => {
+                    for (_, track) in self.tracks.iter_mut() {
+                        if let TrackKind::Audio(data) = &mut track.kind
+                            && let Some(backend) = data.clips.iter_mut().find(|c| c.id == clip.id)
+                        {
+                            backend.update(&clip, &self.tempo, self.sample_rate);
+                            break;
+                        }
+                    }
+                }
                 GuiToPlayerMsg::DuplicateTrack {
                     id,
                     new_id,
@@ -334,8 +877,88 @@ impl PlayerBackend {
                 GuiToPlayerMsg::ToggleMetronome(value) => {
                     self.metronome.enabled = value;
                 }
+                GuiToPlayerMsg::UpdateMetronome(settings) => {
+                    self.metronome.set_settings(settings);
+                }
+                GuiToPlayerMsg::SetSync(settings) => {
+                    if !settings.send_clock
+                        && let Some(msg) = self.clock_master.stop()
+                    {
+                        let _ = self.midi_out_tx.push(MidiOutputEvent {
+                            due: Instant::now(),
+                            port: None,
+                            msg: MidiOutputMsg::Sync(msg),
+                        });
+                    }
+                    self.sync = settings;
+                }
             }
         }
         Ok(())
     }
 }
+
+/// Send the events of an external output, their offsets counting from the frame heard at
+/// `start`. A disabled track only releases its notes. The channels whose note offs do not
+/// fit in the ring are released with an all notes off once it has room.
+fn send_external(
+    tx: &mut Producer<MidiOutputEvent>,
+    output: &mut ExternalOutput,
+    disabled: bool,
+    start: Instant,
+    sample_rate: usize,
+) {
+    let port = output.port().cloned();
+    let delay = output.delay();
+    let mut lost = output.take_lost_releases();
+    if let Some(port) = &port {
+        // Release the channels whose note offs were lost, as soon as the ring has room
+        for channel in 0..16 {
+            if lost & 1 << channel == 0 {
+                continue;
+            }
+            let msg = MidiOutputMsg::Channel {
+                channel,
+                message: MidiMessage::Controller {
+                    controller: ALL_NOTES_OFF.into(),
+                    value: 0.into(),
+                },
+            };
+            if tx
+                .push(MidiOutputEvent {
+                    due: start,
+                    port: Some(port.clone()),
+                    msg,
+                })
+                .is_ok()
+            {
+                lost &= !(1 << channel);
+            }
+        }
+    }
+    for (offset, channel, message) in output.drain_events() {
+        let Some(port) = &port else {
+            continue;
+        };
+        if disabled && !matches!(message, MidiMessage::NoteOff { .. }) {
+            continue;
+        }
+        let time = offset as f32 / sample_rate as f32 + delay;
+        let due = if time >= 0. {
+            start + Duration::from_secs_f32(time)
+        } else {
+            start
+                .checked_sub(Duration::from_secs_f32(-time))
+                .unwrap_or(start)
+        };
+        let sent = tx.push(MidiOutputEvent {
+            due,
+            port: Some(port.clone()),
+            msg: MidiOutputMsg::Channel { channel, message },
+        });
+        if sent.is_err() && matches!(message, MidiMessage::NoteOff { .. }) {
+            lost |= 1 << channel;
+        }
+    }
+    output.lose_releases(lost);
+}
@@ -19,7 +19,7 @@ impl BusTrackData {
 impl Processor for BusTrackData {
     fn process(&mut self, pos: usize, num_frames: usize, sample_rate: usize, mix: &mut Vec<f32>) {
         self.children.par_iter_mut().for_each(|(_, track)| {
-            track.process(pos, num_frames, sample_rate);
+            track.process(Some(pos), num_frames, sample_rate, None, &[]);
         });
 
         for track in self.children.values() {
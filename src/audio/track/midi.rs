@@ -1,22 +1,135 @@
-use fundsp::hacker::AudioUnit;
+#[cfg(test)]
+mod tests;
 
-use crate::audio::{clip::midi::MidiClip, track::Processor};
+use crate::{
+    audio::{
+        clip::midi::MidiClip,
+        instrument::{EngineParams, Instrument, external::ExternalOutput},
+    },
+    core::{instrument::InstrumentParams, message::MidiToPlayerMsg},
+};
+use midly::MidiMessage;
+
+/// Events kept for a block without allocating on the audio thread
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Device playing the events of a MIDI track
+#[derive(Clone)]
+pub enum MidiDevice {
+    Instrument(Instrument),
+    External(ExternalOutput),
+}
+
+impl MidiDevice {
+    pub fn new(params: InstrumentParams, sample_rate: usize) -> Self {
+        let params = match params {
+            InstrumentParams::Synth(params) => EngineParams::Synth(params),
+            InstrumentParams::Sampler(params) => EngineParams::Sampler(params),
+            InstrumentParams::DrumRack(params) => EngineParams::DrumRack(params),
+            InstrumentParams::External(params) => {
+                return Self::External(ExternalOutput::new(params));
+            }
+        };
+        Self::Instrument(Instrument::new(params, sample_rate))
+    }
+
+    /// Play `message` now, outside of the clips
+    pub fn handle(&mut self, message: MidiMessage) {
+        match self {
+            Self::Instrument(instrument) => instrument.handle(message),
+            Self::External(output) => output.handle(0, message),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct MidiTrackData {
-    pub instrument: Box<dyn AudioUnit>,
+    pub device: MidiDevice,
     pub clips: Vec<MidiClip>,
+    /// Events of the current block with their offset in frames
+    events: Vec<(usize, MidiMessage)>,
+    /// Frame expected at the start of the next block, the notes are released
+    /// when the playhead jumps
+    next_pos: Option<usize>,
 }
 
 impl MidiTrackData {
-    fn new(instrument: Box<dyn AudioUnit>) -> Self {
+    pub fn new(device: MidiDevice) -> Self {
         Self {
             clips: Vec::new(),
-            instrument,
+            device,
+            events: Vec::with_capacity(EVENTS_CAPACITY),
+            next_pos: None,
         }
     }
-}
 
-impl Processor for MidiTrackData {
-    fn process(&mut self, pos: usize, num_frames: usize, sample_rate: usize, mix: &mut Vec<f32>) {}
+    /// Release the held notes when the transport stops
+    pub fn stop(&mut self) {
+        self.next_pos = None;
+        match &mut self.device {
+            MidiDevice::Instrument(instrument) => instrument.all_notes_off(),
+            MidiDevice::External(output) => output.release(0),
+        }
+    }
+
+    /// Play the clips from `pos`, or only the `live` events when `None`.
+    /// The block is split at each event so that they start on their frame,
+    /// an external output sends them with their offset instead.
+    pub fn render(
+        &mut self,
+        pos: Option<usize>,
+        num_frames: usize,
+        live: &[MidiToPlayerMsg],
+        mix: &mut [f32],
+    ) {
+        if pos != self.next_pos {
+            match &mut self.device {
+                MidiDevice::Instrument(instrument) => instrument.all_notes_off(),
+                MidiDevice::External(output) => {
+                    output.release(0);
+                    if self.next_pos.is_none() {
+                        output.start(0);
+                    }
+                }
+            }
+        }
+        self.next_pos = pos.map(|pos| pos + num_frames);
+
+        self.events.clear();
+        if let Some(pos) = pos {
+            for clip in self.clips.iter() {
+                clip.render_block(pos, num_frames, &mut self.events);
+            }
+        }
+        // Live events have no timestamp, play them at the start of the block
+        for message in live.iter().filter_map(|event| event.message()) {
+            if self.events.len() < EVENTS_CAPACITY {
+                self.events.push((0, message));
+            }
+        }
+        // Release before attacking at the same frame
+        self.events.sort_unstable_by_key(|(offset, message)| {
+            (*offset, matches!(message, MidiMessage::NoteOn { .. }))
+        });
+
+        let instrument = match &mut self.device {
+            MidiDevice::Instrument(instrument) => instrument,
+            MidiDevice::External(output) => {
+                for (offset, message) in self.events.iter() {
+                    output.handle((*offset).min(num_frames), *message);
+                }
+                return;
+            }
+        };
+        let mut start = 0;
+        for (offset, message) in self.events.iter() {
+            let offset = (*offset).min(num_frames);
+            if offset > start {
+                instrument.render_block(&mut mix[2 * start..2 * offset]);
+                start = offset;
+            }
+            instrument.handle(*message);
+        }
+        instrument.render_block(&mut mix[2 * start..2 * num_frames]);
+    }
 }
@@ -0,0 +1,86 @@
+use crate::{
+    audio::{
+        clip::midi::MidiClip,
+        track::midi::{MidiDevice, MidiTrackData},
+    },
+    core::{
+        instrument::{ExternalParams, InstrumentParams},
+        midi::{MidiClipCore, MidiNote},
+        tempo::TempoMap,
+    },
+};
+use midly::MidiMessage;
+
+const SAMPLE_RATE: usize = 48000;
+
+#[test]
+fn test_note_starts_on_its_frame() {
+    let tempo = TempoMap::new(120.);
+    let mut core = MidiClipCore::new(0., 4.);
+    // Starts at frame 12000
+    core.notes = vec![MidiNote {
+        key: 69,
+        velocity: 127,
+        start: 0.5,
+        length: 1.,
+    }];
+    let mut data = MidiTrackData::new(MidiDevice::new(InstrumentParams::default(), SAMPLE_RATE));
+    data.clips
+        .push(MidiClip::from_core(&core, &tempo, SAMPLE_RATE));
+
+    let mut mix = vec![0.; 2 * 1024];
+    data.render(Some(11000), 1024, &[], &mut mix);
+    assert!(mix[..2 * 1000].iter().all(|s| *s == 0.));
+    assert!(mix[2 * 1000..].iter().any(|s| *s != 0.));
+}
+
+#[test]
+fn test_external_output_releases_notes_on_jump() {
+    let tempo = TempoMap::new(120.);
+    let mut core = MidiClipCore::new(0., 4.);
+    core.notes = vec![MidiNote {
+        key: 60,
+        velocity: 100,
+        start: 0.,
+        length: 2.,
+    }];
+    let params = ExternalParams::default();
+    params.channel.set_value(2.);
+    params.program.set_value(5.);
+    let mut data = MidiTrackData::new(MidiDevice::new(
+        InstrumentParams::External(params),
+        SAMPLE_RATE,
+    ));
+    data.clips
+        .push(MidiClip::from_core(&core, &tempo, SAMPLE_RATE));
+    let events = |data: &mut MidiTrackData| match &mut data.device {
+        MidiDevice::External(output) => output.drain_events().collect::<Vec<_>>(),
+        MidiDevice::Instrument(_) => unreachable!(),
+    };
+
+    // The program is selected when the transport starts, before the notes
+    let mut mix = vec![0.; 2 * 1024];
+    data.render(Some(0), 1024, &[], &mut mix);
+    let started = events(&mut data);
+    assert!(matches!(
+        started[0],
+        (0, 2, MidiMessage::ProgramChange { program }) if program == 5
+    ));
+    assert!(matches!(
+        started[1],
+        (0, 2, MidiMessage::NoteOn { key, .. }) if key == 60
+    ));
+    // Jumping back, as a loop wrap, releases the held note before playing it again
+    data.render(Some(0), 1024, &[], &mut mix);
+    let jumped = events(&mut data);
+    assert!(matches!(
+        jumped[0],
+        (0, 2, MidiMessage::NoteOff { key, .. }) if key == 60
+    ));
+    assert!(matches!(jumped[1], (0, 2, MidiMessage::NoteOn { .. })));
+    // Stopping releases it too
+    data.stop();
+    let stopped = events(&mut data);
+    assert_eq!(stopped.len(), 1);
+    assert!(matches!(stopped[0].2, MidiMessage::NoteOff { .. }));
+}
@@ -4,10 +4,14 @@ pub mod midi;
 
 use crate::{
     audio::{
-        clip::ClipBackend,
+        clip::{ClipBackend, midi::MidiClip},
         track::{audio::AudioTrackData, bus::BusTrackData, midi::MidiTrackData},
     },
-    core::metrics::AudioMetrics,
+    core::{
+        message::MidiToPlayerMsg,
+        metrics::AudioMetrics,
+        track::{MonitorMode, TrackInput},
+    },
 };
 use fundsp::{
     MAX_BUFFER_SIZE,
@@ -24,7 +28,7 @@ trait Processor {
 #[derive(Clone)]
 pub enum TrackKind {
     Audio(AudioTrackData),
-    Midi(MidiTrackData),
+    Midi(Box<MidiTrackData>),
     Bus(BusTrackData),
 }
 
@@ -38,6 +42,11 @@ pub struct TrackBackend {
     pub muted: bool,
     pub net: Net,
 
+    // Input monitoring
+    pub monitor: MonitorMode,
+    pub armed: bool,
+    pub input: TrackInput,
+
     // Effects related
     backend: NetBackend,
     id_hash: HashMap<String, NodeId>,
@@ -63,6 +72,9 @@ impl TrackBackend {
             kind,
 
             muted: false,
+            monitor: MonitorMode::default(),
+            armed: false,
+            input: TrackInput::default(),
             backend,
             net,
             id_hash: HashMap::new(),
@@ -73,7 +85,17 @@ impl TrackBackend {
         }
     }
 
-    pub fn process(&mut self, pos: usize, num_frames: usize, sample_rate: usize) {
+    /// Render the clips from `pos`, silent when `None`, and the monitored `input`
+    /// with its number of channels before the effects.
+    /// MIDI tracks play the monitored `live` events with their instrument instead of the input.
+    pub fn process(
+        &mut self,
+        pos: Option<usize>,
+        num_frames: usize,
+        sample_rate: usize,
+        input: Option<(&[f32], usize)>,
+        live: &[MidiToPlayerMsg],
+    ) {
         // Reset buffers
         self.mix.fill(0.);
         self.mix.resize(num_frames * 2, 0.);
@@ -82,11 +104,20 @@ impl TrackBackend {
         // Render all clips into self.mix
         match &mut self.kind {
             TrackKind::Audio(audio_track_data) => {
-                audio_track_data.process(pos, num_frames, sample_rate, &mut self.mix)
+                if let Some(pos) = pos {
+                    audio_track_data.process(pos, num_frames, sample_rate, &mut self.mix)
+                }
+                if let Some((input, channels)) = input {
+                    self.add_input(input, channels);
+                }
+            }
+            TrackKind::Midi(midi_track_data) => {
+                midi_track_data.render(pos, num_frames, live, &mut self.mix)
             }
-            TrackKind::Midi(_) => todo!(),
             TrackKind::Bus(bus_track_data) => {
-                bus_track_data.process(pos, num_frames, sample_rate, &mut self.mix)
+                if let Some(pos) = pos {
+                    bus_track_data.process(pos, num_frames, sample_rate, &mut self.mix)
+                }
             }
         }
 
@@ -100,6 +131,19 @@ impl TrackBackend {
         }
     }
 
+    /// Add the channels of the track input from the interleaved device `input`
+    fn add_input(&mut self, input: &[f32], channels: usize) {
+        let left = self.input.channel;
+        let right = if self.input.stereo { left + 1 } else { left };
+        if right >= channels {
+            return;
+        }
+        for (frame, mix) in input.chunks(channels).zip(self.mix.chunks_mut(2)) {
+            mix[0] += frame[left];
+            mix[1] += frame[right];
+        }
+    }
+
     fn process_effects(&mut self) {
         let mut input = BufferArray::<U2>::new();
         let mut output = BufferArray::<U2>::new();
@@ -130,6 +174,13 @@ impl TrackBackend {
             || (!solo_tracks.is_empty() && !solo_tracks.contains(&self.id))
     }
 
+    /// Replace the clips of a MIDI track
+    pub fn set_midi_clips(&mut self, clips: Vec<MidiClip>) {
+        if let TrackKind::Midi(data) = &mut self.kind {
+            data.clips = clips;
+        }
+    }
+
     pub fn remove_clip(&mut self, id: String) -> Option<ClipBackend> {
         if let TrackKind::Audio(data) = &mut self.kind
             && let Some(i) = data.clips.iter().position(|clip| clip.id == id)
@@ -202,7 +253,13 @@ impl TrackBackend {
                     };
                 }
             }
-            TrackKind::Midi(_) => {}
+            TrackKind::Midi(midi_track_data) => {
+                for clip in midi_track_data.clips.iter_mut() {
+                    if let Some(new_clip_id) = clip_map.get(&clip.id) {
+                        clip.id = new_clip_id.clone();
+                    };
+                }
+            }
             TrackKind::Bus(_) => {}
         }
         clone
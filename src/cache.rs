@@ -1,8 +1,10 @@
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
 
-use crate::analysis::{AudioInfo, get_audio_info};
+use crate::analysis::{AudioInfo, estimate_file_tempo, get_audio_info};
 
 // Inner struct to keep cache logic encapsulated
 pub struct AudioAnalysisCache {
@@ -31,3 +33,46 @@ impl AudioAnalysisCache {
 pub static AUDIO_ANALYSIS_CACHE: Lazy<AudioAnalysisCache> = Lazy::new(|| AudioAnalysisCache {
     inner: DashMap::new(),
 });
+
+/// Tempo of a file estimated for the file browser
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TempoEstimate {
+    /// Queued for the background job
+    Pending,
+    /// Estimated, `None` when no pulse was found
+    Done(Option<f32>),
+}
+
+/// Tempos estimated on an excerpt of the files, without keeping their data
+pub struct TempoCache {
+    inner: DashMap<PathBuf, TempoEstimate>,
+    jobs: Sender<PathBuf>,
+}
+
+impl TempoCache {
+    /// Tempo of the file at `path`, queued for estimation on the first call
+    pub fn get(&self, path: &Path) -> TempoEstimate {
+        if let Some(estimate) = self.inner.get(path) {
+            return *estimate;
+        }
+        self.inner
+            .insert(path.to_path_buf(), TempoEstimate::Pending);
+        let _ = self.jobs.send(path.to_path_buf());
+        TempoEstimate::Pending
+    }
+}
+
+// A single job estimates the files one after the other
+pub static TEMPO_CACHE: Lazy<TempoCache> = Lazy::new(|| {
+    let (jobs, queue) = mpsc::channel::<PathBuf>();
+    thread::spawn(move || {
+        for path in queue {
+            let tempo = estimate_file_tempo(&path);
+            TEMPO_CACHE.inner.insert(path, TempoEstimate::Done(tempo));
+        }
+    });
+    TempoCache {
+        inner: DashMap::new(),
+        jobs,
+    }
+});
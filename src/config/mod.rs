@@ -1,3 +1,4 @@
+use crate::core::midi_learn::MidiBinding;
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -8,6 +9,12 @@ use std::{
 #[derive(Default, Debug)]
 pub struct Config {
     directories: Vec<PathBuf>,
+    /// Names of the enabled MIDI inputs, every input when never chosen
+    midi_inputs: Option<Vec<String>>,
+    /// Controllers bound to the selected track in every project
+    midi_mappings: Vec<MidiBinding>,
+    /// Name of the MIDI output receiving the clock
+    midi_sync_output: Option<String>,
 }
 
 // Helper functions for serde to convert PathBuf <-> String
@@ -40,6 +47,12 @@ mod serde_pathbuf_vec {
 struct ConfigSerdeHelper {
     #[serde(with = "serde_pathbuf_vec")]
     directories: Vec<PathBuf>,
+    #[serde(default)]
+    midi_inputs: Option<Vec<String>>,
+    #[serde(default)]
+    midi_mappings: Vec<MidiBinding>,
+    #[serde(default)]
+    midi_sync_output: Option<String>,
 }
 
 impl Config {
@@ -50,6 +63,9 @@ impl Config {
                 if let Ok(helper) = serde_json::from_str::<ConfigSerdeHelper>(&data) {
                     return Config {
                         directories: helper.directories,
+                        midi_inputs: helper.midi_inputs,
+                        midi_mappings: helper.midi_mappings,
+                        midi_sync_output: helper.midi_sync_output,
                     };
                 }
             }
@@ -67,6 +83,9 @@ impl Config {
 
             let helper = ConfigSerdeHelper {
                 directories: self.directories.clone(),
+                midi_inputs: self.midi_inputs.clone(),
+                midi_mappings: self.midi_mappings.clone(),
+                midi_sync_output: self.midi_sync_output.clone(),
             };
 
             if let Ok(json) = serde_json::to_string_pretty(&helper) {
@@ -75,19 +94,56 @@ impl Config {
         }
     }
 
+    /// Apply `change` to the config on disk and save it, so that the other
+    /// loaded configs are not overwritten
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        *self = Config::load();
+        change(self);
+        self.save();
+    }
+
     /// Add a directory if it doesn't exist
     pub fn add_dir(&mut self, dir: impl Into<PathBuf>) {
         let dir = dir.into();
         if !self.directories.contains(&dir) {
-            self.directories.push(dir);
-            self.save();
+            self.update(|config| {
+                if !config.directories.contains(&dir) {
+                    config.directories.push(dir);
+                }
+            });
         }
     }
 
     /// Remove a directory
     pub fn remove_dir(&mut self, dir: &Path) {
-        self.directories.retain(|d| d != dir);
-        self.save();
+        self.update(|config| config.directories.retain(|d| d != dir));
+    }
+
+    /// Names of the enabled MIDI inputs, `None` to enable every input
+    pub fn midi_inputs(&self) -> Option<&Vec<String>> {
+        self.midi_inputs.as_ref()
+    }
+
+    pub fn set_midi_inputs(&mut self, inputs: Vec<String>) {
+        self.update(|config| config.midi_inputs = Some(inputs));
+    }
+
+    /// Global controller mapping, following the selected track
+    pub fn midi_mappings(&self) -> &[MidiBinding] {
+        &self.midi_mappings
+    }
+
+    pub fn set_midi_mappings(&mut self, mappings: Vec<MidiBinding>) {
+        self.update(|config| config.midi_mappings = mappings);
+    }
+
+    /// Name of the MIDI output receiving the clock, `None` to send no clock
+    pub fn midi_sync_output(&self) -> Option<&String> {
+        self.midi_sync_output.as_ref()
+    }
+
+    pub fn set_midi_sync_output(&mut self, name: Option<String>) {
+        self.update(|config| config.midi_sync_output = name);
     }
 
     /// Return a clone of all directories
@@ -101,3 +157,11 @@ fn get_config_path() -> Option<PathBuf> {
     ProjectDirs::from("com", "Bytenosis", "Tonique")
         .map(|proj_dirs| proj_dirs.config_dir().join("config.json"))
 }
+
+/// Returns the folder where recorded audio is written, creating it when missing.
+pub fn recordings_dir() -> Option<PathBuf> {
+    let dir = ProjectDirs::from("com", "Bytenosis", "Tonique")
+        .map(|proj_dirs| proj_dirs.data_dir().join("recordings"))?;
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
@@ -1,11 +1,204 @@
-use crate::analysis::AudioInfo;
-use std::{fmt::Debug, time::Duration};
+use crate::{
+    analysis::AudioInfo,
+    core::{project::audio_path, tempo::TempoMap},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// Which part of the source signal a clip plays
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ChannelMode {
+    #[default]
+    Stereo,
+    Left,
+    Right,
+    /// Sum of both channels
+    Mid,
+    /// Difference between both channels
+    Side,
+}
+
+impl ChannelMode {
+    pub const ALL: [ChannelMode; 5] = [
+        ChannelMode::Stereo,
+        ChannelMode::Left,
+        ChannelMode::Right,
+        ChannelMode::Mid,
+        ChannelMode::Side,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            ChannelMode::Stereo => "Stereo",
+            ChannelMode::Left => "Left",
+            ChannelMode::Right => "Right",
+            ChannelMode::Mid => "Mid",
+            ChannelMode::Side => "Side",
+        }
+    }
+
+    /// Map a stereo frame to the output frame
+    #[inline]
+    pub fn apply(&self, left: f32, right: f32) -> (f32, f32) {
+        match self {
+            ChannelMode::Stereo => (left, right),
+            ChannelMode::Left => (left, left),
+            ChannelMode::Right => (right, right),
+            ChannelMode::Mid => {
+                let mid = (left + right) * 0.5;
+                (mid, mid)
+            }
+            ChannelMode::Side => {
+                let side = (left - right) * 0.5;
+                (side, -side)
+            }
+        }
+    }
+}
+
+/// Shape of a clip fade
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum FadeCurve {
+    Linear,
+    /// Keeps a constant power when crossfading two uncorrelated signals
+    #[default]
+    EqualPower,
+    SCurve,
+}
+
+impl FadeCurve {
+    pub const ALL: [FadeCurve; 3] = [FadeCurve::Linear, FadeCurve::EqualPower, FadeCurve::SCurve];
+
+    pub fn name(&self) -> &str {
+        match self {
+            FadeCurve::Linear => "Linear",
+            FadeCurve::EqualPower => "Equal power",
+            FadeCurve::SCurve => "S-curve",
+        }
+    }
+
+    /// Gain of a fade in at `x` between 0 and 1. A fade out uses the gain at `1 - x`.
+    #[inline]
+    pub fn gain(&self, x: f32) -> f32 {
+        let x = x.clamp(0., 1.);
+        match self {
+            FadeCurve::Linear => x,
+            FadeCurve::EqualPower => (x * std::f32::consts::FRAC_PI_2).sin(),
+            FadeCurve::SCurve => 0.5 - 0.5 * (x * std::f32::consts::PI).cos(),
+        }
+    }
+}
+
+/// Fade applied at the start or at the end of a clip
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClipFade {
+    /// Length in beats
+    pub length: f32,
+    pub curve: FadeCurve,
+}
+
+impl ClipFade {
+    pub fn new(length: f32, curve: FadeCurve) -> Self {
+        Self { length, curve }
+    }
+}
+
+/// Pins a time of the source to a beat
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WarpMarker {
+    /// Time in the source in seconds
+    pub source: f32,
+    /// Beat from the start of the source
+    pub beat: f32,
+}
+
+/// Mapping between the source time of a clip and beats.
+/// When disabled the source plays at its own speed whatever the project tempo.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClipWarp {
+    pub enabled: bool,
+    /// Tempo of the source, used after the last marker
+    pub source_bpm: f32,
+    /// Markers sorted by source time and beat
+    pub markers: Vec<WarpMarker>,
+}
+
+impl Default for ClipWarp {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source_bpm: 120.,
+            markers: Vec::new(),
+        }
+    }
+}
+
+impl ClipWarp {
+    /// Segment of the map containing `value`, as ((source, beat), (source, beat)).
+    /// The map starts at (0, 0) and continues at `source_bpm` after the last marker.
+    fn segment(&self, value: f32, from_source: bool) -> ((f32, f32), (f32, f32)) {
+        let mut previous = (0., 0.);
+        for marker in self.markers.iter() {
+            let next = (marker.source, marker.beat);
+            let bound = if from_source { next.0 } else { next.1 };
+            if value < bound {
+                return (previous, next);
+            }
+            previous = next;
+        }
+        (previous, (previous.0 + 60., previous.1 + self.source_bpm))
+    }
+
+    /// Beats from the start of the source at `secs`
+    pub fn source_to_beats(&self, secs: f32) -> f32 {
+        let ((s0, b0), (s1, b1)) = self.segment(secs, true);
+        b0 + (secs - s0) * (b1 - b0) / (s1 - s0).max(f32::EPSILON)
+    }
+
+    /// Source time in seconds at `beats` from the start of the source
+    pub fn beats_to_source(&self, beats: f32) -> f32 {
+        let ((s0, b0), (s1, b1)) = self.segment(beats, false);
+        s0 + (beats - b0) * (s1 - s0) / (b1 - b0).max(f32::EPSILON)
+    }
+
+    /// Insert a marker keeping the current mapping, returns its index
+    pub fn add_marker(&mut self, secs: f32) -> usize {
+        let marker = WarpMarker {
+            source: secs,
+            beat: self.source_to_beats(secs),
+        };
+        let index = self
+            .markers
+            .iter()
+            .position(|m| m.source > secs)
+            .unwrap_or(self.markers.len());
+        self.markers.insert(index, marker);
+        index
+    }
+
+    /// Move the marker at `index` to `beat`, keeping the markers ordered
+    pub fn move_marker(&mut self, index: usize, beat: f32) {
+        let min = if index > 0 {
+            self.markers[index - 1].beat
+        } else {
+            0.
+        };
+        let max = self
+            .markers
+            .get(index + 1)
+            .map_or(f32::INFINITY, |m| m.beat);
+        if let Some(marker) = self.markers.get_mut(index) {
+            marker.beat = beat.clamp(min + 0.01, (max - 0.01).max(min + 0.01));
+        }
+    }
+}
 
 /// A clip representing an audio file placed on a track
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ClipCore {
     pub id: String,
     /// Audio metadata
+    #[serde(with = "audio_path")]
     pub audio: AudioInfo,
     /// Position in beat
     pub position: f32,
@@ -15,6 +208,22 @@ pub struct ClipCore {
     /// Ratio of the trimmed end length over the original length
     /// Between 0 and 1
     pub trim_end: f32,
+    /// Channels of the source played by the clip
+    pub channel_mode: ChannelMode,
+    pub fade_in: ClipFade,
+    pub fade_out: ClipFade,
+    /// Gain in dB
+    pub gain: f32,
+    /// Polarity inversion
+    pub invert: bool,
+    /// Play the source backwards
+    pub reverse: bool,
+    /// Time-stretching of the source to the project tempo
+    pub warp: ClipWarp,
+    /// Pitch shift in semitones, cents are the fractional part
+    pub transpose: f32,
+    /// Keep the spectral envelope of the source when transposing
+    pub preserve_formants: bool,
 }
 
 impl ClipCore {
@@ -25,6 +234,15 @@ impl ClipCore {
             position,
             trim_start: 0.,
             trim_end: 1.,
+            channel_mode: ChannelMode::default(),
+            fade_in: ClipFade::default(),
+            fade_out: ClipFade::default(),
+            gain: 0.,
+            invert: false,
+            reverse: false,
+            warp: ClipWarp::default(),
+            transpose: 0.,
+            preserve_formants: false,
         }
     }
 
@@ -34,35 +252,145 @@ impl ClipCore {
         clone
     }
 
-    pub fn duration(&self) -> Option<Duration> {
-        if let Some(duration) = self.audio.duration {
-            Some(Duration::from_secs_f32(
-                duration.as_secs_f32() * (self.trim_end - self.trim_start),
-            ))
+    /// Duration of the source in seconds
+    fn source_duration(&self) -> f32 {
+        self.audio.duration.unwrap().as_secs_f32()
+    }
+
+    /// Source time in seconds played at the start of the clip
+    fn anchor(&self) -> f32 {
+        let ratio = if self.reverse {
+            self.trim_end
         } else {
-            None
+            self.trim_start
+        };
+        ratio * self.source_duration()
+    }
+
+    /// Source time in seconds played at `beats` from the start of the clip.
+    /// A clip which is not warped plays its source in real time from its position.
+    pub fn source_at(&self, beats: f32, tempo: &TempoMap) -> f32 {
+        let direction = if self.reverse { -1. } else { 1. };
+        if self.warp.enabled {
+            let origin = self.warp.source_to_beats(self.anchor());
+            return self.warp.beats_to_source(origin + direction * beats);
+        }
+        let elapsed =
+            tempo.beats_to_secs(self.position + beats) - tempo.beats_to_secs(self.position);
+        self.anchor() + direction * elapsed as f32
+    }
+
+    /// Beats from the start of the clip where the source time `secs` is played
+    pub fn beats_at(&self, secs: f32, tempo: &TempoMap) -> f32 {
+        let direction = if self.reverse { -1. } else { 1. };
+        if self.warp.enabled {
+            return direction
+                * (self.warp.source_to_beats(secs) - self.warp.source_to_beats(self.anchor()));
         }
+        let start = tempo.beats_to_secs(self.position);
+        let elapsed = (direction * (secs - self.anchor())) as f64;
+        tempo.secs_to_beats(start + elapsed) - self.position
     }
-    pub fn trim_start_at(&mut self, beats: f32, bpm: f32) {
-        let duration = self.audio.duration.unwrap().as_secs_f32() * bpm / 60.;
-        let clamped_beats = beats.clamp(self.position - duration * self.trim_start, self.end(bpm));
-        self.trim_start += (clamped_beats - self.position) / duration;
+
+    /// Move the start of the clip to `beats`. Trim ratios are in source order,
+    /// so a reversed clip is trimmed at the end of its source.
+    pub fn trim_start_at(&mut self, beats: f32, tempo: &TempoMap) {
+        let duration = self.source_duration();
+        // Beats available before the start of the clip
+        let available = if self.reverse {
+            -self.beats_at(duration, tempo)
+        } else {
+            -self.beats_at(0., tempo)
+        };
+        let clamped_beats = beats.clamp(self.position - available, self.end(tempo));
+        let secs = self.source_at(clamped_beats - self.position, tempo) / duration;
+        if self.reverse {
+            self.trim_end = secs;
+        } else {
+            self.trim_start = secs;
+        }
         self.position = clamped_beats;
 
         self.trim_start = self.trim_start.clamp(0., 1.);
+        self.trim_end = self.trim_end.clamp(0., 1.);
+        self.clamp_fades(tempo);
     }
 
-    pub fn trim_end_at(&mut self, beats: f32, bpm: f32) {
-        let duration = self.audio.duration.unwrap().as_secs_f32() * bpm / 60.;
-        self.trim_end = (beats - self.position) / duration + self.trim_start;
-        self.trim_end = self.trim_end.clamp(0., 1.);
+    pub fn trim_end_at(&mut self, beats: f32, tempo: &TempoMap) {
+        let duration = self.source_duration();
+        let secs = self.source_at((beats - self.position).max(0.), tempo) / duration;
+        if self.reverse {
+            self.trim_start = secs.clamp(0., self.trim_end);
+        } else {
+            self.trim_end = secs.clamp(self.trim_start, 1.);
+        }
+        self.clamp_fades(tempo);
+    }
+
+    /// Linear gain of the clip including its polarity
+    pub fn amplitude(&self) -> f32 {
+        let amplitude = 10f32.powf(self.gain / 20.);
+        if self.invert { -amplitude } else { amplitude }
+    }
+
+    /// Beats from the start of the clip of every transient of the source,
+    /// including the ones trimmed away
+    pub fn transients(&self, tempo: &TempoMap) -> Vec<f32> {
+        let Ok(transients) = self.audio.transients.read() else {
+            return Vec::new();
+        };
+        let sample_rate = self.audio.sample_rate as f32;
+        transients
+            .iter()
+            .map(|frame| self.beats_at(*frame as f32 / sample_rate, tempo))
+            .collect()
+    }
+
+    /// Warp the clip from its estimated tempo to the project tempo.
+    /// Returns false when no tempo was detected in the source.
+    pub fn conform_to_tempo(&mut self, tempo: &TempoMap) -> bool {
+        let Some(source_bpm) = self.audio.tempo.read().ok().and_then(|bpm| *bpm) else {
+            return false;
+        };
+        self.conform_to_bpm(source_bpm, tempo);
+        true
+    }
+
+    /// Warp the clip from `source_bpm` to the project tempo
+    pub fn conform_to_bpm(&mut self, source_bpm: f32, tempo: &TempoMap) {
+        self.warp = ClipWarp {
+            enabled: true,
+            source_bpm,
+            markers: Vec::new(),
+        };
+        self.clamp_fades(tempo);
+    }
+
+    /// Ratio between the played and source frequencies
+    pub fn pitch_ratio(&self) -> f32 {
+        2f32.powf(self.transpose / 12.)
+    }
+
+    /// Length of the clip in beats
+    pub fn length(&self, tempo: &TempoMap) -> f32 {
+        let duration = self.source_duration();
+        let ratio = if self.reverse {
+            self.trim_start
+        } else {
+            self.trim_end
+        };
+        self.beats_at(ratio * duration, tempo)
+    }
+
+    /// Shorten the fades so that they fit in the clip
+    pub fn clamp_fades(&mut self, tempo: &TempoMap) {
+        let length = self.length(tempo);
+        self.fade_in.length = self.fade_in.length.clamp(0., length);
+        self.fade_out.length = self.fade_out.length.clamp(0., length - self.fade_in.length);
     }
 
-    pub fn end(&self, bpm: f32) -> f32 {
-        self.position
-            + self.audio.duration.unwrap().as_secs_f32() / 60.
-                * bpm
-                * (self.trim_end - self.trim_start)
+    pub fn end(&self, tempo: &TempoMap) -> f32 {
+        self.position + self.length(tempo)
     }
 }
 
@@ -74,6 +402,15 @@ impl Debug for ClipCore {
             .field("position", &self.position)
             .field("trim_start", &self.trim_start)
             .field("trim_end", &self.trim_end)
+            .field("channel_mode", &self.channel_mode)
+            .field("fade_in", &self.fade_in)
+            .field("fade_out", &self.fade_out)
+            .field("gain", &self.gain)
+            .field("invert", &self.invert)
+            .field("reverse", &self.reverse)
+            .field("warp", &self.warp)
+            .field("transpose", &self.transpose)
+            .field("preserve_formants", &self.preserve_formants)
             .finish()
     }
 }
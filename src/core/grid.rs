@@ -1,8 +1,12 @@
-use std::time::Duration;
-
+use crate::core::{
+    meter::{BarPosition, Meter},
+    tempo::TempoMap,
+};
 use egui::{Align2, Color32, FontId, Painter, Rect, Stroke, Vec2, pos2};
 
 const DEFAULT_THRESHOLD: f32 = 0.3;
+/// Distance in pixels under which a position snaps to a point
+const POINT_SNAP_DISTANCE: f32 = 6.;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GridResolution {
@@ -15,15 +19,39 @@ pub enum GridResolution {
 }
 
 impl GridResolution {
-    /// How many divisions per beat
-    pub fn divisions_per_beat(&self, beats_per_bar: usize) -> f32 {
+    /// Bars between two lines for the resolutions coarser than a beat
+    pub fn bars_per_line(&self) -> Option<i32> {
+        match self {
+            GridResolution::SixTeenBar => Some(16),
+            GridResolution::FourBar => Some(4),
+            GridResolution::Bar => Some(1),
+            _ => None,
+        }
+    }
+
+    /// How many divisions per beat of the time signature
+    pub fn divisions_per_beat(&self) -> usize {
         match self {
-            GridResolution::SixTeenBar => 1.0 / (beats_per_bar * 16) as f32,
-            GridResolution::FourBar => 1.0 / (beats_per_bar * 4) as f32,
-            GridResolution::Bar => 1.0 / (beats_per_bar as f32), // 1 line per bar (4 beats)
-            GridResolution::Beat => 1.0,                         // 1 line per beat
-            GridResolution::Quarter => 4.0,                      // 4 lines per beat
-            GridResolution::Height => 8.0,
+            GridResolution::Quarter => 4, // 4 lines per beat
+            GridResolution::Height => 8,
+            _ => 1,
+        }
+    }
+
+    /// Resolution showing lines a comfortable distance apart at a zoom level
+    pub fn from_pixels_per_beat(pixels_per_beat: f32) -> Self {
+        if pixels_per_beat < 1.0 {
+            GridResolution::SixTeenBar
+        } else if pixels_per_beat < 4.0 {
+            GridResolution::FourBar
+        } else if pixels_per_beat < 15.0 {
+            GridResolution::Bar
+        } else if pixels_per_beat < 80.0 {
+            GridResolution::Beat
+        } else if pixels_per_beat < 300.0 {
+            GridResolution::Quarter
+        } else {
+            GridResolution::Height
         }
     }
 
@@ -39,9 +67,28 @@ impl GridResolution {
     }
 }
 
+/// Kind of a grid line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GridLineKind {
+    Bar,
+    Beat,
+    Division,
+}
+
+/// Line of the grid at a beat of the timeline
+#[derive(Debug, Clone, Copy)]
+pub struct GridLine {
+    pub beat: f32,
+    pub kind: GridLineKind,
+    /// Index of the bar from 0
+    pub bar: i32,
+    /// Index of the beat in the bar from 0
+    pub beat_in_bar: usize,
+}
+
 pub struct GridService {
     pixels_per_beat: f32,
-    beats_per_bar: usize,
+    meter: Meter,
     resolution: GridResolution,
     min_pixels_per_beat: f32,
     max_pixels_per_beat: f32,
@@ -52,7 +99,7 @@ impl GridService {
     pub fn new() -> Self {
         Self {
             pixels_per_beat: 10.,
-            beats_per_bar: 4,
+            meter: Meter::default(),
             resolution: GridResolution::Beat,
             min_pixels_per_beat: 0.5,
             max_pixels_per_beat: 5000.,
@@ -62,9 +109,11 @@ impl GridService {
     pub fn pixels_per_beat(&self) -> f32 {
         self.pixels_per_beat
     }
-    /// Convert a time duration to an actual screen width
-    pub fn duration_to_width(&self, duration: Duration, bpm: f32) -> f32 {
-        duration.as_secs_f32() / 60.0 * bpm * self.pixels_per_beat
+    pub fn meter(&self) -> &Meter {
+        &self.meter
+    }
+    pub fn set_meter(&mut self, meter: Meter) {
+        self.meter = meter;
     }
     /// Position in beats to actual screen x position
     pub fn beats_to_x(&self, beats: f32, viewport: Rect) -> f32 {
@@ -105,179 +154,237 @@ impl GridService {
     }
 
     pub fn snap_at_grid_with_threshold(&self, beats: f32, threshold: f32) -> Option<f32> {
-        let divisions = self.resolution.divisions_per_beat(self.beats_per_bar);
-        let step = 1.0 / divisions; // spacing between grid lines (in beats)
-
-        let nearest_position = (beats / step).round() * step;
+        let (previous, next) = self.cell(beats, self.resolution);
+        let nearest_position = if beats - previous < next - beats {
+            previous
+        } else {
+            next
+        };
 
-        if (beats - nearest_position).abs() < step * threshold {
+        if (beats - nearest_position).abs() < (next - previous) * threshold {
             Some(nearest_position)
         } else {
             None
         }
     }
 
-    fn update_resolution(&mut self) {
-        let new_resolution = if self.pixels_per_beat < 1.0 {
-            GridResolution::SixTeenBar
-        } else if self.pixels_per_beat < 4.0 {
-            GridResolution::FourBar
-        } else if self.pixels_per_beat < 15.0 {
-            GridResolution::Bar
-        } else if self.pixels_per_beat < 80.0 {
-            GridResolution::Beat
-        } else if self.pixels_per_beat < 300.0 {
-            GridResolution::Quarter
+    /// Nearest line of the grid at `resolution`, whatever the distance
+    pub fn snap_at_resolution(&self, beats: f32, resolution: GridResolution) -> f32 {
+        let (previous, next) = self.cell(beats, resolution);
+        if beats - previous < next - beats {
+            previous
         } else {
-            GridResolution::Height
-        };
-        self.resolution = new_resolution;
+            next
+        }
+    }
+
+    /// Line of the grid at `resolution` before `beats`
+    pub fn floor_at_resolution(&self, beats: f32, resolution: GridResolution) -> f32 {
+        self.cell(beats, resolution).0
+    }
+
+    /// Length in beats of the cell of the grid at `resolution` containing `beats`
+    pub fn step_at_resolution(&self, beats: f32, resolution: GridResolution) -> f32 {
+        let (previous, next) = self.cell(beats, resolution);
+        next - previous
+    }
+
+    /// Lines of the grid at `resolution` around `beats`
+    fn cell(&self, beats: f32, resolution: GridResolution) -> (f32, f32) {
+        let position = self.meter.bar_at(beats);
+        match resolution.bars_per_line() {
+            Some(bars) => {
+                let bar = position.bar - position.bar.rem_euclid(bars);
+                (self.meter.bar_start(bar), self.meter.bar_start(bar + bars))
+            }
+            None => {
+                let step =
+                    position.signature.beat_length() / resolution.divisions_per_beat() as f32;
+                let previous = position.start + ((beats - position.start) / step).floor() * step;
+                let bar_end = position.start + position.signature.bar_length();
+                (previous, (previous + step).min(bar_end))
+            }
+        }
+    }
+
+    /// Lines of the grid between the beats `from` and `to`, starting at beat 0
+    pub fn lines(&self, from: f32, to: f32) -> Vec<GridLine> {
+        self.lines_at_resolution(from, to, self.resolution)
+    }
+
+    /// Lines of the grid at `resolution` between the beats `from` and `to`
+    pub fn lines_at_resolution(
+        &self,
+        from: f32,
+        to: f32,
+        resolution: GridResolution,
+    ) -> Vec<GridLine> {
+        let mut lines = Vec::new();
+        let mut position = self.meter.bar_at(from.max(0.));
+        while position.start <= to {
+            let signature = position.signature;
+            match resolution.bars_per_line() {
+                Some(bars) => {
+                    if position.bar % bars == 0 && position.start >= from {
+                        lines.push(GridLine {
+                            beat: position.start,
+                            kind: GridLineKind::Bar,
+                            bar: position.bar,
+                            beat_in_bar: 0,
+                        });
+                    }
+                }
+                None => {
+                    let divisions = resolution.divisions_per_beat();
+                    let step = signature.beat_length() / divisions as f32;
+                    for index in 0..signature.numerator as usize * divisions {
+                        let beat = position.start + index as f32 * step;
+                        if beat < from || beat > to {
+                            continue;
+                        }
+                        let kind = if index == 0 {
+                            GridLineKind::Bar
+                        } else if index % divisions == 0 {
+                            GridLineKind::Beat
+                        } else {
+                            GridLineKind::Division
+                        };
+                        lines.push(GridLine {
+                            beat,
+                            kind,
+                            bar: position.bar,
+                            beat_in_bar: index / divisions,
+                        });
+                    }
+                }
+            }
+            let bar = position.bar + 1;
+            position = BarPosition {
+                bar,
+                start: self.meter.bar_start(bar),
+                signature: self.meter.signature_at_bar(bar),
+            };
+        }
+        lines
+    }
+
+    /// Snap at the nearest of `points` closer than a few pixels
+    pub fn snap_at_points(&self, beats: f32, points: impl IntoIterator<Item = f32>) -> Option<f32> {
+        self.snap_at_points_with_zoom(beats, points, self.pixels_per_beat)
+    }
+
+    /// Snap at the nearest of `points` closer than a few pixels,
+    /// for a view zoomed at `pixels_per_beat` rather than the timeline
+    pub fn snap_at_points_with_zoom(
+        &self,
+        beats: f32,
+        points: impl IntoIterator<Item = f32>,
+        pixels_per_beat: f32,
+    ) -> Option<f32> {
+        let threshold = POINT_SNAP_DISTANCE / pixels_per_beat;
+        points
+            .into_iter()
+            .filter(|point| (point - beats).abs() < threshold)
+            .min_by(|a, b| (a - beats).abs().total_cmp(&(b - beats).abs()))
+    }
+
+    fn update_resolution(&mut self) {
+        self.resolution = GridResolution::from_pixels_per_beat(self.pixels_per_beat);
     }
 
     pub fn render_clip_grid(&self, painter: &Painter, viewport: Rect, rect: Rect, color: Color32) {
-        if self.resolution.divisions_per_beat(self.beats_per_bar)
-            <= GridResolution::Bar.divisions_per_beat(self.beats_per_bar)
-        {
+        if self.resolution.bars_per_line().is_some() {
             return;
         }
-        let divisions_per_beat = GridResolution::Bar.divisions_per_beat(self.beats_per_bar);
-
-        let step = self.pixels_per_beat / divisions_per_beat; // pixel spacing between grid lines
-        let clip_offset = self.offset.x + rect.left() - viewport.left();
-        let mut step_index = (clip_offset / step).floor() as i32;
-        let mut x = rect.left() - clip_offset.rem_euclid(step);
-        while x < rect.right() {
-            // Skip if out of bounds
-            if step_index < 0 {
-                step_index += 1;
-                x += step;
+        let from = self.x_to_beats(rect.left(), viewport);
+        let to = self.x_to_beats(rect.right(), viewport);
+        for line in self.lines(from, to) {
+            if line.kind != GridLineKind::Bar {
                 continue;
             }
-
+            let x = self.beats_to_x(line.beat, viewport);
             painter.line_segment(
                 [pos2(x, rect.top()), pos2(x, rect.bottom())],
                 Stroke::new(1.0, color),
             );
-
-            step_index += 1;
-            x += step;
         }
     }
 
     pub fn render_grid(&self, painter: &Painter, viewport: Rect) {
-        let divisions_per_beat = self.resolution.divisions_per_beat(self.beats_per_bar);
-        let step = self.pixels_per_beat / divisions_per_beat; // pixel spacing between grid lines
-
-        let mut step_index = (self.offset.x / step).floor() as i32;
-        let right = viewport.right();
-        let mut x = viewport.left() - self.offset.x.rem_euclid(step);
-
-        let lines_per_bar = (self.beats_per_bar as f32 * divisions_per_beat).max(1.0) as i32;
-        let lines_per_beat = divisions_per_beat.max(1.0) as i32;
-
-        while x < right {
-            // Skip if out of bounds
-            if step_index < 0 {
-                step_index += 1;
-                x += step;
-                continue;
-            }
-            let is_bar = step_index % lines_per_bar == 0;
-            let is_major_beat = step_index % lines_per_beat == 0;
-
-            let color = if is_bar {
-                Color32::from_gray(90)
-            } else if is_major_beat {
-                Color32::from_gray(70)
-            } else {
-                Color32::from_gray(60)
+        let from = self.x_to_beats(viewport.left(), viewport);
+        let to = self.x_to_beats(viewport.right(), viewport);
+        for line in self.lines(from, to) {
+            let x = self.beats_to_x(line.beat, viewport);
+            let is_bar = line.kind == GridLineKind::Bar;
+            let color = match line.kind {
+                GridLineKind::Bar => Color32::from_gray(90),
+                GridLineKind::Beat => Color32::from_gray(70),
+                GridLineKind::Division => Color32::from_gray(60),
             };
 
             painter.line_segment(
                 [pos2(x, viewport.top()), pos2(x, viewport.bottom())],
                 Stroke::new(if is_bar { 2.0 } else { 1.0 }, color),
             );
-
-            step_index += 1;
-            x += step;
         }
     }
 
-    pub fn render_labels(&self, painter: &Painter, rect: Rect, bpm: f32) {
-        let divisions_per_beat = self.resolution.divisions_per_beat(self.beats_per_bar);
-        let step = self.pixels_per_beat / divisions_per_beat; // pixel spacing between grid lines
-
-        let mut step_index = (self.offset.x / step).floor() as i32;
-        let right = rect.right();
-        let mut x = rect.left() - self.offset.x.rem_euclid(step);
-
-        let lines_per_bar = (self.beats_per_bar as f32 * divisions_per_beat).max(1.0) as i32;
-        let lines_per_beat = divisions_per_beat.max(1.0) as i32;
+    pub fn render_labels(&self, painter: &Painter, rect: Rect, tempo: &TempoMap) {
+        let from = self.x_to_beats(rect.left(), rect);
+        let to = self.x_to_beats(rect.right(), rect);
+        let show_beats = self.resolution.divisions_per_beat() >= 4;
+        for line in self.lines(from, to) {
+            let x = self.beats_to_x(line.beat, rect);
+            let (text, width) = match line.kind {
+                GridLineKind::Bar => (format!("{}", line.bar + 1), 2.0),
+                GridLineKind::Beat if show_beats => {
+                    (format!("{}.{}", line.bar + 1, line.beat_in_bar + 1), 1.0)
+                }
+                _ => continue,
+            };
+            painter.text(
+                pos2(x + 3.0, rect.bottom() - 1.0),
+                Align2::LEFT_BOTTOM,
+                text,
+                FontId::new(8., egui::FontFamily::Monospace),
+                Color32::WHITE,
+            );
+            painter.line_segment(
+                [pos2(x, rect.bottom() - 8.0), pos2(x, rect.bottom())],
+                Stroke::new(width, Color32::from_gray(120)),
+            );
+        }
 
-        while x < right {
-            // Skip if out of bounds
-            if step_index < 0 {
-                step_index += 1;
-                x += step;
+        // Time signatures
+        for (change, beat) in self.meter.changes().iter().zip(self.meter.change_beats()) {
+            let x = self.beats_to_x(*beat, rect);
+            if x < rect.left() - 20. || x > rect.right() {
                 continue;
             }
-
-            let is_bar = step_index % lines_per_bar == 0;
-            let is_major_beat = step_index % lines_per_beat == 0;
-
-            let bar_index = step_index.div_euclid(lines_per_bar) + 1;
-            if is_bar {
-                let text = format!("{}", bar_index);
-                painter.text(
-                    pos2(x + 3.0, rect.bottom() - 1.0),
-                    Align2::LEFT_BOTTOM,
-                    text,
-                    FontId::new(8., egui::FontFamily::Monospace),
-                    Color32::WHITE,
-                );
-                painter.line_segment(
-                    [pos2(x, rect.bottom() - 8.0), pos2(x, rect.bottom())],
-                    Stroke::new(2.0, Color32::from_gray(120)),
-                );
-            }
-            let beat_index =
-                step_index.div_euclid(lines_per_beat) % (self.beats_per_bar as i32) + 1;
-            if divisions_per_beat >= GridResolution::Quarter.divisions_per_beat(self.beats_per_bar)
-                && is_major_beat
-            {
-                let text = format!("{}.{}", bar_index, beat_index);
-                painter.text(
-                    pos2(x + 3.0, rect.bottom() - 1.0),
-                    Align2::LEFT_BOTTOM,
-                    text,
-                    FontId::new(8., egui::FontFamily::Monospace),
-                    Color32::WHITE,
-                );
-                painter.line_segment(
-                    [pos2(x, rect.bottom() - 8.0), pos2(x, rect.bottom())],
-                    Stroke::new(1.0, Color32::from_gray(120)),
-                );
-            }
-
-            step_index += 1;
-            x += step;
+            painter.text(
+                pos2(x + 3.0, rect.center().y),
+                Align2::LEFT_CENTER,
+                change.signature.name(),
+                FontId::new(8., egui::FontFamily::Monospace),
+                Color32::from_gray(200),
+            );
         }
 
-        self.render_time_labels(painter, rect, bpm);
+        self.render_time_labels(painter, rect, tempo);
     }
 
-    fn render_time_labels(&self, painter: &Painter, rect: Rect, bpm: f32) {
+    fn render_time_labels(&self, painter: &Painter, rect: Rect, tempo: &TempoMap) {
         let seconds_step = self.resolution.step_size_secs();
-        let step = self.pixels_per_beat * bpm / 60. * seconds_step;
-        let mut step_index = (self.offset.x / step).floor() as i32;
-        let mut x = rect.left() - self.offset.x.rem_euclid(step);
-
-        while x < rect.right() {
-            // Skip if out of bounds
-            if step_index < 0 {
-                step_index += 1;
-                x += step;
-                continue;
+        let first_beat = self.x_to_beats(rect.left(), rect);
+        let mut step_index = (tempo.beats_to_secs(first_beat) / seconds_step as f64)
+            .floor()
+            .max(0.) as i32;
+
+        loop {
+            let secs = step_index as f64 * seconds_step as f64;
+            let x = self.beats_to_x(tempo.secs_to_beats(secs), rect);
+            if x >= rect.right() {
+                break;
             }
             let time = (step_index as f32 * seconds_step).floor() as i32;
             let seconds = time % 60;
@@ -295,7 +402,6 @@ impl GridService {
                 Stroke::new(2.0, Color32::from_gray(120)),
             );
             step_index += 1;
-            x += step;
         }
     }
 }
@@ -0,0 +1,557 @@
+use crate::{
+    analysis::AudioInfo,
+    core::{
+        midi::note_name,
+        project::{audio_path, optional_audio_path, shared_value},
+    },
+    ui::effect::UIEffect,
+};
+use fundsp::hacker::{Shared, shared};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// Instrument playing the notes of a MIDI track
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum InstrumentParams {
+    Synth(SynthParams),
+    Sampler(SamplerParams),
+    DrumRack(DrumRackParams),
+    /// Events sent to a MIDI output port instead of being played inside
+    External(ExternalParams),
+}
+
+impl Default for InstrumentParams {
+    fn default() -> Self {
+        InstrumentParams::Synth(SynthParams::default())
+    }
+}
+
+impl InstrumentParams {
+    pub fn is_drum_rack(&self) -> bool {
+        matches!(self, InstrumentParams::DrumRack(_))
+    }
+
+    /// Copy of the parameters which can be changed independently
+    pub fn duplicate(&self) -> Self {
+        match self {
+            InstrumentParams::Synth(params) => InstrumentParams::Synth(params.duplicate()),
+            InstrumentParams::Sampler(params) => InstrumentParams::Sampler(params.duplicate()),
+            InstrumentParams::DrumRack(params) => InstrumentParams::DrumRack(params.duplicate()),
+            InstrumentParams::External(params) => InstrumentParams::External(params.duplicate()),
+        }
+    }
+}
+
+/// Waveform of a synth oscillator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 4] = [
+        Waveform::Sine,
+        Waveform::Saw,
+        Waveform::Square,
+        Waveform::Triangle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Waveform::Sine => "Sine",
+            Waveform::Saw => "Saw",
+            Waveform::Square => "Square",
+            Waveform::Triangle => "Triangle",
+        }
+    }
+
+    /// Waveform stored in a shared parameter as its index
+    pub fn from_value(value: f32) -> Self {
+        Self::ALL[(value.round().max(0.) as usize).min(Self::ALL.len() - 1)]
+    }
+
+    pub fn value(&self) -> f32 {
+        Self::ALL.iter().position(|w| w == self).unwrap_or(0) as f32
+    }
+}
+
+/// Times in seconds and sustain level of an envelope
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EnvelopeParams {
+    #[serde(with = "shared_value")]
+    pub attack: Shared,
+    #[serde(with = "shared_value")]
+    pub decay: Shared,
+    #[serde(with = "shared_value")]
+    pub sustain: Shared,
+    #[serde(with = "shared_value")]
+    pub release: Shared,
+}
+
+impl EnvelopeParams {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Self {
+            attack: shared(attack),
+            decay: shared(decay),
+            sustain: shared(sustain),
+            release: shared(release),
+        }
+    }
+
+    pub fn duplicate(&self) -> Self {
+        Self::new(
+            self.attack.value(),
+            self.decay.value(),
+            self.sustain.value(),
+            self.release.value(),
+        )
+    }
+}
+
+/// Parameters of the synth of a MIDI track, shared between its editor and its voices
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SynthParams {
+    /// Waveform index of the first oscillator
+    #[serde(with = "shared_value")]
+    pub osc1_wave: Shared,
+    #[serde(with = "shared_value")]
+    pub osc1_level: Shared,
+    /// Waveform index of the second oscillator
+    #[serde(with = "shared_value")]
+    pub osc2_wave: Shared,
+    #[serde(with = "shared_value")]
+    pub osc2_level: Shared,
+    /// Tuning of the second oscillator in semitones
+    #[serde(with = "shared_value")]
+    pub osc2_detune: Shared,
+    /// Lowpass cutoff in Hz
+    #[serde(with = "shared_value")]
+    pub cutoff: Shared,
+    /// Resonance between 0 and 1
+    #[serde(with = "shared_value")]
+    pub resonance: Shared,
+    /// Octaves added to the cutoff by the filter envelope
+    #[serde(with = "shared_value")]
+    pub filter_amount: Shared,
+    pub filter_envelope: EnvelopeParams,
+    pub amp_envelope: EnvelopeParams,
+    /// How much the velocity changes the level, between 0 and 1
+    #[serde(with = "shared_value")]
+    pub velocity: Shared,
+    /// Semitones of a full pitch bend
+    #[serde(with = "shared_value")]
+    pub bend_range: Shared,
+    /// Vibrato in semitones with the mod wheel fully up
+    #[serde(with = "shared_value")]
+    pub vibrato: Shared,
+    /// Notes played at the same time
+    #[serde(with = "shared_value")]
+    pub polyphony: Shared,
+    #[serde(with = "shared_value")]
+    pub volume: Shared,
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        Self {
+            osc1_wave: shared(Waveform::Saw.value()),
+            osc1_level: shared(0.8),
+            osc2_wave: shared(Waveform::Square.value()),
+            osc2_level: shared(0.),
+            osc2_detune: shared(0.),
+            cutoff: shared(2000.),
+            resonance: shared(0.2),
+            filter_amount: shared(2.),
+            filter_envelope: EnvelopeParams::new(0.01, 0.3, 0.3, 0.3),
+            amp_envelope: EnvelopeParams::new(0.005, 0.2, 0.7, 0.2),
+            velocity: shared(0.8),
+            bend_range: shared(2.),
+            vibrato: shared(0.5),
+            polyphony: shared(16.),
+            volume: shared(0.5),
+        }
+    }
+}
+
+impl SynthParams {
+    /// Copy of the parameters which can be changed independently
+    pub fn duplicate(&self) -> Self {
+        Self {
+            osc1_wave: shared(self.osc1_wave.value()),
+            osc1_level: shared(self.osc1_level.value()),
+            osc2_wave: shared(self.osc2_wave.value()),
+            osc2_level: shared(self.osc2_level.value()),
+            osc2_detune: shared(self.osc2_detune.value()),
+            cutoff: shared(self.cutoff.value()),
+            resonance: shared(self.resonance.value()),
+            filter_amount: shared(self.filter_amount.value()),
+            filter_envelope: self.filter_envelope.duplicate(),
+            amp_envelope: self.amp_envelope.duplicate(),
+            velocity: shared(self.velocity.value()),
+            bend_range: shared(self.bend_range.value()),
+            vibrato: shared(self.vibrato.value()),
+            polyphony: shared(self.polyphony.value()),
+            volume: shared(self.volume.value()),
+        }
+    }
+}
+
+impl Debug for SynthParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SynthParams")
+            .field("osc1_wave", &Waveform::from_value(self.osc1_wave.value()))
+            .field("osc2_wave", &Waveform::from_value(self.osc2_wave.value()))
+            .field("cutoff", &self.cutoff.value())
+            .field("resonance", &self.resonance.value())
+            .field("volume", &self.volume.value())
+            .finish()
+    }
+}
+
+/// Playback of the samples of a sampler
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleMode {
+    /// The whole sample is played, note offs are ignored
+    OneShot,
+    /// The loop is repeated until the end of the release
+    Loop,
+}
+
+impl SampleMode {
+    pub const ALL: [SampleMode; 2] = [SampleMode::OneShot, SampleMode::Loop];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleMode::OneShot => "One-shot",
+            SampleMode::Loop => "Loop",
+        }
+    }
+
+    /// Mode stored in a shared parameter as its index
+    pub fn from_value(value: f32) -> Self {
+        Self::ALL[(value.round().max(0.) as usize).min(Self::ALL.len() - 1)]
+    }
+
+    pub fn value(&self) -> f32 {
+        Self::ALL.iter().position(|m| m == self).unwrap_or(0) as f32
+    }
+}
+
+/// Sample played up to a velocity
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SampleLayer {
+    #[serde(with = "audio_path")]
+    pub audio: AudioInfo,
+    /// Highest velocity playing this layer, the layer above starts after it
+    pub max_velocity: u8,
+}
+
+/// Parameters of a sampler mapping its samples across the keyboard
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SamplerParams {
+    /// Velocity layers sorted by `max_velocity`
+    pub layers: Vec<SampleLayer>,
+    /// Key playing the samples at their original pitch
+    #[serde(with = "shared_value")]
+    pub root: Shared,
+    /// Tuning in semitones
+    #[serde(with = "shared_value")]
+    pub tune: Shared,
+    /// `SampleMode` index
+    #[serde(with = "shared_value")]
+    pub mode: Shared,
+    /// Loop points as ratios of the length of the samples
+    #[serde(with = "shared_value")]
+    pub loop_start: Shared,
+    #[serde(with = "shared_value")]
+    pub loop_end: Shared,
+    pub amp_envelope: EnvelopeParams,
+    /// How much the velocity changes the level, between 0 and 1
+    #[serde(with = "shared_value")]
+    pub velocity: Shared,
+    /// Semitones of a full pitch bend
+    #[serde(with = "shared_value")]
+    pub bend_range: Shared,
+    /// Notes played at the same time
+    #[serde(with = "shared_value")]
+    pub polyphony: Shared,
+    #[serde(with = "shared_value")]
+    pub volume: Shared,
+}
+
+impl SamplerParams {
+    /// Sampler playing `audio` at its pitch on C3
+    pub fn new(audio: AudioInfo) -> Self {
+        Self {
+            layers: vec![SampleLayer {
+                audio,
+                max_velocity: 127,
+            }],
+            root: shared(60.),
+            tune: shared(0.),
+            mode: shared(SampleMode::OneShot.value()),
+            loop_start: shared(0.),
+            loop_end: shared(1.),
+            amp_envelope: EnvelopeParams::new(0.001, 0.1, 1., 0.1),
+            velocity: shared(0.8),
+            bend_range: shared(2.),
+            polyphony: shared(16.),
+            volume: shared(0.8),
+        }
+    }
+
+    /// Add a layer above the existing ones and spread the velocities evenly
+    pub fn add_layer(&mut self, audio: AudioInfo) {
+        self.layers.push(SampleLayer {
+            audio,
+            max_velocity: 127,
+        });
+        let count = self.layers.len();
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            layer.max_velocity = (127 * (i + 1) / count) as u8;
+        }
+    }
+
+    /// Layer played by a note of `velocity`
+    pub fn layer_index(&self, velocity: u8) -> Option<usize> {
+        if self.layers.is_empty() {
+            return None;
+        }
+        Some(
+            self.layers
+                .iter()
+                .position(|layer| velocity <= layer.max_velocity)
+                .unwrap_or(self.layers.len() - 1),
+        )
+    }
+
+    /// Copy of the parameters which can be changed independently
+    pub fn duplicate(&self) -> Self {
+        Self {
+            layers: self.layers.clone(),
+            root: shared(self.root.value()),
+            tune: shared(self.tune.value()),
+            mode: shared(self.mode.value()),
+            loop_start: shared(self.loop_start.value()),
+            loop_end: shared(self.loop_end.value()),
+            amp_envelope: self.amp_envelope.duplicate(),
+            velocity: shared(self.velocity.value()),
+            bend_range: shared(self.bend_range.value()),
+            polyphony: shared(self.polyphony.value()),
+            volume: shared(self.volume.value()),
+        }
+    }
+}
+
+impl Debug for SamplerParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SamplerParams")
+            .field(
+                "layers",
+                &self
+                    .layers
+                    .iter()
+                    .map(|layer| (&layer.audio.name, layer.max_velocity))
+                    .collect::<Vec<_>>(),
+            )
+            .field("root", &self.root.value())
+            .field("mode", &SampleMode::from_value(self.mode.value()))
+            .field("volume", &self.volume.value())
+            .finish()
+    }
+}
+
+/// Pads of a new drum rack
+pub const DEFAULT_DRUM_PADS: usize = 16;
+/// Pads added at once to a drum rack
+pub const DRUM_PAD_ROW: usize = 4;
+/// Key of the first pad, C1
+const FIRST_PAD_KEY: u8 = 36;
+/// Choke groups of a drum rack, group 0 doesn't choke
+pub const CHOKE_GROUPS: usize = 8;
+
+/// Pad of a drum rack playing its sample when its key is hit
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DrumPad {
+    pub key: u8,
+    #[serde(with = "optional_audio_path")]
+    pub sample: Option<AudioInfo>,
+    #[serde(with = "shared_value")]
+    pub volume: Shared,
+    /// Between -1 (left) and 1 (right)
+    #[serde(with = "shared_value")]
+    pub pan: Shared,
+    /// Tuning in semitones
+    #[serde(with = "shared_value")]
+    pub tune: Shared,
+    /// Pads of the same choke group stop each other, 0 for none
+    #[serde(with = "shared_value")]
+    pub choke: Shared,
+    /// Effects applied to the pad only
+    pub effects: Vec<UIEffect>,
+}
+
+impl DrumPad {
+    pub fn new(key: u8) -> Self {
+        Self {
+            key,
+            sample: None,
+            volume: shared(0.8),
+            pan: shared(0.),
+            tune: shared(0.),
+            choke: shared(0.),
+            effects: vec![],
+        }
+    }
+
+    /// Name of the sample, else of the key
+    pub fn name(&self) -> String {
+        self.sample
+            .as_ref()
+            .map_or_else(|| note_name(self.key), |sample| sample.name.clone())
+    }
+
+    /// Choke group of the pad, `None` when it doesn't choke
+    pub fn choke_group(&self) -> Option<usize> {
+        let group = (self.choke.value().round().max(0.) as usize).min(CHOKE_GROUPS);
+        (group > 0).then_some(group)
+    }
+
+    fn duplicate(&self) -> Self {
+        Self {
+            key: self.key,
+            sample: self.sample.clone(),
+            volume: shared(self.volume.value()),
+            pan: shared(self.pan.value()),
+            tune: shared(self.tune.value()),
+            choke: shared(self.choke.value()),
+            effects: self.effects.clone(),
+        }
+    }
+}
+
+/// Parameters of a drum rack, one pad for each key
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DrumRackParams {
+    /// Pads on consecutive keys from C1
+    pub pads: Vec<DrumPad>,
+    /// How much the velocity changes the level, between 0 and 1
+    #[serde(with = "shared_value")]
+    pub velocity: Shared,
+    #[serde(with = "shared_value")]
+    pub volume: Shared,
+}
+
+impl Default for DrumRackParams {
+    fn default() -> Self {
+        Self {
+            pads: (0..DEFAULT_DRUM_PADS)
+                .map(|i| DrumPad::new(FIRST_PAD_KEY + i as u8))
+                .collect(),
+            velocity: shared(0.8),
+            volume: shared(0.8),
+        }
+    }
+}
+
+impl DrumRackParams {
+    /// Add a row of pads after the last one, up to the last key
+    pub fn add_pads(&mut self) {
+        let next = self.pads.last().map_or(FIRST_PAD_KEY, |pad| pad.key + 1);
+        self.pads
+            .extend((next..=127).take(DRUM_PAD_ROW).map(DrumPad::new));
+    }
+
+    /// Pad triggered by `key`
+    pub fn pad_index(&self, key: u8) -> Option<usize> {
+        self.pads.iter().position(|pad| pad.key == key)
+    }
+
+    /// Copy of the parameters which can be changed independently
+    pub fn duplicate(&self) -> Self {
+        Self {
+            pads: self.pads.iter().map(|pad| pad.duplicate()).collect(),
+            velocity: shared(self.velocity.value()),
+            volume: shared(self.volume.value()),
+        }
+    }
+}
+
+impl Debug for DrumRackParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DrumRackParams")
+            .field(
+                "pads",
+                &self
+                    .pads
+                    .iter()
+                    .map(|pad| (pad.key, pad.name(), pad.effects.len()))
+                    .collect::<Vec<_>>(),
+            )
+            .field("volume", &self.volume.value())
+            .finish()
+    }
+}
+
+/// Output of a MIDI track playing an external instrument through a MIDI port
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExternalParams {
+    /// Name of the output port, nothing is sent when `None`
+    pub port: Option<String>,
+    /// Channel of the events, from 0 to 15
+    #[serde(with = "shared_value")]
+    pub channel: Shared,
+    /// Program selected when the transport starts, none when negative
+    #[serde(with = "shared_value")]
+    pub program: Shared,
+    /// Seconds added to the time of the events, negative to send them before the audio
+    /// to compensate for the latency of the instrument
+    #[serde(with = "shared_value")]
+    pub delay: Shared,
+}
+
+impl Default for ExternalParams {
+    fn default() -> Self {
+        Self {
+            port: None,
+            channel: shared(0.),
+            program: shared(-1.),
+            delay: shared(0.),
+        }
+    }
+}
+
+impl ExternalParams {
+    pub fn channel(&self) -> u8 {
+        self.channel.value().round().clamp(0., 15.) as u8
+    }
+
+    pub fn program(&self) -> Option<u8> {
+        let program = self.program.value().round();
+        (program >= 0.).then(|| program.min(127.) as u8)
+    }
+
+    /// Copy of the parameters which can be changed independently
+    pub fn duplicate(&self) -> Self {
+        Self {
+            port: self.port.clone(),
+            channel: shared(self.channel.value()),
+            program: shared(self.program.value()),
+            delay: shared(self.delay.value()),
+        }
+    }
+}
+
+impl Debug for ExternalParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExternalParams")
+            .field("port", &self.port)
+            .field("channel", &self.channel())
+            .field("program", &self.program())
+            .field("delay", &self.delay.value())
+            .finish()
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::message::MidiToPlayerMsg;
+
+/// Octave of the lowest key, key 60 being C3
+const DEFAULT_OCTAVE: i8 = 3;
+const MIN_OCTAVE: i8 = -2;
+const MAX_OCTAVE: i8 = 8;
+const DEFAULT_VELOCITY: u8 = 100;
+/// Velocity added or removed by the velocity keys
+const VELOCITY_STEP: u8 = 20;
+
+/// Computer keyboard played as a MIDI keyboard on the armed MIDI tracks
+#[derive(Debug)]
+pub struct VirtualKeyboard {
+    /// Whether the keys play notes instead of being left to the shortcuts
+    pub active: bool,
+    octave: i8,
+    velocity: u8,
+    /// Semitones of the keys held down with the key of the note they play
+    held: Vec<(u8, u8)>,
+}
+
+impl Default for VirtualKeyboard {
+    fn default() -> Self {
+        Self {
+            active: false,
+            octave: DEFAULT_OCTAVE,
+            velocity: DEFAULT_VELOCITY,
+            held: Vec::new(),
+        }
+    }
+}
+
+impl VirtualKeyboard {
+    pub fn octave(&self) -> i8 {
+        self.octave
+    }
+
+    pub fn velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    /// Note on of the key `semitone` above the lowest key, `None` when already held or out of range
+    pub fn press(&mut self, semitone: u8) -> Option<MidiToPlayerMsg> {
+        if self.held.iter().any(|(held, _)| *held == semitone) {
+            return None;
+        }
+        let key = (self.octave as i16 + 2) * 12 + semitone as i16;
+        let key = u8::try_from(key).ok().filter(|key| *key <= 127)?;
+        self.held.push((semitone, key));
+        Some(MidiToPlayerMsg::NoteOn {
+            channel: 0,
+            key,
+            velocity: self.velocity,
+        })
+    }
+
+    /// Note off of the key `semitone`, releasing the note it played before an octave change
+    pub fn release(&mut self, semitone: u8) -> Option<MidiToPlayerMsg> {
+        let index = self.held.iter().position(|(held, _)| *held == semitone)?;
+        let (_, key) = self.held.remove(index);
+        Some(note_off(key))
+    }
+
+    /// Note offs of every held key
+    pub fn release_all(&mut self) -> Vec<MidiToPlayerMsg> {
+        self.held.drain(..).map(|(_, key)| note_off(key)).collect()
+    }
+
+    /// Move the keys by `octaves`, the held notes playing until released
+    pub fn shift_octave(&mut self, octaves: i8) {
+        self.octave = (self.octave + octaves).clamp(MIN_OCTAVE, MAX_OCTAVE);
+    }
+
+    /// Raise or lower the velocity of the next notes by a step
+    pub fn shift_velocity(&mut self, up: bool) {
+        self.velocity = if up {
+            if self.velocity == 1 {
+                VELOCITY_STEP
+            } else {
+                self.velocity.saturating_add(VELOCITY_STEP).min(127)
+            }
+        } else {
+            self.velocity.saturating_sub(VELOCITY_STEP).max(1)
+        };
+    }
+}
+
+fn note_off(key: u8) -> MidiToPlayerMsg {
+    MidiToPlayerMsg::NoteOff {
+        channel: 0,
+        key,
+        velocity: 0,
+    }
+}
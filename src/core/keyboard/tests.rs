@@ -0,0 +1,45 @@
+use crate::core::{keyboard::VirtualKeyboard, message::MidiToPlayerMsg};
+
+#[test]
+fn test_keyboard_octave_and_velocity() {
+    let mut keyboard = VirtualKeyboard::default();
+    assert_eq!(
+        keyboard.press(0),
+        Some(MidiToPlayerMsg::NoteOn {
+            channel: 0,
+            key: 60,
+            velocity: 100,
+        })
+    );
+    // A held key is not played again by the key repeat
+    assert_eq!(keyboard.press(0), None);
+    // A key held across an octave change releases the note it played
+    keyboard.shift_octave(1);
+    keyboard.shift_velocity(false);
+    assert_eq!(
+        keyboard.press(4),
+        Some(MidiToPlayerMsg::NoteOn {
+            channel: 0,
+            key: 76,
+            velocity: 80,
+        })
+    );
+    assert_eq!(
+        keyboard.release(0),
+        Some(MidiToPlayerMsg::NoteOff {
+            channel: 0,
+            key: 60,
+            velocity: 0,
+        })
+    );
+    assert_eq!(keyboard.release(0), None);
+    assert_eq!(keyboard.release_all().len(), 1);
+    // Keys out of the MIDI range play nothing
+    keyboard.shift_octave(10);
+    assert_eq!(keyboard.octave(), 8);
+    assert_eq!(keyboard.press(16), None);
+    for _ in 0..10 {
+        keyboard.shift_velocity(false);
+    }
+    assert_eq!(keyboard.velocity(), 1);
+}
@@ -1,7 +1,25 @@
-use crate::core::{clip::ClipCore, metrics::GlobalMetrics};
+#[cfg(test)]
+mod tests;
+
+use crate::core::{
+    clip::ClipCore,
+    instrument::InstrumentParams,
+    meter::Meter,
+    metrics::GlobalMetrics,
+    metronome::MetronomeSettings,
+    midi::MidiClipCore,
+    recording::{PEAKS_PER_MESSAGE, TrackRecorder},
+    sync::{SyncMsg, SyncSettings},
+    tempo::TempoMap,
+    track::{MonitorMode, TrackInput},
+};
 use fundsp::hacker::AudioUnit;
+use midly::{
+    MidiMessage,
+    live::{LiveEvent, SystemCommon, SystemRealtime},
+};
 use rtrb::{Consumer, Producer};
-use std::{collections::HashMap, fmt::Debug, path::PathBuf};
+use std::{collections::HashMap, fmt::Debug, path::PathBuf, sync::Arc, time::Instant};
 
 pub type GuiToAudioTx = Producer<GuiToPlayerMsg>;
 pub type AudioToGuiRx = Consumer<ProcessToGuiMsg>;
@@ -10,18 +28,34 @@ pub enum GuiToPlayerMsg {
     // Playback control messages
     Play,
     Pause,
+    /// Start recording into the files of the armed tracks, starting the transport when paused
+    StartRecording(Vec<TrackRecorder>),
+    StopRecording,
     SeekTo(f32),
+    /// Start and end beats of the loop, `None` to play through
+    SetLoop(Option<(f32, f32)>),
     PlayPreview(PathBuf),
     PausePreview(),
     SeekPreview(usize),
 
-    UpdateBPM(f32),
+    /// Built on the GUI thread so that the player shares it without copying
+    UpdateTempo(Arc<TempoMap>),
+    UpdateMeter(Meter),
     // Track messages
     AddTrack(String),
+    /// Add a MIDI track playing its clips with an instrument
+    AddMidiTrack(String, InstrumentParams),
+    /// Replace the instrument of a MIDI track
+    SetInstrument(String, InstrumentParams),
+    /// Play an event on the instrument of a MIDI track, to audition notes
+    PlayMidi(String, MidiToPlayerMsg),
+    /// Event of the computer keyboard, played and recorded as a MIDI input
+    KeyboardMidi(MidiToPlayerMsg),
     RemoveTrack(String),
     MuteTrack(String, bool),
     SoloTracks(Vec<String>),
     ChangeTrackVolume(String, f32),
+    SetTrackMonitor(String, MonitorMode, bool, TrackInput), // track_id, mode, armed, input
 
     // Effect messages
     AddNode(String, usize, String, Box<dyn AudioUnit>), // track_id, index, node, node_id
@@ -29,7 +63,6 @@ pub enum GuiToPlayerMsg {
     SetNodeEnabled(String, String, bool),               // track_id, node_id, enabled
 
     // Clip messages
-    AddClip(String, PathBuf, f32, String, f32, f32), // (track_id, file_path, start_position, clip_id, trim_start, trim_end)
     AddClips(HashMap<String, Vec<ClipCore>>),
     RemoveClip(Vec<String>),           // Vec<clip id>
     MoveClip(String, String, f32),     // clip id, track id, position
@@ -38,6 +71,10 @@ pub enum GuiToPlayerMsg {
         track_id: String,
         clips: HashMap<String, (f32, f32)>,
     },
+    /// Replace the settings of a clip identified by its id
+    UpdateClip(ClipCore),
+    /// Replace the clips of a MIDI track
+    SetMidiClips(String, Vec<MidiClipCore>),
     DuplicateTrack {
         /// Track id to duplicate
         id: String,
@@ -48,12 +85,232 @@ pub enum GuiToPlayerMsg {
     },
     // Metronome
     ToggleMetronome(bool),
+    UpdateMetronome(MetronomeSettings),
+    /// Send the MIDI clock and follow an external transport
+    SetSync(SyncSettings),
 }
 
 pub enum ProcessToGuiMsg {
     PlaybackPos(f32),
     PreviewPos(usize),
     Metrics(GlobalMetrics),
+    /// Number of channels of the output and input devices and the sample rate
+    DeviceConfig {
+        output_channels: usize,
+        input_channels: usize,
+        sample_rate: usize,
+        /// Rate of the input device, its audio is only used when it matches `sample_rate`
+        input_sample_rate: Option<usize>,
+    },
+    /// Beat where the recorded files start and the input and output latency in seconds
+    RecordingStarted(f32, f32),
+    /// Peaks of the last recorded block of the recorders from the index given,
+    /// in the order of `StartRecording`
+    RecordingPeaks(usize, [f32; PEAKS_PER_MESSAGE]),
+    /// Recorders given back once their files are closing
+    RecordingStopped(Vec<TrackRecorder>),
+    /// MIDI input event received while recording, `offset` frames after the playhead at `beat`
+    RecordedMidi {
+        beat: f32,
+        offset: usize,
+        event: MidiToPlayerMsg,
+    },
+    /// Control change of the MIDI inputs, driving the learned controls
+    MidiControl(MidiToPlayerMsg),
+    /// Play state of the external transport followed, with the tempo of an external clock
+    ExternalTransport {
+        playing: bool,
+        bpm: Option<f32>,
+    },
+}
+
+/// MIDI input event with the time it was received, to follow an external clock
+#[derive(Debug, Clone, Copy)]
+pub struct MidiInputEvent {
+    pub time: Instant,
+    pub msg: MidiToPlayerMsg,
+}
+
+/// Message sent by the player to a MIDI output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiOutputMsg {
+    Sync(SyncMsg),
+    Channel { channel: u8, message: MidiMessage },
+}
+
+impl MidiOutputMsg {
+    pub fn bytes(&self) -> Vec<u8> {
+        match *self {
+            Self::Sync(msg) => msg.bytes(),
+            Self::Channel { channel, message } => {
+                let mut bytes = Vec::with_capacity(3);
+                let event = LiveEvent::Midi {
+                    channel: channel.into(),
+                    message,
+                };
+                let _ = event.write_std(&mut bytes);
+                bytes
+            }
+        }
+    }
+}
+
+/// MIDI message to send at `due` to `port`, or to the sync output when `None`
+#[derive(Debug, Clone)]
+pub struct MidiOutputEvent {
+    pub due: Instant,
+    pub port: Option<Arc<str>>,
+    pub msg: MidiOutputMsg,
+}
+
+/// Events of the enabled MIDI inputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiToPlayerMsg {
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        key: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// Bend between -8192 and 8191
+    PitchBend {
+        channel: u8,
+        bend: i16,
+    },
+    /// Sixteenth notes since the start of the song
+    SongPosition(u16),
+    /// MIDI time code quarter frame, `piece` from 0 to 7 carrying 4 bits of the time
+    TimeCode {
+        piece: u8,
+        value: u8,
+    },
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl MidiToPlayerMsg {
+    /// Convert a parsed event, `None` for the events not handled by the player
+    pub fn from_live(event: &LiveEvent) -> Option<Self> {
+        match *event {
+            LiveEvent::Midi { channel, message } => {
+                let channel = channel.as_int();
+                Some(match message {
+                    MidiMessage::NoteOn { key, vel } if vel == 0 => Self::NoteOff {
+                        channel,
+                        key: key.as_int(),
+                        velocity: 0,
+                    },
+                    MidiMessage::NoteOn { key, vel } => Self::NoteOn {
+                        channel,
+                        key: key.as_int(),
+                        velocity: vel.as_int(),
+                    },
+                    MidiMessage::NoteOff { key, vel } => Self::NoteOff {
+                        channel,
+                        key: key.as_int(),
+                        velocity: vel.as_int(),
+                    },
+                    MidiMessage::Aftertouch { key, vel } => Self::PolyPressure {
+                        channel,
+                        key: key.as_int(),
+                        pressure: vel.as_int(),
+                    },
+                    MidiMessage::Controller { controller, value } => Self::ControlChange {
+                        channel,
+                        controller: controller.as_int(),
+                        value: value.as_int(),
+                    },
+                    MidiMessage::ProgramChange { program } => Self::ProgramChange {
+                        channel,
+                        program: program.as_int(),
+                    },
+                    MidiMessage::ChannelAftertouch { vel } => Self::ChannelPressure {
+                        channel,
+                        pressure: vel.as_int(),
+                    },
+                    MidiMessage::PitchBend { bend } => Self::PitchBend {
+                        channel,
+                        bend: bend.as_int(),
+                    },
+                })
+            }
+            LiveEvent::Common(SystemCommon::SongPosition(position)) => {
+                Some(Self::SongPosition(position.as_int()))
+            }
+            LiveEvent::Common(SystemCommon::MidiTimeCodeQuarterFrame(piece, value)) => {
+                Some(Self::TimeCode {
+                    piece: piece as u8,
+                    value: value.as_int(),
+                })
+            }
+            LiveEvent::Common(_) => None,
+            LiveEvent::Realtime(realtime) => match realtime {
+                SystemRealtime::TimingClock => Some(Self::Clock),
+                SystemRealtime::Start => Some(Self::Start),
+                SystemRealtime::Continue => Some(Self::Continue),
+                SystemRealtime::Stop => Some(Self::Stop),
+                _ => None,
+            },
+        }
+    }
+
+    /// Channel message played by instruments, `None` for the system events
+    pub fn message(&self) -> Option<MidiMessage> {
+        Some(match *self {
+            Self::NoteOn { key, velocity, .. } => MidiMessage::NoteOn {
+                key: key.into(),
+                vel: velocity.into(),
+            },
+            Self::NoteOff { key, velocity, .. } => MidiMessage::NoteOff {
+                key: key.into(),
+                vel: velocity.into(),
+            },
+            Self::PolyPressure { key, pressure, .. } => MidiMessage::Aftertouch {
+                key: key.into(),
+                vel: pressure.into(),
+            },
+            Self::ControlChange {
+                controller, value, ..
+            } => MidiMessage::Controller {
+                controller: controller.into(),
+                value: value.into(),
+            },
+            Self::ProgramChange { program, .. } => MidiMessage::ProgramChange {
+                program: program.into(),
+            },
+            Self::ChannelPressure { pressure, .. } => MidiMessage::ChannelAftertouch {
+                vel: pressure.into(),
+            },
+            Self::PitchBend { bend, .. } => MidiMessage::PitchBend {
+                bend: midly::PitchBend::from_int(bend),
+            },
+            _ => return None,
+        })
+    }
 }
 
 impl Debug for GuiToPlayerMsg {
@@ -61,12 +318,30 @@ impl Debug for GuiToPlayerMsg {
         match self {
             Self::Play => write!(f, "Play"),
             Self::Pause => write!(f, "Pause"),
+            Self::StartRecording(arg0) => f.debug_tuple("StartRecording").field(arg0).finish(),
+            Self::StopRecording => write!(f, "StopRecording"),
             Self::SeekTo(arg0) => f.debug_tuple("SeekTo").field(arg0).finish(),
+            Self::SetLoop(arg0) => f.debug_tuple("SetLoop").field(arg0).finish(),
             Self::PlayPreview(arg0) => f.debug_tuple("PlayPreview").field(arg0).finish(),
             Self::PausePreview() => f.debug_tuple("PausePreview").finish(),
             Self::SeekPreview(arg0) => f.debug_tuple("SeekPreview").field(arg0).finish(),
-            Self::UpdateBPM(arg0) => f.debug_tuple("UpdateBPM").field(arg0).finish(),
+            Self::UpdateTempo(arg0) => f.debug_tuple("UpdateTempo").field(arg0).finish(),
+            Self::UpdateMeter(arg0) => f.debug_tuple("UpdateMeter").field(arg0).finish(),
             Self::AddTrack(arg0) => f.debug_tuple("AddTrack").field(arg0).finish(),
+            Self::AddMidiTrack(arg0, arg1) => f
+                .debug_tuple("AddMidiTrack")
+                .field(arg0)
+                .field(arg1)
+                .finish(),
+            Self::SetInstrument(arg0, arg1) => f
+                .debug_tuple("SetInstrument")
+                .field(arg0)
+                .field(arg1)
+                .finish(),
+            Self::PlayMidi(arg0, arg1) => {
+                f.debug_tuple("PlayMidi").field(arg0).field(arg1).finish()
+            }
+            Self::KeyboardMidi(arg0) => f.debug_tuple("KeyboardMidi").field(arg0).finish(),
             Self::RemoveTrack(arg0) => f.debug_tuple("RemoveTrack").field(arg0).finish(),
             Self::MuteTrack(arg0, arg1) => {
                 f.debug_tuple("MuteTrack").field(arg0).field(arg1).finish()
@@ -77,6 +352,13 @@ impl Debug for GuiToPlayerMsg {
                 .field(arg0)
                 .field(arg1)
                 .finish(),
+            Self::SetTrackMonitor(arg0, arg1, arg2, arg3) => f
+                .debug_tuple("SetTrackMonitor")
+                .field(arg0)
+                .field(arg1)
+                .field(arg2)
+                .field(arg3)
+                .finish(),
             Self::AddNode(arg0, arg1, arg2, _) => f
                 .debug_tuple("AddNode")
                 .field(arg0)
@@ -92,15 +374,6 @@ impl Debug for GuiToPlayerMsg {
                 .field(arg1)
                 .field(arg2)
                 .finish(),
-            Self::AddClip(arg0, arg1, arg2, arg3, arg4, arg5) => f
-                .debug_tuple("AddClip")
-                .field(arg0)
-                .field(arg1)
-                .field(arg2)
-                .field(arg3)
-                .field(arg4)
-                .field(arg5)
-                .finish(),
             Self::AddClips(arg0) => f.debug_tuple("AddClips").field(arg0).finish(),
             Self::RemoveClip(arg0) => f.debug_tuple("RemoveClip").field(arg0).finish(),
             Self::MoveClip(arg0, arg1, arg2) => f
@@ -121,6 +394,12 @@ impl Debug for GuiToPlayerMsg {
                 .field("track_id", track_id)
                 .field("clips", clips)
                 .finish(),
+            Self::UpdateClip(arg0) => f.debug_tuple("UpdateClip").field(arg0).finish(),
+            Self::SetMidiClips(arg0, arg1) => f
+                .debug_tuple("SetMidiClips")
+                .field(arg0)
+                .field(arg1)
+                .finish(),
             Self::DuplicateTrack {
                 id,
                 new_id,
@@ -132,6 +411,8 @@ impl Debug for GuiToPlayerMsg {
                 .field("clip_map", clip_map)
                 .finish(),
             Self::ToggleMetronome(val) => f.debug_tuple("ToggleMetronome").field(val).finish(),
+            Self::UpdateMetronome(arg0) => f.debug_tuple("UpdateMetronome").field(arg0).finish(),
+            Self::SetSync(arg0) => f.debug_tuple("SetSync").field(arg0).finish(),
         }
     }
 }
@@ -0,0 +1,54 @@
+use crate::core::{
+    message::{MidiOutputMsg, MidiToPlayerMsg},
+    sync::SyncMsg,
+};
+use midly::{MidiMessage, live::LiveEvent};
+
+#[test]
+fn test_midi_from_live() {
+    let parse = |bytes: &[u8]| MidiToPlayerMsg::from_live(&LiveEvent::parse(bytes).unwrap());
+
+    assert_eq!(
+        parse(&[0x91, 60, 100]),
+        Some(MidiToPlayerMsg::NoteOn {
+            channel: 1,
+            key: 60,
+            velocity: 100
+        })
+    );
+    // A note on without velocity releases the note
+    assert_eq!(
+        parse(&[0x90, 60, 0]),
+        Some(MidiToPlayerMsg::NoteOff {
+            channel: 0,
+            key: 60,
+            velocity: 0
+        })
+    );
+    assert_eq!(
+        parse(&[0xE0, 0x00, 0x40]),
+        Some(MidiToPlayerMsg::PitchBend {
+            channel: 0,
+            bend: 0
+        })
+    );
+    assert_eq!(parse(&[0xF8]), Some(MidiToPlayerMsg::Clock));
+    assert_eq!(
+        parse(&[0xF1, 0x73]),
+        Some(MidiToPlayerMsg::TimeCode { piece: 7, value: 3 })
+    );
+    assert_eq!(parse(&[0xFE]), None);
+}
+
+#[test]
+fn test_midi_output_bytes() {
+    let note = MidiOutputMsg::Channel {
+        channel: 2,
+        message: MidiMessage::NoteOn {
+            key: 60.into(),
+            vel: 100.into(),
+        },
+    };
+    assert_eq!(note.bytes(), vec![0x92, 60, 100]);
+    assert_eq!(MidiOutputMsg::Sync(SyncMsg::Stop).bytes(), vec![0xFC]);
+}
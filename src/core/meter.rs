@@ -0,0 +1,204 @@
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of beats of a bar and their note value
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub numerator: u32,
+    /// Note value of a beat, 4 for a quarter note
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    /// Signatures offered in the menus
+    pub const COMMON: [TimeSignature; 9] = [
+        TimeSignature::new(2, 4),
+        TimeSignature::new(3, 4),
+        TimeSignature::new(4, 4),
+        TimeSignature::new(5, 4),
+        TimeSignature::new(6, 8),
+        TimeSignature::new(7, 8),
+        TimeSignature::new(9, 8),
+        TimeSignature::new(12, 8),
+        TimeSignature::new(2, 2),
+    ];
+
+    pub const fn new(numerator: u32, denominator: u32) -> Self {
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.numerator, self.denominator)
+    }
+
+    /// Length of a beat of the signature in quarter notes, the beats of the timeline
+    pub fn beat_length(&self) -> f32 {
+        4. / self.denominator as f32
+    }
+
+    /// Length of a bar in quarter notes
+    pub fn bar_length(&self) -> f32 {
+        self.numerator as f32 * self.beat_length()
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self::new(4, 4)
+    }
+}
+
+/// Time signature starting at a bar
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MeterChange {
+    /// Index of the bar from 0
+    pub bar: i32,
+    pub signature: TimeSignature,
+}
+
+/// Position of a beat in the bars
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BarPosition {
+    /// Index of the bar from 0
+    pub bar: i32,
+    /// Beat of the timeline at the start of the bar
+    pub start: f32,
+    pub signature: TimeSignature,
+}
+
+/// Time signatures of the project. The first change sits at bar 0 and its signature is kept
+/// before it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "Vec<MeterChange>", from = "Vec<MeterChange>")]
+pub struct Meter {
+    /// Changes sorted by bar
+    changes: Vec<MeterChange>,
+    /// Beat of the timeline at each change
+    beats: Vec<f32>,
+}
+
+impl Meter {
+    pub fn new(signature: TimeSignature) -> Self {
+        Self {
+            changes: vec![MeterChange { bar: 0, signature }],
+            beats: vec![0.],
+        }
+    }
+
+    pub fn changes(&self) -> &[MeterChange] {
+        &self.changes
+    }
+
+    /// Beat of the timeline at the start of each change
+    pub fn change_beats(&self) -> &[f32] {
+        &self.beats
+    }
+
+    /// Index of the change active at `bar`
+    fn change_at_bar(&self, bar: i32) -> usize {
+        self.changes
+            .partition_point(|change| change.bar <= bar)
+            .saturating_sub(1)
+    }
+
+    /// Signature of `bar`
+    pub fn signature_at_bar(&self, bar: i32) -> TimeSignature {
+        self.changes[self.change_at_bar(bar)].signature
+    }
+
+    /// Beat of the timeline at the start of `bar`
+    pub fn bar_start(&self, bar: i32) -> f32 {
+        let index = self.change_at_bar(bar);
+        let change = self.changes[index];
+        self.beats[index] + (bar - change.bar) as f32 * change.signature.bar_length()
+    }
+
+    /// Bar containing `beat`
+    pub fn bar_at(&self, beat: f32) -> BarPosition {
+        let index = self
+            .beats
+            .partition_point(|start| *start <= beat)
+            .saturating_sub(1);
+        let change = self.changes[index];
+        let length = change.signature.bar_length();
+        let bar = change.bar + ((beat - self.beats[index]) / length).floor() as i32;
+        BarPosition {
+            bar,
+            start: self.bar_start(bar),
+            signature: change.signature,
+        }
+    }
+
+    /// First beat of the signature at or after `beat`, with whether it starts a bar
+    pub fn next_beat(&self, beat: f32) -> (f32, bool) {
+        let position = self.bar_at(beat);
+        let beat_length = position.signature.beat_length();
+        let index = ((beat - position.start) / beat_length - 1e-4)
+            .ceil()
+            .max(0.) as u32;
+        if index >= position.signature.numerator {
+            (self.bar_start(position.bar + 1), true)
+        } else {
+            (position.start + index as f32 * beat_length, index == 0)
+        }
+    }
+
+    /// Set the signature from `bar`, replacing the change already at this bar
+    pub fn set(&mut self, bar: i32, signature: TimeSignature) {
+        let bar = bar.max(0);
+        let index = self.change_at_bar(bar);
+        if self.changes[index].bar == bar {
+            self.changes[index].signature = signature;
+        } else {
+            self.changes
+                .insert(index + 1, MeterChange { bar, signature });
+        }
+        self.update();
+    }
+
+    /// Remove the change at `bar`, the change of the first bar cannot be removed
+    pub fn remove(&mut self, bar: i32) {
+        if bar > 0 {
+            self.changes.retain(|change| change.bar != bar);
+            self.update();
+        }
+    }
+
+    /// Recompute the beat of each change
+    fn update(&mut self) {
+        self.beats.resize(self.changes.len(), 0.);
+        for index in 1..self.changes.len() {
+            let previous = self.changes[index - 1];
+            let bars = (self.changes[index].bar - previous.bar) as f32;
+            self.beats[index] = self.beats[index - 1] + bars * previous.signature.bar_length();
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new(TimeSignature::default())
+    }
+}
+
+/// Saved as its changes, the beats are computed again when loaded
+impl From<Meter> for Vec<MeterChange> {
+    fn from(meter: Meter) -> Self {
+        meter.changes
+    }
+}
+
+impl From<Vec<MeterChange>> for Meter {
+    fn from(changes: Vec<MeterChange>) -> Self {
+        let mut meter = Meter::default();
+        for change in changes {
+            meter.set(change.bar, change.signature);
+        }
+        meter
+    }
+}
@@ -0,0 +1,31 @@
+use crate::core::meter::{Meter, TimeSignature};
+
+#[test]
+fn test_meter_changes() {
+    let mut meter = Meter::default();
+    meter.set(2, TimeSignature::new(7, 8));
+    meter.set(4, TimeSignature::new(6, 8));
+    // Two bars of 4/4, two bars of 7/8 then 6/8
+    assert_eq!(meter.bar_start(2), 8.);
+    assert_eq!(meter.bar_start(3), 11.5);
+    assert_eq!(meter.bar_start(5), 18.);
+    let position = meter.bar_at(12.);
+    assert_eq!(position.bar, 3);
+    assert_eq!(position.start, 11.5);
+    assert_eq!(position.signature, TimeSignature::new(7, 8));
+
+    meter.remove(2);
+    assert_eq!(meter.bar_start(4), 16.);
+    assert_eq!(meter.bar_start(5), 19.);
+}
+
+#[test]
+fn test_next_beat() {
+    let mut meter = Meter::new(TimeSignature::new(5, 4));
+    meter.set(1, TimeSignature::new(6, 8));
+    assert_eq!(meter.next_beat(0.), (0., true));
+    assert_eq!(meter.next_beat(3.2), (4., false));
+    assert_eq!(meter.next_beat(4.1), (5., true));
+    assert_eq!(meter.next_beat(5.2), (5.5, false));
+    assert_eq!(meter.next_beat(7.9), (8., true));
+}
@@ -0,0 +1,73 @@
+use crate::analysis::AudioInfo;
+use std::{fmt::Debug, sync::Arc};
+
+/// Longest count-in in bars
+pub const MAX_COUNT_IN: usize = 2;
+
+/// Click sound loaded from a file, downmixed to mono
+#[derive(Clone, PartialEq)]
+pub struct ClickSample {
+    pub name: String,
+    pub data: Arc<Vec<f32>>,
+    pub sample_rate: u32,
+}
+
+impl ClickSample {
+    /// Build the click from an analyzed file, `None` while its data is loading
+    pub fn from_audio_info(info: &AudioInfo) -> Option<Self> {
+        if !info.ready.read().is_ok_and(|ready| *ready) {
+            return None;
+        }
+        let data = info.data.read().ok()?;
+        let mono = data
+            .0
+            .iter()
+            .zip(data.1.iter())
+            .map(|(left, right)| (left + right) / 2.)
+            .collect();
+        Some(Self {
+            name: info.name.clone(),
+            data: Arc::new(mono),
+            sample_rate: info.sample_rate,
+        })
+    }
+}
+
+impl Debug for ClickSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClickSample")
+            .field("name", &self.name)
+            .field("frames", &self.data.len())
+            .field("sample_rate", &self.sample_rate)
+            .finish()
+    }
+}
+
+/// Options of the metronome sent to the player
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetronomeSettings {
+    pub volume: f32,
+    /// Bars counted before play and record
+    pub count_in: usize,
+    /// Only click while recording
+    pub record_only: bool,
+    /// Index of the output pair, 0 for the main outputs 1/2
+    pub output: usize,
+    /// Sound of the beats, a synthesized click when `None`
+    pub tick: Option<ClickSample>,
+    /// Sound of the first beat of the bars, the tick sound when `None`
+    pub accent: Option<ClickSample>,
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        Self {
+            volume: 0.4,
+            count_in: 0,
+            record_only: false,
+            output: 0,
+            tick: None,
+            accent: None,
+        }
+    }
+}
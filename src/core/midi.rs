@@ -0,0 +1,148 @@
+pub mod transform;
+
+use serde::{Deserialize, Serialize};
+
+/// Default length in beats of a created MIDI clip
+pub const DEFAULT_MIDI_CLIP_LENGTH: f32 = 4.;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Name of a MIDI key with its octave, key 60 being C3
+pub fn note_name(key: u8) -> String {
+    format!("{}{}", NOTE_NAMES[key as usize % 12], key as i32 / 12 - 2)
+}
+
+/// Note of a MIDI clip
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiNote {
+    pub key: u8,
+    pub velocity: u8,
+    /// Start in beats from the start of the clip
+    pub start: f32,
+    /// Length in beats
+    pub length: f32,
+}
+
+impl MidiNote {
+    pub fn end(&self) -> f32 {
+        self.start + self.length
+    }
+}
+
+/// Control change of a MIDI clip
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiControl {
+    pub controller: u8,
+    pub value: u8,
+    /// Time in beats from the start of the clip
+    pub time: f32,
+}
+
+/// A clip of notes placed on a MIDI track
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiClipCore {
+    pub id: String,
+    pub name: String,
+    /// Position in beats
+    pub position: f32,
+    /// Length in beats, notes after the end are not played
+    pub length: f32,
+    /// Notes sorted by start
+    pub notes: Vec<MidiNote>,
+    /// Control changes sorted by time
+    pub controls: Vec<MidiControl>,
+}
+
+impl MidiClipCore {
+    pub fn new(position: f32, length: f32) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().into(),
+            name: "MIDI Clip".into(),
+            position,
+            length,
+            notes: Vec::new(),
+            controls: Vec::new(),
+        }
+    }
+
+    pub fn end(&self) -> f32 {
+        self.position + self.length
+    }
+
+    /// Parts of the clip outside of the range between the beats `start` and `end`.
+    /// Notes crossing `start` are shortened, the ones starting in the range are removed.
+    pub fn without_range(&self, start: f32, end: f32) -> Vec<MidiClipCore> {
+        let mut parts = Vec::new();
+        if self.position < start {
+            let mut before = self.clone();
+            before.length = start.min(self.end()) - self.position;
+            before.keep_between(0., before.length);
+            parts.push(before);
+        }
+        if self.end() > end {
+            let mut after = self.clone();
+            if self.position < start {
+                after.id = uuid::Uuid::new_v4().into();
+            }
+            let offset = (end - self.position).max(0.);
+            after.shift(-offset);
+            after.position += offset;
+            after.length -= offset;
+            after.keep_between(0., after.length);
+            parts.push(after);
+        }
+        parts
+    }
+
+    /// Add the notes and the controls of `other`, extending the clip to cover it
+    pub fn merge(&mut self, other: &MidiClipCore) {
+        let position = self.position.min(other.position);
+        let end = self.end().max(other.end());
+        self.shift(self.position - position);
+        self.position = position;
+        self.length = end - position;
+        let offset = other.position - position;
+        self.notes.extend(other.notes.iter().map(|note| MidiNote {
+            start: note.start + offset,
+            ..*note
+        }));
+        self.controls
+            .extend(other.controls.iter().map(|control| MidiControl {
+                time: control.time + offset,
+                ..*control
+            }));
+        self.notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+        self.controls.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    /// Move the notes and the controls by `beats`
+    fn shift(&mut self, beats: f32) {
+        for note in self.notes.iter_mut() {
+            note.start += beats;
+        }
+        for control in self.controls.iter_mut() {
+            control.time += beats;
+        }
+    }
+
+    /// Remove the notes and the controls starting outside of `from..to`, shortening the notes
+    /// ending after `to`
+    fn keep_between(&mut self, from: f32, to: f32) {
+        self.notes
+            .retain(|note| note.start >= from && note.start < to);
+        for note in self.notes.iter_mut() {
+            note.length = note.length.min(to - note.start);
+        }
+        self.controls
+            .retain(|control| control.time >= from && control.time < to);
+    }
+
+    /// Lowest and highest keys of the notes
+    pub fn key_range(&self) -> Option<(u8, u8)> {
+        let low = self.notes.iter().map(|note| note.key).min()?;
+        let high = self.notes.iter().map(|note| note.key).max()?;
+        Some((low, high))
+    }
+}
@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::midi::MidiNote;
+use rand::Rng;
+
+/// Shortest note left by a transform
+const MIN_LENGTH: f32 = 1. / 128.;
+
+/// Grids of the quantization with their length in beats
+pub const QUANTIZE_GRIDS: [(&str, f32); 7] = [
+    ("1/4", 1.),
+    ("1/8", 0.5),
+    ("1/8T", 1. / 3.),
+    ("1/16", 0.25),
+    ("1/16T", 1. / 6.),
+    ("1/32", 0.125),
+    ("1/64", 0.0625),
+];
+
+/// Edit applied at once to the notes of a clip or to the selected ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiTransform {
+    /// Move the notes towards the grid, every second line delayed by the swing
+    Quantize {
+        grid: f32,
+        strength: f32,
+        swing: f32,
+        starts: bool,
+        ends: bool,
+    },
+    /// Move the notes and change their velocity by random amounts up to `timing` beats
+    /// and `velocity`
+    Humanize {
+        timing: f32,
+        velocity: f32,
+    },
+    Transpose(i32),
+    /// Bring the velocities closer to their average, then multiply them
+    Velocity {
+        scale: f32,
+        compress: f32,
+    },
+    /// Extend each note until the next one
+    Legato,
+    /// Play the notes backwards
+    Reverse,
+}
+
+impl MidiTransform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MidiTransform::Quantize { .. } => "Quantize",
+            MidiTransform::Humanize { .. } => "Humanize",
+            MidiTransform::Transpose(_) => "Transpose",
+            MidiTransform::Velocity { .. } => "Velocity",
+            MidiTransform::Legato => "Legato",
+            MidiTransform::Reverse => "Reverse",
+        }
+    }
+
+    /// Transform `notes` sorted by start, of a clip at `position` on the timeline
+    pub fn apply(&self, notes: &mut [MidiNote], position: f32, rng: &mut impl Rng) {
+        match *self {
+            MidiTransform::Quantize {
+                grid,
+                strength,
+                swing,
+                starts,
+                ends,
+            } => {
+                let target = |beats: f32| {
+                    let beats = position + beats;
+                    let line = (beats / grid).floor() as i64;
+                    // Swung lines can be after the next straight one
+                    (line - 1..=line + 1)
+                        .map(|line| {
+                            let swung = if line.rem_euclid(2) == 1 {
+                                swing * grid / 3.
+                            } else {
+                                0.
+                            };
+                            line as f32 * grid + swung
+                        })
+                        .min_by(|a, b| (a - beats).abs().total_cmp(&(b - beats).abs()))
+                        .unwrap_or(beats)
+                        - position
+                };
+                for note in notes.iter_mut() {
+                    let end = note.end();
+                    if starts {
+                        note.start += (target(note.start) - note.start) * strength;
+                        note.start = note.start.max(0.);
+                    }
+                    let end = if ends {
+                        end + (target(end) - end) * strength
+                    } else if starts {
+                        // Moved notes keep their length
+                        note.start + note.length
+                    } else {
+                        end
+                    };
+                    note.length = (end - note.start).max(MIN_LENGTH);
+                }
+            }
+            MidiTransform::Humanize { timing, velocity } => {
+                for note in notes.iter_mut() {
+                    if timing > 0. {
+                        note.start = (note.start + rng.random_range(-timing..=timing)).max(0.);
+                    }
+                    if velocity > 0. {
+                        let offset = rng.random_range(-velocity..=velocity);
+                        note.velocity =
+                            (note.velocity as f32 + offset).round().clamp(1., 127.) as u8;
+                    }
+                }
+            }
+            MidiTransform::Transpose(semitones) => {
+                for note in notes.iter_mut() {
+                    note.key = (note.key as i32 + semitones).clamp(0, 127) as u8;
+                }
+            }
+            MidiTransform::Velocity { scale, compress } => {
+                if notes.is_empty() {
+                    return;
+                }
+                let average =
+                    notes.iter().map(|note| note.velocity as f32).sum::<f32>() / notes.len() as f32;
+                for note in notes.iter_mut() {
+                    let velocity = average + (note.velocity as f32 - average) * (1. - compress);
+                    note.velocity = (velocity * scale).round().clamp(1., 127.) as u8;
+                }
+            }
+            MidiTransform::Legato => {
+                let starts: Vec<f32> = notes.iter().map(|note| note.start).collect();
+                for note in notes.iter_mut() {
+                    if let Some(next) = starts.iter().find(|start| **start > note.start) {
+                        note.length = next - note.start;
+                    }
+                }
+            }
+            MidiTransform::Reverse => {
+                let start = notes
+                    .iter()
+                    .map(|note| note.start)
+                    .fold(f32::INFINITY, f32::min);
+                let end = notes.iter().map(|note| note.end()).fold(0., f32::max);
+                for note in notes.iter_mut() {
+                    note.start = start + end - note.end();
+                }
+            }
+        }
+    }
+}
+
+/// Options of the transforms chosen in the menus
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformSettings {
+    pub grid: f32,
+    pub strength: f32,
+    pub swing: f32,
+    pub starts: bool,
+    pub ends: bool,
+    /// Largest random move in beats
+    pub timing: f32,
+    /// Largest random velocity change
+    pub velocity: f32,
+    pub semitones: i32,
+    pub scale: f32,
+    pub compress: f32,
+}
+
+impl Default for TransformSettings {
+    fn default() -> Self {
+        Self {
+            grid: 0.25,
+            strength: 1.,
+            swing: 0.,
+            starts: true,
+            ends: false,
+            timing: 0.02,
+            velocity: 10.,
+            semitones: 12,
+            scale: 1.,
+            compress: 0.,
+        }
+    }
+}
+
+impl TransformSettings {
+    pub fn quantize(&self) -> MidiTransform {
+        MidiTransform::Quantize {
+            grid: self.grid,
+            strength: self.strength,
+            swing: self.swing,
+            starts: self.starts,
+            ends: self.ends,
+        }
+    }
+
+    pub fn humanize(&self) -> MidiTransform {
+        MidiTransform::Humanize {
+            timing: self.timing,
+            velocity: self.velocity,
+        }
+    }
+
+    pub fn velocity(&self) -> MidiTransform {
+        MidiTransform::Velocity {
+            scale: self.scale,
+            compress: self.compress,
+        }
+    }
+}
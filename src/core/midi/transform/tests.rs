@@ -0,0 +1,72 @@
+use crate::core::midi::{MidiNote, transform::MidiTransform};
+use rand::{SeedableRng, rngs::StdRng};
+
+fn note(key: u8, start: f32, length: f32) -> MidiNote {
+    MidiNote {
+        key,
+        velocity: 100,
+        start,
+        length,
+    }
+}
+
+#[test]
+fn test_quantize_strength_and_swing() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut notes = [note(60, 0.1, 0.5), note(62, 0.6, 0.5)];
+    MidiTransform::Quantize {
+        grid: 0.5,
+        strength: 0.5,
+        swing: 0.,
+        starts: true,
+        ends: false,
+    }
+    .apply(&mut notes, 0., &mut rng);
+    // Half way to the grid, keeping the lengths
+    assert!((notes[0].start - 0.05).abs() < 1e-5);
+    assert!((notes[1].start - 0.55).abs() < 1e-5);
+    assert_eq!(notes[0].length, 0.5);
+
+    // Every second line is late by a third of the grid at full swing,
+    // the clip being placed at beat 1
+    let mut notes = [note(60, -0.05, 0.25), note(62, 0.6, 0.25)];
+    MidiTransform::Quantize {
+        grid: 0.5,
+        strength: 1.,
+        swing: 1.,
+        starts: true,
+        ends: true,
+    }
+    .apply(&mut notes, 1., &mut rng);
+    assert_eq!(notes[0].start, 0.);
+    assert!((notes[1].start - (0.5 + 0.5 / 3.)).abs() < 1e-5);
+    assert!((notes[1].end() - 1.).abs() < 1e-5);
+}
+
+#[test]
+fn test_humanize_stays_in_range() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut notes = [note(60, 1., 1.); 32];
+    MidiTransform::Humanize {
+        timing: 0.1,
+        velocity: 10.,
+    }
+    .apply(&mut notes, 0., &mut rng);
+    assert!(notes.iter().all(|n| (n.start - 1.).abs() <= 0.1));
+    assert!(notes.iter().all(|n| (90..=110).contains(&n.velocity)));
+    assert!(notes.iter().any(|n| n.start != 1.));
+}
+
+#[test]
+fn test_legato_and_reverse() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut notes = [note(60, 0., 0.25), note(64, 0., 0.5), note(67, 2., 0.5)];
+    MidiTransform::Legato.apply(&mut notes, 0., &mut rng);
+    let lengths: Vec<f32> = notes.iter().map(|n| n.length).collect();
+    assert_eq!(lengths, vec![2., 2., 0.5]);
+
+    let mut notes = [note(60, 0., 1.), note(67, 2., 0.5)];
+    MidiTransform::Reverse.apply(&mut notes, 0., &mut rng);
+    assert_eq!(notes[0].start, 1.5);
+    assert_eq!(notes[1].start, 0.);
+}
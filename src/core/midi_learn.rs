@@ -0,0 +1,213 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::message::MidiToPlayerMsg;
+use serde::{Deserialize, Serialize};
+
+/// Seconds for a driven parameter to move most of the way to the controller value
+const SMOOTHING_TIME: f32 = 0.05;
+/// Distance under which a smoothed parameter reaches the controller value
+const SETTLED: f32 = 1e-3;
+
+/// Control driven by a MIDI controller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MidiTarget {
+    TrackVolume(String),
+    EffectParam {
+        track_id: String,
+        effect_id: String,
+        param: usize,
+    },
+    /// Volume of the selected track, for the global mapping
+    SelectedTrackVolume,
+    /// Parameter of the effect at `slot` on the selected track, for the global mapping
+    SelectedEffectParam {
+        slot: usize,
+        param: usize,
+    },
+}
+
+/// Controller bound to a control
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiBinding {
+    pub channel: u8,
+    pub controller: u8,
+    pub target: MidiTarget,
+    /// Part of the control range reached with the controller at 0, from 0 to 1
+    pub min: f32,
+    /// Part of the control range reached with the controller at 127, inverted when under `min`
+    pub max: f32,
+}
+
+impl MidiBinding {
+    pub fn new(channel: u8, controller: u8, target: MidiTarget) -> Self {
+        Self {
+            channel,
+            controller,
+            target,
+            min: 0.,
+            max: 1.,
+        }
+    }
+
+    /// Part of the control range set by `event`, `None` when sent by another controller
+    pub fn scale(&self, event: &MidiToPlayerMsg) -> Option<f32> {
+        match *event {
+            MidiToPlayerMsg::ControlChange {
+                channel,
+                controller,
+                value,
+            } if channel == self.channel && controller == self.controller => {
+                Some(self.min + (self.max - self.min) * value as f32 / 127.)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        format!("CC {} Ch {}", self.controller, self.channel + 1)
+    }
+}
+
+/// Value of a control at `ratio` of its range
+pub fn ratio_to_value(ratio: f32, min: f32, max: f32, log: bool) -> f32 {
+    let ratio = ratio.clamp(0., 1.);
+    if log {
+        min * (max / min).powf(ratio)
+    } else {
+        min + (max - min) * ratio
+    }
+}
+
+/// Part of the range of a control at `value`
+pub fn value_to_ratio(value: f32, min: f32, max: f32, log: bool) -> f32 {
+    let ratio = if log {
+        (value / min).log10() / (max / min).log10()
+    } else {
+        (value - min) / (max - min)
+    };
+    ratio.clamp(0., 1.)
+}
+
+/// Control moving toward the last controller value
+#[derive(Debug, Clone)]
+struct DrivenControl {
+    target: MidiTarget,
+    ratio: f32,
+    goal: f32,
+}
+
+/// Bindings between MIDI controllers and controls, learned by clicking a control and moving a controller
+#[derive(Debug, Default)]
+pub struct MidiLearn {
+    /// Whether clicking a control waits for a controller
+    pub active: bool,
+    /// Control clicked in learn mode, bound to the next controller moved
+    pub learning: Option<MidiTarget>,
+    /// Bindings of the project, saved with it
+    pub bindings: Vec<MidiBinding>,
+    /// Bindings of every project, saved in the config
+    pub global: Vec<MidiBinding>,
+    driven: Vec<DrivenControl>,
+}
+
+impl MidiLearn {
+    pub fn new(global: Vec<MidiBinding>) -> Self {
+        Self {
+            global,
+            ..Default::default()
+        }
+    }
+
+    /// Bind the control being learned to the controller of `event`, replacing its previous binding.
+    /// Returns whether the event was used.
+    pub fn learn(&mut self, event: &MidiToPlayerMsg) -> bool {
+        let MidiToPlayerMsg::ControlChange {
+            channel,
+            controller,
+            ..
+        } = *event
+        else {
+            return false;
+        };
+        let Some(target) = self.learning.take() else {
+            return false;
+        };
+        self.bindings.retain(|binding| {
+            binding.target != target
+                && (binding.channel, binding.controller) != (channel, controller)
+        });
+        self.bindings
+            .push(MidiBinding::new(channel, controller, target));
+        true
+    }
+
+    /// Targets of the bindings of `event` with the part of their range to reach.
+    /// A controller bound in the project overrides the global mapping.
+    pub fn targets(&self, event: &MidiToPlayerMsg) -> Vec<(MidiTarget, f32)> {
+        let targets = |bindings: &[MidiBinding]| -> Vec<(MidiTarget, f32)> {
+            bindings
+                .iter()
+                .filter_map(|binding| Some((binding.target.clone(), binding.scale(event)?)))
+                .collect()
+        };
+        let project = targets(&self.bindings);
+        if project.is_empty() {
+            targets(&self.global)
+        } else {
+            project
+        }
+    }
+
+    /// Whether controller events are awaited, to learn or drive a control
+    pub fn listening(&self) -> bool {
+        self.learning.is_some() || !self.bindings.is_empty() || !self.global.is_empty()
+    }
+
+    /// Move `target` from `ratio` toward `goal` over the next updates
+    pub fn drive(&mut self, target: MidiTarget, ratio: f32, goal: f32) {
+        if let Some(control) = self
+            .driven
+            .iter_mut()
+            .find(|control| control.target == target)
+        {
+            control.goal = goal;
+        } else {
+            self.driven.push(DrivenControl {
+                target,
+                ratio,
+                goal,
+            });
+        }
+    }
+
+    /// Advance the driven controls by `dt` seconds, returning their new ratios
+    pub fn step(&mut self, dt: f32) -> Vec<(MidiTarget, f32)> {
+        let keep = (-dt / SMOOTHING_TIME).exp();
+        let ratios = self
+            .driven
+            .iter_mut()
+            .map(|control| {
+                control.ratio = control.goal + (control.ratio - control.goal) * keep;
+                if (control.ratio - control.goal).abs() < SETTLED {
+                    control.ratio = control.goal;
+                }
+                (control.target.clone(), control.ratio)
+            })
+            .collect();
+        self.driven.retain(|control| control.ratio != control.goal);
+        ratios
+    }
+
+    /// Whether controls are still moving toward their controller value
+    pub fn driving(&self) -> bool {
+        !self.driven.is_empty()
+    }
+
+    /// Binding of `target` in the project
+    pub fn binding(&self, target: &MidiTarget) -> Option<&MidiBinding> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.target == *target)
+    }
+}
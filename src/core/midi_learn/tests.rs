@@ -0,0 +1,68 @@
+use crate::core::{
+    message::MidiToPlayerMsg,
+    midi_learn::{MidiBinding, MidiLearn, MidiTarget, ratio_to_value, value_to_ratio},
+};
+
+fn cc(controller: u8, value: u8) -> MidiToPlayerMsg {
+    MidiToPlayerMsg::ControlChange {
+        channel: 0,
+        controller,
+        value,
+    }
+}
+
+#[test]
+fn test_learn_and_scale_binding() {
+    let volume = MidiTarget::TrackVolume("track".into());
+    let mut learn = MidiLearn::default();
+    // Nothing is learned without a clicked control
+    assert!(!learn.learn(&cc(7, 0)));
+    learn.learning = Some(volume.clone());
+    assert!(learn.learn(&cc(7, 0)));
+    assert!(learn.learning.is_none());
+    // Learning the controller again moves it to the new control
+    learn.learning = Some(MidiTarget::SelectedTrackVolume);
+    learn.learn(&cc(7, 0));
+    assert_eq!(learn.bindings.len(), 1);
+    learn.bindings[0].target = volume.clone();
+
+    learn.bindings[0].min = 0.5;
+    learn.bindings[0].max = 0.;
+    assert_eq!(learn.targets(&cc(7, 127)), vec![(volume.clone(), 0.)]);
+    assert_eq!(learn.targets(&cc(7, 0)), vec![(volume, 0.5)]);
+    assert!(learn.targets(&cc(8, 0)).is_empty());
+
+    // The global mapping drives the controllers not bound in the project
+    learn.global = vec![
+        MidiBinding::new(0, 7, MidiTarget::SelectedTrackVolume),
+        MidiBinding::new(0, 8, MidiTarget::SelectedTrackVolume),
+    ];
+    assert_eq!(
+        learn.targets(&cc(7, 0))[0].0,
+        MidiTarget::TrackVolume("track".into())
+    );
+    assert_eq!(
+        learn.targets(&cc(8, 0))[0].0,
+        MidiTarget::SelectedTrackVolume
+    );
+}
+
+#[test]
+fn test_smoothing_reaches_goal() {
+    let volume = MidiTarget::TrackVolume("track".into());
+    let mut learn = MidiLearn::default();
+    learn.drive(volume.clone(), 0., 1.);
+    let first = learn.step(0.01)[0].1;
+    assert!(first > 0. && first < 1.);
+    // A new controller value only changes the goal
+    learn.drive(volume.clone(), 1., 0.);
+    let second = learn.step(0.01)[0].1;
+    assert!(second < first);
+    for _ in 0..100 {
+        learn.step(0.01);
+    }
+    assert!(!learn.driving());
+
+    let ratio = value_to_ratio(1000., 50., 20_000., true);
+    assert!((ratio_to_value(ratio, 50., 20_000., true) - 1000.).abs() < 0.1);
+}
@@ -1,6 +1,17 @@
 pub mod clip;
 pub mod grid;
+pub mod instrument;
+pub mod keyboard;
 pub mod message;
+pub mod meter;
 pub mod metrics;
+pub mod metronome;
+pub mod midi;
+pub mod midi_learn;
+pub mod project;
+pub mod recording;
 pub mod state;
+pub mod sync;
+pub mod take;
+pub mod tempo;
 pub mod track;
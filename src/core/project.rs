@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::{meter::Meter, midi_learn::MidiBinding, tempo::TempoMap, track::TrackCore};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Extension of the project files
+pub const PROJECT_EXTENSION: &str = "tonique";
+
+/// Project saved to disk as JSON. The audio files are saved as their path.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectFile {
+    pub tempo: TempoMap,
+    pub meter: Meter,
+    /// Start and end beats of the loop region
+    #[serde(default)]
+    pub loop_region: Option<(f32, f32)>,
+    pub master: TrackCore,
+    /// Tracks in their order, without the master track
+    pub tracks: Vec<TrackCore>,
+    /// Controllers bound to the controls of the project
+    #[serde(default)]
+    pub midi_bindings: Vec<MidiBinding>,
+}
+
+impl ProjectFile {
+    /// Read the project saved at `path`, analysing its audio files again
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&data)
+            .map_err(|e| format!("Failed to open the project {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// Serde helpers saving a `Shared` parameter as its value
+pub mod shared_value {
+    use fundsp::hacker::{Shared, shared};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(value: &Shared, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        value.value().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Shared, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(shared(f32::deserialize(deserializer)?))
+    }
+}
+
+/// Serde helpers saving an audio file as its path, analysed again when loaded
+pub mod audio_path {
+    use crate::{analysis::AudioInfo, cache::AUDIO_ANALYSIS_CACHE};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+    use std::path::PathBuf;
+
+    pub fn serialize<S>(audio: &AudioInfo, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        audio.path.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<AudioInfo, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let path = PathBuf::deserialize(deserializer)?;
+        AUDIO_ANALYSIS_CACHE
+            .get_or_analyze(path.clone())
+            .ok_or_else(|| D::Error::custom(format!("cannot read {}", path.display())))
+    }
+}
+
+/// Serde helpers saving an optional audio file as its path
+pub mod optional_audio_path {
+    use crate::analysis::AudioInfo;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    struct Audio(#[serde(with = "super::audio_path")] AudioInfo);
+
+    pub fn serialize<S>(audio: &Option<AudioInfo>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match audio {
+            Some(audio) => serializer.serialize_some(&audio.path),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<AudioInfo>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<Audio>::deserialize(deserializer)?.map(|Audio(audio)| audio))
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{
+    core::{
+        instrument::{DrumRackParams, InstrumentParams},
+        meter::{Meter, TimeSignature},
+        tempo::{TempoCurve, TempoMap, TempoPoint},
+    },
+    ui::{
+        effect::UIEffect,
+        effects::{EffectId, create_effect_from_id},
+    },
+};
+
+#[test]
+fn test_drum_rack_round_trip() {
+    let mut rack = DrumRackParams::default();
+    rack.volume.set_value(0.5);
+    let pad = &mut rack.pads[2];
+    pad.pan.set_value(-0.25);
+    pad.tune.set_value(3.);
+    pad.choke.set_value(2.);
+    let effect = UIEffect::new(create_effect_from_id(EffectId::Equalizer), "track".into());
+    effect.params()[0].value.set_value(800.);
+    pad.effects.push(effect.clone());
+
+    let json = serde_json::to_string(&InstrumentParams::DrumRack(rack)).unwrap();
+    let Ok(InstrumentParams::DrumRack(loaded)) = serde_json::from_str(&json) else {
+        panic!("drum rack not loaded");
+    };
+    assert_eq!(loaded.pads.len(), 16);
+    assert_eq!(loaded.volume.value(), 0.5);
+    let pad = &loaded.pads[2];
+    assert_eq!(pad.key, 38);
+    assert_eq!(pad.pan.value(), -0.25);
+    assert_eq!(pad.tune.value(), 3.);
+    assert_eq!(pad.choke_group(), Some(2));
+    assert_eq!(pad.effects.len(), 1);
+    assert_eq!(pad.effects[0].id(), effect.id());
+    assert_eq!(pad.effects[0].params()[0].value.value(), 800.);
+}
+
+#[test]
+fn test_tempo_and_meter_round_trip() {
+    let mut tempo = TempoMap::new(90.);
+    tempo.insert(TempoPoint::new(8., 140., TempoCurve::Ramp));
+    tempo.insert(TempoPoint::new(16., 100., TempoCurve::Step));
+    let mut meter = Meter::default();
+    meter.set(4, TimeSignature::new(7, 8));
+
+    let loaded: TempoMap = serde_json::from_str(&serde_json::to_string(&tempo).unwrap()).unwrap();
+    assert_eq!(loaded, tempo);
+    let loaded: Meter = serde_json::from_str(&serde_json::to_string(&meter).unwrap()).unwrap();
+    assert_eq!(loaded, meter);
+}
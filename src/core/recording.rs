@@ -0,0 +1,301 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::{
+    message::MidiToPlayerMsg,
+    midi::{MidiClipCore, MidiControl, MidiNote},
+    track::TrackInput,
+};
+use creek::{WavEncoder, WriteDiskStream, WriteStreamOptions, wav_bit_depth::Float32};
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
+
+/// Frames reserved for a block to avoid allocating on the audio thread
+const BUFFER_CAPACITY: usize = 8192;
+/// Recorders whose peaks are sent in a message
+pub const PEAKS_PER_MESSAGE: usize = 16;
+/// Length in beats of a note released as soon as it is played
+const MIN_RECORDED_NOTE_LENGTH: f32 = 1. / 128.;
+
+pub type RecordingStream = WriteDiskStream<WavEncoder<Float32>>;
+
+/// Audio of an armed track written to a WAV file.
+/// Created on the GUI thread, filled on the audio thread and given back when recording stops.
+pub struct TrackRecorder {
+    pub track_id: String,
+    pub input: TrackInput,
+    pub path: PathBuf,
+    stream: RecordingStream,
+    /// Frames written in the file
+    pub frames: usize,
+    /// Frames lost because the disk stream could not take them
+    pub dropped: usize,
+    /// Deinterleaved channels of the current block
+    buffer: [Vec<f32>; 2],
+}
+
+impl TrackRecorder {
+    /// Open a WAV file at `path` recording `input`
+    pub fn new(
+        track_id: &str,
+        input: TrackInput,
+        path: &Path,
+        sample_rate: u32,
+    ) -> Result<Self, String> {
+        let mut stream = RecordingStream::new(
+            path,
+            input.channels() as u16,
+            sample_rate,
+            WriteStreamOptions::default(),
+        )
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        stream.block_until_ready().map_err(|e| e.to_string())?;
+        Ok(Self {
+            track_id: track_id.into(),
+            input,
+            path: path.to_path_buf(),
+            stream,
+            frames: 0,
+            dropped: 0,
+            buffer: [
+                Vec::with_capacity(BUFFER_CAPACITY),
+                Vec::with_capacity(BUFFER_CAPACITY),
+            ],
+        })
+    }
+
+    /// Write the channels of the track from the interleaved `input` block.
+    /// Returns the peak of the block.
+    pub fn write(&mut self, input: &[f32], input_channels: usize) -> f32 {
+        let num_frames = input.len() / input_channels.max(1);
+        let mut peak: f32 = 0.;
+        for (channel, buffer) in self.buffer[..self.input.channels()].iter_mut().enumerate() {
+            buffer.clear();
+            let index = self.input.channel + channel;
+            for frame in input.chunks(input_channels.max(1)) {
+                let sample = frame.get(index).copied().unwrap_or(0.);
+                peak = peak.max(sample.abs());
+                buffer.push(sample);
+            }
+        }
+        let [left, right] = &self.buffer;
+        let channels = [left.as_slice(), right.as_slice()];
+        if self
+            .stream
+            .write(&channels[..self.input.channels()])
+            .is_ok()
+        {
+            self.frames += num_frames;
+        } else {
+            self.dropped += num_frames;
+        }
+        peak
+    }
+
+    /// Flush the remaining frames and close the file
+    pub fn finish(&mut self) {
+        let _ = self.stream.finish_and_close();
+    }
+
+    /// Whether the file is closed and can be read
+    pub fn finished(&mut self) -> bool {
+        let _ = self.stream.poll();
+        self.stream.finish_complete()
+    }
+}
+
+impl Debug for TrackRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackRecorder")
+            .field("track_id", &self.track_id)
+            .field("input", &self.input)
+            .field("path", &self.path)
+            .field("frames", &self.frames)
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+/// How the recorded MIDI is written in the clips of a track
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MidiRecordMode {
+    /// The recording replaces the clips under it
+    #[default]
+    Replace,
+    /// The recording is merged in the clips under it
+    Overdub,
+    /// Each pass of the loop is merged in the clips as soon as it ends, to be heard on the next one
+    LoopOverdub,
+}
+
+impl MidiRecordMode {
+    pub const ALL: [MidiRecordMode; 3] = [
+        MidiRecordMode::Replace,
+        MidiRecordMode::Overdub,
+        MidiRecordMode::LoopOverdub,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MidiRecordMode::Replace => "Replace",
+            MidiRecordMode::Overdub => "Overdub",
+            MidiRecordMode::LoopOverdub => "Loop overdub",
+        }
+    }
+}
+
+/// MIDI input of an armed MIDI track placed on the timeline while recording
+#[derive(Debug, Clone)]
+pub struct MidiRecorder {
+    pub track_id: String,
+    /// Released notes, starting in beats on the timeline
+    notes: Vec<MidiNote>,
+    /// Control changes, in beats on the timeline
+    controls: Vec<MidiControl>,
+    /// Keys held with their start and velocity
+    held: Vec<(u8, f32, u8)>,
+}
+
+impl MidiRecorder {
+    pub fn new(track_id: &str) -> Self {
+        Self {
+            track_id: track_id.into(),
+            notes: Vec::new(),
+            controls: Vec::new(),
+            held: Vec::new(),
+        }
+    }
+
+    /// Add an event received at `beat`
+    pub fn push(&mut self, beat: f32, event: MidiToPlayerMsg) {
+        match event {
+            MidiToPlayerMsg::NoteOn { key, velocity, .. } if velocity > 0 => {
+                self.release(key, beat);
+                self.held.push((key, beat, velocity));
+            }
+            MidiToPlayerMsg::NoteOn { key, .. } | MidiToPlayerMsg::NoteOff { key, .. } => {
+                self.release(key, beat)
+            }
+            MidiToPlayerMsg::ControlChange {
+                controller, value, ..
+            } => self.controls.push(MidiControl {
+                controller,
+                value,
+                time: beat,
+            }),
+            _ => {}
+        }
+    }
+
+    fn release(&mut self, key: u8, beat: f32) {
+        if let Some(index) = self.held.iter().position(|(held, ..)| *held == key) {
+            let (key, start, velocity) = self.held.remove(index);
+            self.notes.push(MidiNote {
+                key,
+                velocity,
+                start,
+                length: (beat - start).max(MIN_RECORDED_NOTE_LENGTH),
+            });
+        }
+    }
+
+    /// Release the held notes at `beat`
+    pub fn release_all(&mut self, beat: f32) {
+        for (key, ..) in self.held.clone() {
+            self.release(key, beat);
+        }
+    }
+
+    /// Release the held notes at the `end` of the loop and hold them again from its `start`
+    pub fn wrap(&mut self, start: f32, end: f32) {
+        let held = self.held.clone();
+        self.release_all(end);
+        self.held = held
+            .into_iter()
+            .map(|(key, _, velocity)| (key, start, velocity))
+            .collect();
+    }
+
+    /// Notes recorded so far, the held ones lasting until `beat`
+    pub fn notes(&self, beat: f32) -> Vec<MidiNote> {
+        let held = self.held.iter().map(|(key, start, velocity)| MidiNote {
+            key: *key,
+            velocity: *velocity,
+            start: *start,
+            length: (beat - start).max(MIN_RECORDED_NOTE_LENGTH),
+        });
+        self.notes.iter().copied().chain(held).collect()
+    }
+
+    /// Clip between the beats `start` and `end` with the released notes and the controls,
+    /// which are removed from the recorder. Returns `None` when nothing was played.
+    pub fn take_clip(&mut self, start: f32, end: f32) -> Option<MidiClipCore> {
+        if self.notes.is_empty() && self.controls.is_empty() {
+            return None;
+        }
+        let mut clip = MidiClipCore::new(start, end - start);
+        clip.notes = self
+            .notes
+            .drain(..)
+            .filter(|note| note.start >= start && note.start < end)
+            .map(|note| MidiNote {
+                start: note.start - start,
+                length: note.length.min(end - note.start),
+                ..note
+            })
+            .collect();
+        clip.controls = self
+            .controls
+            .drain(..)
+            .filter(|control| control.time >= start && control.time < end)
+            .map(|control| MidiControl {
+                time: control.time - start,
+                ..control
+            })
+            .collect();
+        clip.notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+        clip.controls.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Some(clip)
+    }
+}
+
+/// Clips of a track once the recorded `take` is written in its `clips` with `mode`
+pub fn record_into(
+    clips: &[MidiClipCore],
+    take: MidiClipCore,
+    mode: MidiRecordMode,
+) -> Vec<MidiClipCore> {
+    let overlaps = |clip: &MidiClipCore| clip.position < take.end() && clip.end() > take.position;
+    let mut result: Vec<MidiClipCore> = clips
+        .iter()
+        .filter(|clip| !overlaps(clip))
+        .cloned()
+        .collect();
+    match mode {
+        MidiRecordMode::Replace => {
+            for clip in clips.iter().filter(|clip| overlaps(clip)) {
+                result.extend(clip.without_range(take.position, take.end()));
+            }
+            result.push(take);
+        }
+        // The clips under the recording are merged into the first one
+        MidiRecordMode::Overdub | MidiRecordMode::LoopOverdub => {
+            let mut merged = clips.iter().filter(|clip| overlaps(clip));
+            match merged.next() {
+                Some(first) => {
+                    let mut first = first.clone();
+                    for clip in merged {
+                        first.merge(clip);
+                    }
+                    first.merge(&take);
+                    result.push(first);
+                }
+                None => result.push(take),
+            }
+        }
+    }
+    result.sort_by(|a, b| a.position.total_cmp(&b.position));
+    result
+}
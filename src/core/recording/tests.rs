@@ -0,0 +1,92 @@
+use crate::{
+    analysis::get_audio_info,
+    core::{
+        message::MidiToPlayerMsg,
+        midi::{MidiClipCore, MidiNote},
+        recording::{MidiRecordMode, MidiRecorder, TrackRecorder, record_into},
+        track::TrackInput,
+    },
+};
+use std::time::Duration;
+
+#[test]
+fn test_record_channel() {
+    let path = std::env::temp_dir().join("tonique_test_record_channel.wav");
+    let input = TrackInput {
+        channel: 1,
+        stereo: false,
+    };
+    let mut recorder = TrackRecorder::new("track", input, &path, 1000).unwrap();
+    // Three interleaved input channels, only the second one is recorded
+    let block: Vec<f32> = (0..300).map(|i| (i % 3) as f32 * 0.25).collect();
+    assert_eq!(recorder.write(&block, 3), 0.25);
+    assert_eq!(recorder.write(&block, 3), 0.25);
+    assert_eq!(recorder.frames, 200);
+    recorder.finish();
+    while !recorder.finished() {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let info = get_audio_info(&path).unwrap();
+    assert_eq!(info.num_samples, Some(200));
+    assert_eq!(info.sample_rate, 1000);
+    let _ = std::fs::remove_file(path);
+}
+
+fn note_on(key: u8) -> MidiToPlayerMsg {
+    MidiToPlayerMsg::NoteOn {
+        channel: 0,
+        key,
+        velocity: 90,
+    }
+}
+
+fn note_off(key: u8) -> MidiToPlayerMsg {
+    MidiToPlayerMsg::NoteOff {
+        channel: 0,
+        key,
+        velocity: 0,
+    }
+}
+
+#[test]
+fn test_midi_recorder_wraps_held_notes() {
+    let mut recorder = MidiRecorder::new("track");
+    recorder.push(2.5, note_on(60));
+    // The note is still held when the loop goes back from 4 to 0
+    recorder.wrap(0., 4.);
+    recorder.push(1., note_off(60));
+
+    let clip = recorder.take_clip(0., 4.).unwrap();
+    assert_eq!(clip.position, 0.);
+    assert_eq!(clip.length, 4.);
+    let starts: Vec<(f32, f32)> = clip.notes.iter().map(|n| (n.start, n.length)).collect();
+    assert_eq!(starts, vec![(0., 1.), (2.5, 1.5)]);
+    assert!(recorder.take_clip(0., 4.).is_none());
+}
+
+#[test]
+fn test_record_into_modes() {
+    let note = |start| MidiNote {
+        key: 60,
+        velocity: 100,
+        start,
+        length: 1.,
+    };
+    let mut existing = MidiClipCore::new(0., 8.);
+    existing.notes = vec![note(1.), note(5.)];
+    let mut take = MidiClipCore::new(4., 2.);
+    take.notes = vec![note(0.5)];
+
+    // The existing clip is cut around the take
+    let clips = record_into(&[existing.clone()], take.clone(), MidiRecordMode::Replace);
+    let ranges: Vec<(f32, f32)> = clips.iter().map(|c| (c.position, c.length)).collect();
+    assert_eq!(ranges, vec![(0., 4.), (4., 2.), (6., 2.)]);
+    assert_eq!(clips[0].notes, vec![note(1.)]);
+    assert!(clips[2].notes.is_empty());
+
+    // The take is merged in the existing clip
+    let clips = record_into(&[existing.clone()], take, MidiRecordMode::Overdub);
+    assert_eq!(clips.len(), 1);
+    assert_eq!(clips[0].id, existing.id);
+    assert_eq!(clips[0].notes, vec![note(1.), note(4.5), note(5.)]);
+}
@@ -1,6 +1,11 @@
 use crate::core::{
     clip::ClipCore,
+    instrument::InstrumentParams,
+    meter::Meter,
+    midi::{MidiClipCore, MidiNote},
     state::ToniqueProjectState,
+    take::TakeLanes,
+    tempo::TempoMap,
     track::{MutableTrackCore, TrackCore},
 };
 use std::collections::HashMap;
@@ -211,8 +216,12 @@ impl AddClipsAction {
 impl ProjectStateAction for AddClipsAction {
     fn apply(&mut self, state: &mut ToniqueProjectState) {
         if let Some(track) = state.track_service.get(&self.track_id) {
-            (self.added_clips, self.deleted_clips) =
-                track.add_clips(&self.clips, state.bpm, &mut state.tx);
+            (self.added_clips, self.deleted_clips) = track.add_clips(
+                &self.clips,
+                &state.tempo,
+                state.auto_crossfade,
+                &mut state.tx,
+            );
         }
     }
     fn undo(&mut self, state: &mut ToniqueProjectState) {
@@ -299,7 +308,7 @@ impl ProjectStateAction for MoveClipAction {
             &self.id,
             &self.to_track,
             self.to_pos,
-            state.bpm,
+            &state.tempo,
             &self.ignore,
             &mut state.tx,
         );
@@ -351,21 +360,15 @@ impl CutClipAction {
 impl ProjectStateAction for CutClipAction {
     fn apply(&mut self, state: &mut ToniqueProjectState) {
         if let Some(track) = state.track_service.get(&self.track) {
-            self.previous = track.cut_clip_at(self.at, state.bpm, &mut state.tx);
+            self.previous = track.cut_clip_at(self.at, &state.tempo, &mut state.tx);
         }
     }
     fn undo(&mut self, state: &mut ToniqueProjectState) {
         if let Some((original, _, added)) = self.previous.clone() {
             if let Some(track) = state.track_service.get(&self.track) {
                 track.delete_clips(&vec![added.id.clone()], &mut state.tx);
-                track.resize_clip_skip_overlap_check(
-                    &original.id,
-                    original.trim_start,
-                    original.trim_end,
-                    original.position,
-                    &mut state.tx,
-                );
             }
+            state.track_service.update_clip(original, &mut state.tx);
         }
     }
     fn name(&self) -> &str {
@@ -419,7 +422,7 @@ impl ProjectStateAction for DuplicateClipAction {
             (self.added_clips, self.deleted_clips) = state.track_service.duplicate_clips(
                 &self.ids,
                 self.bounds,
-                state.bpm,
+                &state.tempo,
                 &mut state.tx,
             );
         }
@@ -477,7 +480,7 @@ impl ProjectStateAction for ResizeClipAction {
             self.start,
             self.end,
             self.pos,
-            state.bpm,
+            &state.tempo,
             &mut state.tx,
         );
     }
@@ -513,3 +516,253 @@ impl ProjectStateAction for ResizeClipAction {
         "Resize clip"
     }
 }
+
+pub struct UpdateClipAction {
+    old: Option<ClipCore>,
+    new: ClipCore,
+}
+
+impl UpdateClipAction {
+    pub fn new(clip: ClipCore) -> Self {
+        Self {
+            old: None,
+            new: clip,
+        }
+    }
+}
+
+impl ProjectStateAction for UpdateClipAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        self.old = state
+            .track_service
+            .update_clip(self.new.clone(), &mut state.tx);
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        if let Some(old) = self.old.clone() {
+            state.track_service.update_clip(old, &mut state.tx);
+        }
+    }
+    fn name(&self) -> &str {
+        "Update clip"
+    }
+}
+
+pub struct SetTempoAction {
+    old: TempoMap,
+    new: TempoMap,
+}
+
+impl SetTempoAction {
+    pub fn new(old: TempoMap, new: TempoMap) -> Self {
+        Self { old, new }
+    }
+}
+
+impl ProjectStateAction for SetTempoAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        state.set_tempo(self.new.clone());
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        state.set_tempo(self.old.clone());
+    }
+    fn name(&self) -> &str {
+        "Set tempo"
+    }
+}
+
+pub struct SetMeterAction {
+    old: Meter,
+    new: Meter,
+}
+
+impl SetMeterAction {
+    pub fn new(old: Meter, new: Meter) -> Self {
+        Self { old, new }
+    }
+}
+
+impl ProjectStateAction for SetMeterAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        state.set_meter(self.new.clone());
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        state.set_meter(self.old.clone());
+    }
+    fn name(&self) -> &str {
+        "Set time signature"
+    }
+}
+
+pub struct SetTakesAction {
+    track: String,
+    old: TakeLanes,
+    new: TakeLanes,
+}
+
+impl SetTakesAction {
+    pub fn new(track: &String, old: TakeLanes, new: TakeLanes) -> Self {
+        Self {
+            track: track.to_string(),
+            old,
+            new,
+        }
+    }
+}
+
+impl ProjectStateAction for SetTakesAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        state.track_service.set_takes(&self.track, self.new.clone());
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        state.track_service.set_takes(&self.track, self.old.clone());
+    }
+    fn name(&self) -> &str {
+        "Set takes"
+    }
+}
+
+pub struct SetMidiClipsAction {
+    track: String,
+    old: Vec<MidiClipCore>,
+    new: Vec<MidiClipCore>,
+}
+
+impl SetMidiClipsAction {
+    pub fn new(track: &String, old: Vec<MidiClipCore>, new: Vec<MidiClipCore>) -> Self {
+        Self {
+            track: track.to_string(),
+            old,
+            new,
+        }
+    }
+}
+
+impl ProjectStateAction for SetMidiClipsAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        state
+            .track_service
+            .set_midi_clips(&self.track, self.new.clone(), &mut state.tx);
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        state
+            .track_service
+            .set_midi_clips(&self.track, self.old.clone(), &mut state.tx);
+    }
+    fn name(&self) -> &str {
+        "Set MIDI clips"
+    }
+}
+
+pub struct SetMidiNotesAction {
+    track: String,
+    clip: String,
+    old: Vec<MidiNote>,
+    new: Vec<MidiNote>,
+}
+
+impl SetMidiNotesAction {
+    pub fn new(track: &String, clip: &String, old: Vec<MidiNote>, new: Vec<MidiNote>) -> Self {
+        Self {
+            track: track.to_string(),
+            clip: clip.to_string(),
+            old,
+            new,
+        }
+    }
+}
+
+impl ProjectStateAction for SetMidiNotesAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        state.track_service.set_midi_notes(
+            &self.track,
+            &self.clip,
+            self.new.clone(),
+            &mut state.tx,
+        );
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        state.track_service.set_midi_notes(
+            &self.track,
+            &self.clip,
+            self.old.clone(),
+            &mut state.tx,
+        );
+    }
+    fn name(&self) -> &str {
+        "Edit MIDI notes"
+    }
+}
+
+/// Notes of MIDI clips edited by a transform
+pub struct TransformMidiAction {
+    name: &'static str,
+    track: String,
+    /// Clip ids with their notes before and after the transform
+    clips: Vec<(String, Vec<MidiNote>, Vec<MidiNote>)>,
+}
+
+impl TransformMidiAction {
+    pub fn new(
+        name: &'static str,
+        track: &String,
+        clips: Vec<(String, Vec<MidiNote>, Vec<MidiNote>)>,
+    ) -> Self {
+        Self {
+            name,
+            track: track.to_string(),
+            clips,
+        }
+    }
+}
+
+impl ProjectStateAction for TransformMidiAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        for (clip, _, new) in self.clips.iter() {
+            state
+                .track_service
+                .set_midi_notes(&self.track, clip, new.clone(), &mut state.tx);
+        }
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        for (clip, old, _) in self.clips.iter() {
+            state
+                .track_service
+                .set_midi_notes(&self.track, clip, old.clone(), &mut state.tx);
+        }
+    }
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+pub struct SetInstrumentAction {
+    track: String,
+    old: Option<InstrumentParams>,
+    new: InstrumentParams,
+}
+
+impl SetInstrumentAction {
+    pub fn new(track: &String, old: Option<InstrumentParams>, new: InstrumentParams) -> Self {
+        Self {
+            track: track.to_string(),
+            old,
+            new,
+        }
+    }
+}
+
+impl ProjectStateAction for SetInstrumentAction {
+    fn apply(&mut self, state: &mut ToniqueProjectState) {
+        state
+            .track_service
+            .set_instrument(&self.track, Some(self.new.clone()), &mut state.tx);
+    }
+    fn undo(&mut self, state: &mut ToniqueProjectState) {
+        state
+            .track_service
+            .set_instrument(&self.track, self.old.clone(), &mut state.tx);
+    }
+    fn name(&self) -> &str {
+        "Set instrument"
+    }
+}
@@ -3,25 +3,57 @@ mod services;
 #[cfg(test)]
 mod tests;
 use crate::{
+    analysis::AudioInfo,
+    cache::{AUDIO_ANALYSIS_CACHE, TEMPO_CACHE, TempoEstimate},
+    config::Config,
     core::{
         clip::ClipCore,
         grid::GridService,
-        message::{GuiToPlayerMsg, ProcessToGuiMsg},
+        instrument::{InstrumentParams, SamplerParams},
+        keyboard::VirtualKeyboard,
+        message::{GuiToPlayerMsg, MidiToPlayerMsg, ProcessToGuiMsg},
+        meter::{Meter, TimeSignature},
         metrics::GlobalMetrics,
+        metronome::{ClickSample, MetronomeSettings},
+        midi::{
+            DEFAULT_MIDI_CLIP_LENGTH, MidiClipCore, MidiNote,
+            transform::{MidiTransform, TransformSettings},
+        },
+        midi_learn::{MidiBinding, MidiLearn, MidiTarget},
+        project::ProjectFile,
+        recording::{MidiRecordMode, record_into},
         state::{
             action::{
                 AddClipsAction, AddTrackAction, BatchAction, CutClipAction, DeleteClipsAction,
                 DeleteTrackAction, DuplicateClipAction, DuplicateTrackAction, MoveClipAction,
-                ProjectStateAction, ResizeClipAction, SetMutableTrackAction, SetVolumeAction,
+                ProjectStateAction, ResizeClipAction, SetInstrumentAction, SetMeterAction,
+                SetMidiClipsAction, SetMidiNotesAction, SetMutableTrackAction, SetTakesAction,
+                SetTempoAction, SetVolumeAction, TransformMidiAction, UpdateClipAction,
+            },
+            services::{
+                midi_learn::{MidiLearnService, target_name},
+                recording::{RecordingService, add_passes},
+                track::TrackService,
             },
-            services::track::TrackService,
         },
-        track::{MutableTrackCore, TrackCore, TrackReferenceCore},
+        sync::SyncSettings,
+        take::TakeLanes,
+        tempo::{TempoMap, TempoPoint},
+        track::{
+            MonitorMode, MutableTrackCore, TrackCore, TrackInput, TrackReferenceCore, TrackType,
+        },
     },
-    ui::{effect::UIEffect, effects::EffectId},
+    ui::{effect::UIEffect, effects::EffectId, midi_learn::ParamLearn},
 };
 use rtrb::{Consumer, Producer};
-use std::{mem::take, path::PathBuf};
+use std::{
+    mem::take,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Shortest clip in beats created when splitting at transients
+const MIN_SPLIT_LENGTH: f32 = 1. / 32.;
 
 #[derive(Clone, Debug)]
 enum ProjectStatePendingAction {
@@ -35,14 +67,26 @@ pub enum PlaybackState {
 }
 
 pub struct ToniqueProjectState {
-    bpm: f32,
+    tempo: TempoMap,
     playback_position: f32,
     playback_state: PlaybackState,
+    recording: bool,
     preview_playback_state: PlaybackState,
     preview_position: usize,
+    /// Number of channels of the output device
+    output_channels: usize,
+    /// Number of channels of the input device
+    input_channels: usize,
+    sample_rate: usize,
+    /// Rate of the input device, `None` without input
+    input_sample_rate: Option<usize>,
+    /// Start and end beats of the loop region
+    loop_region: Option<(f32, f32)>,
+    loop_enabled: bool,
     pub metrics: GlobalMetrics,
     // Services
     track_service: TrackService,
+    recording_service: RecordingService,
     // Pending
     pending_actions: Vec<ProjectStatePendingAction>,
 
@@ -56,23 +100,56 @@ pub struct ToniqueProjectState {
     // Grid
     pub grid: GridService,
     metronome: bool,
+    metronome_settings: MetronomeSettings,
+    /// Click file being loaded, with whether it is the accent
+    loading_click: Option<(bool, AudioInfo)>,
 
     pub resized_clip: Option<(String, f32, f32, f32)>,
+    /// Clip being edited from the UI, not yet committed
+    pub edited_clip: Option<ClipCore>,
+    auto_crossfade: bool,
+    /// Warp dropped loops to the project tempo
+    conform_on_drop: bool,
+    /// Dropped clips conformed once the tempo of their file is estimated
+    pending_conforms: Vec<String>,
+    /// Clip shown in the clip editor
+    pub opened_clip: Option<String>,
+    /// Options of the MIDI transforms
+    pub transform_settings: TransformSettings,
+    /// Controllers bound to the track and effect controls
+    midi_learn_service: MidiLearnService,
+    keyboard: VirtualKeyboard,
+    sync: SyncSettings,
     // Panels
     pub left_panel_open: bool,
     pub bottom_panel_open: bool,
+    pub tempo_lane_open: bool,
+    pub settings_open: bool,
 }
 
 impl ToniqueProjectState {
-    pub fn new(tx: Producer<GuiToPlayerMsg>, rx: Consumer<ProcessToGuiMsg>) -> Self {
+    pub fn new(mut tx: Producer<GuiToPlayerMsg>, rx: Consumer<ProcessToGuiMsg>) -> Self {
+        let sync = SyncSettings {
+            send_clock: Config::load().midi_sync_output().is_some(),
+            ..Default::default()
+        };
+        let _ = tx.push(GuiToPlayerMsg::SetSync(sync));
         Self {
-            bpm: 120.,
+            tempo: TempoMap::default(),
             playback_position: 0.,
             playback_state: PlaybackState::Paused,
+            recording: false,
             preview_playback_state: PlaybackState::Paused,
             preview_position: 0,
+            output_channels: 2,
+            input_channels: 0,
+            sample_rate: 44100,
+            input_sample_rate: None,
+            loop_region: None,
+            loop_enabled: false,
             metrics: GlobalMetrics::new(),
             track_service: TrackService::new(),
+            recording_service: RecordingService::new(),
             pending_actions: Vec::new(),
             tx,
             rx,
@@ -81,25 +158,120 @@ impl ToniqueProjectState {
             batching: false,
             batch_buffer: Vec::new(),
             resized_clip: None,
+            edited_clip: None,
+            auto_crossfade: false,
+            conform_on_drop: false,
+            pending_conforms: Vec::new(),
+            opened_clip: None,
+            transform_settings: TransformSettings::default(),
+            midi_learn_service: MidiLearnService::new(Config::load().midi_mappings().to_vec()),
+            keyboard: VirtualKeyboard::default(),
+            sync,
             grid: GridService::new(),
             left_panel_open: true,
             bottom_panel_open: false,
+            tempo_lane_open: false,
+            settings_open: false,
             metronome: false,
+            metronome_settings: MetronomeSettings::default(),
+            loading_click: None,
         }
     }
     /// Update each frame the state
     pub fn update(&mut self) {
         self.handle_pending_actions();
         self.handle_messages();
+        self.apply_midi_controls();
+        self.handle_loading_click();
+        self.handle_closing_recordings();
+        self.handle_pending_conforms();
+    }
+    // Project
+    /// Save the tracks, the tempo, the time signatures and the MIDI bindings to `path`
+    pub fn save_project(&self, path: &Path) -> Result<(), String> {
+        ProjectFile {
+            tempo: self.tempo.clone(),
+            meter: self.grid.meter().clone(),
+            loop_region: self.loop_region,
+            master: self.track_service.master_core(),
+            tracks: self.track_service.cores(),
+            midi_bindings: self.midi_learn_service.learn().bindings.clone(),
+        }
+        .save(path)
+    }
+    /// Replace the project by the one saved at `path`, the history is cleared
+    pub fn open_project(&mut self, path: &Path) -> Result<(), String> {
+        let project = ProjectFile::load(path)?;
+        self.pause();
+        self.opened_clip = None;
+        self.edited_clip = None;
+        self.resized_clip = None;
+        self.track_service
+            .replace(project.master, project.tracks, &mut self.tx);
+        self.set_tempo(project.tempo);
+        self.set_meter(project.meter);
+        let (start, end) = project.loop_region.unwrap_or((0., 0.));
+        self.set_loop_region(start, end);
+        self.midi_learn_service.set_bindings(project.midi_bindings);
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        Ok(())
     }
-    // Bpm
+    // Tempo
+    /// Set the tempo of the tempo point active at the playhead. Changes are not saved in undo stack.
     pub fn set_bpm(&mut self, value: f32) {
-        self.bpm = value;
-        let _ = self.tx.push(GuiToPlayerMsg::UpdateBPM(value));
+        let mut tempo = self.tempo.clone();
+        let index = tempo.point_at(self.playback_position);
+        let point = tempo.points()[index];
+        tempo.set(
+            index,
+            TempoPoint {
+                bpm: value,
+                ..point
+            },
+        );
+        self.set_tempo(tempo);
     }
-
+    /// Tempo at the playhead
     pub fn bpm(&self) -> f32 {
-        self.bpm
+        self.tempo.bpm_at(self.playback_position)
+    }
+    /// Replace the tempo map. Changes are not saved in undo stack.
+    pub fn set_tempo(&mut self, tempo: TempoMap) {
+        self.tempo = tempo.clone();
+        let _ = self.tx.push(GuiToPlayerMsg::UpdateTempo(Arc::new(tempo)));
+    }
+    /// Replace the tempo map and save in undo stack given `old`.
+    pub fn commit_tempo(&mut self, old: TempoMap, new: TempoMap) {
+        let action = SetTempoAction::new(old, new);
+        self.apply_action(Box::new(action));
+    }
+    pub fn tempo(&self) -> &TempoMap {
+        &self.tempo
+    }
+    // Meter
+    /// Replace the time signatures. Changes are not saved in undo stack.
+    pub fn set_meter(&mut self, meter: Meter) {
+        self.grid.set_meter(meter.clone());
+        let _ = self.tx.push(GuiToPlayerMsg::UpdateMeter(meter));
+    }
+    /// Set the time signature from `bar` and save in undo stack
+    pub fn set_time_signature(&mut self, bar: i32, signature: TimeSignature) {
+        let old = self.grid.meter().clone();
+        let mut new = old.clone();
+        new.set(bar, signature);
+        self.apply_action(Box::new(SetMeterAction::new(old, new)));
+    }
+    /// Remove the meter change at `bar` and save in undo stack
+    pub fn remove_meter_change(&mut self, bar: i32) {
+        let old = self.grid.meter().clone();
+        let mut new = old.clone();
+        new.remove(bar);
+        self.apply_action(Box::new(SetMeterAction::new(old, new)));
+    }
+    /// Time signature at the playhead
+    pub fn time_signature(&self) -> TimeSignature {
+        self.grid.meter().bar_at(self.playback_position).signature
     }
     // Playback position
     pub fn set_playback_position(&mut self, value: f32) {
@@ -112,6 +284,7 @@ impl ToniqueProjectState {
     // Transport state
     pub fn pause(&mut self) {
         self.playback_state = PlaybackState::Paused;
+        self.recording = false;
         let _ = self.tx.push(GuiToPlayerMsg::Pause);
     }
     pub fn play(&mut self) {
@@ -119,6 +292,107 @@ impl ToniqueProjectState {
         self.preview_playback_state = PlaybackState::Paused;
         let _ = self.tx.push(GuiToPlayerMsg::Play);
     }
+    /// MIDI clock sent and external transport followed
+    pub fn sync(&self) -> SyncSettings {
+        self.sync
+    }
+    pub fn set_sync(&mut self, sync: SyncSettings) {
+        self.sync = sync;
+        let _ = self.tx.push(GuiToPlayerMsg::SetSync(sync));
+    }
+    /// Start or stop recording the armed tracks. Recording starts the transport when paused.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+        if !recording {
+            let _ = self.tx.push(GuiToPlayerMsg::StopRecording);
+            return;
+        }
+        self.playback_state = PlaybackState::Playing;
+        self.preview_playback_state = PlaybackState::Paused;
+        let recorders = self
+            .recording_service
+            .open_recorders(self.track_service.tracks(), self.sample_rate);
+        let _ = self.tx.push(GuiToPlayerMsg::StartRecording(recorders));
+    }
+    pub fn recording(&self) -> bool {
+        self.recording
+    }
+    /// Beat where the current recording starts
+    pub fn record_start(&self) -> Option<f32> {
+        self.recording_service.start()
+    }
+    /// Frames lost by the last recording because the disk was too slow
+    pub fn dropped_frames(&self) -> usize {
+        self.recording_service.dropped_frames()
+    }
+    pub fn clear_dropped_frames(&mut self) {
+        self.recording_service.clear_dropped_frames();
+    }
+    /// Peaks of the blocks recorded by a track
+    pub fn record_peaks(&self, id: &str) -> Option<&Vec<f32>> {
+        self.recording_service.peaks(id)
+    }
+    pub fn midi_record_mode(&self) -> MidiRecordMode {
+        self.recording_service.midi_record_mode()
+    }
+    pub fn set_midi_record_mode(&mut self, mode: MidiRecordMode) {
+        self.recording_service.set_midi_record_mode(mode);
+    }
+    /// Range in beats and notes recorded by a MIDI track, drawn while recording
+    pub fn recorded_midi(&self, id: &str) -> Option<((f32, f32), Vec<MidiNote>)> {
+        self.recording_service
+            .recorded_midi(id, self.playback_position, self.active_loop())
+    }
+    /// Follow the recording to `beat`, the passes are merged in the clips in loop overdub
+    fn advance_midi_recording(&mut self, beat: f32) {
+        if let Some(end) = self
+            .recording_service
+            .advance_midi(beat, self.active_loop())
+        {
+            let takes = self.recording_service.take_midi(end, self.active_loop());
+            self.write_midi_recordings(takes);
+        }
+    }
+    /// Write the recorded `takes` in the clips of their tracks
+    fn write_midi_recordings(&mut self, takes: Vec<(String, MidiClipCore)>) {
+        let mode = self.recording_service.midi_record_mode();
+        if takes.is_empty() {
+            return;
+        }
+        self.begin_batch();
+        for (track_id, take) in takes {
+            let Some(clips) = self
+                .track_service
+                .get(&track_id)
+                .map(|t| t.midi_clips.clone())
+            else {
+                continue;
+            };
+            self.set_midi_clips(&track_id, record_into(&clips, take, mode));
+        }
+        self.commit_batch();
+    }
+    /// Loop playback from `start` to `end`, or clear the loop when the range is empty
+    pub fn set_loop_region(&mut self, start: f32, end: f32) {
+        let (start, end) = (start.min(end).max(0.), start.max(end).max(0.));
+        self.loop_region = (end > start).then_some((start, end));
+        self.loop_enabled = self.loop_region.is_some();
+        let _ = self.tx.push(GuiToPlayerMsg::SetLoop(self.active_loop()));
+    }
+    pub fn loop_region(&self) -> Option<(f32, f32)> {
+        self.loop_region
+    }
+    pub fn toggle_loop(&mut self) {
+        self.loop_enabled = !self.loop_enabled && self.loop_region.is_some();
+        let _ = self.tx.push(GuiToPlayerMsg::SetLoop(self.active_loop()));
+    }
+    pub fn loop_enabled(&self) -> bool {
+        self.loop_enabled
+    }
+    /// Loop region played, `None` when looping is disabled
+    fn active_loop(&self) -> Option<(f32, f32)> {
+        self.loop_region.filter(|_| self.loop_enabled)
+    }
     pub fn pause_preview(&mut self) {
         self.preview_playback_state = PlaybackState::Paused;
         let _ = self.tx.push(GuiToPlayerMsg::PausePreview());
@@ -141,6 +415,90 @@ impl ToniqueProjectState {
     pub fn metronome(&self) -> bool {
         self.metronome
     }
+    pub fn set_metronome_settings(&mut self, settings: MetronomeSettings) {
+        self.metronome_settings = settings.clone();
+        let _ = self.tx.push(GuiToPlayerMsg::UpdateMetronome(settings));
+    }
+    pub fn metronome_settings(&self) -> &MetronomeSettings {
+        &self.metronome_settings
+    }
+    /// Load the file at `path` as the accent or tick of the metronome once decoded
+    pub fn load_click_sample(&mut self, path: PathBuf, accent: bool) {
+        self.loading_click = AUDIO_ANALYSIS_CACHE
+            .get_or_analyze(path)
+            .map(|info| (accent, info));
+    }
+    /// Number of channels of the output device
+    pub fn output_channels(&self) -> usize {
+        self.output_channels
+    }
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+    /// Number of channels of the input device, 0 without input
+    pub fn input_channels(&self) -> usize {
+        self.input_channels
+    }
+    /// Why the audio tracks cannot record, when the input runs at another rate than the output
+    pub fn input_error(&self) -> Option<String> {
+        self.input_sample_rate
+            .filter(|rate| *rate != self.sample_rate)
+            .map(|rate| {
+                format!(
+                    "The input runs at {} Hz and the output at {} Hz, recording needs the same rate",
+                    rate, self.sample_rate
+                )
+            })
+    }
+    /// Crossfade overlapping clips instead of trimming them when adding clips
+    pub fn toggle_auto_crossfade(&mut self) {
+        self.auto_crossfade = !self.auto_crossfade;
+    }
+    pub fn auto_crossfade(&self) -> bool {
+        self.auto_crossfade
+    }
+    /// Warp dropped files with a detected tempo to the project tempo
+    pub fn toggle_conform_on_drop(&mut self) {
+        self.conform_on_drop = !self.conform_on_drop;
+    }
+    pub fn conform_on_drop(&self) -> bool {
+        self.conform_on_drop
+    }
+    /// Warp a dropped clip with the tempo estimated for the file browser.
+    /// Returns false while the estimate is pending, see `conform_when_estimated`.
+    pub fn conform_dropped_clip(&self, clip: &mut ClipCore) -> bool {
+        match TEMPO_CACHE.get(&clip.audio.path) {
+            TempoEstimate::Pending => false,
+            TempoEstimate::Done(bpm) => {
+                if let Some(bpm) = bpm {
+                    clip.conform_to_bpm(bpm, &self.tempo);
+                }
+                true
+            }
+        }
+    }
+    /// Conform an added clip once the tempo of its file is estimated
+    pub fn conform_when_estimated(&mut self, id: &str) {
+        self.pending_conforms.push(id.to_string());
+    }
+    fn handle_pending_conforms(&mut self) {
+        if self.pending_conforms.is_empty() {
+            return;
+        }
+        for id in std::mem::take(&mut self.pending_conforms) {
+            // Clips removed in the meantime are forgotten
+            let Some(mut clip) = self.clip(&id) else {
+                continue;
+            };
+            if self.conform_dropped_clip(&mut clip) {
+                if clip.warp.enabled {
+                    self.update_clip(clip);
+                }
+            } else {
+                self.pending_conforms.push(id);
+            }
+        }
+    }
 
     pub fn playback_state(&self) -> PlaybackState {
         self.playback_state
@@ -179,14 +537,320 @@ impl ToniqueProjectState {
     pub fn set_all_close(&mut self, close: bool) {
         self.track_service.set_all_close(close);
     }
+    // Takes
+    /// Play `take` from `start` to `end` in the comp of a track
+    pub fn comp_take(&mut self, track_id: &String, take: &str, start: f32, end: f32) {
+        let Some(old) = self.track_service.get(track_id).map(|t| t.takes.clone()) else {
+            return;
+        };
+        let mut new = old.clone();
+        if let Some(range) = new.select(take, start, end, &self.tempo) {
+            self.begin_batch();
+            self.set_takes(track_id, old, new, range);
+            self.commit_batch();
+        }
+    }
+    /// Keep the comp as regular clips and remove the takes of a track
+    pub fn flatten_takes(&mut self, track_id: &String) {
+        if let Some(old) = self.track_service.get(track_id).map(|t| t.takes.clone()) {
+            let action = SetTakesAction::new(track_id, old, TakeLanes::new());
+            self.apply_action(Box::new(action));
+        }
+    }
+    /// Show or hide the take lanes of a track
+    pub fn toggle_take_lanes(&mut self, track_id: &String) {
+        if let Some(track) = self.track_service.get(track_id) {
+            track.takes.open = !track.takes.open;
+        }
+    }
+    /// Replace the takes of a track and add the clips of the comp between `start` and `end`
+    fn set_takes(
+        &mut self,
+        track_id: &String,
+        old: TakeLanes,
+        new: TakeLanes,
+        (start, end): (f32, f32),
+    ) {
+        let clips = new.comp_clips(start, end, &self.tempo);
+        self.apply_action(Box::new(SetTakesAction::new(track_id, old, new)));
+        if !clips.is_empty() {
+            self.add_clips(track_id, clips);
+        }
+    }
+    // MIDI clips
+    /// Whether the track plays MIDI clips, audio clips can't be placed on it
+    pub fn is_midi_track(&mut self, track_id: &String) -> bool {
+        self.track_service
+            .get(track_id)
+            .is_some_and(|track| track.kind == TrackType::Midi)
+    }
+    /// Replace the MIDI clips of a track
+    pub fn set_midi_clips(&mut self, track_id: &String, clips: Vec<MidiClipCore>) {
+        let Some(old) = self
+            .track_service
+            .get(track_id)
+            .map(|t| t.midi_clips.clone())
+        else {
+            return;
+        };
+        self.apply_action(Box::new(SetMidiClipsAction::new(track_id, old, clips)));
+    }
+    /// MIDI clip with the id of its track
+    pub fn midi_clip(&self, id: &str) -> Option<(String, MidiClipCore)> {
+        self.track_service
+            .midi_clip(id)
+            .map(|(track, clip)| (track.clone(), clip.clone()))
+    }
+    /// Replace the notes of a MIDI clip
+    pub fn set_midi_notes(&mut self, track_id: &String, clip_id: &String, notes: Vec<MidiNote>) {
+        let Some(old) = self
+            .track_service
+            .midi_clip(clip_id)
+            .map(|(_, clip)| clip.notes.clone())
+        else {
+            return;
+        };
+        self.apply_action(Box::new(SetMidiNotesAction::new(
+            track_id, clip_id, old, notes,
+        )));
+    }
+    /// Apply a transform to all the notes of MIDI clips of a track
+    pub fn transform_midi_clips(
+        &mut self,
+        track_id: &String,
+        clip_ids: &[String],
+        transform: MidiTransform,
+    ) {
+        let mut clips = Vec::new();
+        for id in clip_ids {
+            if let Some((_, clip)) = self.track_service.midi_clip(id) {
+                let all: Vec<usize> = (0..clip.notes.len()).collect();
+                let (notes, _) = Self::transformed(clip, &all, transform);
+                clips.push((id.clone(), clip.notes.clone(), notes));
+            }
+        }
+        self.apply_action(Box::new(TransformMidiAction::new(
+            transform.name(),
+            track_id,
+            clips,
+        )));
+    }
+    /// Apply a transform to the `selected` notes of a MIDI clip.
+    /// Returns the indexes of the transformed notes once sorted.
+    pub fn transform_midi_notes(
+        &mut self,
+        track_id: &String,
+        clip_id: &str,
+        selected: &[usize],
+        transform: MidiTransform,
+    ) -> Vec<usize> {
+        let Some((_, clip)) = self.track_service.midi_clip(clip_id) else {
+            return Vec::new();
+        };
+        let (notes, selected) = Self::transformed(clip, selected, transform);
+        let clips = vec![(clip_id.to_string(), clip.notes.clone(), notes)];
+        self.apply_action(Box::new(TransformMidiAction::new(
+            transform.name(),
+            track_id,
+            clips,
+        )));
+        selected
+    }
+    /// Notes of `clip` with the `selected` ones transformed, sorted by start,
+    /// and the new indexes of the selected notes
+    fn transformed(
+        clip: &MidiClipCore,
+        selected: &[usize],
+        transform: MidiTransform,
+    ) -> (Vec<MidiNote>, Vec<usize>) {
+        let mut indexes: Vec<usize> = selected
+            .iter()
+            .copied()
+            .filter(|index| *index < clip.notes.len())
+            .collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        let mut edited: Vec<MidiNote> = indexes.iter().map(|index| clip.notes[*index]).collect();
+        transform.apply(&mut edited, clip.position, &mut rand::rng());
+
+        let mut tagged: Vec<(MidiNote, bool)> =
+            clip.notes.iter().map(|note| (*note, false)).collect();
+        for (index, note) in indexes.iter().zip(edited) {
+            tagged[*index] = (note, true);
+        }
+        tagged.sort_by(|(a, _), (b, _)| a.start.total_cmp(&b.start));
+        let selected = tagged
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, selected))| *selected)
+            .map(|(index, _)| index)
+            .collect();
+        (tagged.into_iter().map(|(note, _)| note).collect(), selected)
+    }
+    /// Play an event on the instrument of a MIDI track without recording it
+    pub fn play_midi(&mut self, track_id: &str, event: MidiToPlayerMsg) {
+        let _ = self
+            .tx
+            .push(GuiToPlayerMsg::PlayMidi(track_id.to_string(), event));
+    }
+    // Computer keyboard
+    pub fn keyboard(&self) -> &VirtualKeyboard {
+        &self.keyboard
+    }
+    /// Toggle playing the armed MIDI tracks from the computer keyboard
+    pub fn toggle_keyboard(&mut self) {
+        self.keyboard.active = !self.keyboard.active;
+        if !self.keyboard.active {
+            self.release_keyboard();
+        }
+    }
+    /// Play the key `semitone` above the lowest key of the computer keyboard
+    pub fn press_keyboard_key(&mut self, semitone: u8) {
+        if let Some(event) = self.keyboard.press(semitone) {
+            let _ = self.tx.push(GuiToPlayerMsg::KeyboardMidi(event));
+        }
+    }
+    pub fn release_keyboard_key(&mut self, semitone: u8) {
+        if let Some(event) = self.keyboard.release(semitone) {
+            let _ = self.tx.push(GuiToPlayerMsg::KeyboardMidi(event));
+        }
+    }
+    /// Stop the notes held on the computer keyboard
+    pub fn release_keyboard(&mut self) {
+        for event in self.keyboard.release_all() {
+            let _ = self.tx.push(GuiToPlayerMsg::KeyboardMidi(event));
+        }
+    }
+    pub fn shift_keyboard_octave(&mut self, octaves: i8) {
+        self.keyboard.shift_octave(octaves);
+    }
+    pub fn shift_keyboard_velocity(&mut self, up: bool) {
+        self.keyboard.shift_velocity(up);
+    }
+    // MIDI learn
+    pub fn midi_learn(&self) -> &MidiLearn {
+        self.midi_learn_service.learn()
+    }
+    /// Toggle the mode where clicking a control binds it to the next controller moved
+    pub fn toggle_midi_learn(&mut self) {
+        self.midi_learn_service.toggle();
+    }
+    /// Wait for a controller to bind `target`, or stop waiting when it was already
+    pub fn learn_midi(&mut self, target: MidiTarget) {
+        self.midi_learn_service.learn_target(target);
+    }
+    /// Controller bindings of the project, to edit their range
+    pub fn midi_bindings_mut(&mut self) -> &mut Vec<MidiBinding> {
+        self.midi_learn_service.bindings_mut()
+    }
+    /// Replace the global controller mapping and save it in the config
+    pub fn set_global_midi_bindings(&mut self, bindings: Vec<MidiBinding>) {
+        self.midi_learn_service.set_global(bindings);
+    }
+    /// Move a project binding to the global mapping, driving the same control of the selected track
+    pub fn make_midi_binding_global(&mut self, index: usize) {
+        self.midi_learn_service
+            .make_global(index, &mut self.track_service);
+    }
+    /// MIDI learn state of the parameters of an effect
+    pub fn effect_learn(&self, track_id: &str, effect_id: &str) -> ParamLearn {
+        self.midi_learn_service.effect_learn(track_id, effect_id)
+    }
+    /// Name of the control driven by `target`
+    pub fn midi_target_name(&mut self, target: &MidiTarget) -> String {
+        target_name(target, &mut self.track_service)
+    }
+    /// Bind the control being learned or drive the controls bound to the controller of `event`
+    fn handle_midi_control(&mut self, event: &MidiToPlayerMsg) {
+        self.midi_learn_service
+            .handle_control(event, &mut self.track_service);
+    }
+    /// Move the controls driven by controllers toward their controller value
+    fn apply_midi_controls(&mut self) {
+        self.midi_learn_service
+            .apply_controls(&mut self.track_service, &mut self.tx);
+    }
+    /// Replace the instrument of a MIDI track
+    pub fn set_instrument(&mut self, track_id: &String, instrument: InstrumentParams) {
+        let Some(old) = self
+            .track_service
+            .get(track_id)
+            .filter(|track| track.kind == TrackType::Midi)
+            .map(|track| track.instrument.clone())
+        else {
+            return;
+        };
+        self.apply_action(Box::new(SetInstrumentAction::new(
+            track_id, old, instrument,
+        )));
+    }
+    /// Load a sample dropped on a MIDI track: a drum rack gets it on its first empty pad,
+    /// other instruments are replaced by a sampler playing it
+    pub fn drop_sample(&mut self, track_id: &String, audio: AudioInfo) {
+        let instrument = self
+            .track_service
+            .get(track_id)
+            .and_then(|track| track.instrument.clone());
+        if let Some(InstrumentParams::DrumRack(mut rack)) = instrument {
+            if let Some(pad) = rack.pads.iter_mut().find(|pad| pad.sample.is_none()) {
+                pad.sample = Some(audio);
+                self.set_instrument(track_id, InstrumentParams::DrumRack(rack));
+            }
+        } else {
+            self.set_instrument(
+                track_id,
+                InstrumentParams::Sampler(SamplerParams::new(audio)),
+            );
+        }
+    }
+    /// Create an empty MIDI clip at `position`, shortened to fit before the next clip.
+    /// Returns the id of the clip.
+    pub fn add_midi_clip(&mut self, track_id: &String, position: f32) -> Option<String> {
+        let mut clips = self.track_service.get(track_id)?.midi_clips.clone();
+        if clips
+            .iter()
+            .any(|clip| clip.position <= position && position < clip.end())
+        {
+            return None;
+        }
+        let next = clips
+            .iter()
+            .map(|clip| clip.position)
+            .filter(|start| *start > position)
+            .fold(f32::INFINITY, f32::min);
+        let clip = MidiClipCore::new(position, DEFAULT_MIDI_CLIP_LENGTH.min(next - position));
+        let id = clip.id.clone();
+        clips.push(clip);
+        clips.sort_by(|a, b| a.position.total_cmp(&b.position));
+        self.set_midi_clips(track_id, clips);
+        Some(id)
+    }
+    /// Delete the MIDI clips of a track from their ids
+    pub fn delete_midi_clips(&mut self, track_id: &String, ids: &[String]) {
+        let Some(mut clips) = self
+            .track_service
+            .get(track_id)
+            .map(|t| t.midi_clips.clone())
+        else {
+            return;
+        };
+        clips.retain(|clip| !ids.contains(&clip.id));
+        self.set_midi_clips(track_id, clips);
+    }
     // Clips
     /// Add clips and fix all overlaps on the track.
     pub fn add_clips(&mut self, track_id: &String, clips: Vec<ClipCore>) {
+        if self.is_midi_track(track_id) {
+            return;
+        }
         let action = AddClipsAction::new(clips, track_id);
         self.apply_action(Box::new(action));
     }
     /// Move clip to a new position and a new track fixing all overlaps on this track.
     pub fn move_clip(&mut self, id: &String, to_track: &String, to_pos: f32, ignore: &Vec<String>) {
+        if self.is_midi_track(to_track) {
+            return;
+        }
         let action = MoveClipAction::new(id, to_track, to_pos, ignore);
         self.apply_action(Box::new(action));
     }
@@ -200,6 +864,27 @@ impl ToniqueProjectState {
         let action = CutClipAction::new(track_id, position);
         self.apply_action(Box::new(action));
     }
+    /// Cut the clip at each of its transients
+    pub fn split_clip_at_transients(&mut self, id: &str) {
+        let Some(clip) = self.clip(id) else {
+            return;
+        };
+        let Some(track_id) = self
+            .track_service
+            ._track_from_clip_id(&clip.id)
+            .map(|track| track.id.clone())
+        else {
+            return;
+        };
+        let length = clip.length(&self.tempo);
+        self.begin_batch();
+        for beats in clip.transients(&self.tempo) {
+            if beats > MIN_SPLIT_LENGTH && beats < length - MIN_SPLIT_LENGTH {
+                self.cut_clip_at(&track_id, clip.position + beats);
+            }
+        }
+        self.commit_batch();
+    }
     /// Duplicate clips fixing all overlaps on the tracks.
     pub fn duplicate_clips(&mut self, ids: &Vec<String>, bounds: Option<(f32, f32)>) {
         let action = DuplicateClipAction::new(ids, bounds);
@@ -218,6 +903,31 @@ impl ToniqueProjectState {
         let action = ResizeClipAction::new(id, start, end, pos);
         self.apply_action(Box::new(action));
     }
+    /// Edit clip settings for display only.
+    /// Use `update_clip` to apply them and add to undo stack.
+    pub fn edit_clip(&mut self, clip: ClipCore) {
+        self.edited_clip = Some(clip);
+    }
+    /// Replace the settings of a clip (channels, fades...) without overlap checks.
+    pub fn update_clip(&mut self, clip: ClipCore) {
+        self.edited_clip = None;
+        let action = UpdateClipAction::new(clip);
+        self.apply_action(Box::new(action));
+    }
+    /// Get a clip by id, including the edits not yet committed
+    pub fn clip(&self, id: &str) -> Option<ClipCore> {
+        if let Some(clip) = &self.edited_clip
+            && clip.id == id
+        {
+            return Some(clip.clone());
+        }
+        self.track_service.clip(id).cloned()
+    }
+    /// Show the clip in the clip editor of the bottom panel
+    pub fn open_clip_editor(&mut self, id: &str) {
+        self.opened_clip = Some(id.to_string());
+        self.bottom_panel_open = true;
+    }
     /// Add a effect to the track
     /// TODO: Action
     pub fn add_effect(&mut self, id: &String, effect_id: EffectId, index: usize) {
@@ -231,6 +941,13 @@ impl ToniqueProjectState {
             track.remove_effects(indexes, &mut self.tx);
         }
     }
+    /// Effects of a pad of the drum rack of a track. TODO: Action
+    pub fn pad_effects_mut(&mut self, id: &String, pad: usize) -> Option<&mut [UIEffect]> {
+        match self.track_service.get(id)?.instrument.as_mut()? {
+            InstrumentParams::DrumRack(rack) => Some(&mut rack.pads.get_mut(pad)?.effects),
+            _ => None,
+        }
+    }
     /// TODO: Action
     pub fn effects_mut(&mut self, id: &String) -> Option<&mut [UIEffect]> {
         if let Some(track) = self.track_service.get(id) {
@@ -252,6 +969,26 @@ impl ToniqueProjectState {
     pub fn set_mute(&mut self, id: String, mute: bool) {
         self.track_service.set_mute(id, mute, &mut self.tx);
     }
+    /// Arm or disarm a track for recording. Audio tracks are not armed when the input
+    /// cannot be recorded.
+    pub fn set_arm(&mut self, id: &String, arm: bool) {
+        let audio = self
+            .track_service
+            .get_reference(id)
+            .is_some_and(|track| track.kind == TrackType::Audio);
+        if arm && audio && self.input_error().is_some() {
+            return;
+        }
+        self.track_service.set_arm(id, arm, &mut self.tx);
+    }
+    /// Set the input channels recorded by a track
+    pub fn set_track_input(&mut self, id: &String, input: TrackInput) {
+        self.track_service.set_input(id, input, &mut self.tx);
+    }
+    /// Set when a track plays its input through its effects
+    pub fn set_track_monitor(&mut self, id: &String, monitor: MonitorMode) {
+        self.track_service.set_monitor(id, monitor, &mut self.tx);
+    }
     /// Toggle the solo button.
     pub fn toggle_solo(&mut self, id: String, modifier_pressed: bool) {
         self.track_service
@@ -360,14 +1097,108 @@ impl ToniqueProjectState {
         while let Ok(msg) = self.rx.pop() {
             match msg {
                 ProcessToGuiMsg::PlaybackPos(pos) => {
+                    self.advance_midi_recording(pos);
                     self.playback_position = pos;
                     self.playback_state = PlaybackState::Playing;
                 }
                 ProcessToGuiMsg::Metrics(metrics) => self.metrics = metrics,
                 ProcessToGuiMsg::PreviewPos(pos) => self.preview_position = pos,
+                ProcessToGuiMsg::DeviceConfig {
+                    output_channels,
+                    input_channels,
+                    sample_rate,
+                    input_sample_rate,
+                } => {
+                    self.output_channels = output_channels;
+                    self.input_channels = input_channels;
+                    self.sample_rate = sample_rate;
+                    self.input_sample_rate = input_sample_rate;
+                }
+                ProcessToGuiMsg::RecordingStarted(beat, latency) => {
+                    self.recording_service.started(beat, latency);
+                }
+                ProcessToGuiMsg::RecordingPeaks(first, peaks) => {
+                    self.recording_service.push_peaks(first, peaks);
+                }
+                ProcessToGuiMsg::RecordedMidi {
+                    beat,
+                    offset,
+                    event,
+                } => {
+                    self.advance_midi_recording(beat);
+                    let frame = self.tempo.beats_to_frames(beat, self.sample_rate) + offset;
+                    let beat = self.tempo.frames_to_beats(frame, self.sample_rate);
+                    self.recording_service.push_midi(beat, event);
+                }
+                ProcessToGuiMsg::MidiControl(event) => {
+                    self.handle_midi_control(&event);
+                }
+                ProcessToGuiMsg::ExternalTransport { playing, bpm } => {
+                    if let Some(bpm) = bpm
+                        && bpm != self.bpm()
+                    {
+                        self.set_bpm(bpm);
+                    }
+                    if playing {
+                        self.playback_state = PlaybackState::Playing;
+                        self.preview_playback_state = PlaybackState::Paused;
+                    } else {
+                        self.playback_state = PlaybackState::Paused;
+                        self.recording = false;
+                    }
+                }
+                ProcessToGuiMsg::RecordingStopped(recorders) => {
+                    let takes = self
+                        .recording_service
+                        .stop_midi(self.playback_position, self.active_loop());
+                    self.write_midi_recordings(takes);
+                    self.recording_service.stopped(
+                        recorders,
+                        self.active_loop(),
+                        self.playback_position,
+                    );
+                }
             }
         }
     }
+    /// Add the recorded files as clips once closed.
+    /// Recordings over a loop are split into takes and comped from the latest passes.
+    fn handle_closing_recordings(&mut self) {
+        let closed = self.recording_service.closed_recordings(&self.tempo);
+        if closed.clips.is_empty() && closed.takes.is_empty() {
+            return;
+        }
+        self.begin_batch();
+        for (track_id, clips) in closed.clips {
+            self.add_clips(&track_id, clips);
+        }
+        for (track_id, passes) in closed.takes {
+            let Some(old) = self.track_service.get(&track_id).map(|t| t.takes.clone()) else {
+                continue;
+            };
+            let mut new = old.clone();
+            let range = add_passes(&mut new, passes, &self.tempo);
+            self.set_takes(&track_id, old, new, range);
+        }
+        self.commit_batch();
+    }
+    /// Send the loading click to the metronome once its file is decoded
+    fn handle_loading_click(&mut self) {
+        let Some((accent, info)) = &self.loading_click else {
+            return;
+        };
+        let Some(sample) = ClickSample::from_audio_info(info) else {
+            return;
+        };
+        let mut settings = self.metronome_settings.clone();
+        if *accent {
+            settings.accent = Some(sample);
+        } else {
+            settings.tick = Some(sample);
+        }
+        self.loading_click = None;
+        self.set_metronome_settings(settings);
+    }
     /// To make sure some action do not conflict, pending actions are handled during state updates
     fn handle_pending_actions(&mut self) {
         let pendings = take(&mut self.pending_actions);
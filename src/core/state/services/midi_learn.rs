@@ -0,0 +1,261 @@
+use crate::{
+    config::Config,
+    core::{
+        message::{GuiToPlayerMsg, MidiToPlayerMsg},
+        midi_learn::{MidiBinding, MidiLearn, MidiTarget, ratio_to_value, value_to_ratio},
+        state::services::track::TrackService,
+        track::{MAX_GAIN_DB, MIN_GAIN_DB},
+    },
+    ui::{effect::EffectParam, midi_learn::ParamLearn},
+    utils::parse_name,
+};
+use rtrb::Producer;
+use std::time::Instant;
+
+/// Service binding controllers to the track and effect controls and driving them
+pub struct MidiLearnService {
+    learn: MidiLearn,
+    /// Last time the controls driven by controllers moved
+    update: Instant,
+}
+
+impl MidiLearnService {
+    pub fn new(global: Vec<MidiBinding>) -> Self {
+        Self {
+            learn: MidiLearn::new(global),
+            update: Instant::now(),
+        }
+    }
+
+    pub fn learn(&self) -> &MidiLearn {
+        &self.learn
+    }
+
+    /// Toggle the mode where clicking a control binds it to the next controller moved
+    pub fn toggle(&mut self) {
+        self.learn.active = !self.learn.active;
+        self.learn.learning = None;
+    }
+
+    /// Wait for a controller to bind `target`, or stop waiting when it was already
+    pub fn learn_target(&mut self, target: MidiTarget) {
+        if self.learn.learning.as_ref() == Some(&target) {
+            self.learn.learning = None;
+        } else {
+            self.learn.learning = Some(target);
+        }
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Vec<MidiBinding> {
+        &mut self.learn.bindings
+    }
+
+    /// Replace the project bindings by the ones of an opened project
+    pub fn set_bindings(&mut self, bindings: Vec<MidiBinding>) {
+        self.learn.learning = None;
+        self.learn.bindings = bindings;
+    }
+
+    /// Replace the global controller mapping and save it in the config
+    pub fn set_global(&mut self, bindings: Vec<MidiBinding>) {
+        Config::load().set_midi_mappings(bindings.clone());
+        self.learn.global = bindings;
+    }
+
+    /// Move a project binding to the global mapping, driving the same control of the selected track
+    pub fn make_global(&mut self, index: usize, tracks: &mut TrackService) {
+        let Some(mut binding) = self.learn.bindings.get(index).cloned() else {
+            return;
+        };
+        binding.target = match &binding.target {
+            MidiTarget::TrackVolume(_) => MidiTarget::SelectedTrackVolume,
+            MidiTarget::EffectParam {
+                track_id,
+                effect_id,
+                param,
+            } => {
+                let Some(slot) = tracks.get(track_id).and_then(|track| {
+                    track
+                        .effects_mut()
+                        .iter()
+                        .position(|e| e.id() == *effect_id)
+                }) else {
+                    return;
+                };
+                MidiTarget::SelectedEffectParam {
+                    slot,
+                    param: *param,
+                }
+            }
+            target => target.clone(),
+        };
+        self.learn.bindings.remove(index);
+        let mut global = self.learn.global.clone();
+        global.retain(|b| (b.channel, b.controller) != (binding.channel, binding.controller));
+        global.push(binding);
+        self.set_global(global);
+    }
+
+    /// MIDI learn state of the parameters of an effect
+    pub fn effect_learn(&self, track_id: &str, effect_id: &str) -> ParamLearn {
+        let param = |target: &MidiTarget| match target {
+            MidiTarget::EffectParam {
+                track_id: t,
+                effect_id: e,
+                param,
+            } if t == track_id && e == effect_id => Some(*param),
+            _ => None,
+        };
+        ParamLearn {
+            active: self.learn.active,
+            learning: self.learn.learning.as_ref().and_then(param),
+            bound: self
+                .learn
+                .bindings
+                .iter()
+                .filter_map(|binding| param(&binding.target))
+                .collect(),
+            clicked: None,
+        }
+    }
+
+    /// Bind the control being learned or drive the controls bound to the controller of `event`
+    pub fn handle_control(&mut self, event: &MidiToPlayerMsg, tracks: &mut TrackService) {
+        if self.learn.learn(event) {
+            return;
+        }
+        if !self.learn.driving() {
+            self.update = Instant::now();
+        }
+        for (target, goal) in self.learn.targets(event) {
+            if let Some(target) = resolve_target(&target, tracks)
+                && let Some(ratio) = target_ratio(&target, tracks)
+            {
+                self.learn.drive(target, ratio, goal);
+            }
+        }
+    }
+
+    /// Move the controls driven by controllers toward their controller value
+    pub fn apply_controls(&mut self, tracks: &mut TrackService, tx: &mut Producer<GuiToPlayerMsg>) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.update).as_secs_f32();
+        self.update = now;
+        for (target, ratio) in self.learn.step(dt) {
+            match &target {
+                MidiTarget::TrackVolume(id) => {
+                    let gain = ratio_to_value(ratio, MIN_GAIN_DB, MAX_GAIN_DB, false);
+                    tracks.set_volume(id, 10f32.powf(gain / 20.), tx);
+                }
+                MidiTarget::EffectParam { .. } => {
+                    if let Some(param) = effect_param(&target, tracks) {
+                        param
+                            .value
+                            .set_value(ratio_to_value(ratio, param.min, param.max, param.log));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Name of the control driven by `target`
+pub fn target_name(target: &MidiTarget, tracks: &mut TrackService) -> String {
+    let track_name = |tracks: &TrackService, id: &String| {
+        tracks
+            .get_reference(id)
+            .map_or("Deleted track".into(), |track| {
+                parse_name(&track.name, track.index)
+            })
+    };
+    match target {
+        MidiTarget::TrackVolume(id) => format!("{} Volume", track_name(tracks, id)),
+        MidiTarget::EffectParam {
+            track_id,
+            effect_id,
+            param,
+        } => {
+            let name = track_name(tracks, track_id);
+            let effect = tracks.get(track_id).and_then(|track| {
+                track
+                    .effects_mut()
+                    .iter()
+                    .find(|e| e.id() == *effect_id)
+                    .map(|effect| (effect.name.clone(), effect.params()))
+            });
+            match effect {
+                Some((effect, params)) => format!(
+                    "{} {} {}",
+                    name,
+                    effect,
+                    params.get(*param).map_or("", |p| p.name)
+                ),
+                None => format!("{} Deleted effect", name),
+            }
+        }
+        MidiTarget::SelectedTrackVolume => "Selected track Volume".into(),
+        MidiTarget::SelectedEffectParam { slot, param } => {
+            format!("Selected track Effect {} Param {}", slot + 1, param + 1)
+        }
+    }
+}
+
+/// Project control of `target`, the global mapping following the selected track
+fn resolve_target(target: &MidiTarget, tracks: &mut TrackService) -> Option<MidiTarget> {
+    match target {
+        MidiTarget::SelectedTrackVolume => {
+            Some(MidiTarget::TrackVolume(tracks.selected_track()?.id))
+        }
+        MidiTarget::SelectedEffectParam { slot, param } => {
+            let track_id = tracks.selected_track()?.id;
+            let effect_id = tracks.get(&track_id)?.effects_mut().get(*slot)?.id();
+            Some(MidiTarget::EffectParam {
+                track_id,
+                effect_id,
+                param: *param,
+            })
+        }
+        target => Some(target.clone()),
+    }
+}
+
+/// Part of its range where the control of `target` is
+fn target_ratio(target: &MidiTarget, tracks: &mut TrackService) -> Option<f32> {
+    match target {
+        MidiTarget::TrackVolume(id) => {
+            let volume = tracks.get_reference(id)?.volume;
+            let gain = 20. * volume.log10();
+            Some(value_to_ratio(gain, MIN_GAIN_DB, MAX_GAIN_DB, false))
+        }
+        MidiTarget::EffectParam { .. } => {
+            let param = effect_param(target, tracks)?;
+            Some(value_to_ratio(
+                param.value.value(),
+                param.min,
+                param.max,
+                param.log,
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn effect_param(target: &MidiTarget, tracks: &mut TrackService) -> Option<EffectParam> {
+    let MidiTarget::EffectParam {
+        track_id,
+        effect_id,
+        param,
+    } = target
+    else {
+        return None;
+    };
+    tracks
+        .get(track_id)?
+        .effects_mut()
+        .iter()
+        .find(|effect| effect.id() == *effect_id)?
+        .params()
+        .get(*param)
+        .cloned()
+}
@@ -1 +1,3 @@
+pub mod midi_learn;
+pub mod recording;
 pub mod track;
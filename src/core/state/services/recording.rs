@@ -0,0 +1,331 @@
+use crate::{
+    analysis::AudioInfo,
+    cache::AUDIO_ANALYSIS_CACHE,
+    config::recordings_dir,
+    core::{
+        clip::ClipCore,
+        message::MidiToPlayerMsg,
+        midi::{MidiClipCore, MidiNote},
+        recording::{MidiRecordMode, MidiRecorder, PEAKS_PER_MESSAGE, TrackRecorder},
+        take::{Take, TakeLanes, loop_passes},
+        tempo::TempoMap,
+        track::{TrackReferenceCore, TrackType},
+    },
+};
+use std::collections::HashMap;
+
+/// Recorded file being closed
+#[derive(Debug)]
+pub struct ClosingRecording {
+    /// Beat where the recording starts
+    pub start: f32,
+    /// Loop region played while recording
+    pub looped: Option<(f32, f32)>,
+    /// Seconds the recorded audio comes late after the playback heard
+    pub latency: f32,
+    pub recorder: TrackRecorder,
+}
+
+/// Clips and loop passes of the closed recordings, by track
+#[derive(Default)]
+pub struct ClosedRecordings {
+    pub clips: HashMap<String, Vec<ClipCore>>,
+    pub takes: HashMap<String, Vec<Take>>,
+}
+
+/// Service managing the recorders of the armed tracks
+pub struct RecordingService {
+    /// Beat where the current recording starts
+    start: Option<f32>,
+    /// Input and output latency in seconds when the current recording started
+    latency: f32,
+    /// Peaks of the blocks recorded by each track, drawn while recording
+    peaks: HashMap<String, Vec<f32>>,
+    /// Tracks of the audio recorders, in the order sent to the player
+    tracks: Vec<String>,
+    /// Frames the last recording could not write to disk
+    dropped_frames: usize,
+    closing: Vec<ClosingRecording>,
+    /// MIDI input recorded by the armed MIDI tracks
+    midi_recorders: Vec<MidiRecorder>,
+    midi_record_mode: MidiRecordMode,
+    /// Beat of the last recorded block, to find when the loop starts again
+    midi_beat: f32,
+    /// Whether the playhead went back to the start of the loop while recording
+    wrapped: bool,
+}
+
+impl RecordingService {
+    pub fn new() -> Self {
+        Self {
+            start: None,
+            latency: 0.,
+            peaks: HashMap::new(),
+            tracks: Vec::new(),
+            dropped_frames: 0,
+            closing: Vec::new(),
+            midi_recorders: Vec::new(),
+            midi_record_mode: MidiRecordMode::default(),
+            midi_beat: 0.,
+            wrapped: false,
+        }
+    }
+
+    /// Record the armed MIDI tracks and open a file in the recordings folder
+    /// for each armed audio track
+    pub fn open_recorders(
+        &mut self,
+        tracks: impl Iterator<Item = TrackReferenceCore>,
+        sample_rate: usize,
+    ) -> Vec<TrackRecorder> {
+        let tracks: Vec<_> = tracks.filter(|track| track.arm).collect();
+        self.midi_recorders = tracks
+            .iter()
+            .filter(|track| track.kind == TrackType::Midi)
+            .map(|track| MidiRecorder::new(&track.id))
+            .collect();
+        self.wrapped = false;
+        self.dropped_frames = 0;
+        let mut recorders = Vec::new();
+        if let Some(dir) = recordings_dir() {
+            for track in tracks.iter().filter(|track| track.kind == TrackType::Audio) {
+                let name = track.name.replace(['/', '\\'], "-");
+                let path = (1..)
+                    .map(|take| dir.join(format!("{} {}.wav", name, take)))
+                    .find(|path| !path.exists())
+                    .unwrap_or_default();
+                match TrackRecorder::new(&track.id, track.input, &path, sample_rate as u32) {
+                    Ok(recorder) => recorders.push(recorder),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+        }
+        self.tracks = recorders.iter().map(|r| r.track_id.clone()).collect();
+        recorders
+    }
+
+    /// Beat where the current recording starts
+    pub fn start(&self) -> Option<f32> {
+        self.start
+    }
+
+    /// The player started writing the files at `beat`
+    pub fn started(&mut self, beat: f32, latency: f32) {
+        self.start = Some(beat);
+        self.latency = latency;
+        self.peaks.clear();
+        self.midi_beat = beat;
+    }
+
+    /// Add the peaks of a block from the recorder at index `first`
+    pub fn push_peaks(&mut self, first: usize, peaks: [f32; PEAKS_PER_MESSAGE]) {
+        for (id, peak) in self.tracks.iter().skip(first).zip(peaks) {
+            self.peaks.entry(id.clone()).or_default().push(peak);
+        }
+    }
+
+    /// Peaks of the blocks recorded by a track
+    pub fn peaks(&self, id: &str) -> Option<&Vec<f32>> {
+        self.peaks.get(id)
+    }
+
+    /// Frames lost by the last recording because the disk was too slow
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped_frames
+    }
+
+    pub fn clear_dropped_frames(&mut self) {
+        self.dropped_frames = 0;
+    }
+
+    pub fn midi_record_mode(&self) -> MidiRecordMode {
+        self.midi_record_mode
+    }
+
+    pub fn set_midi_record_mode(&mut self, mode: MidiRecordMode) {
+        self.midi_record_mode = mode;
+    }
+
+    /// Range in beats and notes recorded by a MIDI track until `position`
+    pub fn recorded_midi(
+        &self,
+        id: &str,
+        position: f32,
+        looped: Option<(f32, f32)>,
+    ) -> Option<((f32, f32), Vec<MidiNote>)> {
+        let recorder = self
+            .midi_recorders
+            .iter()
+            .find(|recorder| recorder.track_id == id)?;
+        Some((
+            self.midi_record_range(position, looped)?,
+            recorder.notes(position),
+        ))
+    }
+
+    /// Beats recorded from the start of the recording, the whole loop once it played again
+    fn midi_record_range(&self, end: f32, looped: Option<(f32, f32)>) -> Option<(f32, f32)> {
+        let start = self.start?;
+        match looped {
+            Some((loop_start, loop_end)) if self.wrapped => Some((start.min(loop_start), loop_end)),
+            _ => Some((start, end.max(start))),
+        }
+    }
+
+    /// Follow the recording to the block at `beat`, the held notes are cut when the loop
+    /// starts again. Returns the end of the loop when the pass is to be written in the clips.
+    pub fn advance_midi(&mut self, beat: f32, looped: Option<(f32, f32)>) -> Option<f32> {
+        if self.start.is_none() || self.midi_recorders.is_empty() {
+            return None;
+        }
+        let wrapped = beat < self.midi_beat;
+        self.midi_beat = beat;
+        let (loop_start, loop_end) = looped.filter(|_| wrapped)?;
+        self.wrapped = true;
+        for recorder in self.midi_recorders.iter_mut() {
+            recorder.wrap(loop_start, loop_end);
+        }
+        (self.midi_record_mode == MidiRecordMode::LoopOverdub).then_some(loop_end)
+    }
+
+    /// Add a MIDI input event played at `beat` to the recorders
+    pub fn push_midi(&mut self, beat: f32, event: MidiToPlayerMsg) {
+        for recorder in self.midi_recorders.iter_mut() {
+            recorder.push(beat, event);
+        }
+    }
+
+    /// Clips of the notes recorded until `end` by each track, removed from the recorders
+    pub fn take_midi(
+        &mut self,
+        end: f32,
+        looped: Option<(f32, f32)>,
+    ) -> Vec<(String, MidiClipCore)> {
+        let Some((start, end)) = self.midi_record_range(end, looped) else {
+            return Vec::new();
+        };
+        self.midi_recorders
+            .iter_mut()
+            .filter_map(|recorder| {
+                let take = recorder.take_clip(start, end)?;
+                Some((recorder.track_id.clone(), take))
+            })
+            .collect()
+    }
+
+    /// Release the held notes at `position` and take the last clips of the MIDI recorders
+    pub fn stop_midi(
+        &mut self,
+        position: f32,
+        looped: Option<(f32, f32)>,
+    ) -> Vec<(String, MidiClipCore)> {
+        for recorder in self.midi_recorders.iter_mut() {
+            recorder.release_all(position);
+        }
+        let takes = self.take_midi(position, looped);
+        self.midi_recorders.clear();
+        takes
+    }
+
+    /// The player gave the `recorders` back, their files are closing.
+    /// They start at `position` when the recording never started.
+    pub fn stopped(
+        &mut self,
+        recorders: Vec<TrackRecorder>,
+        looped: Option<(f32, f32)>,
+        position: f32,
+    ) {
+        let start = self.start.take().unwrap_or(position);
+        for recorder in recorders.iter().filter(|r| r.dropped > 0) {
+            eprintln!(
+                "{} frames could not be written to {}",
+                recorder.dropped,
+                recorder.path.display()
+            );
+            self.dropped_frames += recorder.dropped;
+        }
+        self.closing
+            .extend(recorders.into_iter().map(|recorder| ClosingRecording {
+                start,
+                looped,
+                latency: self.latency,
+                recorder,
+            }));
+    }
+
+    /// Clips of the recordings whose file is closed, by track.
+    /// Recordings over a loop are split into takes.
+    pub fn closed_recordings(&mut self, tempo: &TempoMap) -> ClosedRecordings {
+        let mut closed = ClosedRecordings::default();
+        for closing in self.closed() {
+            let recorder = &closing.recorder;
+            let Some(info) = AUDIO_ANALYSIS_CACHE.get_or_analyze(recorder.path.clone()) else {
+                continue;
+            };
+            let clip = compensated_clip(info, closing.start, closing.latency, tempo);
+            let passes = closing
+                .looped
+                .map(|(start, end)| loop_passes(&clip, start, end, tempo))
+                .unwrap_or_default();
+            if passes.len() > 1 {
+                closed
+                    .takes
+                    .entry(recorder.track_id.clone())
+                    .or_default()
+                    .extend(passes);
+            } else {
+                closed
+                    .clips
+                    .entry(recorder.track_id.clone())
+                    .or_default()
+                    .push(clip);
+            }
+        }
+        closed
+    }
+
+    /// Recordings whose file is closed, the empty ones are deleted
+    fn closed(&mut self) -> Vec<ClosingRecording> {
+        let mut closed = Vec::new();
+        for mut closing in std::mem::take(&mut self.closing) {
+            if !closing.recorder.finished() {
+                self.closing.push(closing);
+                continue;
+            }
+            self.peaks.remove(&closing.recorder.track_id);
+            if closing.recorder.frames == 0 {
+                let _ = std::fs::remove_file(&closing.recorder.path);
+                continue;
+            }
+            closed.push(closing);
+        }
+        closed
+    }
+}
+
+/// Clip of a recorded file moved earlier by the `latency` of the devices in seconds,
+/// so that it lines up with the playback heard while recording from `start`
+fn compensated_clip(info: AudioInfo, start: f32, latency: f32, tempo: &TempoMap) -> ClipCore {
+    let duration = info.duration.map_or(0., |d| d.as_secs_f32());
+    let secs = tempo.beats_to_secs(start) - latency as f64;
+    let mut clip = ClipCore::new(info, tempo.secs_to_beats(secs.max(0.)));
+    if secs < 0. && duration > 0. {
+        clip.trim_start = (-secs as f32 / duration).min(1.);
+    }
+    clip
+}
+
+/// Add the recorded loop `passes` to the takes of a track and open its lanes.
+/// The latest pass plays where passes overlap. Returns the range of the passes.
+pub fn add_passes(takes: &mut TakeLanes, passes: Vec<Take>, tempo: &TempoMap) -> (f32, f32) {
+    takes.open = true;
+    let mut range = (f32::MAX, f32::MIN);
+    for pass in passes {
+        let (start, end) = (pass.clip.position, pass.clip.end(tempo));
+        range = (range.0.min(start), range.1.max(end));
+        let id = pass.id.clone();
+        takes.takes.push(pass);
+        takes.select(&id, start, end, tempo);
+    }
+    range
+}
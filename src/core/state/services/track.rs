@@ -1,9 +1,13 @@
 use crate::core::{
     clip::ClipCore,
+    instrument::InstrumentParams,
     message::GuiToPlayerMsg,
+    midi::{MidiClipCore, MidiNote},
+    take::TakeLanes,
+    tempo::TempoMap,
     track::{
-        DEFAULT_TRACK_HEIGHT, MutableTrackCore, TRACK_CLOSED_HEIGHT, TrackCore, TrackReferenceCore,
-        TrackSoloState,
+        DEFAULT_TRACK_HEIGHT, MonitorMode, MutableTrackCore, TRACK_CLOSED_HEIGHT, TrackCore,
+        TrackInput, TrackReferenceCore, TrackSoloState, TrackType,
     },
 };
 use rtrb::Producer;
@@ -91,6 +95,17 @@ impl TrackService {
                 )
             })
     }
+    /// Tracks in their order, without the master track
+    pub fn cores(&self) -> Vec<TrackCore> {
+        self.order
+            .iter()
+            .filter(|id| *id != "master")
+            .filter_map(|id| self.tracks.get(id).cloned())
+            .collect()
+    }
+    pub fn master_core(&self) -> TrackCore {
+        self.tracks.get("master").unwrap().clone()
+    }
     pub fn master_track(&self) -> TrackReferenceCore {
         let master_id = "master".to_string();
         self.tracks.get(&master_id).unwrap().get_reference(
@@ -102,15 +117,72 @@ impl TrackService {
     // Mutations
     /// Create a new track at position `index` creating the track and the clips
     pub fn insert(&mut self, track: TrackCore, index: usize, tx: &mut Producer<GuiToPlayerMsg>) {
-        let _ = tx.push(GuiToPlayerMsg::AddTrack(track.id.clone()));
+        match track.kind {
+            TrackType::Audio => {
+                let _ = tx.push(GuiToPlayerMsg::AddTrack(track.id.clone()));
+            }
+            TrackType::Midi => {
+                let _ = tx.push(GuiToPlayerMsg::AddMidiTrack(
+                    track.id.clone(),
+                    track.instrument.clone().unwrap_or_default(),
+                ));
+            }
+        }
         if track.clips.len() > 0 {
             let mut map = HashMap::new();
             map.insert(track.id.clone(), track.clips.clone());
             let _ = tx.push(GuiToPlayerMsg::AddClips(map));
         }
+        if !track.midi_clips.is_empty() {
+            let _ = tx.push(GuiToPlayerMsg::SetMidiClips(
+                track.id.clone(),
+                track.midi_clips.clone(),
+            ));
+        }
+        Self::send_mix(&track, tx);
         self.order.insert(index, track.id.clone());
         self.tracks.insert(track.id.clone(), track);
     }
+    /// Replace the tracks by `tracks` in their order and the master track by `master`
+    pub fn replace(
+        &mut self,
+        master: TrackCore,
+        tracks: Vec<TrackCore>,
+        tx: &mut Producer<GuiToPlayerMsg>,
+    ) {
+        for id in self.order.clone() {
+            self.delete(&id, tx);
+        }
+        if let Some(old) = self.tracks.get_mut("master") {
+            let indexes = (0..old.effects().len()).collect();
+            old.remove_effects(&indexes, tx);
+        }
+        Self::send_mix(&master, tx);
+        self.tracks.insert(master.id.clone(), master);
+        self.selected_tracks.clear();
+        for (index, track) in tracks.into_iter().enumerate() {
+            self.insert(track, index, tx);
+        }
+    }
+    /// Send the volume, the mute, the monitoring and the effects of a track added to the player
+    fn send_mix(track: &TrackCore, tx: &mut Producer<GuiToPlayerMsg>) {
+        let _ = tx.push(GuiToPlayerMsg::ChangeTrackVolume(
+            track.id.clone(),
+            track.volume,
+        ));
+        if track.muted {
+            let _ = tx.push(GuiToPlayerMsg::MuteTrack(track.id.clone(), true));
+        }
+        Self::send_monitor(track, tx);
+        for (index, effect) in track.effects().iter().enumerate() {
+            let _ = tx.push(GuiToPlayerMsg::AddNode(
+                track.id.clone(),
+                index,
+                effect.id(),
+                effect.get_unit(),
+            ));
+        }
+    }
     /// Move track specified by id to position `new_index`
     pub fn move_track(&mut self, id: &str, new_index: usize) {
         if let Some(old_index) = self.order.iter().position(|i| *i == id) {
@@ -170,7 +242,7 @@ impl TrackService {
         &mut self,
         ids: &Vec<String>,
         bounds: Option<(f32, f32)>,
-        bpm: f32,
+        tempo: &TempoMap,
         tx: &mut Producer<GuiToPlayerMsg>,
     ) -> (
         HashMap<String, Vec<ClipCore>>,
@@ -179,7 +251,7 @@ impl TrackService {
         let mut created_clips = HashMap::new();
         let mut deleted_clips = HashMap::new();
         for track in self.tracks.values_mut().filter(|t| t.id != "master") {
-            let (created, deleted) = track.duplicate_clips(ids, bounds, bpm, tx);
+            let (created, deleted) = track.duplicate_clips(ids, bounds, tempo, tx);
             if !created.is_empty() {
                 created_clips.insert(track.id.clone(), created);
             }
@@ -203,6 +275,93 @@ impl TrackService {
             let _ = tx.push(GuiToPlayerMsg::MuteTrack(id, mute));
         }
     }
+    /// Arm or disarm a track for recording
+    pub fn set_arm(&mut self, id: &String, arm: bool, tx: &mut Producer<GuiToPlayerMsg>) {
+        if let Some(track) = self.tracks.get_mut(id) {
+            track.arm = arm;
+            Self::send_monitor(track, tx);
+        }
+    }
+    /// Set the input channels recorded by a track
+    pub fn set_input(&mut self, id: &String, input: TrackInput, tx: &mut Producer<GuiToPlayerMsg>) {
+        if let Some(track) = self.tracks.get_mut(id) {
+            track.input = input;
+            Self::send_monitor(track, tx);
+        }
+    }
+    /// Set when a track plays its input
+    pub fn set_monitor(
+        &mut self,
+        id: &String,
+        monitor: MonitorMode,
+        tx: &mut Producer<GuiToPlayerMsg>,
+    ) {
+        if let Some(track) = self.tracks.get_mut(id) {
+            track.monitor = monitor;
+            Self::send_monitor(track, tx);
+        }
+    }
+    fn send_monitor(track: &TrackCore, tx: &mut Producer<GuiToPlayerMsg>) {
+        let _ = tx.push(GuiToPlayerMsg::SetTrackMonitor(
+            track.id.clone(),
+            track.monitor,
+            track.arm,
+            track.input,
+        ));
+    }
+    /// Replace the clips of a MIDI track
+    pub fn set_midi_clips(
+        &mut self,
+        id: &String,
+        clips: Vec<MidiClipCore>,
+        tx: &mut Producer<GuiToPlayerMsg>,
+    ) {
+        if let Some(track) = self.tracks.get_mut(id) {
+            track.midi_clips = clips.clone();
+            let _ = tx.push(GuiToPlayerMsg::SetMidiClips(id.clone(), clips));
+        }
+    }
+    /// Replace the notes of a MIDI clip, sorted by start
+    pub fn set_midi_notes(
+        &mut self,
+        id: &String,
+        clip_id: &String,
+        mut notes: Vec<MidiNote>,
+        tx: &mut Producer<GuiToPlayerMsg>,
+    ) {
+        let Some(track) = self.tracks.get_mut(id) else {
+            return;
+        };
+        if let Some(clip) = track.midi_clips.iter_mut().find(|clip| clip.id == *clip_id) {
+            notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+            clip.notes = notes;
+            let _ = tx.push(GuiToPlayerMsg::SetMidiClips(
+                id.clone(),
+                track.midi_clips.clone(),
+            ));
+        }
+    }
+    /// Replace the instrument of a MIDI track
+    pub fn set_instrument(
+        &mut self,
+        id: &String,
+        instrument: Option<InstrumentParams>,
+        tx: &mut Producer<GuiToPlayerMsg>,
+    ) {
+        if let Some(track) = self.tracks.get_mut(id) {
+            track.instrument = instrument.clone();
+            let _ = tx.push(GuiToPlayerMsg::SetInstrument(
+                id.clone(),
+                instrument.unwrap_or_default(),
+            ));
+        }
+    }
+    /// Replace the takes of a track
+    pub fn set_takes(&mut self, id: &String, takes: TakeLanes) {
+        if let Some(track) = self.tracks.get_mut(id) {
+            track.takes = takes;
+        }
+    }
     /// Solo/Unsolo a track based on `solo_tracks`.
     pub fn toggle_solo(
         &mut self,
@@ -261,7 +420,7 @@ impl TrackService {
         id: &String,
         to_track: &String,
         to_pos: f32,
-        bpm: f32,
+        tempo: &TempoMap,
         ignore: &Vec<String>,
         tx: &mut Producer<GuiToPlayerMsg>,
     ) -> (Vec<ClipCore>, Vec<ClipCore>) {
@@ -279,7 +438,7 @@ impl TrackService {
             } else {
                 return (Vec::new(), Vec::new());
             };
-            old_track._fix_overlaps(&clip, bpm, &mut deleted_clips, &mut created_clips, ignore);
+            old_track._fix_overlaps(&clip, tempo, &mut deleted_clips, &mut created_clips, ignore);
         } else {
             // Remove clips from its old track
             let new_clip = if let Some(old_track) = self._track_from_clip_id(id)
@@ -295,7 +454,13 @@ impl TrackService {
             // Insert clip into new track if found
             if let (Some(mut clip), Some(new_track)) = (new_clip, self.tracks.get_mut(to_track)) {
                 clip.position = to_pos;
-                new_track._fix_overlaps(&clip, bpm, &mut deleted_clips, &mut created_clips, ignore);
+                new_track._fix_overlaps(
+                    &clip,
+                    tempo,
+                    &mut deleted_clips,
+                    &mut created_clips,
+                    ignore,
+                );
                 new_track.clips.push(clip);
             }
         }
@@ -403,7 +568,7 @@ impl TrackService {
         start: f32,
         end: f32,
         pos: f32,
-        bpm: f32,
+        tempo: &TempoMap,
         tx: &mut Producer<GuiToPlayerMsg>,
     ) -> Option<(
         ClipCore,
@@ -421,7 +586,7 @@ impl TrackService {
         let mut created_clips = Vec::new();
         track._fix_overlaps(
             &new_clip,
-            bpm,
+            tempo,
             &mut deleted_clips,
             &mut created_clips,
             &Vec::new(),
@@ -440,6 +605,20 @@ impl TrackService {
         Some((old_clip, track.clone(), added_clips, deleted_clips))
     }
 
+    /// Replace the clip with the same id by `clip`, without overlap checks.
+    /// Returns the previous version of the clip.
+    pub fn update_clip(
+        &mut self,
+        clip: ClipCore,
+        tx: &mut Producer<GuiToPlayerMsg>,
+    ) -> Option<ClipCore> {
+        let track = self._track_from_clip_id(&clip.id)?;
+        let old_clip = track.clips.iter_mut().find(|c| c.id == clip.id)?;
+        let old = std::mem::replace(old_clip, clip.clone());
+        let _ = tx.push(GuiToPlayerMsg::UpdateClip(clip));
+        Some(old)
+    }
+
     // TODO: Fix copy also effects
     /// Duplicate track identified by `id`, copying all attributes, clips and effects. The new track id is returned.
     pub fn duplicate(&mut self, id: &String, tx: &mut Producer<GuiToPlayerMsg>) -> Option<String> {
@@ -463,6 +642,9 @@ impl TrackService {
             new_id: new_id.clone(),
             clip_map,
         });
+        if let Some(instrument) = self.tracks.get(&new_id).and_then(|t| t.instrument.clone()) {
+            let _ = tx.push(GuiToPlayerMsg::SetInstrument(new_id.clone(), instrument));
+        }
 
         Some(new_id)
     }
@@ -491,6 +673,23 @@ impl TrackService {
         }
     }
 
+    pub fn clip(&self, id: &str) -> Option<&ClipCore> {
+        self.tracks
+            .values()
+            .find_map(|track| track.clips.iter().find(|clip| clip.id == id))
+    }
+
+    /// MIDI clip with the id of its track
+    pub fn midi_clip(&self, id: &str) -> Option<(&String, &MidiClipCore)> {
+        self.tracks.values().find_map(|track| {
+            track
+                .midi_clips
+                .iter()
+                .find(|clip| clip.id == id)
+                .map(|clip| (&track.id, clip))
+        })
+    }
+
     pub fn _track_from_clip_id(&mut self, id: &String) -> Option<&mut TrackCore> {
         for track in self.tracks.values_mut() {
             for clip in track.clips.iter() {
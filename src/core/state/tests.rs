@@ -1,4 +1,13 @@
-use crate::core::{state::ToniqueProjectState, track::TrackCore};
+use crate::{
+    analysis::AudioInfo,
+    core::{
+        clip::ClipCore,
+        midi::MidiNote,
+        midi_learn::{MidiBinding, MidiTarget},
+        state::ToniqueProjectState,
+        track::TrackCore,
+    },
+};
 
 fn setup_state() -> ToniqueProjectState {
     let (tx, _) = rtrb::RingBuffer::new(128);
@@ -6,6 +15,10 @@ fn setup_state() -> ToniqueProjectState {
     ToniqueProjectState::new(tx, rx)
 }
 
+fn audio_info(seconds: f32) -> AudioInfo {
+    AudioInfo::from_samples("test.wav", vec![0.; (seconds * 44100.) as usize], 44100)
+}
+
 #[test]
 fn test_add_track() {
     let mut state = setup_state();
@@ -58,3 +71,73 @@ fn test_delete_track() {
     // deleting a non existant track should no raise errors
     state.delete_track(&"invalid_id".to_string());
 }
+
+#[test]
+fn test_add_clips_crossfade() {
+    let mut state = setup_state();
+    let track = TrackCore::new();
+    state.add_track(track.clone());
+    // 4 seconds at 120 bpm lasts 8 beats
+    state.add_clips(&track.id, vec![ClipCore::new(audio_info(4.), 0.)]);
+    state.toggle_auto_crossfade();
+    state.add_clips(&track.id, vec![ClipCore::new(audio_info(4.), 6.)]);
+
+    let clips = state.tracks().next().unwrap().clips;
+    assert_eq!(clips.len(), 2);
+    let first = clips.iter().find(|c| c.position == 0.).unwrap();
+    let second = clips.iter().find(|c| c.position == 6.).unwrap();
+    // Clips are kept whole and faded over the overlap
+    assert_eq!(first.trim_end, 1.);
+    assert_eq!(first.fade_out.length, 2.);
+    assert_eq!(second.fade_in.length, 2.);
+
+    state.undo();
+    let clips = state.tracks().next().unwrap().clips;
+    assert_eq!(clips.len(), 1);
+    assert_eq!(clips[0].fade_out.length, 0.);
+}
+
+#[test]
+fn test_set_midi_notes_sorted_and_undone() {
+    let mut state = setup_state();
+    let track = TrackCore::midi();
+    state.add_track(track.clone());
+    let clip_id = state.add_midi_clip(&track.id, 0.).unwrap();
+    let note = |key, start| MidiNote {
+        key,
+        velocity: 100,
+        start,
+        length: 0.5,
+    };
+
+    state.set_midi_notes(&track.id, &clip_id, vec![note(64, 1.), note(60, 0.)]);
+    let (track_id, clip) = state.midi_clip(&clip_id).unwrap();
+    assert_eq!(track_id, track.id);
+    assert_eq!(clip.notes, vec![note(60, 0.), note(64, 1.)]);
+
+    state.undo();
+    assert!(state.midi_clip(&clip_id).unwrap().1.notes.is_empty());
+    state.redo();
+    assert_eq!(state.midi_clip(&clip_id).unwrap().1.notes.len(), 2);
+}
+
+#[test]
+fn test_project_saved_and_opened() {
+    let mut state = setup_state();
+    state.add_track(TrackCore::drum_rack());
+    let id = state.tracks().next().unwrap().id;
+    let binding = MidiBinding::new(0, 7, MidiTarget::TrackVolume(id.clone()));
+    state.midi_bindings_mut().push(binding.clone());
+    let path = std::env::temp_dir().join(format!("{}.tonique", uuid::Uuid::new_v4()));
+    state.save_project(&path).unwrap();
+
+    let mut opened = setup_state();
+    let result = opened.open_project(&path);
+    let _ = std::fs::remove_file(&path);
+    result.unwrap();
+    let tracks: Vec<_> = opened.tracks().collect();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].id, id);
+    assert!(tracks[0].instrument.as_ref().unwrap().is_drum_rack());
+    assert_eq!(opened.midi_learn().bindings, vec![binding]);
+}
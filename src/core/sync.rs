@@ -0,0 +1,270 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::message::MidiToPlayerMsg;
+use std::time::{Duration, Instant};
+
+/// MIDI clock ticks per beat
+pub const CLOCK_PPQ: f32 = 24.;
+/// Clock intervals averaged to follow the tempo of an external clock
+const CLOCK_INTERVALS: usize = 24;
+/// Intervals needed before following the tempo of an external clock
+const MIN_CLOCK_INTERVALS: usize = 6;
+/// Gap after which an external clock or time code is considered stopped
+const SYNC_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Transport followed by the player
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SyncSource {
+    /// The transport of the project
+    #[default]
+    Internal,
+    /// Tempo, position and start and stop of an external MIDI clock
+    Clock,
+    /// Position and play state of an external MIDI time code
+    TimeCode,
+}
+
+impl SyncSource {
+    pub const ALL: [SyncSource; 3] = [Self::Internal, Self::Clock, Self::TimeCode];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Internal => "Internal",
+            Self::Clock => "MIDI clock",
+            Self::TimeCode => "MIDI time code",
+        }
+    }
+}
+
+/// MIDI sync settings of the player
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SyncSettings {
+    /// Send the clock and transport to the sync output
+    pub send_clock: bool,
+    pub follow: SyncSource,
+}
+
+/// Message sent to the sync output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMsg {
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    /// Sixteenth notes since the start of the song
+    SongPosition(u16),
+}
+
+impl SyncMsg {
+    pub fn bytes(&self) -> Vec<u8> {
+        match *self {
+            Self::Clock => vec![0xF8],
+            Self::Start => vec![0xFA],
+            Self::Continue => vec![0xFB],
+            Self::Stop => vec![0xFC],
+            Self::SongPosition(position) => {
+                vec![
+                    0xF2,
+                    (position & 0x7F) as u8,
+                    ((position >> 7) & 0x7F) as u8,
+                ]
+            }
+        }
+    }
+}
+
+/// Song position pointer of `beat`, in sixteenth notes
+fn song_position(beat: f32) -> u16 {
+    (beat.max(0.) * 4.).floor().min(0x3FFF as f32) as u16
+}
+
+/// Clock and transport sent while the transport of the project moves
+#[derive(Debug, Default)]
+pub struct ClockMaster {
+    running: bool,
+    /// Beat where the last block ended, to find the jumps of the playhead
+    next_beat: Option<f32>,
+}
+
+impl ClockMaster {
+    /// Add to `out` the messages of the transport playing from `start` to `end` beats, with their beat.
+    /// The position is sent when the transport starts or jumps.
+    pub fn block(&mut self, start: f32, end: f32, out: &mut Vec<(f32, SyncMsg)>) {
+        if !self.running {
+            self.running = true;
+            out.push((start, SyncMsg::SongPosition(song_position(start))));
+            out.push((
+                start,
+                if start == 0. {
+                    SyncMsg::Start
+                } else {
+                    SyncMsg::Continue
+                },
+            ));
+        } else if self.next_beat != Some(start) {
+            out.push((start, SyncMsg::SongPosition(song_position(start))));
+        }
+        let mut tick = (start * CLOCK_PPQ).ceil();
+        while tick / CLOCK_PPQ < end {
+            out.push((tick / CLOCK_PPQ, SyncMsg::Clock));
+            tick += 1.;
+        }
+        self.next_beat = Some(end);
+    }
+
+    /// Stop message when the transport was running
+    pub fn stop(&mut self) -> Option<SyncMsg> {
+        self.next_beat = None;
+        self.running.then(|| {
+            self.running = false;
+            SyncMsg::Stop
+        })
+    }
+}
+
+/// Tempo, position and play state of an external MIDI clock
+#[derive(Debug, Default)]
+pub struct ClockFollower {
+    /// Last intervals between two clocks in seconds, as a ring
+    intervals: [f32; CLOCK_INTERVALS],
+    count: usize,
+    last: Option<Instant>,
+    pub playing: bool,
+    /// Beat of the last song position
+    position: f32,
+    /// Clocks since the song position
+    ticks: u32,
+    /// Whether the next clock is the one of the song position
+    first_tick: bool,
+}
+
+impl ClockFollower {
+    pub fn receive(&mut self, time: Instant, msg: &MidiToPlayerMsg) {
+        match *msg {
+            MidiToPlayerMsg::Clock => {
+                if let Some(last) = self.last {
+                    let interval = time.duration_since(last);
+                    if interval > SYNC_TIMEOUT {
+                        self.count = 0;
+                    } else {
+                        self.intervals[self.count % CLOCK_INTERVALS] = interval.as_secs_f32();
+                        self.count += 1;
+                    }
+                }
+                self.last = Some(time);
+                if self.playing {
+                    if self.first_tick {
+                        self.first_tick = false;
+                    } else {
+                        self.ticks += 1;
+                    }
+                }
+            }
+            MidiToPlayerMsg::Start => {
+                self.position = 0.;
+                self.ticks = 0;
+                self.first_tick = true;
+                self.playing = true;
+            }
+            MidiToPlayerMsg::Continue => {
+                self.first_tick = true;
+                self.playing = true;
+            }
+            MidiToPlayerMsg::Stop => self.playing = false,
+            MidiToPlayerMsg::SongPosition(position) => {
+                self.position = position as f32 / 4.;
+                self.ticks = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Tempo of the clock, `None` until enough clocks are received
+    pub fn bpm(&self) -> Option<f32> {
+        let count = self.count.min(CLOCK_INTERVALS);
+        if count < MIN_CLOCK_INTERVALS {
+            return None;
+        }
+        let interval = self.intervals[..count].iter().sum::<f32>() / count as f32;
+        Some(60. / (interval * CLOCK_PPQ))
+    }
+
+    /// Beat of the clock at `now`, moving on from the last clock by at most one clock
+    pub fn beat(&self, now: Instant) -> f32 {
+        let beat = self.position + self.ticks as f32 / CLOCK_PPQ;
+        match (self.last, self.bpm()) {
+            (Some(last), Some(bpm)) if self.playing && !self.first_tick => {
+                let elapsed = now.saturating_duration_since(last).as_secs_f32() * bpm / 60.;
+                beat + elapsed.min(1. / CLOCK_PPQ)
+            }
+            _ => beat,
+        }
+    }
+}
+
+/// Position and play state of an external MIDI time code
+#[derive(Debug, Default)]
+pub struct TimeCodeFollower {
+    /// Values of the eight quarter frames
+    pieces: [u8; 8],
+    /// Quarter frames received since the last complete time code, one bit each
+    received: u8,
+    /// Seconds of the last complete time code with the time it was received
+    last_code: Option<(f64, Instant)>,
+    last_quarter: Option<Instant>,
+}
+
+impl TimeCodeFollower {
+    /// Receive the quarter frame `piece` of a time code
+    pub fn receive(&mut self, time: Instant, piece: u8, value: u8) {
+        // The time code starts again after a stop, wait for its new position
+        if !self.playing(time) {
+            self.last_code = None;
+            self.received = 0;
+        }
+        let piece = piece as usize % 8;
+        self.pieces[piece] = value & 0x0F;
+        self.received |= 1 << piece;
+        self.last_quarter = Some(time);
+        if piece == 7 {
+            if self.received == 0xFF {
+                self.last_code = Some((self.seconds_of_pieces(), time));
+            }
+            self.received = 0;
+        }
+    }
+
+    /// Seconds of the time code in the quarter frames. The last piece arrives
+    /// two frames after the start of the time code it completes.
+    fn seconds_of_pieces(&self) -> f64 {
+        let p = self.pieces;
+        let fps = match (p[7] >> 1) & 0x03 {
+            0 => 24.,
+            1 => 25.,
+            2 => 29.97,
+            _ => 30.,
+        };
+        let frames = (p[0] | (p[1] & 0x01) << 4) as f64;
+        let seconds = (p[2] | (p[3] & 0x03) << 4) as f64;
+        let minutes = (p[4] | (p[5] & 0x03) << 4) as f64;
+        let hours = (p[6] | (p[7] & 0x01) << 4) as f64;
+        hours * 3600. + minutes * 60. + seconds + (frames + 2.) / fps
+    }
+
+    /// Whether quarter frames are still received at `now`
+    pub fn playing(&self, now: Instant) -> bool {
+        self.last_quarter
+            .is_some_and(|last| now.saturating_duration_since(last) < SYNC_TIMEOUT)
+    }
+
+    /// Seconds of the time code at `now`, `None` when stopped or while the first
+    /// complete time code after a start is not received yet
+    pub fn seconds(&self, now: Instant) -> Option<f64> {
+        if !self.playing(now) {
+            return None;
+        }
+        let (seconds, time) = self.last_code?;
+        Some(seconds + now.saturating_duration_since(time).as_secs_f64())
+    }
+}
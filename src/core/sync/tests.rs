@@ -0,0 +1,86 @@
+use crate::core::{
+    message::MidiToPlayerMsg,
+    sync::{ClockFollower, ClockMaster, SyncMsg, TimeCodeFollower},
+};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_clock_master_ticks_and_transport() {
+    let mut master = ClockMaster::default();
+    let mut out = Vec::new();
+    master.block(0., 0.5, &mut out);
+    master.block(0.5, 1., &mut out);
+    assert_eq!(out[0], (0., SyncMsg::SongPosition(0)));
+    assert_eq!(out[1], (0., SyncMsg::Start));
+    assert_eq!(
+        out.iter().filter(|(_, msg)| *msg == SyncMsg::Clock).count(),
+        24
+    );
+    // A jump of the playhead sends its position
+    out.clear();
+    master.block(4., 4.1, &mut out);
+    assert_eq!(out[0], (4., SyncMsg::SongPosition(16)));
+    assert_eq!(master.stop(), Some(SyncMsg::Stop));
+    assert_eq!(master.stop(), None);
+    // Starting again away from the start continues
+    out.clear();
+    master.block(2., 2.01, &mut out);
+    assert_eq!(out[1], (2., SyncMsg::Continue));
+    assert_eq!(SyncMsg::SongPosition(200).bytes(), vec![0xF2, 72, 1]);
+}
+
+#[test]
+fn test_clock_follower_tempo_and_position() {
+    let mut follower = ClockFollower::default();
+    let start = Instant::now();
+    // 120 bpm, a clock every 1 / 48 s
+    let tick = Duration::from_secs_f64(1. / 48.);
+    assert_eq!(follower.bpm(), None);
+    follower.receive(start, &MidiToPlayerMsg::SongPosition(8));
+    follower.receive(start, &MidiToPlayerMsg::Continue);
+    for i in 0..49 {
+        follower.receive(start + tick * i, &MidiToPlayerMsg::Clock);
+    }
+    assert!((follower.bpm().unwrap() - 120.).abs() < 0.01);
+    assert!(follower.playing);
+    let now = start + tick * 48;
+    assert!((follower.beat(now) - 4.).abs() < 1e-3);
+    // Moving on by at most a clock when the clock is late
+    assert!((follower.beat(now + Duration::from_secs(1)) - (4. + 1. / 24.)).abs() < 1e-3);
+    follower.receive(now, &MidiToPlayerMsg::Stop);
+    assert!(!follower.playing);
+}
+
+#[test]
+fn test_time_code_follower() {
+    let mut follower = TimeCodeFollower::default();
+    let start = Instant::now();
+    // 01:02:03 frame 4 at 25 fps
+    let pieces = [4, 0, 3, 0, 2, 0, 1, 0b0010];
+    let quarter = Duration::from_millis(10);
+    for (i, value) in pieces.into_iter().enumerate() {
+        assert_eq!(follower.seconds(start), None);
+        follower.receive(start + quarter * i as u32, i as u8, value);
+    }
+    let now = start + quarter * 7;
+    let expected = 3600. + 2. * 60. + 3. + 6. / 25.;
+    assert!((follower.seconds(now).unwrap() - expected).abs() < 1e-6);
+    assert!(follower.playing(now));
+    assert!(!follower.playing(now + Duration::from_secs(1)));
+}
+
+#[test]
+fn test_time_code_restart_waits_for_code() {
+    let mut follower = TimeCodeFollower::default();
+    let start = Instant::now();
+    let pieces = [4, 0, 3, 0, 2, 0, 1, 0b0010];
+    let quarter = Duration::from_millis(10);
+    for (i, value) in pieces.into_iter().enumerate() {
+        follower.receive(start + quarter * i as u32, i as u8, value);
+    }
+    // Started again elsewhere after a stop
+    let restart = start + Duration::from_secs(1);
+    follower.receive(restart, 0, 0);
+    assert!(follower.playing(restart));
+    assert_eq!(follower.seconds(restart), None);
+}
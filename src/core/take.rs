@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::{clip::ClipCore, tempo::TempoMap};
+use serde::{Deserialize, Serialize};
+
+/// Shortest range in beats of a comp segment
+const MIN_SEGMENT_LENGTH: f32 = 1. / 64.;
+
+/// Pass of a loop recording kept in a lane under its track
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Take {
+    pub id: String,
+    /// Audio recorded during the pass, placed where it was recorded
+    pub clip: ClipCore,
+}
+
+impl Take {
+    pub fn new(clip: ClipCore) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().into(),
+            clip,
+        }
+    }
+}
+
+/// Range of the timeline played from a take
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompSegment {
+    pub start: f32,
+    pub end: f32,
+    pub take: String,
+}
+
+/// Takes of a track and the ranges chosen from each of them.
+/// The comp itself is made of the regular clips of the track.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TakeLanes {
+    pub takes: Vec<Take>,
+    /// Segments sorted by start, without overlap
+    pub comp: Vec<CompSegment>,
+    /// Show the lanes under the track
+    pub open: bool,
+}
+
+impl TakeLanes {
+    pub fn new() -> Self {
+        Self {
+            takes: Vec::new(),
+            comp: Vec::new(),
+            open: true,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.takes.is_empty()
+    }
+
+    pub fn take(&self, id: &str) -> Option<&Take> {
+        self.takes.iter().find(|take| take.id == id)
+    }
+
+    /// Play `take` from `start` to `end`, limited to the range of the take.
+    /// Returns the selected range.
+    pub fn select(
+        &mut self,
+        take: &str,
+        start: f32,
+        end: f32,
+        tempo: &TempoMap,
+    ) -> Option<(f32, f32)> {
+        let clip = &self.take(take)?.clip;
+        let start = start.max(clip.position);
+        let end = end.min(clip.end(tempo));
+        if end - start < MIN_SEGMENT_LENGTH {
+            return None;
+        }
+        // Cut the segments overlapping the range
+        let mut comp = Vec::with_capacity(self.comp.len() + 2);
+        for segment in self.comp.drain(..) {
+            if segment.end <= start || segment.start >= end {
+                comp.push(segment);
+                continue;
+            }
+            if segment.start < start {
+                comp.push(CompSegment {
+                    end: start,
+                    ..segment.clone()
+                });
+            }
+            if segment.end > end {
+                comp.push(CompSegment {
+                    start: end,
+                    ..segment
+                });
+            }
+        }
+        comp.push(CompSegment {
+            start,
+            end,
+            take: take.into(),
+        });
+        comp.retain(|segment| segment.end - segment.start >= MIN_SEGMENT_LENGTH);
+        comp.sort_by(|a, b| a.start.total_cmp(&b.start));
+        // Merge neighbours of the same take
+        self.comp = Vec::with_capacity(comp.len());
+        for segment in comp {
+            match self.comp.last_mut() {
+                Some(last) if last.take == segment.take && last.end >= segment.start => {
+                    last.end = last.end.max(segment.end);
+                }
+                _ => self.comp.push(segment),
+            }
+        }
+        Some((start, end))
+    }
+
+    /// Clips of the comp between `start` and `end`, cut from their takes
+    pub fn comp_clips(&self, start: f32, end: f32, tempo: &TempoMap) -> Vec<ClipCore> {
+        self.comp
+            .iter()
+            .filter(|segment| segment.end > start && segment.start < end)
+            .filter_map(|segment| {
+                let mut clip = self.take(&segment.take)?.clip.clone_with_new_id();
+                clip.trim_end_at(segment.end.min(end), tempo);
+                clip.trim_start_at(segment.start.max(start), tempo);
+                Some(clip)
+            })
+            .collect()
+    }
+}
+
+/// Split the clip of a recording made while looping from `loop_start` to `loop_end`
+/// into one take per pass. The first pass stays where recording started,
+/// the others are placed at the start of the loop.
+pub fn loop_passes(clip: &ClipCore, loop_start: f32, loop_end: f32, tempo: &TempoMap) -> Vec<Take> {
+    let duration = clip.audio.duration.map_or(0., |d| d.as_secs_f64());
+    let first = tempo.beats_to_secs(loop_end) - tempo.beats_to_secs(clip.position);
+    let length = tempo.beats_to_secs(loop_end) - tempo.beats_to_secs(loop_start);
+    // Source time at the start of the clip, after the trimmed latency
+    let offset = clip.trim_start as f64 * duration;
+    if duration <= 0. || length <= 0. || first <= 0. || duration <= offset + first {
+        return vec![Take::new(clip.clone())];
+    }
+    let mut takes = Vec::new();
+    let mut start = offset;
+    let mut end = offset + first;
+    let mut position = clip.position;
+    while start < duration {
+        let mut pass = clip.clone_with_new_id();
+        pass.position = position;
+        pass.trim_start = (start / duration) as f32;
+        pass.trim_end = (end.min(duration) / duration) as f32;
+        takes.push(Take::new(pass));
+        (start, end, position) = (end, end + length, loop_start);
+    }
+    takes
+}
@@ -0,0 +1,60 @@
+use crate::{
+    analysis::AudioInfo,
+    core::{
+        clip::ClipCore,
+        take::{Take, TakeLanes, loop_passes},
+        tempo::TempoMap,
+    },
+};
+
+fn take(position: f32, seconds: f32) -> Take {
+    let audio = AudioInfo::from_samples("take.wav", vec![0.; (seconds * 44100.) as usize], 44100);
+    Take::new(ClipCore::new(audio, position))
+}
+
+#[test]
+fn test_select_takes() {
+    // Two passes of a 4 beat loop at 120 bpm
+    let tempo = TempoMap::default();
+    let mut lanes = TakeLanes::new();
+    lanes.takes = vec![take(0., 2.), take(0., 2.)];
+    let (first, second) = (lanes.takes[0].id.clone(), lanes.takes[1].id.clone());
+
+    assert_eq!(lanes.select(&second, 0., 4., &tempo), Some((0., 4.)));
+    assert_eq!(lanes.select(&first, 1., 2., &tempo), Some((1., 2.)));
+    let ranges: Vec<_> = lanes
+        .comp
+        .iter()
+        .map(|segment| (segment.start, segment.end, segment.take == first))
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![(0., 1., false), (1., 2., true), (2., 4., false)]
+    );
+
+    // Selecting the second take again merges the segments, limited to the take
+    assert_eq!(lanes.select(&second, 0.5, 8., &tempo), Some((0.5, 4.)));
+    assert_eq!(lanes.comp.len(), 1);
+
+    let clips = lanes.comp_clips(1., 3., &tempo);
+    assert_eq!(clips.len(), 1);
+    assert_eq!(clips[0].position, 1.);
+    assert!((clips[0].end(&tempo) - 3.).abs() < 1e-4);
+}
+
+#[test]
+fn test_loop_passes() {
+    // 4 seconds recorded from beat 2 over a loop of beats 0 to 4 at 120 bpm
+    let tempo = TempoMap::default();
+    let recording = take(2., 4.).clip;
+    let passes = loop_passes(&recording, 0., 4., &tempo);
+    let ranges: Vec<_> = passes
+        .iter()
+        .map(|take| (take.clip.position, take.clip.end(&tempo)))
+        .collect();
+    assert_eq!(ranges.len(), 3);
+    for ((position, end), expected) in ranges.into_iter().zip([(2., 4.), (0., 4.), (0., 2.)]) {
+        assert!((position - expected.0).abs() < 1e-4);
+        assert!((end - expected.1).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,246 @@
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+/// Lowest tempo of a point
+pub const MIN_BPM: f32 = 20.;
+/// Highest tempo of a point
+pub const MAX_BPM: f32 = 999.;
+/// Smallest distance in beats between two tempo points
+const MIN_POINT_DISTANCE: f32 = 1. / 64.;
+
+/// How the tempo goes from a point to the next one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TempoCurve {
+    /// Constant until the next point
+    #[default]
+    Step,
+    /// Linear change in beats up to the tempo of the next point
+    Ramp,
+}
+
+impl TempoCurve {
+    pub fn name(&self) -> &str {
+        match self {
+            TempoCurve::Step => "Step",
+            TempoCurve::Ramp => "Ramp",
+        }
+    }
+}
+
+/// Tempo change at a beat of the timeline
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TempoPoint {
+    pub beat: f32,
+    pub bpm: f32,
+    pub curve: TempoCurve,
+}
+
+impl TempoPoint {
+    pub fn new(beat: f32, bpm: f32, curve: TempoCurve) -> Self {
+        Self { beat, bpm, curve }
+    }
+}
+
+/// Tempo track of the project, mapping beats to seconds.
+/// The first point sits at beat 0 and its tempo is kept before it, the last tempo is kept after
+/// the last point.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "Vec<TempoPoint>", from = "Vec<TempoPoint>")]
+pub struct TempoMap {
+    /// Points sorted by beat
+    points: Vec<TempoPoint>,
+    /// Seconds at each point
+    secs: Vec<f64>,
+}
+
+impl TempoMap {
+    pub fn new(bpm: f32) -> Self {
+        Self {
+            points: vec![TempoPoint::new(0., bpm, TempoCurve::Step)],
+            secs: vec![0.],
+        }
+    }
+
+    pub fn points(&self) -> &[TempoPoint] {
+        &self.points
+    }
+
+    /// Index of the point active at `beat`
+    pub fn point_at(&self, beat: f32) -> usize {
+        self.points
+            .partition_point(|point| point.beat <= beat)
+            .saturating_sub(1)
+    }
+
+    /// Tempo in beats per minute at `beat`
+    pub fn bpm_at(&self, beat: f32) -> f32 {
+        let index = self.point_at(beat);
+        let point = self.points[index];
+        match (point.curve, self.points.get(index + 1)) {
+            (TempoCurve::Ramp, Some(next)) if beat > point.beat => {
+                let ratio = (beat - point.beat) / (next.beat - point.beat);
+                point.bpm + (next.bpm - point.bpm) * ratio
+            }
+            _ => point.bpm,
+        }
+    }
+
+    /// Slope of the tempo after the point at `index`, in bpm per beat
+    fn slope(&self, index: usize) -> f64 {
+        let point = self.points[index];
+        match (point.curve, self.points.get(index + 1)) {
+            (TempoCurve::Ramp, Some(next)) => {
+                (next.bpm - point.bpm) as f64 / (next.beat - point.beat) as f64
+            }
+            _ => 0.,
+        }
+    }
+
+    /// Seconds elapsed from the point at `index` to `beats` after it
+    fn segment_secs(&self, index: usize, beats: f64) -> f64 {
+        let bpm = self.points[index].bpm as f64;
+        let slope = self.slope(index);
+        if slope.abs() < 1e-9 {
+            return beats * 60. / bpm;
+        }
+        60. / slope * ((bpm + slope * beats) / bpm).ln()
+    }
+
+    /// Beats elapsed from the point at `index` after `secs` seconds
+    fn segment_beats(&self, index: usize, secs: f64) -> f64 {
+        let bpm = self.points[index].bpm as f64;
+        let slope = self.slope(index);
+        if slope.abs() < 1e-9 {
+            return secs * bpm / 60.;
+        }
+        bpm * ((slope * secs / 60.).exp() - 1.) / slope
+    }
+
+    /// Time in seconds of `beat`
+    pub fn beats_to_secs(&self, beat: f32) -> f64 {
+        if beat < 0. {
+            return beat as f64 * 60. / self.points[0].bpm as f64;
+        }
+        let index = self.point_at(beat);
+        self.secs[index] + self.segment_secs(index, (beat - self.points[index].beat) as f64)
+    }
+
+    /// Beat played at `secs` seconds
+    pub fn secs_to_beats(&self, secs: f64) -> f32 {
+        if secs < 0. {
+            return (secs * self.points[0].bpm as f64 / 60.) as f32;
+        }
+        let index = self.secs.partition_point(|s| *s <= secs).saturating_sub(1);
+        self.points[index].beat + self.segment_beats(index, secs - self.secs[index]) as f32
+    }
+
+    /// Frame of `beat` at `sample_rate`
+    pub fn beats_to_frames(&self, beat: f32, sample_rate: usize) -> usize {
+        (self.beats_to_secs(beat) * sample_rate as f64).round() as usize
+    }
+
+    /// Beat played at `frame` at `sample_rate`
+    pub fn frames_to_beats(&self, frame: usize, sample_rate: usize) -> f32 {
+        self.secs_to_beats(frame as f64 / sample_rate as f64)
+    }
+
+    /// Insert a point, replacing the one closer than the minimum distance
+    /// between points, which keeps its beat. Returns its index.
+    pub fn insert(&mut self, point: TempoPoint) -> usize {
+        let point = TempoPoint {
+            beat: point.beat.max(0.),
+            bpm: point.bpm.clamp(MIN_BPM, MAX_BPM),
+            ..point
+        };
+        let index = self.point_at(point.beat);
+        let close = [index, index + 1].into_iter().find(|i| {
+            self.points
+                .get(*i)
+                .is_some_and(|p| (p.beat - point.beat).abs() < MIN_POINT_DISTANCE)
+        });
+        let index = if let Some(index) = close {
+            self.points[index] = TempoPoint {
+                beat: self.points[index].beat,
+                ..point
+            };
+            index
+        } else {
+            self.points.insert(index + 1, point);
+            index + 1
+        };
+        self.update();
+        index
+    }
+
+    /// Replace the point at `index`, keeping it between its neighbours.
+    /// The first point always stays at beat 0.
+    pub fn set(&mut self, index: usize, point: TempoPoint) {
+        let min = if index > 0 {
+            self.points[index - 1].beat + MIN_POINT_DISTANCE
+        } else {
+            0.
+        };
+        let max = self
+            .points
+            .get(index + 1)
+            .map_or(f32::INFINITY, |next| next.beat - MIN_POINT_DISTANCE);
+        if let Some(current) = self.points.get_mut(index) {
+            *current = TempoPoint {
+                beat: if index == 0 {
+                    0.
+                } else {
+                    point.beat.clamp(min, max.max(min))
+                },
+                bpm: point.bpm.clamp(MIN_BPM, MAX_BPM),
+                curve: point.curve,
+            };
+        }
+        self.update();
+    }
+
+    /// Remove the point at `index`, the first point cannot be removed
+    pub fn remove(&mut self, index: usize) {
+        if index > 0 && index < self.points.len() {
+            self.points.remove(index);
+            self.update();
+        }
+    }
+
+    /// Recompute the seconds at each point
+    fn update(&mut self) {
+        self.secs.resize(self.points.len(), 0.);
+        for index in 1..self.points.len() {
+            let beats = (self.points[index].beat - self.points[index - 1].beat) as f64;
+            self.secs[index] = self.secs[index - 1] + self.segment_secs(index - 1, beats);
+        }
+    }
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        Self::new(120.)
+    }
+}
+
+/// Saved as its points, the seconds are computed again when loaded
+impl From<TempoMap> for Vec<TempoPoint> {
+    fn from(tempo: TempoMap) -> Self {
+        tempo.points
+    }
+}
+
+impl From<Vec<TempoPoint>> for TempoMap {
+    fn from(points: Vec<TempoPoint>) -> Self {
+        let mut tempo = TempoMap::default();
+        for (index, point) in points.into_iter().enumerate() {
+            if index == 0 {
+                tempo.set(0, point);
+            } else {
+                tempo.insert(point);
+            }
+        }
+        tempo
+    }
+}
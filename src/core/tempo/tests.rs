@@ -0,0 +1,51 @@
+use crate::core::tempo::{TempoCurve, TempoMap, TempoPoint};
+
+#[test]
+fn test_step_tempo() {
+    let mut tempo = TempoMap::new(120.);
+    tempo.insert(TempoPoint::new(8., 60., TempoCurve::Step));
+    // 8 beats at 120 bpm then 4 beats at 60 bpm
+    assert!((tempo.beats_to_secs(8.) - 4.).abs() < 1e-9);
+    assert!((tempo.beats_to_secs(12.) - 8.).abs() < 1e-9);
+    assert!((tempo.secs_to_beats(6.) - 10.).abs() < 1e-5);
+    assert_eq!(tempo.bpm_at(7.9), 120.);
+    assert_eq!(tempo.bpm_at(8.), 60.);
+    // The first tempo is kept before the first point
+    assert!((tempo.beats_to_secs(-2.) + 1.).abs() < 1e-9);
+}
+
+#[test]
+fn test_ramp_tempo() {
+    let mut tempo = TempoMap::new(60.);
+    tempo.set(0, TempoPoint::new(0., 60., TempoCurve::Ramp));
+    tempo.insert(TempoPoint::new(4., 120., TempoCurve::Step));
+    assert_eq!(tempo.bpm_at(2.), 90.);
+    // Integral of 60 / (60 + 15 b) over 4 beats
+    let expected = 4. * 2f64.ln();
+    assert!((tempo.beats_to_secs(4.) - expected).abs() < 1e-9);
+    assert!((tempo.beats_to_secs(6.) - expected - 1.).abs() < 1e-9);
+    for beat in [0.5, 2., 3.99, 5.] {
+        assert!((tempo.secs_to_beats(tempo.beats_to_secs(beat)) - beat).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn test_insert_merges_close_points() {
+    let mut tempo = TempoMap::new(60.);
+    tempo.set(0, TempoPoint::new(0., 60., TempoCurve::Ramp));
+    assert_eq!(tempo.insert(TempoPoint::new(4., 120., TempoCurve::Step)), 1);
+    // A point too close to an existing one replaces it instead of making a zero-width ramp
+    assert_eq!(
+        tempo.insert(TempoPoint::new(4.001, 90., TempoCurve::Step)),
+        1
+    );
+    assert_eq!(
+        tempo.insert(TempoPoint::new(0.001, 80., TempoCurve::Ramp)),
+        0
+    );
+    assert_eq!(tempo.points().len(), 2);
+    assert_eq!(tempo.points()[0].beat, 0.);
+    assert_eq!(tempo.points()[1].beat, 4.);
+    assert_eq!(tempo.bpm_at(4.), 90.);
+    assert!(tempo.beats_to_secs(6.).is_finite());
+}
@@ -1,7 +1,14 @@
 use std::collections::HashMap;
 
 use crate::{
-    core::{clip::ClipCore, message::GuiToPlayerMsg},
+    core::{
+        clip::{ClipCore, ClipFade, FadeCurve},
+        instrument::{DrumRackParams, ExternalParams, InstrumentParams},
+        message::GuiToPlayerMsg,
+        midi::MidiClipCore,
+        take::TakeLanes,
+        tempo::TempoMap,
+    },
     ui::{
         effect::UIEffect,
         effects::{EffectId, create_effect_from_id},
@@ -10,6 +17,7 @@ use crate::{
 use egui::Color32;
 use rand::Rng;
 use rtrb::Producer;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
@@ -19,16 +27,106 @@ pub enum TrackSoloState {
     Solo,
 }
 
+/// Channels of the input device recorded by an armed track
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrackInput {
+    /// Index of the first channel
+    pub channel: usize,
+    /// Record `channel` and the next one
+    pub stereo: bool,
+}
+
+impl TrackInput {
+    pub fn name(&self) -> String {
+        if self.stereo {
+            format!("In {}/{}", self.channel + 1, self.channel + 2)
+        } else {
+            format!("In {}", self.channel + 1)
+        }
+    }
+
+    /// Number of recorded channels
+    pub fn channels(&self) -> usize {
+        if self.stereo { 2 } else { 1 }
+    }
+}
+
+impl Default for TrackInput {
+    fn default() -> Self {
+        Self {
+            channel: 0,
+            stereo: true,
+        }
+    }
+}
+
+/// When a track plays its input through its effects
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum MonitorMode {
+    Off,
+    /// Always monitor the input
+    On,
+    /// Monitor the input while armed, except when playing back without recording
+    #[default]
+    Auto,
+}
+
+impl MonitorMode {
+    pub const ALL: [MonitorMode; 3] = [MonitorMode::Off, MonitorMode::On, MonitorMode::Auto];
+
+    pub fn name(&self) -> &str {
+        match self {
+            MonitorMode::Off => "Off",
+            MonitorMode::On => "On",
+            MonitorMode::Auto => "Auto",
+        }
+    }
+
+    /// Whether the input is heard for a track `armed` with the transport `playing` and `recording`
+    pub fn active(&self, armed: bool, playing: bool, recording: bool) -> bool {
+        match self {
+            MonitorMode::Off => false,
+            MonitorMode::On => true,
+            MonitorMode::Auto => armed && (!playing || recording),
+        }
+    }
+}
+
+/// What a track plays
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum TrackType {
+    /// Audio clips and the audio input
+    #[default]
+    Audio,
+    /// MIDI clips and the MIDI inputs played by an instrument
+    Midi,
+}
+
 pub const DEFAULT_TRACK_HEIGHT: f32 = 60.;
 pub const TRACK_CLOSED_HEIGHT: f32 = 22.;
+/// Height of a take lane under its track
+pub const TAKE_LANE_HEIGHT: f32 = 30.;
+/// Gain range of the track volume in dB
+pub const MIN_GAIN_DB: f32 = -40.;
+pub const MAX_GAIN_DB: f32 = 5.;
 /// A track containing multiple clips
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "TrackFile", from = "TrackFile")]
 pub struct TrackCore {
     pub id: String,
+    pub kind: TrackType,
     pub clips: Vec<ClipCore>,
+    /// Clips of a MIDI track
+    pub midi_clips: Vec<MidiClipCore>,
+    /// Instrument playing the MIDI clips
+    pub instrument: Option<InstrumentParams>,
     pub muted: bool,
     pub volume: f32,
     pub arm: bool,
+    pub input: TrackInput,
+    pub monitor: MonitorMode,
+    /// Takes of the loop recordings
+    pub takes: TakeLanes,
     /// TODO Should not mix ui in the state
     effects: Vec<UIEffect>,
     pub mutable: MutableTrackCore,
@@ -39,10 +137,16 @@ impl TrackCore {
     pub fn new() -> Self {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
+            kind: TrackType::Audio,
             clips: vec![],
+            midi_clips: vec![],
+            instrument: None,
             muted: false,
             volume: 1.,
             arm: false,
+            input: TrackInput::default(),
+            monitor: MonitorMode::default(),
+            takes: TakeLanes::new(),
             mutable: MutableTrackCore::new(),
             old_mutable: MutableTrackCore::new(),
             effects: vec![],
@@ -54,6 +158,28 @@ impl TrackCore {
         track.id = id.into();
         track
     }
+    /// Create a MIDI track with its instrument
+    pub fn midi() -> Self {
+        let mut track = Self::new();
+        track.kind = TrackType::Midi;
+        track.instrument = Some(InstrumentParams::default());
+        track.mutable.name = "# MIDI Track".into();
+        track
+    }
+    /// Create a MIDI track playing a drum rack
+    pub fn drum_rack() -> Self {
+        let mut track = Self::midi();
+        track.instrument = Some(InstrumentParams::DrumRack(DrumRackParams::default()));
+        track.mutable.name = "# Drum Rack".into();
+        track
+    }
+    /// Create a MIDI track playing an external instrument through a MIDI output
+    pub fn external() -> Self {
+        let mut track = Self::midi();
+        track.instrument = Some(InstrumentParams::External(ExternalParams::default()));
+        track.mutable.name = "# External MIDI".into();
+        track
+    }
 
     pub fn get_reference(
         &self,
@@ -63,7 +189,18 @@ impl TrackCore {
     ) -> TrackReferenceCore {
         TrackReferenceCore {
             arm: self.arm,
+            input: self.input,
+            monitor: self.monitor,
+            lanes_height: if self.takes.open && !self.mutable.closed {
+                self.takes.takes.len() as f32 * TAKE_LANE_HEIGHT
+            } else {
+                0.
+            },
+            takes: self.takes.clone(),
+            kind: self.kind,
             clips: self.clips.clone(),
+            midi_clips: self.midi_clips.clone(),
+            instrument: self.instrument.clone(),
             closed: self.mutable.closed,
             color: self.mutable.color,
             height: self.mutable.height,
@@ -94,43 +231,73 @@ impl TrackCore {
     }
 
     /// Add clips to this track by making sure no overlap occurs.
+    /// When `crossfade` is set, clips overlapping only one edge of an added clip are kept
+    /// and faded into it instead of being trimmed.
     /// Updates at the same time the audio thread.
     /// Returns created and deleted clips in the process.
     pub fn add_clips(
         &mut self,
         added_clips: &Vec<ClipCore>,
-        bpm: f32,
+        tempo: &TempoMap,
+        crossfade: bool,
         tx: &mut Producer<GuiToPlayerMsg>,
     ) -> (Vec<ClipCore>, Vec<ClipCore>) {
         let mut deleted_clips = Vec::new();
         let mut created_clips = Vec::new();
+        let mut added_clips = added_clips.clone();
 
         // Fix overlap
-        for added_clip in added_clips.iter() {
+        for added_clip in added_clips.iter_mut() {
             let mut new_clips = vec![];
             let start = added_clip.position;
-            let end = added_clip.end(bpm);
+            let end = added_clip.end(tempo);
 
             for clip in self.clips.iter() {
                 // No overlap
-                if clip.position >= end || clip.end(bpm) <= start {
+                if clip.position >= end || clip.end(tempo) <= start {
                     new_clips.push(clip.clone());
                     continue;
                 }
                 deleted_clips.push(clip.clone());
+                // Sample overlaps the start of the new clip only
+                if crossfade && clip.position < start && clip.end(tempo) <= end {
+                    let length = clip.end(tempo) - start;
+                    let mut faded = clip.clone_with_new_id();
+                    faded.fade_out = ClipFade::new(length, FadeCurve::EqualPower);
+                    faded.clamp_fades(tempo);
+                    added_clip.fade_in = ClipFade::new(length, FadeCurve::EqualPower);
+                    added_clip.clamp_fades(tempo);
+
+                    created_clips.push(faded.clone());
+                    new_clips.push(faded);
+                    continue;
+                }
+                // Sample overlaps the end of the new clip only
+                if crossfade && clip.position >= start && clip.end(tempo) > end {
+                    let length = end - clip.position;
+                    let mut faded = clip.clone_with_new_id();
+                    faded.fade_in = ClipFade::new(length, FadeCurve::EqualPower);
+                    faded.clamp_fades(tempo);
+                    added_clip.fade_out = ClipFade::new(length, FadeCurve::EqualPower);
+                    added_clip.clamp_fades(tempo);
+
+                    created_clips.push(faded.clone());
+                    new_clips.push(faded);
+                    continue;
+                }
                 // Sample overlaps before new clip
                 if clip.position < start {
                     let mut trimmed = clip.clone_with_new_id();
-                    trimmed.trim_end_at(start, bpm);
+                    trimmed.trim_end_at(start, tempo);
 
                     created_clips.push(trimmed.clone());
                     new_clips.push(trimmed);
                 }
 
                 // Sample overlaps after new clip
-                if clip.end(bpm) > end {
+                if clip.end(tempo) > end {
                     let mut trimmed = clip.clone_with_new_id();
-                    trimmed.trim_start_at(end, bpm);
+                    trimmed.trim_start_at(end, tempo);
 
                     created_clips.push(trimmed.clone());
                     new_clips.push(trimmed);
@@ -162,7 +329,7 @@ impl TrackCore {
     pub fn _fix_overlaps(
         &mut self,
         added_clip: &ClipCore,
-        bpm: f32,
+        tempo: &TempoMap,
         deleted_clips: &mut Vec<ClipCore>,
         created_clips: &mut Vec<ClipCore>,
         ignore: &Vec<String>,
@@ -170,10 +337,10 @@ impl TrackCore {
         // Vec of clips after update
         let mut new_clips = vec![];
         let start = added_clip.position;
-        let end = added_clip.end(bpm);
+        let end = added_clip.end(tempo);
         for clip in self.clips.iter() {
             // No overlap or clip already added
-            if (clip.position > end || clip.end(bpm) < start)
+            if (clip.position > end || clip.end(tempo) < start)
                 || clip.id == added_clip.id
                 || ignore.contains(&clip.id)
             {
@@ -184,16 +351,16 @@ impl TrackCore {
             // Sample overlaps before new clip
             if clip.position < start {
                 let mut trimmed = clip.clone_with_new_id();
-                trimmed.trim_end_at(start, bpm);
+                trimmed.trim_end_at(start, tempo);
 
                 created_clips.push(trimmed.clone());
                 new_clips.push(trimmed);
             }
 
             // Sample overlaps after new clip
-            if clip.end(bpm) > end {
+            if clip.end(tempo) > end {
                 let mut trimmed = clip.clone_with_new_id();
-                trimmed.trim_start_at(end, bpm);
+                trimmed.trim_start_at(end, tempo);
 
                 created_clips.push(trimmed.clone());
                 new_clips.push(trimmed);
@@ -205,39 +372,31 @@ impl TrackCore {
     pub fn cut_clip_at(
         &mut self,
         position: f32,
-        bpm: f32,
+        tempo: &TempoMap,
         tx: &mut Producer<GuiToPlayerMsg>,
     ) -> Option<(ClipCore, ClipCore, ClipCore)> {
         let mut found_clip = None;
         // Find corresponding clip
         for clip in self.clips.iter_mut() {
-            if clip.position < position && position < clip.end(bpm) {
+            if clip.position < position && position < clip.end(tempo) {
                 let original = clip.clone();
                 // Create right clip
                 let mut right_clip = clip.clone_with_new_id();
-                right_clip.trim_start_at(position, bpm);
+                right_clip.fade_in = ClipFade::default();
+                right_clip.trim_start_at(position, tempo);
                 // Resize left clip
-                clip.trim_end_at(position, bpm);
+                clip.fade_out = ClipFade::default();
+                clip.trim_end_at(position, tempo);
                 found_clip = Some((original, clip.clone(), right_clip.clone()));
                 break;
             }
         }
         if let Some((original, left_clip, right_clip)) = found_clip {
             // Uppdate audio thread
-            let _ = tx.push(GuiToPlayerMsg::AddClip(
-                self.id.clone(),
-                right_clip.audio.path.clone(),
-                right_clip.position,
-                right_clip.id.clone(),
-                right_clip.trim_start,
-                right_clip.trim_end,
-            ));
-            let _ = tx.push(GuiToPlayerMsg::ResizeClip(
-                left_clip.id.clone(),
-                left_clip.trim_start,
-                left_clip.trim_end,
-                left_clip.position,
-            ));
+            let mut map = HashMap::new();
+            map.insert(self.id.clone(), vec![right_clip.clone()]);
+            let _ = tx.push(GuiToPlayerMsg::AddClips(map));
+            let _ = tx.push(GuiToPlayerMsg::UpdateClip(left_clip.clone()));
             // Add new clip
             self.clips.push(right_clip.clone());
             return Some((original, left_clip, right_clip));
@@ -249,7 +408,7 @@ impl TrackCore {
         &mut self,
         ids: &Vec<String>,
         bounds: Option<(f32, f32)>,
-        bpm: f32,
+        tempo: &TempoMap,
         tx: &mut Producer<GuiToPlayerMsg>,
     ) -> (Vec<ClipCore>, Vec<ClipCore>) {
         let mut created_clips = Vec::new();
@@ -260,18 +419,18 @@ impl TrackCore {
                 let mut duplicated_clip = clip.clone_with_new_id();
 
                 if let Some((start_pos, end_pos)) = bounds {
-                    duplicated_clip.trim_start_at(start_pos.max(duplicated_clip.position), bpm);
-                    duplicated_clip.trim_end_at(end_pos, bpm);
+                    duplicated_clip.trim_start_at(start_pos.max(duplicated_clip.position), tempo);
+                    duplicated_clip.trim_end_at(end_pos, tempo);
                     duplicated_clip.position += end_pos - start_pos;
                 } else {
-                    duplicated_clip.position = clip.end(bpm);
+                    duplicated_clip.position = clip.end(tempo);
                 }
                 created_clips.push(duplicated_clip);
             }
         }
         // Update track with new clips
         if !created_clips.is_empty() {
-            let (created, deleted) = self.add_clips(&created_clips, bpm, tx);
+            let (created, deleted) = self.add_clips(&created_clips, tempo, false, tx);
             created_clips = created;
             deleted_clips.extend(deleted);
         }
@@ -282,6 +441,10 @@ impl TrackCore {
     pub fn duplicate(&self) -> (Self, HashMap<String, String>) {
         let mut clone = self.clone();
         clone.id = Uuid::new_v4().into();
+        clone.instrument = self
+            .instrument
+            .as_ref()
+            .map(|instrument| instrument.duplicate());
 
         let mut map = HashMap::new();
 
@@ -291,33 +454,16 @@ impl TrackCore {
             clip.id = new_id.clone();
             map.insert(old_id, new_id);
         }
+        for clip in &mut clone.midi_clips {
+            let old_id = clip.id.clone();
+            let new_id: String = Uuid::new_v4().into();
+            clip.id = new_id.clone();
+            map.insert(old_id, new_id);
+        }
 
         (clone, map)
     }
 
-    pub fn resize_clip_skip_overlap_check(
-        &mut self,
-        id: &String,
-        trim_start: f32,
-        trim_end: f32,
-        position: f32,
-        tx: &mut Producer<GuiToPlayerMsg>,
-    ) {
-        let Some(clip) = self.clips.iter_mut().find(|c| &c.id == id) else {
-            return;
-        };
-        clip.trim_start = trim_start;
-        clip.trim_end = trim_end;
-        clip.position = position;
-
-        let _ = tx.push(GuiToPlayerMsg::ResizeClip(
-            id.clone(),
-            trim_start,
-            trim_end,
-            position,
-        ));
-    }
-
     // Effect management
     pub fn add_effect(&mut self, id: EffectId, index: usize, tx: &mut Producer<GuiToPlayerMsg>) {
         let effect = create_effect_from_id(id);
@@ -344,18 +490,101 @@ impl TrackCore {
         self.effects = new_effects;
     }
 
+    pub fn effects(&self) -> &[UIEffect] {
+        &self.effects
+    }
+
     pub fn effects_mut(&mut self) -> &mut [UIEffect] {
         &mut self.effects
     }
 }
 
+/// Saved fields of a track, a loaded track is not armed
+#[derive(Serialize, Deserialize)]
+struct TrackFile {
+    id: String,
+    name: String,
+    color: [u8; 3],
+    height: f32,
+    closed: bool,
+    kind: TrackType,
+    muted: bool,
+    volume: f32,
+    input: TrackInput,
+    monitor: MonitorMode,
+    clips: Vec<ClipCore>,
+    midi_clips: Vec<MidiClipCore>,
+    instrument: Option<InstrumentParams>,
+    takes: TakeLanes,
+    effects: Vec<UIEffect>,
+}
+
+impl From<TrackCore> for TrackFile {
+    fn from(track: TrackCore) -> Self {
+        let color = track.mutable.color;
+        Self {
+            id: track.id,
+            name: track.mutable.name,
+            color: [color.r(), color.g(), color.b()],
+            height: track.mutable.height,
+            closed: track.mutable.closed,
+            kind: track.kind,
+            muted: track.muted,
+            volume: track.volume,
+            input: track.input,
+            monitor: track.monitor,
+            clips: track.clips,
+            midi_clips: track.midi_clips,
+            instrument: track.instrument,
+            takes: track.takes,
+            effects: track.effects,
+        }
+    }
+}
+
+impl From<TrackFile> for TrackCore {
+    fn from(file: TrackFile) -> Self {
+        let [r, g, b] = file.color;
+        let mutable = MutableTrackCore {
+            name: file.name,
+            height: file.height,
+            closed: file.closed,
+            color: Color32::from_rgb(r, g, b),
+        };
+        Self {
+            id: file.id,
+            kind: file.kind,
+            clips: file.clips,
+            midi_clips: file.midi_clips,
+            instrument: file.instrument,
+            muted: file.muted,
+            volume: file.volume,
+            arm: false,
+            input: file.input,
+            monitor: file.monitor,
+            takes: file.takes,
+            effects: file.effects,
+            old_mutable: mutable.clone(),
+            mutable,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TrackReferenceCore {
     pub id: String,
+    pub kind: TrackType,
     pub clips: Vec<ClipCore>,
+    pub midi_clips: Vec<MidiClipCore>,
+    pub instrument: Option<InstrumentParams>,
     pub muted: bool,
     pub volume: f32,
     pub arm: bool,
+    pub input: TrackInput,
+    pub monitor: MonitorMode,
+    pub takes: TakeLanes,
+    /// Height of the take lanes shown under the track
+    pub lanes_height: f32,
     pub name: String,
     pub height: f32,
     pub closed: bool,
@@ -366,6 +595,11 @@ pub struct TrackReferenceCore {
 }
 
 impl TrackReferenceCore {
+    /// Height of the track with its take lanes
+    pub fn full_height(&self) -> f32 {
+        self.height + self.lanes_height
+    }
+
     pub fn disabled(&self) -> bool {
         self.muted && !matches!(self.solo, crate::core::track::TrackSoloState::Solo)
             || matches!(self.solo, crate::core::track::TrackSoloState::Soloing)
@@ -0,0 +1,98 @@
+use cpal::BufferSize;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rtrb::{Consumer, RingBuffer};
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+
+/// Seconds of input buffered between the input and output streams
+const INPUT_BUFFER_SECS: usize = 1;
+
+/// Audio captured by the input stream, read by the player
+pub struct AudioInput {
+    /// Interleaved samples
+    pub samples: Consumer<f32>,
+    pub channels: usize,
+    pub sample_rate: usize,
+    /// Seconds between the capture of the samples and their callback, stored as `f32` bits
+    pub latency: Arc<AtomicU32>,
+}
+
+impl AudioInput {
+    pub fn latency(&self) -> f32 {
+        f32::from_bits(self.latency.load(Ordering::Relaxed))
+    }
+}
+
+/// Open the default input device at the sample rate of the output device when supported,
+/// else at its default rate. Returns the stream and the audio it captures.
+pub fn spawn_input_stream() -> Option<(cpal::Stream, AudioInput)> {
+    let host = cpal::default_host();
+
+    let device = host.default_input_device()?;
+    let default_config = device.default_input_config().ok()?;
+    let channels = default_config.channels();
+
+    // Record at the playback rate so recorded files line up with the timeline
+    let output_rate = host
+        .default_output_device()
+        .and_then(|output| output.default_output_config().ok())
+        .map(|config| config.sample_rate());
+    let sample_rate = output_rate
+        .filter(|rate| {
+            device.supported_input_configs().is_ok_and(|mut configs| {
+                configs.any(|config| {
+                    config.channels() == channels
+                        && config.min_sample_rate() <= *rate
+                        && config.max_sample_rate() >= *rate
+                })
+            })
+        })
+        .unwrap_or(default_config.sample_rate());
+
+    let config = cpal::StreamConfig {
+        channels,
+        sample_rate,
+        buffer_size: BufferSize::Default,
+    };
+
+    let (mut input_tx, input_rx) =
+        RingBuffer::<f32>::new(INPUT_BUFFER_SECS * sample_rate.0 as usize * channels as usize);
+
+    let latency = Arc::new(AtomicU32::new(0));
+    let callback_latency = latency.clone();
+
+    let stream = device
+        .build_input_stream(
+            &config,
+            move |data: &[f32], info: &cpal::InputCallbackInfo| {
+                let timestamp = info.timestamp();
+                if let Some(delay) = timestamp.callback.duration_since(&timestamp.capture) {
+                    callback_latency.store(delay.as_secs_f32().to_bits(), Ordering::Relaxed);
+                }
+                for sample in data {
+                    if input_tx.push(*sample).is_err() {
+                        break;
+                    }
+                }
+            },
+            move |err| {
+                eprintln!("{}", err);
+            },
+            None,
+        )
+        .ok()?;
+
+    stream.play().ok()?;
+
+    Some((
+        stream,
+        AudioInput {
+            samples: input_rx,
+            channels: channels as usize,
+            sample_rate: sample_rate.0 as usize,
+            latency,
+        },
+    ))
+}
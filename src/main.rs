@@ -1,6 +1,6 @@
 use crate::{
     audio::midi::spawn_midi_thread,
-    core::message::{GuiToPlayerMsg, ProcessToGuiMsg},
+    core::message::{GuiToPlayerMsg, MidiInputEvent, MidiOutputEvent, ProcessToGuiMsg},
     ui::spawn_ui_thread,
 };
 
@@ -11,6 +11,7 @@ mod audio;
 mod cache;
 mod config;
 mod core;
+mod input;
 mod output;
 mod ui;
 
@@ -18,13 +19,21 @@ pub mod utils;
 mod waveform;
 fn main() {
     // Create channels
-    let (to_gui_tx, from_process_rx) = RingBuffer::<ProcessToGuiMsg>::new(256);
-    let (to_process_tx, from_gui_rx) = RingBuffer::<GuiToPlayerMsg>::new(256);
-    let (midi_tx, midi_rx) = RingBuffer::<Vec<u8>>::new(256);
-    // Midi thread that collects midi inputs
-    spawn_midi_thread(midi_tx);
+    let (to_gui_tx, from_process_rx) = RingBuffer::<ProcessToGuiMsg>::new(1024);
+    // Opening a project sends a few messages for each of its tracks at once
+    let (to_process_tx, from_gui_rx) = RingBuffer::<GuiToPlayerMsg>::new(4096);
+    let (midi_tx, midi_rx) = RingBuffer::<MidiInputEvent>::new(1024);
+    let (midi_out_tx, midi_out_rx) = RingBuffer::<MidiOutputEvent>::new(1024);
+    // Midi thread that collects midi inputs and sends the clock and the external tracks
+    let midi = spawn_midi_thread(midi_tx, midi_out_rx);
+    // Input stream that captures the audio to record
+    let (_input_stream, input) = match input::spawn_input_stream() {
+        Some((stream, input)) => (Some(stream), Some(input)),
+        None => (None, None),
+    };
     // Audio thread that plays sound to the device
-    let _cpal_stream = output::spawn_cpal_stream(to_gui_tx, from_gui_rx, midi_rx);
+    let _cpal_stream =
+        output::spawn_cpal_stream(to_gui_tx, from_gui_rx, midi_rx, midi_out_tx, input);
     // Ui thread (main thread). Opens the app window
-    spawn_ui_thread(to_process_tx, from_process_rx).unwrap();
+    spawn_ui_thread(to_process_tx, from_process_rx, midi).unwrap();
 }
@@ -1,5 +1,6 @@
 use crate::audio::player::PlayerBackend;
-use crate::core::message::{GuiToPlayerMsg, ProcessToGuiMsg};
+use crate::core::message::{GuiToPlayerMsg, MidiInputEvent, MidiOutputEvent, ProcessToGuiMsg};
+use crate::input::AudioInput;
 use cpal::BufferSize;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use rtrb::{Consumer, Producer};
@@ -7,7 +8,9 @@ use rtrb::{Consumer, Producer};
 pub fn spawn_cpal_stream(
     to_gui_tx: Producer<ProcessToGuiMsg>,
     from_gui_rx: Consumer<GuiToPlayerMsg>,
-    midi_rx: Consumer<Vec<u8>>,
+    midi_rx: Consumer<MidiInputEvent>,
+    midi_out_tx: Producer<MidiOutputEvent>,
+    input: Option<AudioInput>,
 ) -> cpal::Stream {
     // Setup cpal audio output
 
@@ -17,20 +20,37 @@ pub fn spawn_cpal_stream(
         .default_output_device()
         .expect("no output device available");
 
-    let sample_rate = device.default_output_config().unwrap().sample_rate();
+    let default_config = device.default_output_config().unwrap();
+    let sample_rate = default_config.sample_rate();
+    // Extra outputs can receive the metronome
+    let channels = default_config.channels().max(2);
 
     let config = cpal::StreamConfig {
-        channels: 2,
+        channels,
         sample_rate,
         buffer_size: BufferSize::Default,
     };
 
-    let mut player = PlayerBackend::new(to_gui_tx, from_gui_rx, midi_rx, sample_rate.0 as usize);
+    let mut player = PlayerBackend::new(
+        to_gui_tx,
+        from_gui_rx,
+        midi_rx,
+        midi_out_tx,
+        sample_rate.0 as usize,
+        channels as usize,
+        input,
+    );
 
     let stream = device
         .build_output_stream(
             &config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| player.mix_audio(data),
+            move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                let timestamp = info.timestamp();
+                if let Some(delay) = timestamp.playback.duration_since(&timestamp.callback) {
+                    player.output_latency = delay.as_secs_f32();
+                }
+                player.mix_audio(data)
+            },
             move |err| {
                 eprintln!("{}", err);
             },
@@ -1,13 +1,21 @@
 use crate::{
+    audio::midi::MidiHandle,
     core::{
         message::{AudioToGuiRx, GuiToAudioTx},
         state::{PlaybackState, ToniqueProjectState},
     },
-    ui::panels::{
-        bottom_panel::UIBottomPanel, central_panel::UICentralPanel, left_panel::UILeftPanel,
-        top_bar::UITopBar,
+    ui::{
+        keyboard::keyboard_input,
+        panels::{
+            bottom_panel::UIBottomPanel, central_panel::UICentralPanel, left_panel::UILeftPanel,
+            settings::UISettings, top_bar::UITopBar,
+        },
     },
 };
+use std::time::Duration;
+
+/// Interval between two reads of the controllers when no control is moving
+const MIDI_POLL_INTERVAL: Duration = Duration::from_millis(30);
 
 pub struct ToniqueApp {
     state: ToniqueProjectState,
@@ -15,16 +23,23 @@ pub struct ToniqueApp {
     bottom_panel: UIBottomPanel,
     left_panel: UILeftPanel,
     central_panel: UICentralPanel,
+    settings: UISettings,
 }
 
 impl ToniqueApp {
-    pub fn new(tx: GuiToAudioTx, rx: AudioToGuiRx, _cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(
+        tx: GuiToAudioTx,
+        rx: AudioToGuiRx,
+        midi: MidiHandle,
+        _cc: &eframe::CreationContext<'_>,
+    ) -> Self {
         Self {
             state: ToniqueProjectState::new(tx, rx),
             top_bar: UITopBar::new(),
-            bottom_panel: UIBottomPanel::new(),
+            bottom_panel: UIBottomPanel::new(midi.clone()),
             left_panel: UILeftPanel::new(),
             central_panel: UICentralPanel::new(),
+            settings: UISettings::new(midi),
         }
     }
 }
@@ -38,9 +53,17 @@ impl eframe::App for ToniqueApp {
         {
             ctx.request_repaint();
         }
+        // Follow the controllers bound to controls
+        if self.state.midi_learn().driving() {
+            ctx.request_repaint();
+        } else if self.state.midi_learn().listening() {
+            ctx.request_repaint_after(MIDI_POLL_INTERVAL);
+        }
+        keyboard_input(ctx, &mut self.state);
         self.top_bar.show(ctx, &mut self.state);
         self.bottom_panel.show(ctx, &mut self.state);
         self.left_panel.show(ctx, &mut self.state);
         self.central_panel.show(ctx, &mut self.state);
+        self.settings.show(ctx, &mut self.state);
     }
 }
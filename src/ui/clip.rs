@@ -1,19 +1,53 @@
 use crate::{
     core::{
-        clip::ClipCore, grid::GridService, state::ToniqueProjectState, track::TRACK_CLOSED_HEIGHT,
+        clip::{ChannelMode, ClipCore, FadeCurve},
+        grid::GridService,
+        state::ToniqueProjectState,
+        tempo::TempoMap,
+        track::TRACK_CLOSED_HEIGHT,
+    },
+    ui::{
+        waveform::UIWaveform,
+        widget::context_menu::{ContextMenuButton, ContextMenuLabel, ContextMenuSeparator},
     },
-    ui::{waveform::UIWaveform, widget::context_menu::ContextMenuButton},
 };
 use egui::{
-    Align2, Color32, CursorIcon, FontFamily, FontId, Pos2, Rect, Response, Sense, Stroke, Ui, Vec2,
-    pos2, vec2,
+    Align2, Color32, CursorIcon, DragValue, FontFamily, FontId, Painter, Pos2, Rect, Response,
+    Sense, Shape, Stroke, Ui, Vec2, pos2, vec2,
+};
+use egui_phosphor::fill::{
+    ARROWS_IN_LINE_HORIZONTAL, CHECK, MICROPHONE, PENCIL_SIMPLE, PLUS_MINUS, REWIND, SCISSORS,
+    SPEAKER_HIGH, TRASH, WAVE_SINE,
 };
-use egui_phosphor::fill::TRASH;
 
 const PADDING_TEXT: f32 = 4.;
 const BORDER_WIDTH: f32 = 2.;
 const HEADER_HEIGHT: f32 = 16.;
 const MIN_HANDLE_WIDTH: f32 = 20.;
+const FADE_HANDLE_SIZE: f32 = 8.;
+const FADE_CURVE_SEGMENTS: usize = 16;
+const TRANSIENT_MARKER_HEIGHT: f32 = 5.;
+
+/// Beats of the transients of `clip` when it starts at `position`
+pub fn transients_at(clip: &ClipCore, tempo: &TempoMap, position: f32) -> Vec<f32> {
+    clip.transients(tempo)
+        .into_iter()
+        .map(|t| t + position)
+        .collect()
+}
+
+/// Snap `beats` at the nearest transient of the clip, then at the grid
+pub fn snap_at_transients(
+    grid: &GridService,
+    tempo: &TempoMap,
+    clip: &ClipCore,
+    beats: f32,
+) -> Option<f32> {
+    // Transients of the clip take precedence over the grid
+    grid.snap_at_points(beats, transients_at(clip, tempo, clip.position))
+        .or_else(|| grid.snap_at_grid_option(beats))
+}
+
 #[derive(Clone)]
 pub struct UIClip {
     waveform: UIWaveform,
@@ -50,7 +84,7 @@ impl UIClip {
                 ui,
                 &state.grid,
                 Rect::from_min_size(Pos2::new(pos.x - 2.0, pos.y), Vec2::new(4., size.y)),
-                state.bpm(),
+                state.tempo(),
                 viewport,
                 &mut clip_copy,
             );
@@ -66,7 +100,7 @@ impl UIClip {
                     Pos2::new(pos.x + size.x - 2.0, pos.y),
                     Vec2::new(4., size.y),
                 ),
-                state.bpm(),
+                state.tempo(),
                 viewport,
                 &mut clip_copy,
             );
@@ -128,33 +162,78 @@ impl UIClip {
             Stroke::new(1.0, color.blend(Color32::from_black_alpha(50))),
         );
         // Waveform
-        if show_waveform && let Ok(data) = clip.audio.data.read() {
+        if show_waveform {
             let mut shapes = Vec::new();
             let waveform_rect = Rect::from_min_max(
                 Pos2::new(pos.x.max(viewport.left()), pos.y + HEADER_HEIGHT),
                 Pos2::new((pos.x + size.x).min(viewport.right()), pos.y + size.y),
             );
-
-            let start_ratio = (waveform_rect.left() - pos.x) / size.x
-                * (clip.trim_end - clip.trim_start)
-                + clip.trim_start;
-
-            let end_ratio = clip.trim_end
-                - (pos.x + size.x - waveform_rect.right()) / size.x
-                    * (clip.trim_end - clip.trim_start);
-
-            self.waveform.paint(
+            let pixels_per_beat = state.grid.pixels_per_beat();
+            self.waveform.paint_clip(
                 &mut shapes,
                 waveform_rect,
-                data,
-                start_ratio,
-                end_ratio,
-                clip.audio.num_samples.unwrap(),
-                clip.audio.channels >= 2,
+                clip,
+                state.tempo(),
+                (waveform_rect.left() - pos.x) / pixels_per_beat
+                    ..(waveform_rect.right() - pos.x) / pixels_per_beat,
                 Color32::BLACK,
             );
             painter.add(shapes);
         };
+        // Transients
+        if show_waveform {
+            let length = clip.length(state.tempo());
+            let pixels_per_beat = state.grid.pixels_per_beat();
+            for beats in clip.transients(state.tempo()) {
+                let x = pos.x + beats * pixels_per_beat;
+                if beats <= 0. || beats >= length || x < viewport.left() || x > viewport.right() {
+                    continue;
+                }
+                painter.line_segment(
+                    [
+                        pos2(x, pos.y + HEADER_HEIGHT),
+                        pos2(x, pos.y + HEADER_HEIGHT + TRANSIENT_MARKER_HEIGHT),
+                    ],
+                    Stroke::new(1., Color32::from_black_alpha(120)),
+                );
+            }
+        }
+        // Fades
+        if show_waveform {
+            let rect = Rect::from_min_max(pos2(pos.x, pos.y + HEADER_HEIGHT), pos + size);
+            let pixels_per_beat = state.grid.pixels_per_beat();
+            self.paint_fade(
+                &painter,
+                rect,
+                clip.fade_in.length * pixels_per_beat,
+                clip.fade_in.curve,
+                true,
+            );
+            self.paint_fade(
+                &painter,
+                rect,
+                clip.fade_out.length * pixels_per_beat,
+                clip.fade_out.curve,
+                false,
+            );
+        }
+        // Fade handles
+        let mut faded = false;
+        let mut fade_stopped = false;
+        if show_waveform && size.x > MIN_HANDLE_WIDTH {
+            for fade_in in [true, false] {
+                let fade_handle = self.fade_handle(
+                    ui,
+                    state,
+                    Rect::from_min_size(pos, size),
+                    viewport,
+                    &mut clip_copy,
+                    fade_in,
+                );
+                faded = faded || fade_handle.dragged();
+                fade_stopped = fade_stopped || fade_handle.drag_stopped();
+            }
+        }
         // Draw an overlay when audio not ready
         if let Ok(ready) = clip.audio.ready.read()
             && !*ready
@@ -163,6 +242,9 @@ impl UIClip {
         }
 
         response.context_menu(|ui| self.contex_menu(ui, clip, state));
+        if response.double_clicked() {
+            state.open_clip_editor(&clip.id);
+        }
         // Update cursor icon
         if response.dragged() {
             ui.ctx().set_cursor_icon(CursorIcon::Grabbing);
@@ -170,6 +252,12 @@ impl UIClip {
             ui.ctx().set_cursor_icon(CursorIcon::Grab);
         };
 
+        if faded {
+            state.edit_clip(clip_copy.clone());
+        }
+        if fade_stopped {
+            state.update_clip(clip_copy.clone());
+        }
         if resized {
             state.resize_clip(
                 &clip_copy.id,
@@ -195,7 +283,7 @@ impl UIClip {
         ui: &mut Ui,
         grid: &GridService,
         rect: Rect,
-        bpm: f32,
+        tempo: &TempoMap,
         viewport: Rect,
         clip: &mut ClipCore,
     ) -> Response {
@@ -204,10 +292,9 @@ impl UIClip {
         if response.dragged()
             && let Some(mouse_pos) = ui.input(|i| i.pointer.interact_pos())
         {
-            clip.trim_start_at(
-                grid.snap_at_grid(grid.x_to_beats(mouse_pos.x, viewport)),
-                bpm,
-            );
+            let beats = grid.x_to_beats(mouse_pos.x, viewport);
+            let beats = snap_at_transients(grid, tempo, clip, beats).unwrap_or(beats);
+            clip.trim_start_at(beats, tempo);
         }
 
         if response.hovered() {
@@ -224,7 +311,7 @@ impl UIClip {
         ui: &mut Ui,
         grid: &GridService,
         rect: Rect,
-        bpm: f32,
+        tempo: &TempoMap,
         viewport: Rect,
         clip: &mut ClipCore,
     ) -> Response {
@@ -233,10 +320,9 @@ impl UIClip {
         if response.dragged()
             && let Some(mouse_pos) = ui.input(|i| i.pointer.interact_pos())
         {
-            clip.trim_end_at(
-                grid.snap_at_grid(grid.x_to_beats(mouse_pos.x, viewport)),
-                bpm,
-            );
+            let beats = grid.x_to_beats(mouse_pos.x, viewport);
+            let beats = snap_at_transients(grid, tempo, clip, beats).unwrap_or(beats);
+            clip.trim_end_at(beats, tempo);
         }
 
         if response.hovered() {
@@ -248,8 +334,249 @@ impl UIClip {
         response
     }
 
+    fn fade_handle(
+        &mut self,
+        ui: &mut Ui,
+        state: &ToniqueProjectState,
+        clip_rect: Rect,
+        viewport: Rect,
+        clip: &mut ClipCore,
+        fade_in: bool,
+    ) -> Response {
+        let (grid, tempo) = (&state.grid, state.tempo());
+        let pixels_per_beat = grid.pixels_per_beat();
+        let x = if fade_in {
+            (clip_rect.left() + clip.fade_in.length * pixels_per_beat)
+                .max(clip_rect.left() + FADE_HANDLE_SIZE / 2.)
+        } else {
+            (clip_rect.right() - clip.fade_out.length * pixels_per_beat)
+                .min(clip_rect.right() - FADE_HANDLE_SIZE / 2.)
+        };
+        let rect = Rect::from_center_size(
+            pos2(x, clip_rect.top() + HEADER_HEIGHT + FADE_HANDLE_SIZE / 2.),
+            Vec2::splat(FADE_HANDLE_SIZE),
+        );
+        let response = ui.allocate_rect(rect, Sense::drag());
+
+        if response.dragged()
+            && let Some(mouse_pos) = ui.input(|i| i.pointer.interact_pos())
+        {
+            let beats = grid.x_to_beats(mouse_pos.x, viewport);
+            if fade_in {
+                clip.fade_in.length = beats - clip.position;
+            } else {
+                clip.fade_out.length = clip.end(tempo) - beats;
+            }
+            let length = clip.length(tempo);
+            if fade_in {
+                clip.fade_in.length = clip.fade_in.length.clamp(0., length - clip.fade_out.length);
+            } else {
+                clip.fade_out.length = clip.fade_out.length.clamp(0., length - clip.fade_in.length);
+            }
+        }
+
+        let painter = ui.painter_at(viewport);
+        painter.rect_filled(
+            rect,
+            1.0,
+            if response.hovered() || response.dragged() {
+                Color32::WHITE
+            } else {
+                Color32::from_white_alpha(120)
+            },
+        );
+        if response.hovered() {
+            ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::ResizeHorizontal);
+        }
+
+        response
+    }
+
+    /// Paint the fade curve and shade the attenuated area
+    fn paint_fade(
+        &self,
+        painter: &Painter,
+        rect: Rect,
+        width: f32,
+        curve: FadeCurve,
+        fade_in: bool,
+    ) {
+        if width <= 0. {
+            return;
+        }
+        let points: Vec<Pos2> = (0..=FADE_CURVE_SEGMENTS)
+            .map(|i| {
+                let t = i as f32 / FADE_CURVE_SEGMENTS as f32;
+                let x = if fade_in {
+                    rect.left() + t * width
+                } else {
+                    rect.right() - t * width
+                };
+                pos2(x, rect.bottom() - curve.gain(t) * rect.height())
+            })
+            .collect();
+        for segment in points.windows(2) {
+            painter.add(Shape::convex_polygon(
+                vec![
+                    segment[0],
+                    pos2(segment[0].x, rect.top()),
+                    pos2(segment[1].x, rect.top()),
+                    segment[1],
+                ],
+                Color32::from_black_alpha(60),
+                Stroke::NONE,
+            ));
+        }
+        painter.add(Shape::line(points, Stroke::new(1.0, Color32::WHITE)));
+    }
+
     fn contex_menu(&self, ui: &mut Ui, clip: &ClipCore, state: &mut ToniqueProjectState) {
         ui.vertical(|ui| {
+            ui.add(ContextMenuLabel::new(&clip.audio.name));
+            if ui
+                .add(ContextMenuButton::new(PENCIL_SIMPLE, "Edit"))
+                .clicked()
+            {
+                state.open_clip_editor(&clip.id);
+                ui.close();
+            }
+            ui.horizontal(|ui| {
+                ui.add(ContextMenuLabel::new("Gain"));
+                let mut edited = clip.clone();
+                let response = ui.add(
+                    DragValue::new(&mut edited.gain)
+                        .range(-48.0..=24.0)
+                        .speed(0.1)
+                        .fixed_decimals(1)
+                        .suffix(" dB"),
+                );
+                if response.changed() {
+                    state.edit_clip(edited.clone());
+                }
+                if response.drag_stopped() || response.lost_focus() {
+                    state.update_clip(edited);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(ContextMenuLabel::new("Transpose"));
+                let mut semitones = clip.transpose.round();
+                let mut cents = ((clip.transpose - semitones) * 100.).round();
+                let semitones_response = ui.add(
+                    DragValue::new(&mut semitones)
+                        .range(-24.0..=24.0)
+                        .speed(0.1)
+                        .fixed_decimals(0)
+                        .suffix(" st"),
+                );
+                let cents_response = ui.add(
+                    DragValue::new(&mut cents)
+                        .range(-50.0..=50.0)
+                        .speed(0.5)
+                        .fixed_decimals(0)
+                        .suffix(" ct"),
+                );
+                let mut edited = clip.clone();
+                edited.transpose = semitones + cents / 100.;
+                let response = semitones_response | cents_response;
+                if response.changed() {
+                    state.edit_clip(edited.clone());
+                }
+                if response.drag_stopped() || response.lost_focus() {
+                    state.update_clip(edited);
+                }
+            });
+            if ui
+                .add(ContextMenuButton::new(
+                    if clip.preserve_formants {
+                        CHECK
+                    } else {
+                        MICROPHONE
+                    },
+                    "Preserve formants",
+                ))
+                .clicked()
+            {
+                let mut clip = clip.clone();
+                clip.preserve_formants = !clip.preserve_formants;
+                state.update_clip(clip);
+            }
+            if ui
+                .add(ContextMenuButton::new(
+                    if clip.invert { CHECK } else { PLUS_MINUS },
+                    "Invert polarity",
+                ))
+                .clicked()
+            {
+                let mut clip = clip.clone();
+                clip.invert = !clip.invert;
+                state.update_clip(clip);
+            }
+            if ui
+                .add(ContextMenuButton::new(
+                    if clip.reverse { CHECK } else { REWIND },
+                    "Reverse",
+                ))
+                .clicked()
+            {
+                let mut clip = clip.clone();
+                clip.reverse = !clip.reverse;
+                state.update_clip(clip);
+            }
+            ContextMenuButton::new(SPEAKER_HIGH, "Channels").submenu(ui, |ui| {
+                for mode in ChannelMode::ALL {
+                    let icon = if clip.channel_mode == mode { CHECK } else { "" };
+                    if ui.add(ContextMenuButton::new(icon, mode.name())).clicked() {
+                        let mut clip = clip.clone();
+                        clip.channel_mode = mode;
+                        state.update_clip(clip);
+                        ui.close();
+                    }
+                }
+            });
+            for fade_in in [true, false] {
+                let (text, fade) = if fade_in {
+                    ("Fade in", clip.fade_in)
+                } else {
+                    ("Fade out", clip.fade_out)
+                };
+                ContextMenuButton::new(WAVE_SINE, text).submenu(ui, |ui| {
+                    for curve in FadeCurve::ALL {
+                        let icon = if fade.curve == curve { CHECK } else { "" };
+                        if ui.add(ContextMenuButton::new(icon, curve.name())).clicked() {
+                            let mut clip = clip.clone();
+                            if fade_in {
+                                clip.fade_in.curve = curve;
+                            } else {
+                                clip.fade_out.curve = curve;
+                            }
+                            state.update_clip(clip);
+                            ui.close();
+                        }
+                    }
+                });
+            }
+            if let Ok(tempo) = clip.audio.tempo.read()
+                && let Some(tempo) = *tempo
+                && ui
+                    .add(ContextMenuButton::new(
+                        ARROWS_IN_LINE_HORIZONTAL,
+                        &format!("Conform from {:.1} BPM", tempo),
+                    ))
+                    .clicked()
+            {
+                let mut clip = clip.clone();
+                clip.conform_to_tempo(state.tempo());
+                state.update_clip(clip);
+                ui.close();
+            }
+            if ui
+                .add(ContextMenuButton::new(SCISSORS, "Split at transients"))
+                .clicked()
+            {
+                state.split_clip_at_transients(&clip.id);
+                ui.close();
+            }
+            ui.add(ContextMenuSeparator::new());
             if ui
                 .add(ContextMenuButton::new(TRASH, "Delete").text_color(Color32::LIGHT_RED))
                 .clicked()
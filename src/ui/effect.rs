@@ -1,18 +1,26 @@
-use crate::core::metrics::AudioMetrics;
+use crate::{
+    core::metrics::AudioMetrics,
+    ui::{
+        effects::{EffectId, create_effect_from_id},
+        midi_learn::ParamLearn,
+    },
+};
 use egui::{
     Button, Color32, Frame, InnerResponse, Label, Margin, Rect, Response, RichText, Sense, Stroke,
     Ui, Vec2,
 };
-use fundsp::hacker::AudioUnit;
+use fundsp::{hacker::AudioUnit, shared::Shared};
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
-pub trait UIEffectContent: UIEffectContentClone {
+pub trait UIEffectContent: UIEffectContentClone + Send {
     // show ui and update effect
     fn ui(
         &mut self,
         ui: &mut Ui,
         metrics: &mut AudioMetrics,
         enabled: bool,
+        learn: &mut ParamLearn,
         // tx: &mut Producer<GuiToPlayerMsg>,
     );
     // effect window width
@@ -21,13 +29,31 @@ pub trait UIEffectContent: UIEffectContentClone {
     fn get_unit(&self) -> Box<dyn AudioUnit>;
     // effect id
     fn id(&self) -> String;
+    // keep the id of a saved effect
+    fn set_id(&mut self, id: String);
+    // kind of effect, creating it again when loaded
+    fn kind(&self) -> EffectId;
+    // parameters which can be driven by a MIDI controller
+    fn params(&self) -> Vec<EffectParam>;
+}
+
+/// Parameter of an effect, shared with its audio processing unit
+#[derive(Clone)]
+pub struct EffectParam {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    /// Whether the control moves on a logarithmic scale
+    pub log: bool,
+    pub value: Shared,
 }
 
 pub trait UIEffectContentClone {
     fn clone_box(&self) -> Box<dyn UIEffectContent>;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "EffectFile", from = "EffectFile")]
 pub struct UIEffect {
     id: String,
     pub track_id: String,
@@ -36,6 +62,51 @@ pub struct UIEffect {
     content: Box<dyn UIEffectContent>,
 }
 
+/// Saved effect, its content is created again from its kind with the values of its parameters
+#[derive(Serialize, Deserialize)]
+struct EffectFile {
+    id: String,
+    kind: EffectId,
+    track_id: String,
+    enabled: bool,
+    name: String,
+    params: Vec<f32>,
+}
+
+impl From<UIEffect> for EffectFile {
+    fn from(effect: UIEffect) -> Self {
+        Self {
+            kind: effect.content.kind(),
+            params: effect
+                .params()
+                .iter()
+                .map(|param| param.value.value())
+                .collect(),
+            id: effect.id,
+            track_id: effect.track_id,
+            enabled: effect.enabled,
+            name: effect.name,
+        }
+    }
+}
+
+impl From<EffectFile> for UIEffect {
+    fn from(file: EffectFile) -> Self {
+        let mut content = create_effect_from_id(file.kind);
+        content.set_id(file.id.clone());
+        for (param, value) in content.params().iter().zip(file.params) {
+            param.value.set_value(value);
+        }
+        Self {
+            id: file.id,
+            track_id: file.track_id,
+            enabled: file.enabled,
+            name: file.name,
+            content,
+        }
+    }
+}
+
 impl<T> UIEffectContentClone for T
 where
     T: 'static + UIEffectContent + Clone,
@@ -79,6 +150,7 @@ impl UIEffect {
         ui: &mut Ui,
         metrics: &mut AudioMetrics,
         selected: bool,
+        learn: &mut ParamLearn,
         // state: &mut ToniqueProjectState,
     ) -> InnerResponse<Response> {
         ui.set_height(ui.available_height());
@@ -101,7 +173,7 @@ impl UIEffect {
                         ui.vertical(|ui| {
                             ui.set_width(self.content.width());
                             let bar_response = self.top_bar(ui);
-                            self.content.ui(ui, metrics, self.enabled);
+                            self.content.ui(ui, metrics, self.enabled, learn);
                             bar_response
                         })
                         .inner
@@ -176,4 +248,18 @@ impl UIEffect {
     pub fn id(&self) -> String {
         self.id.clone()
     }
+
+    pub fn width(&self) -> f32 {
+        self.content.width()
+    }
+
+    /// Parameters which can be driven by a MIDI controller
+    pub fn params(&self) -> Vec<EffectParam> {
+        self.content.params()
+    }
+
+    /// Audio processing unit of the effect
+    pub fn get_unit(&self) -> Box<dyn AudioUnit> {
+        self.content.get_unit()
+    }
 }
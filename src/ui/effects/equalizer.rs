@@ -1,6 +1,11 @@
 use crate::{
     core::metrics::AudioMetrics,
-    ui::{buttons::paint_circle_button, effect::UIEffectContent},
+    ui::{
+        buttons::paint_circle_button,
+        effect::{EffectParam, UIEffectContent},
+        effects::EffectId,
+        midi_learn::ParamLearn,
+    },
 };
 use egui::{Color32, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
 use fundsp::{
@@ -98,7 +103,16 @@ impl EqualizerEffect {
 }
 
 impl UIEffectContent for EqualizerEffect {
-    fn ui(&mut self, ui: &mut Ui, metrics: &mut AudioMetrics, enabled: bool) {
+    fn ui(
+        &mut self,
+        ui: &mut Ui,
+        metrics: &mut AudioMetrics,
+        enabled: bool,
+        learn: &mut ParamLearn,
+    ) {
+        // Follow the values set by MIDI controllers
+        self.cutoff = self.cutoff_shared.value();
+        self.q = self.q_shared.value();
         let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::all());
         let full_rect = response.rect;
 
@@ -206,6 +220,8 @@ impl UIEffectContent for EqualizerEffect {
             self.q_shared.set_value(self.q);
             self.cutoff_shared.set_value(self.cutoff);
         }
+        learn.control(ui, &freq_res, 0);
+        learn.control(ui, &q_res, 1);
 
         painter.add(shapes);
     }
@@ -222,4 +238,31 @@ impl UIEffectContent for EqualizerEffect {
     fn id(&self) -> String {
         self.id.clone()
     }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+
+    fn kind(&self) -> EffectId {
+        EffectId::Equalizer
+    }
+
+    fn params(&self) -> Vec<EffectParam> {
+        vec![
+            EffectParam {
+                name: "Freq",
+                min: self.min_freq,
+                max: self.max_freq,
+                log: true,
+                value: self.cutoff_shared.clone(),
+            },
+            EffectParam {
+                name: "Q",
+                min: 0.5,
+                max: 10.0,
+                log: false,
+                value: self.q_shared.clone(),
+            },
+        ]
+    }
 }
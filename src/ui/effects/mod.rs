@@ -1,8 +1,9 @@
 use crate::ui::{effect::UIEffectContent, effects::equalizer::EqualizerEffect};
+use serde::{Deserialize, Serialize};
 
 pub mod equalizer;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum EffectId {
     Equalizer,
 }
@@ -0,0 +1,322 @@
+use crate::{
+    core::{
+        instrument::{CHOKE_GROUPS, DRUM_PAD_ROW, DrumRackParams, InstrumentParams},
+        metrics::AudioMetrics,
+        midi::note_name,
+        state::ToniqueProjectState,
+    },
+    ui::{
+        effect::UIEffect,
+        effects::create_effect_from_id,
+        instruments::{Knob, device_frame, knob_grid, percent, section, semitones},
+        midi_learn::ParamLearn,
+        panels::left_panel::DragPayload,
+        widget::{context_menu::ContextMenuButton, square_button::SquareButton},
+    },
+};
+use egui::{
+    Align2, Color32, FontFamily, FontId, Layout, Rect, Response, RichText, Sense, Stroke,
+    StrokeKind, Ui, UiBuilder, pos2, vec2,
+};
+use egui_phosphor::regular::{PLUS, TRASH};
+
+const PAD_WIDTH: f32 = 56.;
+const PADS_WIDTH: f32 = DRUM_PAD_ROW as f32 * PAD_WIDTH;
+const CONTROLS_WIDTH: f32 = 210.;
+/// Width of the effect chain when empty
+const EMPTY_CHAIN_WIDTH: f32 = 120.;
+const SELECTED_COLOR: Color32 = Color32::from_rgb(230, 170, 60);
+
+/// Editor of a drum rack: the pads, the selected pad and its effect chain
+pub struct UIDrumRack {
+    selected: usize,
+    /// Metrics given to the effects of the pads, which have no meter
+    metrics: AudioMetrics,
+}
+
+impl UIDrumRack {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            metrics: AudioMetrics::new(),
+        }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        track_id: &String,
+        params: &DrumRackParams,
+        state: &mut ToniqueProjectState,
+    ) -> Response {
+        self.selected = self.selected.min(params.pads.len().saturating_sub(1));
+        let chain_width = params.pads.get(self.selected).map_or(0., |pad| {
+            pad.effects.iter().map(|effect| effect.width() + 8.).sum()
+        });
+        let width = PADS_WIDTH + CONTROLS_WIDTH + chain_width.max(EMPTY_CHAIN_WIDTH) + 24.;
+        let mut edited = None;
+        let response = device_frame(ui, "Drum Rack", width, |ui, painter, rect| {
+            let pads_rect = Rect::from_min_size(
+                rect.left_top() + vec2(6., 6.),
+                vec2(PADS_WIDTH, rect.height() - 12.),
+            );
+            if let Some(params) = self.pads_ui(ui, pads_rect, params) {
+                edited = Some(params);
+            }
+
+            let Some(pad) = params.pads.get(self.selected) else {
+                return;
+            };
+            let controls_rect = rect.with_min_x(pads_rect.right() + 6.);
+            let knob = knob_grid(controls_rect);
+            let id = format!("drum-rack-{}-{}", track_id, self.selected);
+            section(
+                painter,
+                knob(0., 0.),
+                4.,
+                &format!("Pad {}", note_name(pad.key)),
+            );
+            Knob::linear("Volume", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(0., 0.),
+                &pad.volume,
+                &id,
+            );
+            Knob::linear("Pan", -1., 1., pan).show(ui, painter, knob(1., 0.), &pad.pan, &id);
+            Knob::linear("Tune", -24., 24., semitones).show(
+                ui,
+                painter,
+                knob(2., 0.),
+                &pad.tune,
+                &id,
+            );
+            Knob::linear("Choke", 0., CHOKE_GROUPS as f32, choke).show(
+                ui,
+                painter,
+                knob(3., 0.),
+                &pad.choke,
+                &id,
+            );
+            section(painter, knob(0., 1.), 2., "Rack");
+            Knob::linear("Velocity", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(0., 1.),
+                &params.velocity,
+                &id,
+            );
+            Knob::linear("Master", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(1., 1.),
+                &params.volume,
+                &id,
+            );
+            if pad.sample.is_some() {
+                let rect = Rect::from_center_size(knob(3., 1.) + vec2(0., 4.), vec2(30., 14.));
+                if ui
+                    .put(
+                        rect,
+                        SquareButton::new("Clear").tooltip("Remove the sample of the pad"),
+                    )
+                    .clicked()
+                {
+                    let mut params = params.clone();
+                    params.pads[self.selected].sample = None;
+                    edited = Some(params);
+                }
+            }
+
+            let chain_rect = Rect::from_min_max(
+                pos2(knob(4., 0.).x, rect.top() + 6.),
+                rect.right_bottom() - vec2(6., 6.),
+            );
+            if let Some(params) = self.chain_ui(ui, chain_rect, track_id, params, state) {
+                edited = Some(params);
+            }
+        });
+        if let Some(params) = edited {
+            state.set_instrument(track_id, InstrumentParams::DrumRack(params));
+        }
+        response
+    }
+
+    /// Grid of the pads, which are selected by a click and load the files dropped on them.
+    /// Returns the edited parameters.
+    fn pads_ui(
+        &mut self,
+        ui: &mut Ui,
+        rect: Rect,
+        params: &DrumRackParams,
+    ) -> Option<DrumRackParams> {
+        let mut edited = None;
+        let rows = params.pads.len().div_ceil(DRUM_PAD_ROW) + 1;
+        let height = ((rect.height() - 4.) / rows as f32).clamp(14., 34.);
+        let painter = ui.painter_at(rect);
+        // Pads are shown from the bottom row like on a pad controller
+        for (index, pad) in params.pads.iter().enumerate() {
+            let row = rows - 2 - index / DRUM_PAD_ROW;
+            let pad_rect = Rect::from_min_size(
+                rect.left_top()
+                    + vec2(
+                        (index % DRUM_PAD_ROW) as f32 * PAD_WIDTH,
+                        row as f32 * height,
+                    ),
+                vec2(PAD_WIDTH, height),
+            )
+            .shrink(1.);
+            let response = ui.interact(pad_rect, ui.id().with(("pad", index)), Sense::click());
+            let hovered = response.dnd_hover_payload::<DragPayload>().is_some();
+            let fill = match (&pad.sample, hovered) {
+                (_, true) => Color32::from_gray(90),
+                (Some(_), false) => Color32::from_gray(60),
+                (None, false) => Color32::from_gray(40),
+            };
+            painter.rect_filled(pad_rect, 2., fill);
+            if index == self.selected {
+                painter.rect_stroke(
+                    pad_rect,
+                    2.,
+                    Stroke::new(1., SELECTED_COLOR),
+                    StrokeKind::Inside,
+                );
+            }
+            painter.text(
+                pad_rect.left_top() + vec2(3., 1.),
+                Align2::LEFT_TOP,
+                note_name(pad.key),
+                FontId::new(7., FontFamily::Proportional),
+                Color32::from_gray(140),
+            );
+            if let Some(sample) = &pad.sample {
+                let galley = painter.layout(
+                    sample.name.clone(),
+                    FontId::new(8., FontFamily::Proportional),
+                    Color32::from_gray(220),
+                    pad_rect.width() - 6.,
+                );
+                painter.galley(
+                    pad_rect.left_bottom()
+                        + vec2(3., -galley.size().y.min(pad_rect.height() - 9.) - 1.),
+                    galley,
+                    Color32::from_gray(220),
+                );
+            }
+
+            if response.clicked() {
+                self.selected = index;
+            }
+            if let Some(payload) = response.dnd_release_payload::<DragPayload>()
+                && let DragPayload::File(audio) = payload.as_ref()
+            {
+                let mut params = params.clone();
+                params.pads[index].sample = Some(audio.clone());
+                self.selected = index;
+                edited = Some(params);
+            }
+        }
+
+        let button_rect = Rect::from_min_size(
+            rect.left_top() + vec2(1., (rows - 1) as f32 * height + 1.),
+            vec2(PADS_WIDTH - 2., height.min(16.)),
+        );
+        if params.pads.last().is_some_and(|pad| pad.key < 127)
+            && ui
+                .put(
+                    button_rect,
+                    SquareButton::ghost(format!("{PLUS} Add pads")).size(button_rect.size()),
+                )
+                .clicked()
+        {
+            let mut params = params.clone();
+            params.add_pads();
+            edited = Some(params);
+        }
+        edited
+    }
+
+    /// Effect chain of the selected pad, effects are added by dropping them on it.
+    /// Returns the edited parameters.
+    fn chain_ui(
+        &mut self,
+        ui: &mut Ui,
+        rect: Rect,
+        track_id: &String,
+        params: &DrumRackParams,
+        state: &mut ToniqueProjectState,
+    ) -> Option<DrumRackParams> {
+        let mut edited = None;
+        let mut removed = None;
+        let response = ui
+            .scope_builder(
+                UiBuilder::new()
+                    .max_rect(rect)
+                    .layout(Layout::left_to_right(egui::Align::Min)),
+                |ui| {
+                    let Some(effects) = state.pad_effects_mut(track_id, self.selected) else {
+                        return;
+                    };
+                    if effects.is_empty() {
+                        ui.label(
+                            RichText::new("Drop effects here to process the pad")
+                                .size(8.)
+                                .color(Color32::from_gray(100)),
+                        );
+                    }
+                    for (index, effect) in effects.iter_mut().enumerate() {
+                        effect
+                            .ui(ui, &mut self.metrics, false, &mut ParamLearn::default())
+                            .inner
+                            .context_menu(|ui| {
+                                if ui.add(ContextMenuButton::new(TRASH, "Remove")).clicked() {
+                                    removed = Some(index);
+                                    ui.close();
+                                }
+                            });
+                        ui.add_space(8.);
+                    }
+                },
+            )
+            .response;
+        if response.dnd_hover_payload::<DragPayload>().is_some() {
+            ui.painter().rect_stroke(
+                rect,
+                2.,
+                Stroke::new(1., SELECTED_COLOR),
+                StrokeKind::Inside,
+            );
+        }
+        if let Some(payload) = response.dnd_release_payload::<DragPayload>()
+            && let DragPayload::Effect(effect_id) = *payload
+        {
+            let mut params = params.clone();
+            params.pads[self.selected].effects.push(UIEffect::new(
+                create_effect_from_id(effect_id),
+                track_id.clone(),
+            ));
+            edited = Some(params);
+        }
+        if let Some(index) = removed {
+            let mut params = params.clone();
+            params.pads[self.selected].effects.remove(index);
+            edited = Some(params);
+        }
+        edited
+    }
+}
+
+fn pan(value: f32) -> String {
+    match (value * 100.).round() as i32 {
+        0 => "C".into(),
+        pan if pan < 0 => format!("{}L", -pan),
+        pan => format!("{}R", pan),
+    }
+}
+
+fn choke(value: f32) -> String {
+    match value.round() as usize {
+        0 => "Off".into(),
+        group => group.to_string(),
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    audio::midi::MidiHandle,
+    core::{
+        instrument::{ExternalParams, InstrumentParams},
+        state::ToniqueProjectState,
+    },
+    ui::{
+        instruments::{Knob, device_frame, knob_grid, section},
+        widget::item_button::ItemButton,
+    },
+};
+use egui::{Color32, Rect, Response, RichText, ScrollArea, Ui, UiBuilder, pos2, vec2};
+
+const PORTS_WIDTH: f32 = 180.;
+const WIDTH: f32 = PORTS_WIDTH + 130.;
+/// Delay range in seconds of the output
+const MAX_DELAY: f32 = 0.2;
+
+/// Editor of the MIDI output of a track playing an external instrument
+pub struct UIExternal {
+    midi: MidiHandle,
+}
+
+impl UIExternal {
+    pub fn new(midi: MidiHandle) -> Self {
+        Self { midi }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        track_id: &String,
+        params: &ExternalParams,
+        state: &mut ToniqueProjectState,
+    ) -> Response {
+        let id = format!("external-{}", track_id);
+        let mut port = None;
+        let response = device_frame(ui, "External MIDI", WIDTH, |ui, painter, rect| {
+            let knob = knob_grid(rect);
+            section(painter, knob(0., 0.), 2., "Output");
+            Knob::linear("Channel", 0., 15., channel).show(
+                ui,
+                painter,
+                knob(0., 0.),
+                &params.channel,
+                &id,
+            );
+            Knob::linear("Program", -1., 127., program).show(
+                ui,
+                painter,
+                knob(1., 0.),
+                &params.program,
+                &id,
+            );
+            section(painter, knob(0., 1.), 1., "Latency");
+            Knob::linear("Delay", -MAX_DELAY, MAX_DELAY, milliseconds).show(
+                ui,
+                painter,
+                knob(0., 1.),
+                &params.delay,
+                &id,
+            );
+
+            let ports_rect = Rect::from_min_size(
+                pos2(knob(2.2, 0.).x, rect.top() + 6.),
+                vec2(PORTS_WIDTH, rect.height() - 12.),
+            );
+            port = self.ports_ui(ui, ports_rect, params);
+        });
+        // Clicking the selected port deselects it, the track then sends nothing
+        if let Some(port) = port {
+            let mut params = params.clone();
+            params.port = (params.port.as_ref() != Some(&port)).then_some(port);
+            state.set_instrument(track_id, InstrumentParams::External(params));
+        }
+        response
+    }
+
+    /// Output ports found by the last scan, returns the one clicked
+    fn ports_ui(&self, ui: &mut Ui, rect: Rect, params: &ExternalParams) -> Option<String> {
+        let mut clicked = None;
+        ui.scope_builder(UiBuilder::new().max_rect(rect), |ui| {
+            ui.label(
+                RichText::new("MIDI output")
+                    .size(8.)
+                    .color(Color32::from_gray(130)),
+            );
+            ui.add_space(2.);
+            let mut outputs: Vec<String> = self
+                .midi
+                .outputs()
+                .into_iter()
+                .map(|output| output.name)
+                .collect();
+            // The selected port stays listed while unplugged
+            if let Some(port) = &params.port
+                && !outputs.contains(port)
+            {
+                outputs.push(port.clone());
+            }
+            if outputs.is_empty() {
+                ui.label(RichText::new("No MIDI output found").color(Color32::from_gray(150)));
+            }
+            ScrollArea::vertical().show(ui, |ui| {
+                for name in outputs {
+                    let selected = params.port.as_ref() == Some(&name);
+                    if ui.add(ItemButton::new(&name).selected(selected)).clicked() {
+                        clicked = Some(name);
+                    }
+                }
+            });
+        });
+        clicked
+    }
+}
+
+fn channel(value: f32) -> String {
+    format!("Ch {:.0}", value.round() + 1.)
+}
+
+fn program(value: f32) -> String {
+    if value.round() < 0. {
+        "Off".into()
+    } else {
+        format!("{:.0}", value.round() + 1.)
+    }
+}
+
+fn milliseconds(value: f32) -> String {
+    format!("{:+.0}ms", value * 1000.)
+}
@@ -0,0 +1,229 @@
+use crate::{
+    audio::midi::MidiHandle,
+    core::{
+        instrument::{EnvelopeParams, InstrumentParams},
+        state::ToniqueProjectState,
+    },
+    ui::{
+        buttons::paint_circle_button,
+        instruments::{
+            drum_rack::UIDrumRack, external::UIExternal, sampler::UISampler, synth::UISynth,
+        },
+        widget::square_button::SquareButton,
+    },
+};
+use egui::{
+    Align2, Color32, FontFamily, FontId, Frame, Margin, Painter, Pos2, Rect, Response, RichText,
+    Sense, Stroke, Ui, vec2,
+};
+use fundsp::hacker::Shared;
+
+mod drum_rack;
+mod external;
+mod sampler;
+mod synth;
+
+/// Horizontal space between two knobs
+const KNOB_SPACING: f32 = 34.;
+const ROW_HEIGHT: f32 = 62.;
+const SECTION_COLOR: Color32 = Color32::from_gray(60);
+
+/// Editor of the instrument of a MIDI track, shown before the effects of the track
+pub struct UIInstrument {
+    sampler: UISampler,
+    drum_rack: UIDrumRack,
+    external: UIExternal,
+}
+
+impl UIInstrument {
+    pub fn new(midi: MidiHandle) -> Self {
+        Self {
+            sampler: UISampler::new(),
+            drum_rack: UIDrumRack::new(),
+            external: UIExternal::new(midi),
+        }
+    }
+
+    /// Show the editor, returns its response
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        track_id: &String,
+        params: &InstrumentParams,
+        state: &mut ToniqueProjectState,
+    ) -> Response {
+        match params {
+            InstrumentParams::Synth(params) => UISynth::ui(ui, track_id, params),
+            InstrumentParams::Sampler(params) => self.sampler.ui(ui, track_id, params, state),
+            InstrumentParams::DrumRack(params) => self.drum_rack.ui(ui, track_id, params, state),
+            InstrumentParams::External(params) => self.external.ui(ui, track_id, params, state),
+        }
+    }
+}
+
+/// Frame of an instrument with its title bar. `content` paints in the rect below the bar.
+fn device_frame(
+    ui: &mut Ui,
+    name: &str,
+    width: f32,
+    content: impl FnOnce(&mut Ui, &Painter, Rect),
+) -> Response {
+    ui.set_height(ui.available_height());
+    Frame::new()
+        .fill(ui.visuals().faint_bg_color)
+        .stroke(Stroke::new(
+            1.0 / ui.pixels_per_point(),
+            Color32::from_gray(100),
+        ))
+        .corner_radius(2.0)
+        .show(ui, |ui| {
+            ui.set_height(ui.available_height());
+            ui.vertical(|ui| {
+                ui.set_width(width);
+                Frame::new()
+                    .fill(Color32::from_gray(100))
+                    .inner_margin(Margin::symmetric(4, 0))
+                    .show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        ui.label(RichText::new(name).size(8.).color(Color32::from_gray(20)));
+                    });
+                let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::hover());
+                content(ui, &painter, response.rect);
+            });
+        })
+        .response
+}
+
+/// Position of the knobs on a grid starting at the top left of `rect`
+fn knob_grid(rect: Rect) -> impl Fn(f32, f32) -> Pos2 {
+    move |index, row| rect.left_top() + vec2(20. + index * KNOB_SPACING, 34. + row * ROW_HEIGHT)
+}
+
+/// Title of a group of `count` knobs starting at `first`
+fn section(painter: &Painter, first: Pos2, count: f32, name: &str) {
+    let rect = Rect::from_min_size(
+        first - vec2(17., 28.),
+        vec2(count * KNOB_SPACING, ROW_HEIGHT - 6.),
+    );
+    painter.rect_stroke(
+        rect,
+        2.,
+        Stroke::new(1., SECTION_COLOR),
+        egui::StrokeKind::Inside,
+    );
+    painter.text(
+        rect.left_top() + vec2(3., 1.),
+        Align2::LEFT_TOP,
+        name,
+        FontId::new(8., FontFamily::Proportional),
+        Color32::from_gray(130),
+    );
+}
+
+/// Button in a knob slot cycling through the `names` of the values of `param`
+fn choice_button(ui: &mut Ui, center: Pos2, param: &Shared, names: &[&str], id: &str) {
+    let index = (param.value().round().max(0.) as usize).min(names.len() - 1);
+    let rect = Rect::from_center_size(center + vec2(0., 4.), vec2(30., 14.));
+    let response = ui
+        .push_id(id, |ui| {
+            ui.put(
+                rect,
+                SquareButton::new(names[index])
+                    .size(rect.size())
+                    .font(FontId::new(8., FontFamily::Proportional)),
+            )
+        })
+        .inner;
+    if response.clicked() {
+        param.set_value(((index + 1) % names.len()) as f32);
+    }
+}
+
+/// Attack, decay, sustain and release knobs
+fn envelope(ui: &mut Ui, painter: &Painter, first: Pos2, params: &EnvelopeParams, id: &str) {
+    let at = |index: f32| first + vec2(index * KNOB_SPACING, 0.);
+    Knob::log("A", 0.001, 5., seconds).show(ui, painter, at(0.), &params.attack, id);
+    Knob::log("D", 0.001, 5., seconds).show(ui, painter, at(1.), &params.decay, id);
+    Knob::linear("S", 0., 1., percent).show(ui, painter, at(2.), &params.sustain, id);
+    Knob::log("R", 0.001, 10., seconds).show(ui, painter, at(3.), &params.release, id);
+}
+
+/// Knob editing a shared parameter
+struct Knob {
+    name: &'static str,
+    min: f32,
+    max: f32,
+    log: bool,
+    format: fn(f32) -> String,
+}
+
+impl Knob {
+    fn linear(name: &'static str, min: f32, max: f32, format: fn(f32) -> String) -> Self {
+        Self {
+            name,
+            min,
+            max,
+            log: false,
+            format,
+        }
+    }
+
+    fn log(name: &'static str, min: f32, max: f32, format: fn(f32) -> String) -> Self {
+        Self {
+            log: true,
+            ..Self::linear(name, min, max, format)
+        }
+    }
+
+    fn show(self, ui: &mut Ui, painter: &Painter, center: Pos2, param: &Shared, id: &str) {
+        let mut value = param.value().clamp(self.min, self.max);
+        let label = (self.format)(value);
+        let response = paint_circle_button(
+            ui,
+            painter,
+            center + vec2(0., 4.),
+            &mut value,
+            id.into(),
+            self.name.into(),
+            Some(label),
+            self.min,
+            self.max,
+            self.log,
+        );
+        if response.dragged() {
+            param.set_value(value);
+        }
+    }
+}
+
+fn percent(value: f32) -> String {
+    format!("{:.0}%", value * 100.)
+}
+
+fn semitones(value: f32) -> String {
+    format!("{:+.1}st", value)
+}
+
+fn octaves(value: f32) -> String {
+    format!("{:.1}oct", value)
+}
+
+fn hertz(value: f32) -> String {
+    if value < 1000. {
+        format!("{:.0}Hz", value)
+    } else {
+        format!("{:.1}kHz", value / 1000.)
+    }
+}
+
+fn seconds(value: f32) -> String {
+    if value < 1. {
+        format!("{:.0}ms", value * 1000.)
+    } else {
+        format!("{:.1}s", value)
+    }
+}
+
+fn voices(value: f32) -> String {
+    format!("{:.0}", value.round())
+}
@@ -0,0 +1,281 @@
+use crate::{
+    core::{
+        instrument::{InstrumentParams, SampleMode, SamplerParams},
+        midi::note_name,
+        state::ToniqueProjectState,
+    },
+    ui::{
+        instruments::{
+            Knob, choice_button, device_frame, envelope, knob_grid, percent, section, semitones,
+            voices,
+        },
+        panels::left_panel::DragPayload,
+        waveform::UIWaveform,
+        widget::square_button::SquareButton,
+    },
+};
+use egui::{
+    Align2, Color32, CursorIcon, DragValue, FontFamily, FontId, Id, Label, Painter, Rect, Response,
+    RichText, Sense, Shape, Stroke, Ui, UiBuilder, pos2, vec2,
+};
+use fundsp::hacker::Shared;
+
+const WIDTH: f32 = 610.;
+const WAVEFORM_WIDTH: f32 = 180.;
+const LAYERS_WIDTH: f32 = 130.;
+const LOOP_COLOR: Color32 = Color32::from_rgb(230, 170, 60);
+
+/// Editor of a sampler
+pub struct UISampler {
+    /// Layer and maximum velocity being edited
+    editing: Option<(usize, u8)>,
+}
+
+impl UISampler {
+    pub fn new() -> Self {
+        Self { editing: None }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        track_id: &String,
+        params: &SamplerParams,
+        state: &mut ToniqueProjectState,
+    ) -> Response {
+        let id = format!("sampler-{}", track_id);
+        let modes = SampleMode::ALL.map(|mode| mode.name());
+        let mut edited = None;
+        let response = device_frame(ui, "Sampler", WIDTH, |ui, painter, rect| {
+            let waveform_rect = Rect::from_min_size(
+                rect.left_top() + vec2(6., 6.),
+                vec2(WAVEFORM_WIDTH, (rect.height() - 12.).clamp(20., 110.)),
+            );
+            Self::waveform_ui(ui, painter, waveform_rect, params, &id);
+
+            let knob = knob_grid(rect.with_min_x(waveform_rect.right() + 6.));
+            // Sample
+            section(painter, knob(0., 0.), 3., "Sample");
+            Knob::linear("Root", 0., 127., |value| note_name(value.round() as u8)).show(
+                ui,
+                painter,
+                knob(0., 0.),
+                &params.root,
+                &id,
+            );
+            Knob::linear("Tune", -24., 24., semitones).show(
+                ui,
+                painter,
+                knob(1., 0.),
+                &params.tune,
+                &id,
+            );
+            choice_button(
+                ui,
+                knob(2., 0.),
+                &params.mode,
+                &modes,
+                &format!("{id}-mode"),
+            );
+            // Loop
+            section(painter, knob(3.2, 0.), 2., "Loop");
+            Knob::linear("Start", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(3.2, 0.),
+                &params.loop_start,
+                &id,
+            );
+            Knob::linear("End", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(4.2, 0.),
+                &params.loop_end,
+                &id,
+            );
+            // Envelope
+            section(painter, knob(0., 1.), 4., "Amp");
+            envelope(
+                ui,
+                painter,
+                knob(0., 1.),
+                &params.amp_envelope,
+                &format!("{id}-amp"),
+            );
+            // Output
+            section(painter, knob(4.2, 1.), 4., "Output");
+            Knob::linear("Velocity", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(4.2, 1.),
+                &params.velocity,
+                &id,
+            );
+            Knob::linear("Bend", 0., 24., semitones).show(
+                ui,
+                painter,
+                knob(5.2, 1.),
+                &params.bend_range,
+                &id,
+            );
+            Knob::linear("Voices", 1., 32., voices).show(
+                ui,
+                painter,
+                knob(6.2, 1.),
+                &params.polyphony,
+                &id,
+            );
+            Knob::linear("Volume", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(7.2, 1.),
+                &params.volume,
+                &id,
+            );
+
+            let layers_rect = Rect::from_min_size(
+                pos2(knob(8.2, 0.).x, rect.top() + 6.),
+                vec2(LAYERS_WIDTH, rect.height() - 12.),
+            );
+            edited = self.layers_ui(ui, layers_rect, params);
+        });
+
+        // Dropped files are added as velocity layers
+        if let Some(payload) = response.dnd_release_payload::<DragPayload>()
+            && let DragPayload::File(audio) = payload.as_ref()
+        {
+            let mut params = params.clone();
+            params.add_layer(audio.clone());
+            edited = Some(params);
+        }
+        if let Some(params) = edited {
+            state.set_instrument(track_id, InstrumentParams::Sampler(params));
+        }
+        response
+    }
+
+    /// Waveform of the softest layer with the loop points, which can be dragged
+    fn waveform_ui(ui: &mut Ui, painter: &Painter, rect: Rect, params: &SamplerParams, id: &str) {
+        painter.rect_filled(rect, 2., Color32::from_gray(30));
+        let Some(layer) = params.layers.first() else {
+            return;
+        };
+        if let Ok(data) = layer.audio.data.try_read() {
+            let mut shapes = vec![];
+            UIWaveform::new().paint(
+                &mut shapes,
+                rect,
+                &data,
+                0.,
+                1.,
+                data.0.len() as u64,
+                !layer.audio.layout.is_mono(),
+                Color32::from_gray(170),
+            );
+            painter.extend(shapes);
+        }
+        painter.text(
+            rect.left_top() + vec2(3., 1.),
+            Align2::LEFT_TOP,
+            &layer.audio.name,
+            FontId::new(8., FontFamily::Proportional),
+            Color32::from_gray(200),
+        );
+
+        if SampleMode::from_value(params.mode.value()) != SampleMode::Loop {
+            return;
+        }
+        let x = |ratio: f32| rect.left() + ratio.clamp(0., 1.) * rect.width();
+        let start = x(params.loop_start.value());
+        let end = x(params.loop_end.value());
+        painter.add(Shape::rect_filled(
+            Rect::from_x_y_ranges(start..=end.max(start), rect.y_range()),
+            0.,
+            LOOP_COLOR.gamma_multiply(0.15),
+        ));
+        for (x, param, name) in [
+            (start, &params.loop_start, "start"),
+            (end, &params.loop_end, "end"),
+        ] {
+            painter.vline(x, rect.y_range(), Stroke::new(1., LOOP_COLOR));
+            Self::loop_handle(ui, rect, x, param, &format!("{id}-loop-{name}"));
+        }
+    }
+
+    /// Drag the loop point stored in `param`, drawn at `x`
+    fn loop_handle(ui: &mut Ui, rect: Rect, x: f32, param: &Shared, id: &str) {
+        let response = ui
+            .interact(
+                Rect::from_x_y_ranges(x - 3.0..=x + 3., rect.y_range()),
+                Id::new(id),
+                Sense::drag(),
+            )
+            .on_hover_and_drag_cursor(CursorIcon::ResizeHorizontal);
+        if response.dragged()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            param.set_value(((pos.x - rect.left()) / rect.width()).clamp(0., 1.));
+        }
+    }
+
+    /// List of the velocity layers. Returns the edited parameters.
+    fn layers_ui(
+        &mut self,
+        ui: &mut Ui,
+        rect: Rect,
+        params: &SamplerParams,
+    ) -> Option<SamplerParams> {
+        let mut edited = None;
+        ui.scope_builder(UiBuilder::new().max_rect(rect), |ui| {
+            ui.label(
+                RichText::new("Velocity layers")
+                    .size(8.)
+                    .color(Color32::from_gray(130)),
+            );
+            ui.add_space(2.);
+            for (index, layer) in params.layers.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let mut value = self
+                        .editing
+                        .filter(|(i, _)| *i == index)
+                        .map_or(layer.max_velocity, |(_, value)| value);
+                    let response = ui
+                        .add(DragValue::new(&mut value).range(1..=127).prefix("≤ "))
+                        .on_hover_text("Highest velocity playing the layer");
+                    if response.changed() {
+                        self.editing = Some((index, value));
+                    }
+                    if (response.drag_stopped() || (response.changed() && !response.dragged()))
+                        && let Some((index, value)) = self.editing.take()
+                    {
+                        let mut params = params.clone();
+                        params.layers[index].max_velocity = value;
+                        params.layers.sort_by_key(|layer| layer.max_velocity);
+                        edited = Some(params);
+                    }
+                    if params.layers.len() > 1
+                        && ui
+                            .add(
+                                SquareButton::ghost(egui_phosphor::regular::X)
+                                    .tooltip("Remove the layer"),
+                            )
+                            .clicked()
+                    {
+                        let mut params = params.clone();
+                        params.layers.remove(index);
+                        edited = Some(params);
+                    }
+                    ui.add_space(4.);
+                    ui.add(Label::new(RichText::new(&layer.audio.name).size(8.)).truncate());
+                });
+                ui.add_space(2.);
+            }
+            ui.label(
+                RichText::new("Drop samples to add layers")
+                    .size(8.)
+                    .color(Color32::from_gray(100)),
+            );
+        });
+        edited
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    core::instrument::{SynthParams, Waveform},
+    ui::instruments::{
+        Knob, choice_button, device_frame, envelope, hertz, knob_grid, octaves, percent, section,
+        semitones, voices,
+    },
+};
+use egui::{Response, Ui};
+
+const WIDTH: f32 = 430.;
+
+/// Editor of a synth
+pub struct UISynth;
+
+impl UISynth {
+    pub fn ui(ui: &mut Ui, track_id: &str, params: &SynthParams) -> Response {
+        let id = format!("synth-{}", track_id);
+        let waves = Waveform::ALL.map(|wave| wave.name());
+        device_frame(ui, "Synth", WIDTH, |ui, painter, rect| {
+            let knob = knob_grid(rect);
+
+            // Oscillators
+            section(painter, knob(0., 0.), 2., "Osc 1");
+            choice_button(
+                ui,
+                knob(0., 0.),
+                &params.osc1_wave,
+                &waves,
+                &format!("{id}-osc1"),
+            );
+            Knob::linear("Level", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(1., 0.),
+                &params.osc1_level,
+                &id,
+            );
+            section(painter, knob(2.2, 0.), 3., "Osc 2");
+            choice_button(
+                ui,
+                knob(2.2, 0.),
+                &params.osc2_wave,
+                &waves,
+                &format!("{id}-osc2"),
+            );
+            Knob::linear("Level 2", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(3.2, 0.),
+                &params.osc2_level,
+                &id,
+            );
+            Knob::linear("Detune", -24., 24., semitones).show(
+                ui,
+                painter,
+                knob(4.2, 0.),
+                &params.osc2_detune,
+                &id,
+            );
+
+            // Filter
+            section(painter, knob(5.4, 0.), 3., "Filter");
+            Knob::log("Cutoff", 20., 18000., hertz).show(
+                ui,
+                painter,
+                knob(5.4, 0.),
+                &params.cutoff,
+                &id,
+            );
+            Knob::linear("Reso", 0., 0.95, percent).show(
+                ui,
+                painter,
+                knob(6.4, 0.),
+                &params.resonance,
+                &id,
+            );
+            Knob::linear("Env", 0., 6., octaves).show(
+                ui,
+                painter,
+                knob(7.4, 0.),
+                &params.filter_amount,
+                &id,
+            );
+
+            // Play
+            section(painter, knob(8.6, 0.), 3., "Play");
+            Knob::linear("Velocity", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(8.6, 0.),
+                &params.velocity,
+                &id,
+            );
+            Knob::linear("Bend", 0., 24., semitones).show(
+                ui,
+                painter,
+                knob(9.6, 0.),
+                &params.bend_range,
+                &id,
+            );
+            Knob::linear("Vibrato", 0., 2., semitones).show(
+                ui,
+                painter,
+                knob(10.6, 0.),
+                &params.vibrato,
+                &id,
+            );
+
+            // Envelopes
+            section(painter, knob(0., 1.), 4., "Amp");
+            envelope(
+                ui,
+                painter,
+                knob(0., 1.),
+                &params.amp_envelope,
+                &format!("{id}-amp"),
+            );
+            section(painter, knob(4.2, 1.), 4., "Filter env");
+            envelope(
+                ui,
+                painter,
+                knob(4.2, 1.),
+                &params.filter_envelope,
+                &format!("{id}-filter"),
+            );
+
+            // Output
+            section(painter, knob(8.6, 1.), 2., "Output");
+            Knob::linear("Voices", 1., 32., voices).show(
+                ui,
+                painter,
+                knob(8.6, 1.),
+                &params.polyphony,
+                &id,
+            );
+            Knob::linear("Volume", 0., 1., percent).show(
+                ui,
+                painter,
+                knob(9.6, 1.),
+                &params.volume,
+                &id,
+            );
+        })
+    }
+}
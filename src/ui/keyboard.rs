@@ -0,0 +1,72 @@
+use crate::core::state::ToniqueProjectState;
+use egui::{Context, Event, Key};
+
+/// Keys of the computer keyboard playing notes, with their semitone above the lowest key
+const NOTE_KEYS: [(Key, u8); 17] = [
+    (Key::A, 0),
+    (Key::W, 1),
+    (Key::S, 2),
+    (Key::E, 3),
+    (Key::D, 4),
+    (Key::F, 5),
+    (Key::T, 6),
+    (Key::G, 7),
+    (Key::Y, 8),
+    (Key::H, 9),
+    (Key::U, 10),
+    (Key::J, 11),
+    (Key::K, 12),
+    (Key::O, 13),
+    (Key::L, 14),
+    (Key::P, 15),
+    (Key::Semicolon, 16),
+];
+
+/// Play the armed MIDI tracks from the computer keyboard when it is active.
+/// Keys held with a modifier are left to the shortcuts, and the notes stop when the window loses focus.
+pub fn keyboard_input(ctx: &Context, state: &mut ToniqueProjectState) {
+    if !state.keyboard().active {
+        return;
+    }
+    let (events, focused) = ctx.input(|i| (i.events.clone(), i.focused));
+    if !focused {
+        state.release_keyboard();
+        return;
+    }
+    let editing = ctx.memory(|m| m.focused().is_some());
+    for event in events {
+        let Event::Key {
+            key,
+            physical_key,
+            pressed,
+            repeat,
+            modifiers,
+        } = event
+        else {
+            continue;
+        };
+        // Physical keys keep the layout of a QWERTY keyboard on other layouts
+        let key = physical_key.unwrap_or(key);
+        let note = NOTE_KEYS
+            .iter()
+            .find(|(note_key, _)| *note_key == key)
+            .map(|(_, semitone)| *semitone);
+        if !pressed {
+            if let Some(semitone) = note {
+                state.release_keyboard_key(semitone);
+            }
+            continue;
+        }
+        if repeat || editing || modifiers.any() {
+            continue;
+        }
+        match (key, note) {
+            (_, Some(semitone)) => state.press_keyboard_key(semitone),
+            (Key::Z, _) => state.shift_keyboard_octave(-1),
+            (Key::X, _) => state.shift_keyboard_octave(1),
+            (Key::C, _) => state.shift_keyboard_velocity(false),
+            (Key::V, _) => state.shift_keyboard_velocity(true),
+            _ => {}
+        }
+    }
+}
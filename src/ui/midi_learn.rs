@@ -0,0 +1,57 @@
+use crate::ui::theme::PRIMARY_COLOR;
+use egui::{Color32, Rect, Response, Stroke, StrokeKind, Ui};
+
+/// Outline of the controls bound to a controller in learn mode
+const BOUND_COLOR: Color32 = Color32::from_rgb(240, 180, 60);
+
+/// MIDI learn state of the parameters of an effect
+#[derive(Debug, Default)]
+pub struct ParamLearn {
+    /// Whether clicking a parameter waits for a controller
+    pub active: bool,
+    /// Parameter waiting for a controller
+    pub learning: Option<usize>,
+    /// Parameters bound to a controller
+    pub bound: Vec<usize>,
+    /// Parameter clicked in learn mode
+    pub clicked: Option<usize>,
+}
+
+impl ParamLearn {
+    /// Outline the control of `param` in learn mode and remember when it is clicked
+    pub fn control(&mut self, ui: &Ui, response: &Response, param: usize) {
+        if !self.active {
+            return;
+        }
+        paint_learn(
+            ui,
+            response.rect,
+            self.learning == Some(param),
+            self.bound.contains(&param),
+        );
+        if response.clicked() {
+            self.clicked = Some(param);
+        }
+    }
+}
+
+/// Outline a control in learn mode, filled while it waits for a controller
+pub fn paint_learn(ui: &Ui, rect: Rect, learning: bool, bound: bool) {
+    let color = if bound && !learning {
+        BOUND_COLOR
+    } else {
+        PRIMARY_COLOR
+    };
+    let fill = if learning {
+        color.gamma_multiply(0.4)
+    } else {
+        Color32::TRANSPARENT
+    };
+    ui.painter().rect(
+        rect.expand(2.),
+        2.,
+        fill,
+        Stroke::new(1., color),
+        StrokeKind::Outside,
+    );
+}
@@ -0,0 +1,136 @@
+use crate::{
+    core::midi::transform::{MidiTransform, QUANTIZE_GRIDS, TransformSettings},
+    ui::widget::context_menu::{ContextMenuButton, ContextMenuLabel, ContextMenuSeparator},
+};
+use egui::{DragValue, Ui};
+use egui_phosphor::regular::{
+    ARROW_LINE_RIGHT, ARROWS_DOWN_UP, ARROWS_LEFT_RIGHT, CHART_BAR, CHECK, MAGNET, SHUFFLE,
+};
+
+/// Menu of the MIDI transforms with their options. Returns the transform to apply.
+pub fn transform_menu(ui: &mut Ui, settings: &mut TransformSettings) -> Option<MidiTransform> {
+    let mut transform = None;
+    ContextMenuButton::new(MAGNET, "Quantize").submenu(ui, |ui| {
+        for (name, grid) in QUANTIZE_GRIDS {
+            let icon = if settings.grid == grid { CHECK } else { "" };
+            if ui.add(ContextMenuButton::new(icon, name)).clicked() {
+                settings.grid = grid;
+            }
+        }
+        ui.add(ContextMenuSeparator::new());
+        percent_row(ui, "Strength", &mut settings.strength);
+        percent_row(ui, "Swing", &mut settings.swing);
+        for (name, value) in [
+            ("Starts", &mut settings.starts),
+            ("Ends", &mut settings.ends),
+        ] {
+            let icon = if *value { CHECK } else { "" };
+            if ui.add(ContextMenuButton::new(icon, name)).clicked() {
+                *value = !*value;
+            }
+        }
+        ui.add(ContextMenuSeparator::new());
+        if ui.add(ContextMenuButton::new(MAGNET, "Quantize")).clicked() {
+            transform = Some(settings.quantize());
+        }
+    });
+    ContextMenuButton::new(SHUFFLE, "Humanize").submenu(ui, |ui| {
+        ui.horizontal(|ui| {
+            ui.add(ContextMenuLabel::new("Timing"));
+            ui.add(
+                DragValue::new(&mut settings.timing)
+                    .range(0.0..=0.25)
+                    .speed(0.001)
+                    .fixed_decimals(3)
+                    .suffix(" beat"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(ContextMenuLabel::new("Velocity"));
+            ui.add(
+                DragValue::new(&mut settings.velocity)
+                    .range(0.0..=64.0)
+                    .speed(0.2)
+                    .fixed_decimals(0),
+            );
+        });
+        if ui
+            .add(ContextMenuButton::new(SHUFFLE, "Humanize"))
+            .clicked()
+        {
+            transform = Some(settings.humanize());
+        }
+    });
+    ContextMenuButton::new(ARROWS_DOWN_UP, "Transpose").submenu(ui, |ui| {
+        ui.horizontal(|ui| {
+            ui.add(ContextMenuLabel::new("Semitones"));
+            ui.add(
+                DragValue::new(&mut settings.semitones)
+                    .range(-48..=48)
+                    .speed(0.1)
+                    .suffix(" st"),
+            );
+        });
+        if ui
+            .add(ContextMenuButton::new(ARROWS_DOWN_UP, "Transpose"))
+            .clicked()
+        {
+            transform = Some(MidiTransform::Transpose(settings.semitones));
+        }
+    });
+    ContextMenuButton::new(CHART_BAR, "Velocity").submenu(ui, |ui| {
+        percent_row(ui, "Compress", &mut settings.compress);
+        ui.horizontal(|ui| {
+            ui.add(ContextMenuLabel::new("Scale"));
+            let mut percent = settings.scale * 100.;
+            if ui
+                .add(
+                    DragValue::new(&mut percent)
+                        .range(0.0..=200.0)
+                        .speed(0.5)
+                        .fixed_decimals(0)
+                        .suffix(" %"),
+                )
+                .changed()
+            {
+                settings.scale = percent / 100.;
+            }
+        });
+        if ui.add(ContextMenuButton::new(CHART_BAR, "Apply")).clicked() {
+            transform = Some(settings.velocity());
+        }
+    });
+    if ui
+        .add(ContextMenuButton::new(ARROW_LINE_RIGHT, "Legato"))
+        .clicked()
+    {
+        transform = Some(MidiTransform::Legato);
+    }
+    if ui
+        .add(ContextMenuButton::new(ARROWS_LEFT_RIGHT, "Reverse"))
+        .clicked()
+    {
+        transform = Some(MidiTransform::Reverse);
+    }
+    transform
+}
+
+/// Edit a ratio between 0 and 1 shown as a percentage
+fn percent_row(ui: &mut Ui, label: &str, value: &mut f32) {
+    ui.horizontal(|ui| {
+        ui.add(ContextMenuLabel::new(label));
+        let mut percent = *value * 100.;
+        if ui
+            .add(
+                DragValue::new(&mut percent)
+                    .range(0.0..=100.0)
+                    .speed(0.5)
+                    .fixed_decimals(0)
+                    .suffix(" %"),
+            )
+            .changed()
+        {
+            *value = percent / 100.;
+        }
+    });
+}
@@ -1,4 +1,5 @@
 use crate::{
+    audio::midi::MidiHandle,
     core::message::{AudioToGuiRx, GuiToAudioTx},
     ui::{app::ToniqueApp, font::get_fonts, theme::get_app_style, window::get_native_options},
 };
@@ -9,6 +10,10 @@ mod clip;
 pub mod effect;
 pub mod effects;
 pub mod font;
+mod instruments;
+mod keyboard;
+pub mod midi_learn;
+mod midi_transform;
 pub mod panels;
 mod theme;
 mod track;
@@ -18,7 +23,11 @@ mod waveform;
 mod widget;
 mod window;
 
-pub fn spawn_ui_thread(tx: GuiToAudioTx, rx: AudioToGuiRx) -> Result<(), eframe::Error> {
+pub fn spawn_ui_thread(
+    tx: GuiToAudioTx,
+    rx: AudioToGuiRx,
+    midi: MidiHandle,
+) -> Result<(), eframe::Error> {
     eframe::run_native(
         "Tonique",
         get_native_options(),
@@ -26,7 +35,7 @@ pub fn spawn_ui_thread(tx: GuiToAudioTx, rx: AudioToGuiRx) -> Result<(), eframe:
             cc.egui_ctx.set_fonts(get_fonts());
             cc.egui_ctx.set_style(get_app_style());
             cc.egui_ctx.set_theme(Theme::Dark);
-            Ok(Box::new(ToniqueApp::new(tx, rx, cc)))
+            Ok(Box::new(ToniqueApp::new(tx, rx, midi, cc)))
         }),
     )
 }
@@ -1,6 +1,11 @@
 use crate::{
-    core::{state::ToniqueProjectState, track::TrackReferenceCore},
-    ui::panels::left_panel::DragPayload,
+    audio::midi::MidiHandle,
+    core::{midi_learn::MidiTarget, state::ToniqueProjectState, track::TrackReferenceCore},
+    ui::{
+        instruments::UIInstrument,
+        panels::left_panel::DragPayload,
+        view::{clip_editor::UIClipEditor, piano_roll::UIPianoRoll},
+    },
     utils::parse_name,
 };
 use egui::{
@@ -14,14 +19,20 @@ pub struct UIBottomPanel {
     selected: Vec<usize>,
     offset: f32,
     insert_index: Option<usize>,
+    clip_editor: UIClipEditor,
+    piano_roll: UIPianoRoll,
+    instrument: UIInstrument,
 }
 
 impl UIBottomPanel {
-    pub fn new() -> Self {
+    pub fn new(midi: MidiHandle) -> Self {
         Self {
             selected: vec![],
             offset: 0.,
             insert_index: None,
+            clip_editor: UIClipEditor::new(),
+            piano_roll: UIPianoRoll::new(),
+            instrument: UIInstrument::new(midi),
         }
     }
 
@@ -33,7 +44,16 @@ impl UIBottomPanel {
             .show_animated(ctx, state.bottom_panel_open, |ui| {
                 ui.set_height(ui.available_height());
 
-                if let Some(selected) = state.selected_track() {
+                if let Some(id) = state.opened_clip.clone() {
+                    if let Some(clip) = state.clip(&id) {
+                        self.clip_editor.ui(ui, clip, state);
+                    } else if let Some((track_id, clip)) = state.midi_clip(&id) {
+                        self.piano_roll.ui(ui, &track_id, clip, state);
+                    } else {
+                        // Clip deleted
+                        state.opened_clip = None;
+                    }
+                } else if let Some(selected) = state.selected_track() {
                     self.ui(ui, selected, state);
                 }
             });
@@ -64,11 +84,28 @@ impl UIBottomPanel {
         self.top_bar(ui, &track);
 
         let effects_len = state.effects_mut(&track.id).map_or(0, |t| t.len());
+        let mut learns: Vec<_> = state
+            .effects_mut(&track.id)
+            .map(|effects| effects.iter().map(|effect| effect.id()).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|effect_id| state.effect_learn(&track.id, &effect_id))
+            .collect();
+        let mut learned = None;
         let inner = ScrollArea::horizontal().show(ui, |ui| {
             ui.allocate_ui_with_layout(
                 ui.available_size(),
                 Layout::left_to_right(egui::Align::Min),
                 |ui| {
+                    if let Some(instrument) = &track.instrument {
+                        ui.add_space(8.);
+                        let response = self.instrument.ui(ui, &track.id, instrument, state);
+                        // Effects dropped on the instrument are not added to the track
+                        if response.contains_pointer() {
+                            drag_payload = None;
+                            insert_index = None;
+                        }
+                    }
                     if let Some(effects) = state.effects_mut(&track.id).take() {
                         for (i, effect) in effects.iter_mut().enumerate() {
                             // Add space
@@ -80,7 +117,16 @@ impl UIBottomPanel {
                                 ui.add_space(8.);
                             }
 
-                            let response = effect.ui(ui, &mut metrics, self.selected.contains(&i));
+                            let learn = &mut learns[i];
+                            let response =
+                                effect.ui(ui, &mut metrics, self.selected.contains(&i), learn);
+                            if let Some(param) = learn.clicked {
+                                learned = Some(MidiTarget::EffectParam {
+                                    track_id: track.id.clone(),
+                                    effect_id: effect.id(),
+                                    param,
+                                });
+                            }
 
                             // Select effect
                             if response.inner.clicked() {
@@ -113,6 +159,10 @@ impl UIBottomPanel {
             );
         });
 
+        if let Some(target) = learned {
+            state.learn_midi(target);
+        }
+
         if ui.input(|i| i.key_pressed(Key::Delete)) && self.selected.len() > 0 {
             state.remove_effects(&track.id, &self.selected);
         }
@@ -1,6 +1,9 @@
 use crate::{
     core::state::ToniqueProjectState,
-    ui::view::{navigation_bar::UINavigationBar, timeline::UITimeline, tracks::UITracks},
+    ui::view::{
+        navigation_bar::UINavigationBar, tempo_lane::UITempoLane, timeline::UITimeline,
+        tracks::UITracks,
+    },
 };
 use egui::{Color32, Context, Frame, Margin, Rect, Sense, Stroke, Ui, Vec2, pos2, vec2};
 
@@ -10,6 +13,7 @@ pub const PLAYHEAD_COLOR: Color32 = Color32::WHITE;
 pub struct UICentralPanel {
     timeline: UITimeline,
     navigation_bar: UINavigationBar,
+    tempo_lane: UITempoLane,
     tracks: UITracks,
 }
 
@@ -18,6 +22,7 @@ impl UICentralPanel {
         Self {
             timeline: UITimeline::new(),
             navigation_bar: UINavigationBar::new(),
+            tempo_lane: UITempoLane::new(),
             tracks: UITracks::new(),
         }
     }
@@ -38,6 +43,9 @@ impl UICentralPanel {
         let available_rect = ui.available_rect_before_wrap();
         // Draw navigation bar on top
         self.navigation_bar.ui(ui, state, self.tracks.width);
+        if state.tempo_lane_open {
+            self.tempo_lane.ui(ui, state, self.tracks.width);
+        }
 
         let (viewport, _) = ui.allocate_exact_size(ui.available_size(), Sense::all());
         ui.set_clip_rect(viewport);
@@ -1,4 +1,5 @@
 pub mod bottom_panel;
 pub mod central_panel;
 pub mod left_panel;
+pub mod settings;
 pub mod top_bar;
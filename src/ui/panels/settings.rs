@@ -0,0 +1,246 @@
+use crate::{
+    audio::midi::MidiHandle,
+    core::{
+        state::ToniqueProjectState,
+        sync::{SyncSettings, SyncSource},
+    },
+    ui::{
+        theme::PRIMARY_COLOR,
+        widget::{item_button::ItemButton, square_button::SquareButton},
+    },
+};
+use egui::{Color32, Context, DragValue, Frame, Margin, RichText, Ui, vec2};
+use egui_phosphor::fill::{CHECK_SQUARE, CIRCLE, GLOBE, SQUARE, X};
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SettingsPage {
+    Audio,
+    Midi,
+    Mappings,
+}
+
+/// Window with the device settings
+pub struct UISettings {
+    midi: MidiHandle,
+    page: SettingsPage,
+}
+
+impl UISettings {
+    pub fn new(midi: MidiHandle) -> Self {
+        Self {
+            midi,
+            page: SettingsPage::Midi,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, state: &mut ToniqueProjectState) {
+        let mut open = state.settings_open;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .default_size(vec2(360., 240.))
+            .frame(
+                Frame::new()
+                    .fill(Color32::from_gray(40))
+                    .inner_margin(Margin::same(6))
+                    .corner_radius(4.0),
+            )
+            .show(ctx, |ui| {
+                ui.horizontal_top(|ui| {
+                    ui.vertical(|ui| {
+                        ui.set_width(80.);
+                        self.page_button(ui, SettingsPage::Audio, "Audio");
+                        self.page_button(ui, SettingsPage::Midi, "MIDI");
+                        self.page_button(ui, SettingsPage::Mappings, "Mappings");
+                    });
+                    ui.add_space(8.);
+                    ui.vertical(|ui| {
+                        ui.set_min_width(260.);
+                        match self.page {
+                            SettingsPage::Audio => Self::audio_ui(ui, state),
+                            SettingsPage::Midi => self.midi_ui(ui, state),
+                            SettingsPage::Mappings => Self::mappings_ui(ui, state),
+                        }
+                    });
+                });
+            });
+        state.settings_open = open;
+    }
+
+    fn page_button(&mut self, ui: &mut Ui, page: SettingsPage, name: &str) {
+        let res = ui.add(
+            SquareButton::new(name)
+                .size(vec2(80., 22.))
+                .fill(if self.page == page {
+                    PRIMARY_COLOR
+                } else {
+                    Color32::from_gray(100)
+                }),
+        );
+        if res.clicked() {
+            self.page = page;
+        }
+        ui.add_space(2.);
+    }
+
+    fn audio_ui(ui: &mut Ui, state: &ToniqueProjectState) {
+        ui.label(RichText::new("Audio device").strong());
+        ui.add_space(4.);
+        ui.add(ItemButton::new("Sample rate").detail(format!("{} Hz", state.sample_rate())));
+        ui.add(ItemButton::new("Outputs").detail(state.output_channels()));
+        ui.add(ItemButton::new("Inputs").detail(state.input_channels()));
+    }
+
+    /// MIDI inputs found on the system, enabled by clicking them, and the MIDI sync
+    fn midi_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
+        ui.label(RichText::new("MIDI inputs").strong());
+        ui.add_space(4.);
+        let ports = self.midi.ports();
+        if ports.is_empty() {
+            ui.label(RichText::new("No MIDI input found").color(Color32::from_gray(150)));
+        }
+        for port in ports {
+            let check = if port.enabled { CHECK_SQUARE } else { SQUARE };
+            let status = if port.connected {
+                format!("{} Connected", CIRCLE)
+            } else {
+                String::new()
+            };
+            let res = ui.add(
+                ItemButton::new(format!("{} {}", check, port.name))
+                    .detail(status)
+                    .selected(port.enabled),
+            );
+            if res.clicked() {
+                self.midi.set_enabled(&port.name, !port.enabled);
+            }
+        }
+
+        ui.add_space(8.);
+        ui.label(RichText::new("Send clock").strong());
+        ui.add_space(4.);
+        let outputs = self.midi.outputs();
+        if outputs.is_empty() {
+            ui.label(RichText::new("No MIDI output found").color(Color32::from_gray(150)));
+        }
+        for output in outputs {
+            let check = if output.enabled { CHECK_SQUARE } else { SQUARE };
+            let status = if output.connected {
+                format!("{} Connected", CIRCLE)
+            } else {
+                String::new()
+            };
+            let res = ui.add(
+                ItemButton::new(format!("{} {}", check, output.name))
+                    .detail(status)
+                    .selected(output.enabled),
+            );
+            if res.clicked() {
+                let send_clock = !output.enabled;
+                self.midi
+                    .set_sync_output(send_clock.then_some(output.name.clone()));
+                state.set_sync(SyncSettings {
+                    send_clock,
+                    ..state.sync()
+                });
+            }
+        }
+
+        ui.add_space(8.);
+        ui.label(RichText::new("Follow").strong());
+        ui.add_space(4.);
+        for source in SyncSource::ALL {
+            let selected = state.sync().follow == source;
+            let check = if selected { CHECK_SQUARE } else { SQUARE };
+            let res =
+                ui.add(ItemButton::new(format!("{} {}", check, source.name())).selected(selected));
+            if res.clicked() {
+                state.set_sync(SyncSettings {
+                    follow: source,
+                    ..state.sync()
+                });
+            }
+        }
+        // Follow plugged and unplugged devices
+        ui.ctx().request_repaint_after(Duration::from_secs(1));
+    }
+
+    /// Controllers bound in the project and in the global mapping, with their range
+    fn mappings_ui(ui: &mut Ui, state: &mut ToniqueProjectState) {
+        ui.label(RichText::new("Project mappings").strong());
+        ui.add_space(4.);
+        if state.midi_learn().bindings.is_empty() {
+            ui.label(
+                RichText::new("Turn on MIDI learn, click a control and move a controller")
+                    .color(Color32::from_gray(150)),
+            );
+        }
+        let mut removed = None;
+        let mut global = None;
+        for (index, binding) in state.midi_learn().bindings.clone().iter().enumerate() {
+            let name = state.midi_target_name(&binding.target);
+            ui.horizontal(|ui| {
+                ui.label(format!("{}  {}", binding.name(), name));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button(X).on_hover_text("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                    if ui
+                        .small_button(GLOBE)
+                        .on_hover_text("Move to the global mapping, following the selected track")
+                        .clicked()
+                    {
+                        global = Some(index);
+                    }
+                    let binding = &mut state.midi_bindings_mut()[index];
+                    ui.add(range_value(&mut binding.max));
+                    ui.label("to");
+                    ui.add(range_value(&mut binding.min));
+                });
+            });
+        }
+        if let Some(index) = removed {
+            state.midi_bindings_mut().remove(index);
+        } else if let Some(index) = global {
+            state.make_midi_binding_global(index);
+        }
+
+        ui.add_space(8.);
+        ui.label(RichText::new("Global mapping").strong());
+        ui.add_space(4.);
+        let bindings = state.midi_learn().global.clone();
+        if bindings.is_empty() {
+            ui.label(RichText::new("No global mapping").color(Color32::from_gray(150)));
+        }
+        for (index, binding) in bindings.iter().enumerate() {
+            let name = state.midi_target_name(&binding.target);
+            ui.horizontal(|ui| {
+                ui.label(format!("{}  {}", binding.name(), name));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button(X).on_hover_text("Remove").clicked() {
+                        let mut bindings = bindings.clone();
+                        bindings.remove(index);
+                        state.set_global_midi_bindings(bindings);
+                    }
+                });
+            });
+        }
+    }
+}
+
+/// Part of the control range reached by a controller, shown as a percentage
+fn range_value(value: &mut f32) -> DragValue<'_> {
+    DragValue::new(value)
+        .range(0.0..=1.0)
+        .speed(0.01)
+        .custom_formatter(|value, _| format!("{:.0}%", value * 100.))
+        .custom_parser(|text| {
+            text.trim_end_matches('%')
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .map(|value| value / 100.)
+        })
+}
@@ -1,24 +1,47 @@
 use egui::{
-    Color32, Context, FontFamily, FontId, Frame, Layout, Margin, Pos2, Rangef, Response, Sense,
-    Stroke, Ui, Vec2,
+    Color32, Context, DragValue, FontFamily, FontId, Frame, Layout, Margin, Popup, Pos2, Rangef,
+    Response, Sense, Slider, Stroke, Ui, Vec2,
 };
-use egui_phosphor::{fill::SIDEBAR_SIMPLE, regular::RECORD};
+use egui_phosphor::{
+    fill::{
+        ARROWS_IN_LINE_HORIZONTAL, CHECK, FADERS, FLOPPY_DISK, FOLDER_OPEN, GEAR, INTERSECT,
+        KEYBOARD, REPEAT, SIDEBAR_SIMPLE, SPEAKER_HIGH, TIMER, WARNING, WAVEFORM,
+    },
+    regular::RECORD,
+};
+use rfd::FileDialog;
 
 use crate::{
-    core::state::{PlaybackState, ToniqueProjectState},
+    core::{
+        meter::TimeSignature,
+        metronome::{MAX_COUNT_IN, MetronomeSettings},
+        midi::note_name,
+        project::PROJECT_EXTENSION,
+        recording::MidiRecordMode,
+        state::{PlaybackState, ToniqueProjectState},
+    },
     ui::{
         font::{PHOSPHOR_FILL, PHOSPHOR_REGULAR},
         theme::PRIMARY_COLOR,
-        widget::{input::NumberInput, square_button::SquareButton},
+        widget::{
+            context_menu::{ContextMenuButton, ContextMenuLabel, ContextMenuSeparator},
+            input::NumberInput,
+            square_button::SquareButton,
+        },
     },
 };
 const BUTTON_SIZE: f32 = 22.;
 const PRIMARY_BUTTON_COLOR: Color32 = Color32::from_gray(150);
 const WAVE_LOW: Color32 = Color32::from_gray(60);
 const WAVE_HIGH: Color32 = Color32::BLACK;
+const RECORD_COLOR: Color32 = Color32::from_rgb(220, 70, 70);
+/// Extensions of the files offered as metronome sounds
+const CLICK_EXTENSIONS: [&str; 5] = ["wav", "mp3", "flac", "ogg", "aiff"];
 
 pub struct UITopBar {
     bpm_input: NumberInput,
+    /// Why the project could not be saved or opened, cleared with a click
+    project_error: Option<String>,
 }
 
 impl UITopBar {
@@ -28,6 +51,7 @@ impl UITopBar {
                 .fill(PRIMARY_BUTTON_COLOR)
                 .text_color(Color32::from_gray(30))
                 .with_range(Rangef::new(10., 1000.)),
+            project_error: None,
         }
     }
 
@@ -49,6 +73,9 @@ impl UITopBar {
             ui.spacing_mut().item_spacing = Vec2::new(2.0, 2.0);
             self.sidebar_ui(ui, state);
             self.metronome_ui(ui, state);
+            self.metronome_volume_ui(ui, state);
+            self.crossfade_ui(ui, state);
+            self.conform_ui(ui, state);
             if self.play_button_ui(ui, state.playback_state()).clicked() {
                 if state.playback_state() == PlaybackState::Playing {
                     state.pause();
@@ -56,12 +83,21 @@ impl UITopBar {
                     state.play();
                 }
             };
-            self.record_button_ui(ui);
+            let record = self.record_button_ui(ui, state.recording());
+            if record.clicked() {
+                state.set_recording(!state.recording());
+            }
+            self.midi_record_mode_menu(&record, state);
+            self.dropped_frames_ui(ui, state);
+            self.loop_ui(ui, state);
+            self.midi_learn_ui(ui, state);
+            self.keyboard_ui(ui, state);
             self.bpm_input.value = state.bpm();
             self.bpm_input.ui(ui);
             if self.bpm_input.value != state.bpm() {
                 state.set_bpm(self.bpm_input.value);
             }
+            self.time_signature_ui(ui, state);
 
             ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
                 if self.redo_ui(ui, state).clicked() {
@@ -70,6 +106,8 @@ impl UITopBar {
                 if self.undo_ui(ui, state).clicked() {
                     state.undo();
                 };
+                self.project_ui(ui, state);
+                self.settings_ui(ui, state);
                 self.usage_ui(ui, state);
                 self.fps_ui(ui);
                 self.waveform_ui(ui, state);
@@ -103,7 +141,7 @@ impl UITopBar {
         )
     }
 
-    fn record_button_ui(&mut self, ui: &mut Ui) {
+    fn record_button_ui(&mut self, ui: &mut Ui, recording: bool) -> Response {
         ui.add(
             SquareButton::new(RECORD)
                 .square(BUTTON_SIZE)
@@ -111,10 +149,61 @@ impl UITopBar {
                     14.,
                     egui::FontFamily::Name(PHOSPHOR_FILL.into()),
                 ))
-                .fill(PRIMARY_BUTTON_COLOR)
+                .fill(if recording {
+                    RECORD_COLOR
+                } else {
+                    PRIMARY_BUTTON_COLOR
+                })
                 .color(Color32::from_gray(30))
-                .tooltip("Record"),
+                .tooltip(if recording {
+                    "Stop recording"
+                } else {
+                    "Record"
+                }),
+        )
+    }
+
+    /// Warning shown when the last recording lost frames, cleared with a click
+    fn dropped_frames_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
+        let dropped = state.dropped_frames();
+        if dropped == 0 {
+            return;
+        }
+        let seconds = dropped as f32 / state.sample_rate() as f32;
+        let response = ui.add(
+            SquareButton::new(WARNING)
+                .square(BUTTON_SIZE)
+                .font(FontId::new(
+                    14.,
+                    egui::FontFamily::Name(PHOSPHOR_FILL.into()),
+                ))
+                .color(RECORD_COLOR)
+                .tooltip(format!(
+                    "{:.2}s of the last recording could not be written to disk",
+                    seconds
+                )),
         );
+        if response.clicked() {
+            state.clear_dropped_frames();
+        }
+    }
+
+    /// How the MIDI tracks record, chosen with a right click on the record button
+    fn midi_record_mode_menu(&mut self, response: &Response, state: &mut ToniqueProjectState) {
+        response.context_menu(|ui| {
+            ui.add(ContextMenuLabel::new("MIDI recording"));
+            for mode in MidiRecordMode::ALL {
+                let icon = if mode == state.midi_record_mode() {
+                    CHECK
+                } else {
+                    ""
+                };
+                if ui.add(ContextMenuButton::new(icon, mode.name())).clicked() {
+                    state.set_midi_record_mode(mode);
+                    ui.close();
+                }
+            }
+        });
     }
 
     fn sidebar_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
@@ -143,10 +232,55 @@ impl UITopBar {
         res
     }
 
+    /// Time signature of the bar at the playhead
+    fn time_signature_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
+        let signature = state.time_signature();
+        let response = ui.add(
+            SquareButton::new(signature.name())
+                .size(Vec2::new(36., BUTTON_SIZE))
+                .font(FontId::monospace(11.))
+                .fill(PRIMARY_BUTTON_COLOR)
+                .color(Color32::from_gray(30))
+                .tooltip("Time signature"),
+        );
+        let bar = state.grid.meter().bar_at(state.playback_position()).bar;
+        Popup::menu(&response).show(|ui| {
+            ui.add(ContextMenuLabel::new(format!("From bar {}", bar + 1)));
+            for common in TimeSignature::COMMON {
+                let icon = if common == signature { CHECK } else { "" };
+                if ui
+                    .add(ContextMenuButton::new(icon, &common.name()))
+                    .clicked()
+                {
+                    state.set_time_signature(bar, common);
+                    ui.close();
+                }
+            }
+            ui.add(ContextMenuSeparator::new());
+            ui.horizontal(|ui| {
+                let mut custom = signature;
+                let numerator = ui.add(DragValue::new(&mut custom.numerator).range(1..=32));
+                ui.label("/");
+                let mut denominator = false;
+                for value in [2, 4, 8, 16] {
+                    denominator |= ui
+                        .selectable_value(&mut custom.denominator, value, value.to_string())
+                        .changed();
+                }
+                if numerator.changed() || denominator {
+                    state.set_time_signature(bar, custom);
+                }
+            });
+        });
+    }
+
     fn metronome_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
+        // Blink on each beat of the time signature
+        let position = state.grid.meter().bar_at(state.playback_position());
+        let beat = (state.playback_position() - position.start) / position.signature.beat_length();
         let click = state.metronome()
             && matches!(state.playback_state(), PlaybackState::Playing)
-            && state.playback_position() % 1.0 < 0.5;
+            && beat % 1.0 < 0.5;
         let res = ui.add(
             SquareButton::new(egui_phosphor::fill::METRONOME)
                 .square(BUTTON_SIZE)
@@ -172,6 +306,227 @@ impl UITopBar {
         if res.clicked() {
             state.toggle_metronome();
         }
+        res.context_menu(|ui| Self::metronome_menu_ui(ui, state));
+
+        res
+    }
+
+    fn metronome_volume_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
+        let mut volume = state.metronome_settings().volume;
+        ui.spacing_mut().slider_width = 50.;
+        let response = ui
+            .add(Slider::new(&mut volume, 0.0..=1.0).show_value(false))
+            .on_hover_text("Metronome volume");
+        if response.changed() {
+            state.set_metronome_settings(MetronomeSettings {
+                volume,
+                ..state.metronome_settings().clone()
+            });
+        }
+    }
+
+    /// Count-in, sounds and output of the metronome
+    fn metronome_menu_ui(ui: &mut Ui, state: &mut ToniqueProjectState) {
+        let settings = state.metronome_settings().clone();
+        ContextMenuButton::new(TIMER, "Count-in").submenu(ui, |ui| {
+            for bars in 0..=MAX_COUNT_IN {
+                let icon = if settings.count_in == bars { CHECK } else { "" };
+                let text = match bars {
+                    0 => "Off".to_string(),
+                    1 => "1 bar".to_string(),
+                    bars => format!("{bars} bars"),
+                };
+                if ui.add(ContextMenuButton::new(icon, &text)).clicked() {
+                    state.set_metronome_settings(MetronomeSettings {
+                        count_in: bars,
+                        ..settings.clone()
+                    });
+                    ui.close();
+                }
+            }
+        });
+        let icon = if settings.record_only { CHECK } else { "" };
+        if ui
+            .add(ContextMenuButton::new(icon, "Only while recording"))
+            .clicked()
+        {
+            state.set_metronome_settings(MetronomeSettings {
+                record_only: !settings.record_only,
+                ..settings.clone()
+            });
+            ui.close();
+        }
+        ui.add(ContextMenuSeparator::new());
+        ContextMenuButton::new(WAVEFORM, "Sound").submenu(ui, |ui| {
+            let icon = if settings.tick.is_none() && settings.accent.is_none() {
+                CHECK
+            } else {
+                ""
+            };
+            if ui.add(ContextMenuButton::new(icon, "Default")).clicked() {
+                state.set_metronome_settings(MetronomeSettings {
+                    tick: None,
+                    accent: None,
+                    ..settings.clone()
+                });
+                ui.close();
+            }
+            for (accent, sample) in [(false, &settings.tick), (true, &settings.accent)] {
+                let text = match (accent, sample) {
+                    (false, Some(sample)) => format!("Click: {}", sample.name),
+                    (false, None) => "Load click...".to_string(),
+                    (true, Some(sample)) => format!("Accent: {}", sample.name),
+                    (true, None) => "Load accent...".to_string(),
+                };
+                if ui.add(ContextMenuButton::new(FOLDER_OPEN, &text)).clicked() {
+                    if let Some(path) = FileDialog::new()
+                        .add_filter("Audio", &CLICK_EXTENSIONS)
+                        .pick_file()
+                    {
+                        state.load_click_sample(path, accent);
+                    }
+                    ui.close();
+                }
+            }
+        });
+        ContextMenuButton::new(SPEAKER_HIGH, "Output").submenu(ui, |ui| {
+            for pair in 0..(state.output_channels() / 2).max(1) {
+                let icon = if settings.output == pair { CHECK } else { "" };
+                let text = format!("{}/{}", 2 * pair + 1, 2 * pair + 2);
+                if ui.add(ContextMenuButton::new(icon, &text)).clicked() {
+                    state.set_metronome_settings(MetronomeSettings {
+                        output: pair,
+                        ..settings.clone()
+                    });
+                    ui.close();
+                }
+            }
+        });
+    }
+
+    fn loop_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
+        let res = ui.add_enabled(
+            state.loop_region().is_some(),
+            SquareButton::new(REPEAT)
+                .square(BUTTON_SIZE)
+                .fill(if state.loop_enabled() {
+                    PRIMARY_COLOR
+                } else {
+                    PRIMARY_BUTTON_COLOR
+                })
+                .color(Color32::from_gray(30))
+                .tooltip("Loop, drag in the ruler to set the region"),
+        );
+        if res.clicked() {
+            state.toggle_loop();
+        }
+
+        res
+    }
+
+    fn midi_learn_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
+        let res = ui.add(
+            SquareButton::new(FADERS)
+                .square(BUTTON_SIZE)
+                .fill(if state.midi_learn().active {
+                    PRIMARY_COLOR
+                } else {
+                    PRIMARY_BUTTON_COLOR
+                })
+                .color(Color32::from_gray(30))
+                .tooltip("MIDI learn, click a control then move a controller to bind them"),
+        );
+        if res.clicked() {
+            state.toggle_midi_learn();
+        }
+
+        res
+    }
+
+    fn keyboard_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
+        let res = ui.add(
+            SquareButton::new(KEYBOARD)
+                .square(BUTTON_SIZE)
+                .fill(if state.keyboard().active {
+                    PRIMARY_COLOR
+                } else {
+                    PRIMARY_BUTTON_COLOR
+                })
+                .color(Color32::from_gray(30))
+                .tooltip(
+                    "Play the armed MIDI tracks from the computer keyboard\n\
+                     A to ; notes, Z and X octave, C and V velocity",
+                ),
+        );
+        if res.clicked() {
+            state.toggle_keyboard();
+        }
+        if state.keyboard().active {
+            let keyboard = state.keyboard();
+            let lowest = ((keyboard.octave() as i32 + 2) * 12) as u8;
+            ui.add(
+                SquareButton::new(format!("{} {}", note_name(lowest), keyboard.velocity()))
+                    .size(Vec2::new(50., BUTTON_SIZE))
+                    .font(FontId::new(10., FontFamily::Proportional))
+                    .fill(PRIMARY_BUTTON_COLOR)
+                    .color(Color32::from_gray(30))
+                    .tooltip("Lowest key and velocity of the computer keyboard"),
+            );
+        }
+
+        res
+    }
+
+    fn crossfade_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
+        let res = ui.add(
+            SquareButton::new(INTERSECT)
+                .square(BUTTON_SIZE)
+                .font(FontId::new(
+                    15.,
+                    if state.auto_crossfade() {
+                        FontFamily::Name(PHOSPHOR_FILL.into())
+                    } else {
+                        egui::FontFamily::Name(PHOSPHOR_REGULAR.into())
+                    },
+                ))
+                .fill(if state.auto_crossfade() {
+                    PRIMARY_COLOR
+                } else {
+                    PRIMARY_BUTTON_COLOR
+                })
+                .color(Color32::from_gray(30))
+                .tooltip("Crossfade overlapping clips"),
+        );
+        if res.clicked() {
+            state.toggle_auto_crossfade();
+        }
+
+        res
+    }
+
+    fn conform_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
+        let res = ui.add(
+            SquareButton::new(ARROWS_IN_LINE_HORIZONTAL)
+                .square(BUTTON_SIZE)
+                .font(FontId::new(
+                    15.,
+                    if state.conform_on_drop() {
+                        FontFamily::Name(PHOSPHOR_FILL.into())
+                    } else {
+                        egui::FontFamily::Name(PHOSPHOR_REGULAR.into())
+                    },
+                ))
+                .fill(if state.conform_on_drop() {
+                    PRIMARY_COLOR
+                } else {
+                    PRIMARY_BUTTON_COLOR
+                })
+                .color(Color32::from_gray(30))
+                .tooltip("Conform dropped loops to the project tempo"),
+        );
+        if res.clicked() {
+            state.toggle_conform_on_drop();
+        }
 
         res
     }
@@ -220,6 +575,23 @@ impl UITopBar {
         };
     }
 
+    fn settings_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
+        let res = ui.add(
+            SquareButton::ghost(GEAR)
+                .square(BUTTON_SIZE)
+                .font(FontId::new(15., FontFamily::Name(PHOSPHOR_FILL.into())))
+                .color(if state.settings_open {
+                    PRIMARY_COLOR
+                } else {
+                    Color32::from_gray(180)
+                })
+                .tooltip("Settings"),
+        );
+        if res.clicked() {
+            state.settings_open = !state.settings_open;
+        }
+    }
+
     fn usage_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
         ui.add(
             SquareButton::new(format!("{:.0}%", (state.metrics.latency * 100.).round()))
@@ -245,6 +617,56 @@ impl UITopBar {
         )
     }
 
+    /// Buttons opening and saving the project, with the error of the last attempt
+    fn project_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
+        let dialog = || FileDialog::new().add_filter("Project", &[PROJECT_EXTENSION]);
+        let save = ui.add(
+            SquareButton::ghost(FLOPPY_DISK)
+                .square(BUTTON_SIZE)
+                .font(FontId::new(
+                    15.,
+                    egui::FontFamily::Name(PHOSPHOR_REGULAR.into()),
+                ))
+                .tooltip("Save project"),
+        );
+        if save.clicked()
+            && let Some(path) = dialog()
+                .set_file_name(format!("Project.{}", PROJECT_EXTENSION))
+                .save_file()
+        {
+            self.project_error = state.save_project(&path).err();
+        }
+        let open = ui.add(
+            SquareButton::ghost(FOLDER_OPEN)
+                .square(BUTTON_SIZE)
+                .font(FontId::new(
+                    15.,
+                    egui::FontFamily::Name(PHOSPHOR_REGULAR.into()),
+                ))
+                .tooltip("Open project"),
+        );
+        if open.clicked()
+            && let Some(path) = dialog().pick_file()
+        {
+            self.project_error = state.open_project(&path).err();
+        }
+        if let Some(error) = &self.project_error {
+            let response = ui.add(
+                SquareButton::new(WARNING)
+                    .square(BUTTON_SIZE)
+                    .font(FontId::new(
+                        14.,
+                        egui::FontFamily::Name(PHOSPHOR_FILL.into()),
+                    ))
+                    .color(RECORD_COLOR)
+                    .tooltip(error.clone()),
+            );
+            if response.clicked() {
+                self.project_error = None;
+            }
+        }
+    }
+
     fn undo_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) -> Response {
         ui.add_enabled(
             state.can_undo(),
@@ -1,13 +1,15 @@
 use crate::{
     core::{
+        midi_learn::MidiTarget,
         state::ToniqueProjectState,
         track::{
-            DEFAULT_TRACK_HEIGHT, MutableTrackCore, TRACK_CLOSED_HEIGHT, TrackCore,
-            TrackReferenceCore,
+            DEFAULT_TRACK_HEIGHT, MAX_GAIN_DB, MIN_GAIN_DB, MonitorMode, MutableTrackCore,
+            TRACK_CLOSED_HEIGHT, TrackCore, TrackInput, TrackReferenceCore, TrackType,
         },
     },
     ui::{
         font::PHOSPHOR_FILL,
+        midi_learn::paint_learn,
         widget::{
             context_menu::{ContextMenuButton, ContextMenuLabel, ContextMenuSeparator},
             meter::LoudnessMeter as Meter,
@@ -22,8 +24,11 @@ use egui::{
     TextEdit, Ui, Vec2, epaint::MarginF32,
 };
 use egui_phosphor::{
-    fill::{COPY, PALETTE, PLUS, TRASH},
-    regular::{MUSIC_NOTE_SIMPLE, TEXT_T},
+    fill::{
+        ARROWS_IN_LINE_VERTICAL, CHECK, COPY, PALETTE, PIANO_KEYS, PLUGS, PLUS, ROWS, SQUARES_FOUR,
+        TRASH,
+    },
+    regular::{HEADPHONES, MUSIC_NOTE_SIMPLE, TEXT_T},
 };
 use rand::Rng;
 use std::ops::RangeInclusive;
@@ -40,7 +45,6 @@ pub struct UITrack {
     old_volume: f32,
     prev_height: f32,
     edit: bool,
-    arm: bool,
     _edit_lost_focus: bool,
 }
 
@@ -48,7 +52,6 @@ impl UITrack {
     pub fn new() -> Self {
         Self {
             _edit_lost_focus: false,
-            arm: false,
             edit: false,
             gain: 0.,
             old_volume: 1.0,
@@ -125,7 +128,7 @@ impl UITrack {
 
                             let text_width = ui.available_width()
                                 - 4. * PADDING
-                                - 3. * BUTTON_SIZE
+                                - 4. * BUTTON_SIZE
                                 - METER_WIDTH;
                             // Track label
                             if text_width > 0. {
@@ -137,7 +140,10 @@ impl UITrack {
                             // Track controls
                             let mute_res = self.mute_button(ui, is_solo, track);
                             let solo_res = self.solo_button(ui, is_solo, track);
-                            let arm_res = self.arm_button(ui);
+                            let arm_error = state
+                                .input_error()
+                                .filter(|_| track.kind == TrackType::Audio);
+                            let arm_res = self.arm_button(ui, track, arm_error);
 
                             if mute_res.clicked() {
                                 state.set_mute(track.id.clone(), !track.muted);
@@ -147,14 +153,32 @@ impl UITrack {
                                     .toggle_solo(track.id.clone(), ui.input(|i| i.modifiers.shift));
                             }
                             if arm_res.clicked() {
-                                self.arm = !self.arm;
+                                state.set_arm(&track.id, !track.arm);
+                            }
+                            if track.kind == TrackType::Audio {
+                                arm_res.context_menu(|ui| Self::input_menu(ui, track, state));
+                            }
+                            let monitor_res = self.monitor_button(ui, track);
+                            if monitor_res.clicked() {
+                                let next = match track.monitor {
+                                    MonitorMode::Off => MonitorMode::Auto,
+                                    MonitorMode::Auto => MonitorMode::On,
+                                    MonitorMode::On => MonitorMode::Off,
+                                };
+                                state.set_track_monitor(&track.id, next);
                             }
+                            monitor_res.context_menu(|ui| Self::monitor_menu(ui, track, state));
                         });
                         let track_mut = state.track_mut(&track.id);
                         // Extra controls
                         if !track_mut.closed {
                             let prev_gain = self.gain;
-                            self.gain_slider(ui, RangeInclusive::new(-40., 5.), track, state);
+                            self.gain_slider(
+                                ui,
+                                RangeInclusive::new(MIN_GAIN_DB, MAX_GAIN_DB),
+                                track,
+                                state,
+                            );
                             volume_changed = prev_gain != self.gain;
                         };
                     });
@@ -241,6 +265,24 @@ impl UITrack {
                 {
                     state.add_track_at(TrackCore::new(), track.index);
                 }
+                if ui
+                    .add(ContextMenuButton::new(PIANO_KEYS, "Add MIDI Track"))
+                    .clicked()
+                {
+                    state.add_track_at(TrackCore::midi(), track.index);
+                }
+                if ui
+                    .add(ContextMenuButton::new(SQUARES_FOUR, "Add Drum Rack Track"))
+                    .clicked()
+                {
+                    state.add_track_at(TrackCore::drum_rack(), track.index);
+                }
+                if ui
+                    .add(ContextMenuButton::new(PLUGS, "Add External MIDI Track"))
+                    .clicked()
+                {
+                    state.add_track_at(TrackCore::external(), track.index);
+                }
                 if ui.add(ContextMenuButton::new(COPY, "Duplicate")).clicked() {
                     state.duplicate_track(&track.id);
                 };
@@ -257,6 +299,26 @@ impl UITrack {
                     );
                     state.commit_track_mut(&track.id);
                 }
+                if !track.takes.is_empty() {
+                    ui.add(ContextMenuSeparator::new());
+                    let label = if track.takes.open {
+                        "Hide Takes"
+                    } else {
+                        "Show Takes"
+                    };
+                    if ui.add(ContextMenuButton::new(ROWS, label)).clicked() {
+                        state.toggle_take_lanes(&track.id);
+                    }
+                    if ui
+                        .add(ContextMenuButton::new(
+                            ARROWS_IN_LINE_VERTICAL,
+                            "Flatten Comp",
+                        ))
+                        .clicked()
+                    {
+                        state.flatten_takes(&track.id);
+                    }
+                }
                 ui.add(ContextMenuSeparator::new());
                 if ui
                     .add(ContextMenuButton::new(TRASH, "Delete").text_color(Color32::LIGHT_RED))
@@ -292,18 +354,81 @@ impl UITrack {
         }))
     }
 
-    fn arm_button(&mut self, ui: &mut Ui) -> Response {
+    /// Arm button, showing the `error` preventing the track from recording
+    fn arm_button(
+        &mut self,
+        ui: &mut Ui,
+        track: &TrackReferenceCore,
+        error: Option<String>,
+    ) -> Response {
         ui.add(
             SquareButton::new(MUSIC_NOTE_SIMPLE)
                 .square(BUTTON_SIZE)
-                .fill(if self.arm {
+                .fill(if track.arm {
                     Color32::from_rgb(220, 30, 30)
                 } else {
                     ui.visuals().widgets.inactive.bg_fill
-                }),
+                })
+                .tooltip(
+                    error.unwrap_or_else(|| format!("Arm for recording ({})", track.input.name())),
+                ),
+        )
+    }
+
+    fn monitor_button(&mut self, ui: &mut Ui, track: &TrackReferenceCore) -> Response {
+        ui.add(
+            SquareButton::new(HEADPHONES)
+                .square(BUTTON_SIZE)
+                .fill(match track.monitor {
+                    MonitorMode::On => Color32::from_rgb(30, 140, 200),
+                    MonitorMode::Auto if track.arm => Color32::from_rgb(20, 80, 110),
+                    _ => ui.visuals().widgets.inactive.bg_fill,
+                })
+                .tooltip(format!("Monitor input ({})", track.monitor.name())),
         )
     }
 
+    fn monitor_menu(ui: &mut Ui, track: &TrackReferenceCore, state: &mut ToniqueProjectState) {
+        ui.add(ContextMenuLabel::new("Monitor"));
+        for mode in MonitorMode::ALL {
+            let icon = if track.monitor == mode { CHECK } else { "" };
+            if ui.add(ContextMenuButton::new(icon, mode.name())).clicked() {
+                state.set_track_monitor(&track.id, mode);
+                ui.close();
+            }
+        }
+    }
+
+    /// Input channels recorded by the track, mono channels then stereo pairs
+    fn input_menu(ui: &mut Ui, track: &TrackReferenceCore, state: &mut ToniqueProjectState) {
+        let channels = state.input_channels();
+        if channels == 0 {
+            ui.add(ContextMenuLabel::new("No input device"));
+            return;
+        }
+        ui.add(ContextMenuLabel::new("Input"));
+        let mono = (0..channels).map(|channel| TrackInput {
+            channel,
+            stereo: false,
+        });
+        let stereo = (0..channels.saturating_sub(1))
+            .step_by(2)
+            .map(|channel| TrackInput {
+                channel,
+                stereo: true,
+            });
+        for input in mono.chain(stereo) {
+            let icon = if track.input == input { CHECK } else { "" };
+            if ui
+                .add(ContextMenuButton::new(icon, &input.name()))
+                .clicked()
+            {
+                state.set_track_input(&track.id, input);
+                ui.close();
+            }
+        }
+    }
+
     fn open_button(
         &mut self,
         ui: &mut Ui,
@@ -404,6 +529,17 @@ impl UITrack {
             Color32::WHITE,
         );
 
+        // Bind the volume to the next controller moved
+        if state.midi_learn().active {
+            let target = MidiTarget::TrackVolume(track.id.clone());
+            if response.clicked() {
+                state.learn_midi(target.clone());
+            }
+            let learning = state.midi_learn().learning.as_ref() == Some(&target);
+            let bound = state.midi_learn().binding(&target).is_some();
+            paint_learn(ui, rect, learning, bound);
+        }
+
         response
     }
 
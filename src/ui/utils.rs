@@ -16,7 +16,7 @@ pub fn get_track_y(track_index: usize, viewport: Rect, state: &ToniqueProjectSta
             return y;
         }
         curr_index += 1;
-        y += track.height + HANDLE_HEIGHT;
+        y += track.full_height() + HANDLE_HEIGHT;
     }
     return (track_index - curr_index) as f32 * (DEFAULT_TRACK_HEIGHT + HANDLE_HEIGHT) + y;
 }
@@ -37,11 +37,11 @@ pub fn find_track_at(
 
     for track in tracks {
         if y - state.grid.offset.y <= y_pos
-            && y_pos <= y + track.height + HANDLE_HEIGHT - state.grid.offset.y
+            && y_pos <= y + track.full_height() + HANDLE_HEIGHT - state.grid.offset.y
         {
             return (Some(track), y);
         }
-        y += track.height + HANDLE_HEIGHT;
+        y += track.full_height() + HANDLE_HEIGHT;
     }
     (None, y)
 }
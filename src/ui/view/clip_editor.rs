@@ -0,0 +1,285 @@
+use crate::{
+    core::{clip::ClipCore, meter::Meter, state::ToniqueProjectState},
+    ui::{
+        font::PHOSPHOR_REGULAR, theme::PRIMARY_COLOR, waveform::UIWaveform,
+        widget::square_button::SquareButton,
+    },
+};
+use egui::{
+    Align2, Color32, DragValue, FontFamily, FontId, Frame, Layout, Margin, Painter, Rect, RichText,
+    Sense, Stroke, Ui, pos2, vec2,
+};
+
+const RULER_HEIGHT: f32 = 14.;
+const MARKER_HANDLE_WIDTH: f32 = 8.;
+const MIN_PIXELS_PER_BEAT: f32 = 4.;
+
+/// Editor of the clip opened in the bottom panel.
+/// Warp markers are added with a double click, dragged on the ruler and removed with a right click.
+pub struct UIClipEditor {
+    waveform: UIWaveform,
+}
+
+impl UIClipEditor {
+    pub fn new() -> Self {
+        Self {
+            waveform: UIWaveform::new(),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, clip: ClipCore, state: &mut ToniqueProjectState) {
+        self.top_bar(ui, &clip, state);
+
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0., Color32::from_gray(30));
+
+        let tempo = state.tempo().clone();
+        let length = clip.length(&tempo);
+        if length <= 0. || clip.audio.duration.is_none() {
+            return;
+        }
+        let ruler = Rect::from_min_size(rect.min, vec2(rect.width(), RULER_HEIGHT));
+        let content = Rect::from_min_max(pos2(rect.left(), ruler.bottom()), rect.max);
+        let pixels_per_beat = rect.width() / length;
+        let x_to_beats = |x: f32| ((x - rect.left()) / pixels_per_beat).clamp(0., length);
+
+        Self::paint_grid(
+            &painter,
+            ruler,
+            content,
+            &clip,
+            state.grid.meter(),
+            pixels_per_beat,
+            length,
+        );
+        let transients: Vec<f32> = clip
+            .transients(&tempo)
+            .into_iter()
+            .filter(|beats| (0.0..=length).contains(beats))
+            .collect();
+        for beats in transients.iter() {
+            let x = rect.left() + beats * pixels_per_beat;
+            painter.line_segment(
+                [pos2(x, content.top()), pos2(x, content.bottom())],
+                Stroke::new(1., Color32::from_white_alpha(25)),
+            );
+        }
+        let mut shapes = Vec::new();
+        self.waveform.paint_clip(
+            &mut shapes,
+            content,
+            &clip,
+            &tempo,
+            0.0..length,
+            PRIMARY_COLOR,
+        );
+        painter.add(shapes);
+
+        if !clip.warp.enabled {
+            return;
+        }
+
+        let mut edited = None;
+        let mut committed = None;
+        for (index, marker) in clip.warp.markers.iter().enumerate() {
+            let beats = clip.beats_at(marker.source, &tempo);
+            if !(0.0..=length).contains(&beats) {
+                continue;
+            }
+            let x = rect.left() + beats * pixels_per_beat;
+            let handle = Rect::from_center_size(
+                pos2(x, ruler.center().y),
+                vec2(MARKER_HANDLE_WIDTH, RULER_HEIGHT),
+            );
+            let handle_response = ui.interact(
+                handle,
+                ui.id().with(("warp-marker", &clip.id, index)),
+                Sense::click_and_drag(),
+            );
+
+            if handle_response.dragged()
+                && let Some(mouse_pos) = ui.input(|i| i.pointer.interact_pos())
+            {
+                // Snap on the transients, then on the project grid
+                let beats_at_mouse = x_to_beats(mouse_pos.x);
+                let target = state
+                    .grid
+                    .snap_at_points_with_zoom(
+                        beats_at_mouse,
+                        transients.iter().copied(),
+                        pixels_per_beat,
+                    )
+                    .unwrap_or_else(|| {
+                        state.grid.snap_at_grid(clip.position + beats_at_mouse) - clip.position
+                    });
+                // Markers are stored in source order, reversed clips read them backwards
+                let direction = if clip.reverse { -1. } else { 1. };
+                let mut clip = clip.clone();
+                clip.warp
+                    .move_marker(index, marker.beat + direction * (target - beats));
+                clip.clamp_fades(&tempo);
+                edited = Some(clip);
+            }
+            if handle_response.drag_stopped() {
+                committed = Some(clip.clone());
+            }
+            if handle_response.secondary_clicked() {
+                let mut clip = clip.clone();
+                clip.warp.markers.remove(index);
+                clip.clamp_fades(&tempo);
+                committed = Some(clip);
+            }
+
+            let color = if handle_response.hovered() || handle_response.dragged() {
+                Color32::WHITE
+            } else {
+                Color32::from_gray(200)
+            };
+            painter.line_segment(
+                [pos2(x, ruler.top()), pos2(x, content.bottom())],
+                Stroke::new(1., color),
+            );
+            painter.rect_filled(handle, 1., color);
+            if handle_response.hovered() {
+                ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::ResizeHorizontal);
+            }
+        }
+
+        // Add a marker
+        if response.double_clicked()
+            && let Some(mouse_pos) = response.interact_pointer_pos()
+        {
+            let mut clip = clip.clone();
+            let secs = clip.source_at(x_to_beats(mouse_pos.x), &tempo);
+            clip.warp.add_marker(secs);
+            committed = Some(clip);
+        }
+
+        if let Some(clip) = committed {
+            state.update_clip(clip);
+        } else if let Some(clip) = edited {
+            state.edit_clip(clip);
+        }
+    }
+
+    fn top_bar(&mut self, ui: &mut Ui, clip: &ClipCore, state: &mut ToniqueProjectState) {
+        Frame::new()
+            .fill(PRIMARY_COLOR)
+            .stroke(Stroke::new(1.0, Color32::DARK_GRAY))
+            .inner_margin(Margin {
+                bottom: 1,
+                top: 1,
+                left: 5,
+                right: 5,
+            })
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(&clip.audio.name)
+                            .size(10.)
+                            .color(Color32::from_gray(20)),
+                    );
+
+                    let mut enabled = clip.warp.enabled;
+                    if ui
+                        .toggle_value(&mut enabled, RichText::new("Warp").size(10.))
+                        .clicked()
+                    {
+                        let mut clip = clip.clone();
+                        clip.warp.enabled = enabled;
+                        if enabled && clip.warp.markers.is_empty() {
+                            // Keep the clip length when the source tempo is unknown
+                            clip.warp.source_bpm = state.tempo().bpm_at(clip.position);
+                        }
+                        clip.clamp_fades(state.tempo());
+                        state.update_clip(clip);
+                    }
+
+                    ui.add_enabled_ui(clip.warp.enabled, |ui| {
+                        ui.label(
+                            RichText::new("Source BPM")
+                                .size(10.)
+                                .color(Color32::from_gray(20)),
+                        );
+                        let mut edited = clip.clone();
+                        let response = ui.add(
+                            DragValue::new(&mut edited.warp.source_bpm)
+                                .range(20.0..=999.0)
+                                .speed(0.1)
+                                .fixed_decimals(2),
+                        );
+                        if response.changed() {
+                            edited.clamp_fades(state.tempo());
+                            state.edit_clip(edited.clone());
+                        }
+                        if response.drag_stopped() || response.lost_focus() {
+                            state.update_clip(edited);
+                        }
+                    });
+
+                    ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                        let close = ui.add(
+                            SquareButton::ghost(egui_phosphor::regular::X)
+                                .square(12.)
+                                .color(Color32::from_gray(30))
+                                .font(FontId::new(10., FontFamily::Name(PHOSPHOR_REGULAR.into())))
+                                .tooltip("Close the clip editor"),
+                        );
+                        if close.clicked() {
+                            state.opened_clip = None;
+                        }
+                    });
+                });
+            });
+    }
+
+    /// Paint the project beats and bars over the clip
+    fn paint_grid(
+        painter: &Painter,
+        ruler: Rect,
+        content: Rect,
+        clip: &ClipCore,
+        meter: &Meter,
+        pixels_per_beat: f32,
+        length: f32,
+    ) {
+        painter.rect_filled(ruler, 0., Color32::from_gray(45));
+        let end = clip.position + length;
+        let mut bar = meter.bar_at(clip.position).bar;
+        while meter.bar_start(bar) <= end {
+            let start = meter.bar_start(bar);
+            let signature = meter.signature_at_bar(bar);
+            let beat_length = signature.beat_length();
+            // Only show the bars when the beats are too close
+            let beats = if beat_length * pixels_per_beat < MIN_PIXELS_PER_BEAT {
+                1
+            } else {
+                signature.numerator
+            };
+            for index in 0..beats {
+                let beat = start + index as f32 * beat_length;
+                if beat < clip.position || beat > end {
+                    continue;
+                }
+                let x = ruler.left() + (beat - clip.position) * pixels_per_beat;
+                let is_bar = index == 0;
+                painter.line_segment(
+                    [pos2(x, content.top()), pos2(x, content.bottom())],
+                    Stroke::new(1., Color32::from_white_alpha(if is_bar { 40 } else { 15 })),
+                );
+                if is_bar {
+                    painter.text(
+                        pos2(x + 2., ruler.center().y),
+                        Align2::LEFT_CENTER,
+                        format!("{}", bar + 1),
+                        FontId::new(9., FontFamily::Monospace),
+                        Color32::from_gray(160),
+                    );
+                }
+            }
+            bar += 1;
+        }
+    }
+}
@@ -12,7 +12,7 @@ use egui::{
 
 use crate::{
     analysis::AudioInfo,
-    cache::AUDIO_ANALYSIS_CACHE,
+    cache::{AUDIO_ANALYSIS_CACHE, TEMPO_CACHE, TempoEstimate},
     core::state::ToniqueProjectState,
     ui::{
         panels::left_panel::DragPayload,
@@ -192,15 +192,23 @@ impl UIItems {
             .unwrap_or(OsStr::new(""))
             .to_string_lossy();
 
-        let res = ui.add(
-            ItemButton::new(format!(
-                "{}{} {}",
-                " ".repeat((file.depth - 1) * 2),
-                icon,
-                name
-            ))
-            .selected(selected),
-        );
+        let mut item = ItemButton::new(format!(
+            "{}{} {}",
+            " ".repeat((file.depth - 1) * 2),
+            icon,
+            name
+        ))
+        .selected(selected);
+        // Tempo estimated in the background on the start of the file
+        if is_audio && let TempoEstimate::Done(Some(tempo)) = TEMPO_CACHE.get(&file.path) {
+            let decimals = if (tempo - tempo.round()).abs() < 0.05 {
+                0
+            } else {
+                1
+            };
+            item = item.detail(format!("{:.*} BPM", decimals, tempo));
+        }
+        let res = ui.add(item);
 
         let pressed = res.clicked() || (selected && ui.input(|i| i.key_pressed(Key::Enter)));
 
@@ -57,9 +57,10 @@ impl UIPreview {
                 ui.add(
                     Label::new(
                         RichText::new(format!(
-                            "Format: {:.1}kHz {}-bit",
+                            "Format: {:.1}kHz {}-bit {}",
                             selected_audio.sample_rate as f32 / 1000.,
                             selected_audio.bit_depth.unwrap_or(16),
+                            selected_audio.layout.name(),
                         ))
                         .size(9.),
                     )
@@ -100,7 +101,7 @@ impl UIPreview {
             self.waveform.paint(
                 &mut shapes,
                 response.rect,
-                data,
+                &data,
                 0.,
                 1.,
                 audio.num_samples.unwrap(),
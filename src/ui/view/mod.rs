@@ -1,4 +1,7 @@
+pub mod clip_editor;
 pub mod filebrowser;
 pub mod navigation_bar;
+pub mod piano_roll;
+pub mod tempo_lane;
 pub mod timeline;
 pub mod tracks;
@@ -1,18 +1,36 @@
-use egui::{Sense, Ui, Vec2, vec2};
-use egui_phosphor::fill::{ARROWS_IN_LINE_VERTICAL, ARROWS_OUT_LINE_VERTICAL, LINE_SEGMENTS};
+use egui::{Color32, Rect, Sense, Ui, Vec2, vec2};
+use egui_phosphor::fill::{
+    ARROWS_IN_LINE_VERTICAL, ARROWS_OUT_LINE_VERTICAL, CHART_LINE, CHECK, LINE_SEGMENTS,
+    MUSIC_NOTES, TRASH,
+};
 
 use crate::{
-    core::state::ToniqueProjectState,
-    ui::{view::tracks::DRAGGER_WIDTH, widget::square_button::SquareButton},
+    core::{meter::TimeSignature, state::ToniqueProjectState},
+    ui::{
+        theme::PRIMARY_COLOR,
+        view::tracks::DRAGGER_WIDTH,
+        widget::{
+            context_menu::{ContextMenuButton, ContextMenuLabel, ContextMenuSeparator},
+            square_button::SquareButton,
+        },
+    },
 };
 
-pub struct UINavigationBar {}
+pub struct UINavigationBar {
+    /// Bar under the pointer when the context menu was opened
+    context_bar: i32,
+    /// Beat where the drag of the loop region started
+    loop_drag_start: Option<f32>,
+}
 
 pub const NAVIGATION_BAR_HEIGHT: f32 = 30.;
 
 impl UINavigationBar {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            context_bar: 0,
+            loop_drag_start: None,
+        }
     }
 
     pub fn ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState, track_width: f32) {
@@ -30,16 +48,55 @@ impl UINavigationBar {
 
             // Draw rectangle
             painter.rect_filled(nav_bar_rect, 0.0, egui::Color32::from_gray(80));
+            // Draw loop region
+            if let Some((start, end)) = state.loop_region() {
+                let rect = Rect::from_x_y_ranges(
+                    state.grid.beats_to_x(start, nav_bar_rect)
+                        ..=state.grid.beats_to_x(end, nav_bar_rect),
+                    nav_bar_rect.y_range(),
+                );
+                let color = if state.loop_enabled() {
+                    PRIMARY_COLOR.gamma_multiply(0.5)
+                } else {
+                    Color32::from_white_alpha(25)
+                };
+                painter.rect_filled(rect.intersect(nav_bar_rect), 0.0, color);
+            }
             // Draw Labels
             state
                 .grid
-                .render_labels(&painter, nav_bar_rect, state.bpm());
+                .render_labels(&painter, nav_bar_rect, state.tempo());
+            // Drag to set the loop region
+            if let Some(mouse_pos) = nav_bar_response.interact_pointer_pos() {
+                let beat = state
+                    .grid
+                    .snap_at_grid(state.grid.x_to_beats(mouse_pos.x, nav_bar_rect));
+                if nav_bar_response.drag_started() {
+                    self.loop_drag_start = Some(beat);
+                }
+                if nav_bar_response.dragged()
+                    && let Some(start) = self.loop_drag_start
+                {
+                    state.set_loop_region(start, beat);
+                }
+            }
+            if nav_bar_response.drag_stopped() {
+                self.loop_drag_start = None;
+            }
             // Move to cursor on click
             if nav_bar_response.clicked()
                 && let Some(mouse_pos) = nav_bar_response.interact_pointer_pos()
             {
                 state.set_playback_position(state.grid.x_to_beats(mouse_pos.x, nav_bar_rect));
             }
+            // Time signature changes
+            if nav_bar_response.secondary_clicked()
+                && let Some(mouse_pos) = nav_bar_response.interact_pointer_pos()
+            {
+                let beat = state.grid.x_to_beats(mouse_pos.x, nav_bar_rect);
+                self.context_bar = state.grid.meter().bar_at(beat).bar;
+            }
+            nav_bar_response.context_menu(|ui| self.context_menu(ui, state));
             // Zoom
             if ui.input(|i| i.raw_scroll_delta.y != 0.0)
                 && let Some(mouse_pos) = nav_bar_response.hover_pos()
@@ -52,6 +109,44 @@ impl UINavigationBar {
         });
     }
 
+    fn context_menu(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
+        let bar = self.context_bar.max(0);
+        let signature = state.grid.meter().signature_at_bar(bar);
+        ui.add(ContextMenuLabel::new(format!("Bar {}", bar + 1)));
+        ContextMenuButton::new(MUSIC_NOTES, "Time signature").submenu(ui, |ui| {
+            for common in TimeSignature::COMMON {
+                let icon = if common == signature { CHECK } else { "" };
+                if ui
+                    .add(ContextMenuButton::new(icon, &common.name()))
+                    .clicked()
+                {
+                    state.set_time_signature(bar, common);
+                    ui.close();
+                }
+            }
+        });
+        if bar > 0
+            && state
+                .grid
+                .meter()
+                .changes()
+                .iter()
+                .any(|change| change.bar == bar)
+        {
+            ui.add(ContextMenuSeparator::new());
+            if ui
+                .add(
+                    ContextMenuButton::new(TRASH, "Remove time signature")
+                        .text_color(Color32::LIGHT_RED),
+                )
+                .clicked()
+            {
+                state.remove_meter_change(bar);
+                ui.close();
+            }
+        }
+    }
+
     fn right_ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState) {
         ui.scope(|ui| {
             ui.spacing_mut().item_spacing = Vec2::new(4.0, 4.0);
@@ -67,6 +162,13 @@ impl UINavigationBar {
             {
                 state.set_all_close(true);
             };
+            let mut tempo_button = SquareButton::new(CHART_LINE).tooltip("Tempo");
+            if state.tempo_lane_open {
+                tempo_button = tempo_button.fill(PRIMARY_COLOR);
+            }
+            if ui.add(tempo_button).clicked() {
+                state.tempo_lane_open = !state.tempo_lane_open;
+            };
             ui.add_enabled(false, SquareButton::new(LINE_SEGMENTS).tooltip("Automate"));
         });
     }
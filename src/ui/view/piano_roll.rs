@@ -0,0 +1,887 @@
+use crate::{
+    core::{
+        grid::{GridLineKind, GridResolution},
+        message::MidiToPlayerMsg,
+        midi::{MidiClipCore, MidiNote, note_name},
+        state::ToniqueProjectState,
+    },
+    ui::{
+        font::PHOSPHOR_REGULAR, midi_transform::transform_menu, theme::PRIMARY_COLOR,
+        widget::square_button::SquareButton,
+    },
+};
+use egui::{
+    Align2, Color32, CursorIcon, FontFamily, FontId, Frame, Key, Layout, Margin, Modifiers,
+    Painter, Popup, Pos2, Rect, RichText, Sense, Stroke, Ui, Vec2, pos2, vec2,
+};
+
+const RULER_HEIGHT: f32 = 14.;
+const KEYBOARD_WIDTH: f32 = 44.;
+const VELOCITY_HEIGHT: f32 = 50.;
+const KEY_HEIGHT: f32 = 10.;
+const MIN_PIXELS_PER_BEAT: f32 = 10.;
+const MAX_PIXELS_PER_BEAT: f32 = 2000.;
+/// Width in pixels of the end of a note resizing it
+const RESIZE_HANDLE_WIDTH: f32 = 6.;
+/// Shortest note when snapping is disabled
+const MIN_NOTE_LENGTH: f32 = 1. / 64.;
+const DEFAULT_VELOCITY: u8 = 100;
+const NOTE_COLOR: Color32 = PRIMARY_COLOR;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DragMode {
+    Move,
+    Resize,
+    Velocity,
+    Select,
+}
+
+/// Notes edited by a drag, committed when it stops
+struct NoteDrag {
+    mode: DragMode,
+    /// Note under the pointer when the drag started
+    anchor: usize,
+    /// Notes before the drag
+    original: Vec<MidiNote>,
+    notes: Vec<MidiNote>,
+}
+
+/// Piano roll of the MIDI clip opened in the bottom panel.
+/// Clicking or dragging on an empty area draws a note, shift dragging selects the notes in a rectangle.
+/// Notes are moved by their body, resized by their end and removed with a right click or delete.
+/// Snapping on the grid is disabled while alt is held.
+pub struct UIPianoRoll {
+    clip_id: String,
+    pixels_per_beat: f32,
+    /// Scroll in pixels from the start of the clip and the highest key
+    scroll: Vec2,
+    /// Indexes of the selected notes
+    selected: Vec<usize>,
+    drag: Option<NoteDrag>,
+    /// Velocity of the drawn notes, the one of the last edited note
+    velocity: u8,
+    /// Key played on the instrument
+    auditioned: Option<u8>,
+}
+
+impl UIPianoRoll {
+    pub fn new() -> Self {
+        Self {
+            clip_id: String::new(),
+            pixels_per_beat: 60.,
+            scroll: Vec2::ZERO,
+            selected: Vec::new(),
+            drag: None,
+            velocity: DEFAULT_VELOCITY,
+            auditioned: None,
+        }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        track_id: &String,
+        clip: MidiClipCore,
+        state: &mut ToniqueProjectState,
+    ) {
+        self.top_bar(ui, track_id, &clip, state);
+
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0., Color32::from_gray(30));
+        let ruler = Rect::from_min_max(
+            pos2(rect.left() + KEYBOARD_WIDTH, rect.top()),
+            pos2(rect.right(), rect.top() + RULER_HEIGHT),
+        );
+        let keyboard = Rect::from_min_max(
+            pos2(rect.left(), ruler.bottom()),
+            pos2(ruler.left(), rect.bottom() - VELOCITY_HEIGHT),
+        );
+        let notes_rect =
+            Rect::from_min_max(keyboard.right_top(), pos2(rect.right(), keyboard.bottom()));
+        let velocity_rect = Rect::from_min_max(
+            pos2(notes_rect.left(), notes_rect.bottom()),
+            rect.right_bottom(),
+        );
+        if notes_rect.width() <= 0. || notes_rect.height() <= 0. {
+            return;
+        }
+
+        if self.clip_id != clip.id {
+            self.open(&clip, notes_rect);
+        }
+        if rect.contains(ui.input(|i| i.pointer.hover_pos()).unwrap_or(Pos2::ZERO)) {
+            self.scroll_and_zoom(ui, notes_rect);
+        }
+        self.scroll.y = self
+            .scroll
+            .y
+            .clamp(0., (128. * KEY_HEIGHT - notes_rect.height()).max(0.));
+
+        let notes = self
+            .drag
+            .as_ref()
+            .map_or_else(|| clip.notes.clone(), |drag| drag.notes.clone());
+        self.selected.retain(|index| *index < notes.len());
+
+        self.paint_grid(&painter, ruler, notes_rect, &clip, state);
+        let mut audition = self.keyboard_ui(ui, &painter, keyboard);
+        let notes_audition = self.notes_ui(ui, &painter, notes_rect, &clip, track_id, state);
+        audition = audition.or(notes_audition);
+        self.velocity_ui(ui, &painter, velocity_rect, &clip, track_id, state);
+
+        // Keyboard shortcuts while the pointer is over the piano roll
+        if ui.rect_contains_pointer(rect) {
+            if ui.input(|i| i.modifiers.command && i.key_pressed(Key::A)) {
+                self.selected = (0..notes.len()).collect();
+            }
+            let delete = ui.input_mut(|i| {
+                i.consume_key(Modifiers::NONE, Key::Delete)
+                    || i.consume_key(Modifiers::NONE, Key::Backspace)
+            });
+            if delete && !self.selected.is_empty() && self.drag.is_none() {
+                let notes = notes
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| !self.selected.contains(index))
+                    .map(|(_, note)| *note)
+                    .collect();
+                self.selected.clear();
+                state.set_midi_notes(track_id, &clip.id, notes);
+            }
+        }
+
+        self.audition(audition, track_id, state);
+    }
+
+    /// Fit the clip in the view and center its notes
+    fn open(&mut self, clip: &MidiClipCore, notes_rect: Rect) {
+        self.clip_id = clip.id.clone();
+        self.selected.clear();
+        self.drag = None;
+        self.pixels_per_beat = (notes_rect.width() / clip.length.max(1.))
+            .clamp(MIN_PIXELS_PER_BEAT, MAX_PIXELS_PER_BEAT);
+        let center = clip
+            .key_range()
+            .map_or(60., |(low, high)| (low as f32 + high as f32) / 2.);
+        self.scroll = vec2(0., (127. - center) * KEY_HEIGHT - notes_rect.height() / 2.);
+    }
+
+    /// Scroll with the wheel and zoom the time with ctrl
+    fn scroll_and_zoom(&mut self, ui: &mut Ui, notes_rect: Rect) {
+        let (scroll, zoom, pointer) =
+            ui.input(|i| (i.smooth_scroll_delta, i.zoom_delta(), i.pointer.hover_pos()));
+        if zoom != 1. {
+            let x = pointer.map_or(notes_rect.left(), |pos| pos.x) - notes_rect.left();
+            let beat = (x + self.scroll.x) / self.pixels_per_beat;
+            self.pixels_per_beat =
+                (self.pixels_per_beat * zoom).clamp(MIN_PIXELS_PER_BEAT, MAX_PIXELS_PER_BEAT);
+            self.scroll.x = beat * self.pixels_per_beat - x;
+        } else {
+            self.scroll -= scroll;
+        }
+        self.scroll.x = self.scroll.x.max(0.);
+    }
+
+    fn resolution(&self) -> GridResolution {
+        GridResolution::from_pixels_per_beat(self.pixels_per_beat)
+    }
+
+    fn beats_to_x(&self, beats: f32, notes_rect: Rect) -> f32 {
+        notes_rect.left() + beats * self.pixels_per_beat - self.scroll.x
+    }
+
+    fn x_to_beats(&self, x: f32, notes_rect: Rect) -> f32 {
+        (x - notes_rect.left() + self.scroll.x) / self.pixels_per_beat
+    }
+
+    fn key_to_y(&self, key: u8, notes_rect: Rect) -> f32 {
+        notes_rect.top() + (127 - key) as f32 * KEY_HEIGHT - self.scroll.y
+    }
+
+    fn y_to_key(&self, y: f32, notes_rect: Rect) -> u8 {
+        (127. - ((y - notes_rect.top() + self.scroll.y) / KEY_HEIGHT).floor()).clamp(0., 127.) as u8
+    }
+
+    fn note_rect(&self, note: &MidiNote, notes_rect: Rect) -> Rect {
+        let x0 = self.beats_to_x(note.start, notes_rect);
+        let x1 = self.beats_to_x(note.end(), notes_rect).max(x0 + 2.);
+        let y = self.key_to_y(note.key, notes_rect);
+        Rect::from_min_max(pos2(x0, y), pos2(x1, y + KEY_HEIGHT))
+    }
+
+    /// Snap a position in beats from the start of the clip on the project grid
+    fn snap(&self, beats: f32, clip: &MidiClipCore, state: &ToniqueProjectState, ui: &Ui) -> f32 {
+        if ui.input(|i| i.modifiers.alt) {
+            return beats;
+        }
+        state
+            .grid
+            .snap_at_resolution(clip.position + beats, self.resolution())
+            - clip.position
+    }
+
+    /// Rows of the keys, bars and beats of the project grid and the end of the clip
+    fn paint_grid(
+        &self,
+        painter: &Painter,
+        ruler: Rect,
+        notes_rect: Rect,
+        clip: &MidiClipCore,
+        state: &ToniqueProjectState,
+    ) {
+        for key in 0..128u8 {
+            let y = self.key_to_y(key, notes_rect);
+            if y + KEY_HEIGHT < notes_rect.top() || y > notes_rect.bottom() {
+                continue;
+            }
+            if is_black(key) {
+                painter.rect_filled(
+                    Rect::from_x_y_ranges(notes_rect.x_range(), y..=y + KEY_HEIGHT),
+                    0.,
+                    Color32::from_gray(25),
+                );
+            }
+            if key % 12 == 0 {
+                painter.hline(
+                    notes_rect.x_range(),
+                    y + KEY_HEIGHT,
+                    Stroke::new(1., Color32::from_gray(50)),
+                );
+            }
+        }
+
+        painter.rect_filled(ruler, 0., Color32::from_gray(45));
+        let from = self.x_to_beats(notes_rect.left(), notes_rect);
+        let to = self.x_to_beats(notes_rect.right(), notes_rect);
+        for line in state.grid.lines_at_resolution(
+            clip.position + from,
+            clip.position + to,
+            self.resolution(),
+        ) {
+            let x = self.beats_to_x(line.beat - clip.position, notes_rect);
+            let alpha = match line.kind {
+                GridLineKind::Bar => 45,
+                GridLineKind::Beat => 22,
+                GridLineKind::Division => 10,
+            };
+            painter.vline(
+                x,
+                notes_rect.top()..=notes_rect.bottom() + VELOCITY_HEIGHT,
+                Stroke::new(1., Color32::from_white_alpha(alpha)),
+            );
+            if line.kind == GridLineKind::Bar {
+                painter.text(
+                    pos2(x + 2., ruler.center().y),
+                    Align2::LEFT_CENTER,
+                    format!("{}", line.bar + 1),
+                    FontId::new(9., FontFamily::Monospace),
+                    Color32::from_gray(160),
+                );
+            }
+        }
+
+        // Notes after the end are not played
+        let end = self.beats_to_x(clip.length, notes_rect);
+        if end < notes_rect.right() {
+            painter.rect_filled(
+                Rect::from_min_max(
+                    pos2(end.max(notes_rect.left()), ruler.top()),
+                    notes_rect.max,
+                ),
+                0.,
+                Color32::from_black_alpha(90),
+            );
+        }
+    }
+
+    /// Keys on the left, pressing one plays it. Returns the pressed key.
+    fn keyboard_ui(&mut self, ui: &mut Ui, painter: &Painter, keyboard: Rect) -> Option<u8> {
+        let response = ui.interact(keyboard, ui.id().with("piano-roll-keyboard"), Sense::drag());
+        let pressed = response
+            .is_pointer_button_down_on()
+            .then(|| ui.input(|i| i.pointer.interact_pos()))
+            .flatten()
+            .map(|pos| self.y_to_key(pos.y, keyboard));
+
+        let painter = painter.with_clip_rect(keyboard);
+        for key in 0..128u8 {
+            let y = self.key_to_y(key, keyboard);
+            if y + KEY_HEIGHT < keyboard.top() || y > keyboard.bottom() {
+                continue;
+            }
+            let key_rect = Rect::from_x_y_ranges(keyboard.x_range(), y..=y + KEY_HEIGHT);
+            let color = if pressed == Some(key) {
+                NOTE_COLOR
+            } else if is_black(key) {
+                Color32::from_gray(40)
+            } else {
+                Color32::from_gray(210)
+            };
+            painter.rect(
+                key_rect.shrink2(vec2(0., 0.5)),
+                0.,
+                color,
+                Stroke::NONE,
+                egui::StrokeKind::Inside,
+            );
+            if key % 12 == 0 {
+                painter.text(
+                    key_rect.right_center() - vec2(2., 0.),
+                    Align2::RIGHT_CENTER,
+                    note_name(key),
+                    FontId::new(8., FontFamily::Proportional),
+                    Color32::from_gray(40),
+                );
+            }
+        }
+        pressed
+    }
+
+    /// Draw, select, move, resize and remove the notes. Returns the key to audition.
+    fn notes_ui(
+        &mut self,
+        ui: &mut Ui,
+        painter: &Painter,
+        notes_rect: Rect,
+        clip: &MidiClipCore,
+        track_id: &String,
+        state: &mut ToniqueProjectState,
+    ) -> Option<u8> {
+        let response = ui.interact(
+            notes_rect,
+            ui.id().with("piano-roll-notes"),
+            Sense::click_and_drag(),
+        );
+        let notes = self
+            .drag
+            .as_ref()
+            .map_or_else(|| clip.notes.clone(), |drag| drag.notes.clone());
+        let shift = ui.input(|i| i.modifiers.shift);
+        let hovered = response
+            .hover_pos()
+            .and_then(|pos| self.note_at(&notes, pos, notes_rect));
+
+        // Start a drag where the button was pressed
+        if response.drag_started()
+            && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+        {
+            self.drag = Some(match self.note_at(&notes, origin, notes_rect) {
+                Some((index, resize)) => {
+                    if !self.selected.contains(&index) {
+                        if !shift {
+                            self.selected.clear();
+                        }
+                        self.selected.push(index);
+                    }
+                    NoteDrag {
+                        mode: if resize {
+                            DragMode::Resize
+                        } else {
+                            DragMode::Move
+                        },
+                        anchor: index,
+                        original: notes.clone(),
+                        notes: notes.clone(),
+                    }
+                }
+                None if shift => NoteDrag {
+                    mode: DragMode::Select,
+                    anchor: 0,
+                    original: notes.clone(),
+                    notes: notes.clone(),
+                },
+                // Draw a note resized by the drag
+                None => {
+                    let mut notes = notes.clone();
+                    let note = self.new_note(origin, notes_rect, clip, state, ui);
+                    notes.push(note);
+                    self.selected = vec![notes.len() - 1];
+                    NoteDrag {
+                        mode: DragMode::Resize,
+                        anchor: notes.len() - 1,
+                        original: notes.clone(),
+                        notes,
+                    }
+                }
+            });
+        }
+
+        if let Some(drag) = self
+            .drag
+            .as_ref()
+            .filter(|drag| drag.mode != DragMode::Velocity)
+            && response.dragged()
+            && let (Some(origin), Some(pos)) =
+                ui.input(|i| (i.pointer.press_origin(), i.pointer.interact_pos()))
+        {
+            let mode = drag.mode;
+            match mode {
+                DragMode::Move | DragMode::Resize => {
+                    let notes = self.dragged_notes(drag, pos - origin, clip, state, ui);
+                    self.drag.as_mut().unwrap().notes = notes;
+                }
+                DragMode::Select => {
+                    let area = Rect::from_two_pos(origin, pos);
+                    painter.rect(
+                        area,
+                        0.,
+                        Color32::from_white_alpha(15),
+                        Stroke::new(1., Color32::from_gray(160)),
+                        egui::StrokeKind::Inside,
+                    );
+                    self.selected = notes
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, note)| self.note_rect(note, notes_rect).intersects(area))
+                        .map(|(index, _)| index)
+                        .collect();
+                }
+                DragMode::Velocity => {}
+            }
+        }
+
+        if response.drag_stopped()
+            && let Some(drag) = self.drag.take_if(|drag| drag.mode != DragMode::Velocity)
+            && drag.mode != DragMode::Select
+        {
+            self.commit(drag.notes, track_id, clip, state);
+        }
+
+        if response.clicked()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            match self.note_at(&notes, pos, notes_rect) {
+                Some((index, _)) if shift => {
+                    if let Some(position) = self.selected.iter().position(|i| *i == index) {
+                        self.selected.remove(position);
+                    } else {
+                        self.selected.push(index);
+                    }
+                }
+                Some((index, _)) => {
+                    self.selected = vec![index];
+                    self.velocity = notes[index].velocity;
+                }
+                None if shift => self.selected.clear(),
+                None => {
+                    let mut notes = notes.clone();
+                    notes.push(self.new_note(pos, notes_rect, clip, state, ui));
+                    self.selected = vec![notes.len() - 1];
+                    self.commit(notes, track_id, clip, state);
+                }
+            }
+        }
+        if response.secondary_clicked()
+            && let Some(pos) = response.interact_pointer_pos()
+            && let Some((index, _)) = self.note_at(&notes, pos, notes_rect)
+        {
+            let mut notes = notes.clone();
+            notes.remove(index);
+            self.selected.clear();
+            state.set_midi_notes(track_id, &clip.id, notes);
+        }
+
+        // Notes
+        let painter = painter.with_clip_rect(notes_rect);
+        let notes = self.drag.as_ref().map_or(notes, |drag| drag.notes.clone());
+        for (index, note) in notes.iter().enumerate() {
+            let rect = self.note_rect(note, notes_rect);
+            if !rect.intersects(notes_rect) {
+                continue;
+            }
+            let selected = self.selected.contains(&index);
+            let fill = NOTE_COLOR.gamma_multiply(0.4 + 0.6 * note.velocity as f32 / 127.);
+            painter.rect(
+                rect,
+                1.,
+                fill,
+                Stroke::new(
+                    1.,
+                    if selected {
+                        Color32::WHITE
+                    } else {
+                        Color32::from_black_alpha(120)
+                    },
+                ),
+                egui::StrokeKind::Inside,
+            );
+            if rect.width() > 30. {
+                painter.text(
+                    rect.left_center() + vec2(2., 0.),
+                    Align2::LEFT_CENTER,
+                    note_name(note.key),
+                    FontId::new(7., FontFamily::Proportional),
+                    Color32::from_gray(20),
+                );
+            }
+        }
+
+        match (&self.drag, hovered) {
+            (Some(drag), _) if drag.mode == DragMode::Resize => {
+                ui.ctx().set_cursor_icon(CursorIcon::ResizeHorizontal)
+            }
+            (Some(drag), _) if drag.mode == DragMode::Move => {
+                ui.ctx().set_cursor_icon(CursorIcon::Grabbing)
+            }
+            (None, Some((_, true))) => ui.ctx().set_cursor_icon(CursorIcon::ResizeHorizontal),
+            (None, Some((_, false))) => ui.ctx().set_cursor_icon(CursorIcon::Grab),
+            _ => {}
+        }
+
+        // The grabbed note is played while the button is down
+        if !response.is_pointer_button_down_on() {
+            return None;
+        }
+        match &self.drag {
+            Some(drag) if drag.mode == DragMode::Move || drag.mode == DragMode::Resize => {
+                drag.notes.get(drag.anchor).map(|note| note.key)
+            }
+            Some(_) => None,
+            None => ui
+                .input(|i| i.pointer.press_origin())
+                .and_then(|origin| self.note_at(&notes, origin, notes_rect))
+                .map(|(index, _)| notes[index].key),
+        }
+    }
+
+    /// Note under `pos`, the last drawn on top, and whether the pointer is on its end
+    fn note_at(&self, notes: &[MidiNote], pos: Pos2, notes_rect: Rect) -> Option<(usize, bool)> {
+        if !notes_rect.contains(pos) {
+            return None;
+        }
+        notes.iter().enumerate().rev().find_map(|(index, note)| {
+            let rect = self.note_rect(note, notes_rect);
+            rect.contains(pos).then(|| {
+                let handle = RESIZE_HANDLE_WIDTH.min(rect.width() / 3.);
+                (index, pos.x > rect.right() - handle)
+            })
+        })
+    }
+
+    /// Note one grid step long, starting on the grid line before `pos`
+    fn new_note(
+        &mut self,
+        pos: Pos2,
+        notes_rect: Rect,
+        clip: &MidiClipCore,
+        state: &ToniqueProjectState,
+        ui: &Ui,
+    ) -> MidiNote {
+        let beats = self.x_to_beats(pos.x, notes_rect).max(0.);
+        let (start, length) = if ui.input(|i| i.modifiers.alt) {
+            (beats, MIN_NOTE_LENGTH.max(1. / 4.))
+        } else {
+            let absolute = clip.position + beats;
+            let resolution = self.resolution();
+            (
+                state.grid.floor_at_resolution(absolute, resolution) - clip.position,
+                state.grid.step_at_resolution(absolute, resolution),
+            )
+        };
+        MidiNote {
+            key: self.y_to_key(pos.y, notes_rect),
+            velocity: self.velocity,
+            start: start.max(0.),
+            length,
+        }
+    }
+
+    /// Selected notes moved or resized by the pointer moved by `delta` since the drag started.
+    /// The grabbed note is snapped on the grid and the others follow it.
+    fn dragged_notes(
+        &self,
+        drag: &NoteDrag,
+        delta: Vec2,
+        clip: &MidiClipCore,
+        state: &ToniqueProjectState,
+        ui: &Ui,
+    ) -> Vec<MidiNote> {
+        let mut notes = drag.original.clone();
+        let Some(anchor) = drag.original.get(drag.anchor) else {
+            return notes;
+        };
+        let beats = delta.x / self.pixels_per_beat;
+        let selected = || {
+            self.selected
+                .iter()
+                .filter_map(|index| drag.original.get(*index))
+        };
+        match drag.mode {
+            DragMode::Move => {
+                let start = self.snap(anchor.start + beats, clip, state, ui);
+                // Keep the selection after the start of the clip and in the key range
+                let earliest = selected()
+                    .map(|note| note.start)
+                    .fold(f32::INFINITY, f32::min);
+                let offset = (start - anchor.start).max(-earliest);
+                let low = selected().map(|note| note.key as i32).min().unwrap_or(0);
+                let high = selected().map(|note| note.key as i32).max().unwrap_or(127);
+                let keys = (-(delta.y / KEY_HEIGHT).round() as i32).clamp(-low, 127 - high);
+                for index in self.selected.iter() {
+                    if let Some(note) = notes.get_mut(*index) {
+                        note.start += offset;
+                        note.key = (note.key as i32 + keys) as u8;
+                    }
+                }
+            }
+            DragMode::Resize => {
+                let end = self.snap(anchor.end() + beats, clip, state, ui);
+                let offset = end - anchor.end();
+                for index in self.selected.iter() {
+                    if let Some(note) = notes.get_mut(*index) {
+                        note.length = (note.length + offset).max(MIN_NOTE_LENGTH);
+                    }
+                }
+            }
+            DragMode::Velocity | DragMode::Select => {}
+        }
+        notes
+    }
+
+    /// Velocity of each note, dragged to change the velocity of the selection
+    fn velocity_ui(
+        &mut self,
+        ui: &mut Ui,
+        painter: &Painter,
+        rect: Rect,
+        clip: &MidiClipCore,
+        track_id: &String,
+        state: &mut ToniqueProjectState,
+    ) {
+        painter.rect_filled(rect, 0., Color32::from_gray(22));
+        painter.hline(
+            rect.x_range(),
+            rect.top(),
+            Stroke::new(1., Color32::from_gray(60)),
+        );
+        painter.text(
+            pos2(rect.left() - KEYBOARD_WIDTH + 3., rect.top() + 3.),
+            Align2::LEFT_TOP,
+            "Velocity",
+            FontId::new(8., FontFamily::Proportional),
+            Color32::from_gray(130),
+        );
+        let lane = rect.shrink2(vec2(0., 4.));
+        let response = ui.interact(
+            rect,
+            ui.id().with("piano-roll-velocity"),
+            Sense::click_and_drag(),
+        );
+        let notes = self
+            .drag
+            .as_ref()
+            .map_or_else(|| clip.notes.clone(), |drag| drag.notes.clone());
+        let value_at = |y: f32| ((lane.bottom() - y) / lane.height() * 127.).clamp(1., 127.);
+
+        if response.drag_started()
+            && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+            && let Some(index) = self.velocity_at(&notes, origin.x, rect)
+        {
+            if !self.selected.contains(&index) {
+                self.selected = vec![index];
+            }
+            self.drag = Some(NoteDrag {
+                mode: DragMode::Velocity,
+                anchor: index,
+                original: notes.clone(),
+                notes: notes.clone(),
+            });
+        }
+        if let Some(drag) = self
+            .drag
+            .as_mut()
+            .filter(|drag| drag.mode == DragMode::Velocity)
+            && response.dragged()
+            && let Some(pos) = ui.input(|i| i.pointer.interact_pos())
+            && let Some(anchor) = drag.original.get(drag.anchor)
+        {
+            // The selection keeps the differences between the velocities
+            let offset = value_at(pos.y) - anchor.velocity as f32;
+            for index in self.selected.iter() {
+                if let (Some(note), Some(original)) =
+                    (drag.notes.get_mut(*index), drag.original.get(*index))
+                {
+                    note.velocity =
+                        (original.velocity as f32 + offset).round().clamp(1., 127.) as u8;
+                }
+            }
+            self.velocity = drag.notes[drag.anchor].velocity;
+        }
+        if response.drag_stopped()
+            && let Some(drag) = self.drag.take_if(|drag| drag.mode == DragMode::Velocity)
+        {
+            self.commit(drag.notes, track_id, clip, state);
+        }
+
+        let painter = painter.with_clip_rect(rect);
+        for (index, note) in notes.iter().enumerate() {
+            let x = self.beats_to_x(note.start, rect);
+            if x < rect.left() - 4. || x > rect.right() + 4. {
+                continue;
+            }
+            let y = lane.bottom() - note.velocity as f32 / 127. * lane.height();
+            let color = if self.selected.contains(&index) {
+                Color32::WHITE
+            } else {
+                NOTE_COLOR
+            };
+            painter.vline(x, y..=lane.bottom(), Stroke::new(2., color));
+            painter.circle_filled(pos2(x, y), 2.5, color);
+        }
+        if response.hovered() {
+            ui.ctx().set_cursor_icon(CursorIcon::ResizeVertical);
+        }
+    }
+
+    /// Note whose velocity bar is the closest to `x`, selected notes first
+    fn velocity_at(&self, notes: &[MidiNote], x: f32, rect: Rect) -> Option<usize> {
+        notes
+            .iter()
+            .enumerate()
+            .map(|(index, note)| (index, (self.beats_to_x(note.start, rect) - x).abs()))
+            .filter(|(_, distance)| *distance < RESIZE_HANDLE_WIDTH)
+            .min_by(|(a, da), (b, db)| {
+                (!self.selected.contains(a), da)
+                    .partial_cmp(&(!self.selected.contains(b), db))
+                    .unwrap()
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// Replace the notes of the clip, keeping the selection once they are sorted
+    fn commit(
+        &mut self,
+        notes: Vec<MidiNote>,
+        track_id: &String,
+        clip: &MidiClipCore,
+        state: &mut ToniqueProjectState,
+    ) {
+        if notes == clip.notes {
+            return;
+        }
+        let mut tagged: Vec<(MidiNote, bool)> = notes
+            .into_iter()
+            .enumerate()
+            .map(|(index, note)| (note, self.selected.contains(&index)))
+            .collect();
+        tagged.sort_by(|(a, _), (b, _)| a.start.total_cmp(&b.start));
+        self.selected = tagged
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, selected))| *selected)
+            .map(|(index, _)| index)
+            .collect();
+        let notes = tagged.into_iter().map(|(note, _)| note).collect();
+        state.set_midi_notes(track_id, &clip.id, notes);
+    }
+
+    /// Play `key` on the instrument of the track, releasing the previous one
+    fn audition(&mut self, key: Option<u8>, track_id: &str, state: &mut ToniqueProjectState) {
+        if key == self.auditioned {
+            return;
+        }
+        if let Some(key) = self.auditioned.take() {
+            state.play_midi(
+                track_id,
+                MidiToPlayerMsg::NoteOff {
+                    channel: 0,
+                    key,
+                    velocity: 0,
+                },
+            );
+        }
+        if let Some(key) = key {
+            state.play_midi(
+                track_id,
+                MidiToPlayerMsg::NoteOn {
+                    channel: 0,
+                    key,
+                    velocity: self.velocity,
+                },
+            );
+        }
+        self.auditioned = key;
+    }
+
+    fn top_bar(
+        &mut self,
+        ui: &mut Ui,
+        track_id: &String,
+        clip: &MidiClipCore,
+        state: &mut ToniqueProjectState,
+    ) {
+        Frame::new()
+            .fill(PRIMARY_COLOR)
+            .stroke(Stroke::new(1.0, Color32::DARK_GRAY))
+            .inner_margin(Margin {
+                bottom: 1,
+                top: 1,
+                left: 5,
+                right: 5,
+            })
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(&clip.name)
+                            .size(10.)
+                            .color(Color32::from_gray(20)),
+                    );
+                    if !self.selected.is_empty() {
+                        ui.label(
+                            RichText::new(format!("{} selected", self.selected.len()))
+                                .size(10.)
+                                .color(Color32::from_gray(40)),
+                        );
+                    }
+
+                    ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                        let close = ui.add(
+                            SquareButton::ghost(egui_phosphor::regular::X)
+                                .square(12.)
+                                .color(Color32::from_gray(30))
+                                .font(FontId::new(10., FontFamily::Name(PHOSPHOR_REGULAR.into())))
+                                .tooltip("Close the piano roll"),
+                        );
+                        if close.clicked() {
+                            state.opened_clip = None;
+                        }
+                        let tools = ui.add(
+                            SquareButton::ghost(egui_phosphor::regular::MAGIC_WAND)
+                                .square(12.)
+                                .color(Color32::from_gray(30))
+                                .font(FontId::new(10., FontFamily::Name(PHOSPHOR_REGULAR.into())))
+                                .tooltip("Transform the selected notes, or all of them"),
+                        );
+                        Popup::menu(&tools).show(|ui| {
+                            let mut settings = state.transform_settings.clone();
+                            let transform = transform_menu(ui, &mut settings);
+                            state.transform_settings = settings;
+                            if let Some(transform) = transform {
+                                if self.selected.is_empty() {
+                                    state.transform_midi_clips(
+                                        track_id,
+                                        std::slice::from_ref(&clip.id),
+                                        transform,
+                                    );
+                                } else {
+                                    self.selected = state.transform_midi_notes(
+                                        track_id,
+                                        &clip.id,
+                                        &self.selected,
+                                        transform,
+                                    );
+                                }
+                                ui.close();
+                            }
+                        });
+                    });
+                });
+            });
+    }
+}
+
+fn is_black(key: u8) -> bool {
+    matches!(key % 12, 1 | 3 | 6 | 8 | 10)
+}
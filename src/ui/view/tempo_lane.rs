@@ -0,0 +1,202 @@
+use crate::{
+    core::{
+        state::ToniqueProjectState,
+        tempo::{MAX_BPM, MIN_BPM, TempoCurve, TempoMap, TempoPoint},
+    },
+    ui::{
+        theme::PRIMARY_COLOR,
+        view::tracks::DRAGGER_WIDTH,
+        widget::context_menu::{ContextMenuButton, ContextMenuSeparator},
+    },
+};
+use egui::{
+    Align2, Color32, FontFamily, FontId, Rect, RichText, Sense, Shape, Stroke, Ui, pos2, vec2,
+};
+use egui_phosphor::fill::{CHECK, TRASH};
+
+pub const TEMPO_LANE_HEIGHT: f32 = 40.;
+const POINT_RADIUS: f32 = 4.;
+/// Vertical margin in pixels around the curve
+const LANE_PADDING: f32 = 6.;
+/// Tempo range in bpm shown above and below the points
+const BPM_MARGIN: f32 = 10.;
+
+/// Tempo track shown under the navigation bar.
+/// Points are added with a double click, dragged in beats and tempo and edited with a right click.
+pub struct UITempoLane {
+    /// Tempo map before the current drag
+    drag_start: Option<TempoMap>,
+}
+
+impl UITempoLane {
+    pub fn new() -> Self {
+        Self { drag_start: None }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, state: &mut ToniqueProjectState, track_width: f32) {
+        ui.horizontal(|ui| {
+            let (response, painter) = ui.allocate_painter(
+                vec2(ui.available_width() - track_width, TEMPO_LANE_HEIGHT),
+                Sense::click(),
+            );
+            let rect = response.rect;
+            painter.rect_filled(rect, 0., Color32::from_gray(40));
+
+            let tempo = state.tempo().clone();
+            // Keep the scale while dragging a point
+            let (low, high) = self
+                .drag_start
+                .as_ref()
+                .unwrap_or(&tempo)
+                .points()
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), point| {
+                    (low.min(point.bpm), high.max(point.bpm))
+                });
+            let (low, high) = (
+                (low - BPM_MARGIN).max(MIN_BPM),
+                (high + BPM_MARGIN).min(MAX_BPM),
+            );
+            let bpm_to_y = |bpm: f32| {
+                rect.bottom()
+                    - LANE_PADDING
+                    - (bpm - low) / (high - low) * (rect.height() - 2. * LANE_PADDING)
+            };
+            let y_to_bpm = |y: f32| {
+                low + (rect.bottom() - LANE_PADDING - y) / (rect.height() - 2. * LANE_PADDING)
+                    * (high - low)
+            };
+
+            // Curve
+            let points = tempo.points();
+            let mut line = Vec::new();
+            for (index, point) in points.iter().enumerate() {
+                let x = state.grid.beats_to_x(point.beat, rect);
+                line.push(pos2(x, bpm_to_y(point.bpm)));
+                match points.get(index + 1) {
+                    Some(next) => {
+                        let next_x = state.grid.beats_to_x(next.beat, rect);
+                        if point.curve == TempoCurve::Step {
+                            line.push(pos2(next_x, bpm_to_y(point.bpm)));
+                        }
+                    }
+                    None => line.push(pos2(rect.right(), bpm_to_y(point.bpm))),
+                }
+            }
+            let painter = painter.with_clip_rect(rect);
+            painter.add(Shape::line(line, Stroke::new(1., PRIMARY_COLOR)));
+
+            // Points
+            let mut edited = None;
+            for (index, point) in points.iter().enumerate() {
+                let center = pos2(state.grid.beats_to_x(point.beat, rect), bpm_to_y(point.bpm));
+                if center.x < rect.left() - POINT_RADIUS || center.x > rect.right() + POINT_RADIUS {
+                    continue;
+                }
+                let handle = Rect::from_center_size(center, vec2(3., 3.) * POINT_RADIUS);
+                let point_response = ui.interact(
+                    handle,
+                    ui.id().with(("tempo-point", index)),
+                    Sense::click_and_drag(),
+                );
+
+                if point_response.drag_started() {
+                    self.drag_start = Some(tempo.clone());
+                }
+                if point_response.dragged()
+                    && let Some(mouse_pos) = ui.input(|i| i.pointer.interact_pos())
+                {
+                    let beat = state.grid.x_to_beats(mouse_pos.x, rect);
+                    let mut moved = tempo.clone();
+                    moved.set(
+                        index,
+                        TempoPoint {
+                            beat: state.grid.snap_at_grid(beat),
+                            bpm: (y_to_bpm(mouse_pos.y) * 10.).round() / 10.,
+                            curve: point.curve,
+                        },
+                    );
+                    edited = Some(moved);
+                }
+                if point_response.drag_stopped()
+                    && let Some(old) = self.drag_start.take()
+                {
+                    state.commit_tempo(old, tempo.clone());
+                }
+                point_response.context_menu(|ui| {
+                    for curve in [TempoCurve::Step, TempoCurve::Ramp] {
+                        let icon = if point.curve == curve { CHECK } else { "" };
+                        if ui.add(ContextMenuButton::new(icon, curve.name())).clicked() {
+                            let mut new = tempo.clone();
+                            new.set(index, TempoPoint { curve, ..*point });
+                            state.commit_tempo(tempo.clone(), new);
+                            ui.close();
+                        }
+                    }
+                    if index > 0 {
+                        ui.add(ContextMenuSeparator::new());
+                        if ui
+                            .add(
+                                ContextMenuButton::new(TRASH, "Delete")
+                                    .text_color(Color32::LIGHT_RED),
+                            )
+                            .clicked()
+                        {
+                            let mut new = tempo.clone();
+                            new.remove(index);
+                            state.commit_tempo(tempo.clone(), new);
+                            ui.close();
+                        }
+                    }
+                });
+
+                let active = point_response.hovered() || point_response.dragged();
+                painter.circle_filled(
+                    center,
+                    POINT_RADIUS,
+                    if active {
+                        Color32::WHITE
+                    } else {
+                        PRIMARY_COLOR
+                    },
+                );
+                if active {
+                    ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::Grab);
+                }
+                painter.text(
+                    center + vec2(POINT_RADIUS + 2., 0.),
+                    Align2::LEFT_CENTER,
+                    format!("{:.1}", point.bpm),
+                    FontId::new(8., FontFamily::Monospace),
+                    Color32::from_gray(180),
+                );
+            }
+
+            // Add a point
+            if response.double_clicked()
+                && let Some(mouse_pos) = response.interact_pointer_pos()
+            {
+                let beat = state
+                    .grid
+                    .snap_at_grid(state.grid.x_to_beats(mouse_pos.x, rect));
+                let mut new = tempo.clone();
+                new.insert(TempoPoint::new(
+                    beat,
+                    (y_to_bpm(mouse_pos.y) * 10.).round() / 10.,
+                    TempoCurve::Step,
+                ));
+                state.commit_tempo(tempo.clone(), new);
+            }
+            if let Some(edited) = edited {
+                state.set_tempo(edited);
+            }
+
+            ui.add_space(DRAGGER_WIDTH + 4.0);
+            ui.label(
+                RichText::new("Tempo")
+                    .size(10.)
+                    .color(Color32::from_gray(200)),
+            );
+        });
+    }
+}
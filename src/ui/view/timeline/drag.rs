@@ -5,7 +5,7 @@ use crate::{
         track::{DEFAULT_TRACK_HEIGHT, TrackCore, TrackSoloState},
     },
     ui::{
-        clip::UIClip,
+        clip::{UIClip, transients_at},
         track::HANDLE_HEIGHT,
         utils::{find_track_at, get_track_y},
         view::timeline::UITimeline,
@@ -81,7 +81,7 @@ impl UITimeline {
                         });
                     }
                 }
-                y += track.height + HANDLE_HEIGHT;
+                y += track.full_height() + HANDLE_HEIGHT;
             }
             self.selected_clips.clip_ids = new_selected_clips;
             self.drag_state = Some(DragState {
@@ -111,11 +111,18 @@ impl UITimeline {
                     let new_position = state
                         .grid
                         .x_to_beats(mouse_pos.x - element.mouse_delta.x, viewport);
-                    let snapped_position = state.grid.snap_at_grid_option(new_position);
-                    if let Some(pos) = snapped_position
-                        && (pos - new_position).abs() < beat_delta.abs()
-                    {
-                        beat_delta = pos - new_position;
+                    // The start and the transients of the clip snap at the grid
+                    let points = std::iter::once(new_position).chain(transients_at(
+                        &element.clip,
+                        state.tempo(),
+                        new_position,
+                    ));
+                    for point in points {
+                        if let Some(pos) = state.grid.snap_at_grid_option(point)
+                            && (pos - point).abs() < beat_delta.abs()
+                        {
+                            beat_delta = pos - point;
+                        }
                     }
                 }
             }
@@ -126,7 +133,7 @@ impl UITimeline {
 
             let mut track_indexes = Vec::new();
             for element in drag_state.elements.iter_mut() {
-                if let Some(duration) = element.clip.duration() {
+                if element.clip.audio.duration.is_some() {
                     // Calculate track index
                     let track_index =
                         (mouse_track_index + element.track_index_delta).max(0) as usize;
@@ -135,7 +142,7 @@ impl UITimeline {
                     // Calculate y pos
                     let y = get_track_y(track_index, viewport, state);
                     // Calculate width
-                    let width = state.grid.duration_to_width(duration, state.bpm());
+                    let width = element.clip.length(state.tempo()) * state.grid.pixels_per_beat();
                     // Calculate x pos
                     let new_position = state
                         .grid
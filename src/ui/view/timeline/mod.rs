@@ -3,11 +3,13 @@ use crate::{
     cache::AUDIO_ANALYSIS_CACHE,
     core::{
         clip::ClipCore,
+        midi::MidiNote,
         state::ToniqueProjectState,
-        track::{DEFAULT_TRACK_HEIGHT, TrackCore, TrackReferenceCore},
+        track::{DEFAULT_TRACK_HEIGHT, TAKE_LANE_HEIGHT, TrackCore, TrackReferenceCore, TrackType},
     },
     ui::{
         clip::UIClip,
+        midi_transform::transform_menu,
         panels::left_panel::DragPayload,
         theme::PRIMARY_COLOR,
         track::HANDLE_HEIGHT,
@@ -16,9 +18,15 @@ use crate::{
             drag::DragState,
             selection::{ClipSelection, Multiselect},
         },
+        waveform::UIWaveform,
+        widget::context_menu::{ContextMenuButton, ContextMenuLabel},
     },
 };
-use egui::{Color32, DragAndDrop, Pos2, Rect, Response, Sense, Stroke, Ui, Vec2, pos2, vec2};
+use egui::{
+    Align2, Color32, DragAndDrop, FontId, PointerButton, Pos2, Rect, Response, Sense, Stroke, Ui,
+    Vec2, pos2, vec2,
+};
+use egui_phosphor::{fill::TRASH, regular::MAGIC_WAND};
 mod drag;
 mod keys;
 mod selection;
@@ -29,6 +37,8 @@ pub struct UITimeline {
     drag_state: Option<DragState>,
     clicked_pos: Option<Pos2>,
     multiselect_start: Option<Multiselect>,
+    /// Take swiped in a lane, with the beat where the swipe started
+    swipe: Option<(String, f32)>,
 }
 
 impl UITimeline {
@@ -38,6 +48,7 @@ impl UITimeline {
             drag_state: None,
             clicked_pos: None,
             multiselect_start: None,
+            swipe: None,
         }
     }
 
@@ -123,8 +134,8 @@ impl UITimeline {
             let view_bottom = view_top + viewport.height();
 
             // Skip if track is entirely outside the visible vertical range
-            if track_bottom < view_top || y > view_bottom {
-                y += track.height + HANDLE_HEIGHT;
+            if track_bottom + track.lanes_height < view_top || y > view_bottom {
+                y += track.full_height() + HANDLE_HEIGHT;
                 continue;
             }
 
@@ -155,6 +166,11 @@ impl UITimeline {
                     clip.trim_end = *end;
                     clip.position = *pos;
                 }
+                if let Some(edited) = &state.edited_clip
+                    && clip.id == edited.id
+                {
+                    clip = edited.clone();
+                }
                 let dragged = self.render_clip(
                     &track,
                     &clip,
@@ -171,16 +187,303 @@ impl UITimeline {
                 }
             }
 
+            if track.kind == TrackType::Midi {
+                self.render_midi_clips(ui, state, &track, track_rect);
+            }
+            self.render_recording(ui, state, &track, track_rect);
             self.handle_track_hover(ui, state, &track, track_rect);
 
             y += track.height;
             self.paint_track_separator(ui, viewport, offset, y);
             y += HANDLE_HEIGHT;
+            if track.lanes_height > 0. {
+                self.render_take_lanes(ui, state, &track, viewport, y - offset.y);
+                y += track.lanes_height;
+            }
         }
 
         self.handle_dragged_clips(ui, dragged_track_index, viewport, dragged_clip, state);
     }
 
+    /// Draw the MIDI clips of a track with their notes. Double-clicking an empty place
+    /// of the track creates a clip on the beat.
+    fn render_midi_clips(
+        &self,
+        ui: &mut Ui,
+        state: &mut ToniqueProjectState,
+        track: &TrackReferenceCore,
+        track_rect: Rect,
+    ) {
+        let painter = ui.painter_at(track_rect);
+        let color = if track.disabled() {
+            Color32::from_gray(100)
+        } else {
+            track.color
+        };
+        for clip in track.midi_clips.iter() {
+            let left = state.grid.beats_to_x(clip.position, track_rect);
+            let right = state.grid.beats_to_x(clip.end(), track_rect);
+            if right < track_rect.left() || left > track_rect.right() {
+                continue;
+            }
+            let rect = Rect::from_x_y_ranges(left..=right, track_rect.y_range());
+            painter.rect_filled(rect, 2.0, color.gamma_multiply(0.6));
+            painter.text(
+                rect.left_top() + vec2(4., 2.),
+                Align2::LEFT_TOP,
+                &clip.name,
+                FontId::new(9., egui::FontFamily::Proportional),
+                Color32::WHITE,
+            );
+            // Notes scaled to the keys used by the clip
+            if let Some((low, high)) = clip.key_range()
+                && !track.closed
+            {
+                let area = Rect::from_min_max(rect.min + vec2(0., 14.), rect.max - vec2(0., 2.));
+                let row = area.height() / (high - low + 1).max(12) as f32;
+                for note in clip.notes.iter().filter(|note| note.start < clip.length) {
+                    let x0 = state
+                        .grid
+                        .beats_to_x(clip.position + note.start, track_rect);
+                    let x1 = state
+                        .grid
+                        .beats_to_x(clip.position + note.end().min(clip.length), track_rect);
+                    let y = area.bottom() - (note.key - low + 1) as f32 * row;
+                    painter.rect_filled(
+                        Rect::from_min_max(pos2(x0, y), pos2(x1.max(x0 + 1.), y + row.max(1.))),
+                        0.,
+                        Color32::from_black_alpha(180),
+                    );
+                }
+            }
+            let response = ui.interact(
+                rect,
+                ui.make_persistent_id(format!("midi_clip_{}", clip.id)),
+                Sense::click(),
+            );
+            if response.clicked() {
+                state.select_track(&track.id);
+                state.open_clip_editor(&clip.id);
+            }
+            response.context_menu(|ui| {
+                ui.add(ContextMenuLabel::new(&clip.name));
+                ContextMenuButton::new(MAGIC_WAND, "Transform").submenu(ui, |ui| {
+                    let mut settings = state.transform_settings.clone();
+                    let transform = transform_menu(ui, &mut settings);
+                    state.transform_settings = settings;
+                    if let Some(transform) = transform {
+                        state.transform_midi_clips(
+                            &track.id,
+                            std::slice::from_ref(&clip.id),
+                            transform,
+                        );
+                        ui.close();
+                    }
+                });
+                if ui
+                    .add(ContextMenuButton::new(TRASH, "Delete").text_color(Color32::LIGHT_RED))
+                    .clicked()
+                {
+                    state.delete_midi_clips(&track.id, std::slice::from_ref(&clip.id));
+                    ui.close();
+                }
+            });
+        }
+
+        if ui.input(|i| i.pointer.button_double_clicked(PointerButton::Primary))
+            && let Some(pos) = ui.input(|i| i.pointer.interact_pos())
+            && track_rect.contains(pos)
+        {
+            let beat = state.grid.x_to_beats(pos.x, track_rect).floor().max(0.);
+            state.add_midi_clip(&track.id, beat);
+        }
+    }
+
+    /// Draw the audio recorded by the track from the record start to the playhead
+    fn render_recording(
+        &self,
+        ui: &mut Ui,
+        state: &ToniqueProjectState,
+        track: &TrackReferenceCore,
+        track_rect: Rect,
+    ) {
+        if let Some(((start, end), notes)) = state.recorded_midi(&track.id) {
+            self.render_midi_recording(ui, state, track, track_rect, (start, end, &notes));
+            return;
+        }
+        let (Some(start), Some(peaks)) = (state.record_start(), state.record_peaks(&track.id))
+        else {
+            return;
+        };
+        let left = state.grid.beats_to_x(start, track_rect);
+        let right = state.grid.beats_to_x(state.playback_position(), track_rect);
+        if right <= left || peaks.is_empty() {
+            return;
+        }
+        let painter = ui.painter_at(track_rect);
+        let rect = Rect::from_x_y_ranges(left..=right, track_rect.y_range());
+        painter.rect_filled(rect, 2.0, track.color.gamma_multiply(0.5));
+        let step = (right - left) / peaks.len() as f32;
+        // Only the visible blocks
+        let first = ((track_rect.left() - left) / step).max(0.) as usize;
+        let last = (((track_rect.right() - left) / step).max(0.) as usize + 1).min(peaks.len());
+        for (index, peak) in peaks.iter().enumerate().take(last).skip(first) {
+            let x = left + index as f32 * step;
+            let height = peak.min(1.) * rect.height() / 2.;
+            painter.line_segment(
+                [
+                    pos2(x, rect.center().y - height),
+                    pos2(x, rect.center().y + height),
+                ],
+                Stroke::new(step.max(1.), Color32::from_black_alpha(150)),
+            );
+        }
+    }
+
+    /// Draw the notes recorded by a MIDI track between `start` and `end`
+    fn render_midi_recording(
+        &self,
+        ui: &mut Ui,
+        state: &ToniqueProjectState,
+        track: &TrackReferenceCore,
+        track_rect: Rect,
+        (start, end, notes): (f32, f32, &[MidiNote]),
+    ) {
+        let left = state.grid.beats_to_x(start, track_rect);
+        let right = state.grid.beats_to_x(end, track_rect);
+        if right <= left {
+            return;
+        }
+        let painter = ui.painter_at(track_rect);
+        let rect = Rect::from_x_y_ranges(left..=right, track_rect.y_range());
+        painter.rect_filled(rect, 2.0, track.color.gamma_multiply(0.5));
+        let Some(low) = notes.iter().map(|note| note.key).min() else {
+            return;
+        };
+        let high = notes.iter().map(|note| note.key).max().unwrap_or(low);
+        let area = Rect::from_min_max(rect.min + vec2(0., 2.), rect.max - vec2(0., 2.));
+        let row = area.height() / (high - low + 1).max(12) as f32;
+        for note in notes {
+            let x0 = state.grid.beats_to_x(note.start, track_rect);
+            let x1 = state.grid.beats_to_x(note.end(), track_rect);
+            let y = area.bottom() - (note.key - low + 1) as f32 * row;
+            painter.rect_filled(
+                Rect::from_min_max(pos2(x0, y), pos2(x1.max(x0 + 1.), y + row.max(1.))),
+                0.,
+                Color32::from_black_alpha(180),
+            );
+        }
+    }
+
+    /// Draw the takes of a track in lanes starting at `top`, the comped ranges highlighted.
+    /// Swiping across a lane plays the take over the swiped range, clicking plays it over
+    /// the comp segment under the pointer.
+    fn render_take_lanes(
+        &mut self,
+        ui: &mut Ui,
+        state: &mut ToniqueProjectState,
+        track: &TrackReferenceCore,
+        viewport: Rect,
+        top: f32,
+    ) {
+        let painter = ui.painter_at(viewport);
+        let tempo = state.tempo().clone();
+        let pixels_per_beat = state.grid.pixels_per_beat();
+        for (index, take) in track.takes.takes.iter().enumerate() {
+            let lane = Rect::from_min_size(
+                pos2(viewport.left(), top + index as f32 * TAKE_LANE_HEIGHT),
+                vec2(viewport.width(), TAKE_LANE_HEIGHT),
+            );
+            if !lane.intersects(viewport) {
+                continue;
+            }
+            painter.rect_filled(lane, 0., Color32::from_gray(28));
+            painter.hline(
+                lane.x_range(),
+                lane.bottom(),
+                Stroke::new(0.5, Color32::from_gray(50)),
+            );
+            let clip = &take.clip;
+            let clip_rect = Rect::from_x_y_ranges(
+                state.grid.beats_to_x(clip.position, viewport)
+                    ..=state.grid.beats_to_x(clip.end(&tempo), viewport),
+                lane.top() + 1.0..=lane.bottom() - 1.0,
+            );
+            painter.rect_filled(clip_rect, 2.0, track.color.gamma_multiply(0.15));
+            for segment in track.takes.comp.iter().filter(|s| s.take == take.id) {
+                let rect = Rect::from_x_y_ranges(
+                    state.grid.beats_to_x(segment.start, viewport)
+                        ..=state.grid.beats_to_x(segment.end, viewport),
+                    clip_rect.y_range(),
+                );
+                painter.rect_filled(rect, 2.0, track.color.gamma_multiply(0.6));
+            }
+            let visible = clip_rect.intersect(viewport);
+            if visible.width() > 0. {
+                let mut shapes = Vec::new();
+                UIWaveform::new().paint_clip(
+                    &mut shapes,
+                    visible,
+                    clip,
+                    &tempo,
+                    (visible.left() - clip_rect.left()) / pixels_per_beat
+                        ..(visible.right() - clip_rect.left()) / pixels_per_beat,
+                    Color32::from_black_alpha(160),
+                );
+                painter.add(shapes);
+            }
+
+            // Swipe
+            let response = ui.interact(
+                clip_rect.intersect(viewport),
+                ui.make_persistent_id(format!("take-{}", take.id)),
+                Sense::click_and_drag(),
+            );
+            let pointer_beat = response
+                .interact_pointer_pos()
+                .or(ui.input(|i| i.pointer.hover_pos()))
+                .map(|pos| {
+                    state
+                        .grid
+                        .snap_at_grid(state.grid.x_to_beats(pos.x, viewport))
+                });
+            if response.drag_started()
+                && let Some(beat) = pointer_beat
+            {
+                self.swipe = Some((take.id.clone(), beat));
+            }
+            if let Some((id, start)) = &self.swipe
+                && *id == take.id
+                && let Some(beat) = pointer_beat
+            {
+                let rect = Rect::from_x_y_ranges(
+                    state.grid.beats_to_x(start.min(beat), viewport)
+                        ..=state.grid.beats_to_x(start.max(beat), viewport),
+                    clip_rect.y_range(),
+                );
+                painter.rect_filled(rect, 2.0, Color32::from_white_alpha(40));
+                if response.drag_stopped() {
+                    state.comp_take(&track.id, &take.id, start.min(beat), start.max(beat));
+                }
+            }
+            if response.drag_stopped() {
+                self.swipe = None;
+            }
+            if response.clicked()
+                && let Some(pos) = response.interact_pointer_pos()
+            {
+                let beat = state.grid.x_to_beats(pos.x, viewport);
+                let (start, end) = track
+                    .takes
+                    .comp
+                    .iter()
+                    .find(|s| s.start <= beat && beat < s.end)
+                    .map_or((clip.position, clip.end(&tempo)), |s| (s.start, s.end));
+                state.comp_take(&track.id, &take.id, start, end);
+            }
+        }
+    }
+
     fn handle_track_hover(
         &mut self,
         ui: &mut Ui,
@@ -222,9 +525,7 @@ impl UITimeline {
         dragged: bool,
     ) -> bool {
         let x = state.grid.beats_to_x(clip.position, viewport);
-        let width = state
-            .grid
-            .duration_to_width(clip.duration().unwrap(), state.bpm());
+        let width = clip.length(state.tempo()) * state.grid.pixels_per_beat();
 
         if x + width < viewport.left() || x > viewport.right() {
             return false;
@@ -322,8 +623,14 @@ impl UITimeline {
 
                             let new_track = TrackCore::new();
                             state.add_track(new_track.clone());
-                            let clip = ClipCore::new(audio_info, snapped_position);
+                            let mut clip = ClipCore::new(audio_info, snapped_position);
+                            let deferred =
+                                state.conform_on_drop() && !state.conform_dropped_clip(&mut clip);
+                            let id = clip.id.clone();
                             state.add_clips(&new_track.id, vec![clip]);
+                            if deferred {
+                                state.conform_when_estimated(&id);
+                            }
                         }
                     }
                 }
@@ -342,7 +649,7 @@ impl UITimeline {
         state: &mut ToniqueProjectState,
     ) {
         // Render preview clip
-        if let Some(duration) = audio_info.duration
+        if audio_info.duration.is_some()
             && let Some(mouse_pos) = ui.ctx().input(|i| i.pointer.hover_pos())
             && viewport.contains(mouse_pos)
         {
@@ -355,14 +662,40 @@ impl UITimeline {
             // Find corresponding track
             let (track, y) = find_track_at(state, viewport, mouse_y);
 
+            // Samples dropped on a MIDI track are loaded by its instrument
+            if let Some(track) = track.as_ref().filter(|t| t.kind == TrackType::Midi) {
+                let rect = Rect::from_x_y_ranges(
+                    viewport.x_range(),
+                    y - offset.y..=y - offset.y + track.height,
+                );
+                let painter = ui.painter_at(viewport);
+                painter.rect_filled(rect, 0., track.color.gamma_multiply(0.2));
+                painter.text(
+                    pos2(mouse_pos.x + 12., rect.center().y),
+                    Align2::LEFT_CENTER,
+                    if track.instrument.as_ref().is_some_and(|i| i.is_drum_rack()) {
+                        "Add to the drum rack"
+                    } else {
+                        "Create a sampler"
+                    },
+                    FontId::proportional(10.),
+                    Color32::from_gray(220),
+                );
+                if is_released {
+                    state.drop_sample(&track.id, audio_info);
+                }
+                return;
+            }
+
             let height = track.as_ref().map_or(DEFAULT_TRACK_HEIGHT, |t| t.height);
             let show_waveform = track.as_ref().map_or(true, |t| !t.closed);
             let color = track.as_ref().map_or(Color32::WHITE, |t| t.color);
-            let width = state.grid.duration_to_width(duration, state.bpm());
+            let mut clip = ClipCore::new(audio_info, snapped_position);
+            let deferred = state.conform_on_drop() && !state.conform_dropped_clip(&mut clip);
+            let width = clip.length(state.tempo()) * state.grid.pixels_per_beat();
 
             let pos = pos2(x, y - offset.y);
             let size = Vec2::new(width, height);
-            let clip = ClipCore::new(audio_info, snapped_position);
             // render clip
             UIClip::new().ui(
                 ui,
@@ -385,8 +718,12 @@ impl UITimeline {
                     state.add_track(new_track.clone());
                     new_track.id
                 };
+                let clip_id = clip.id.clone();
                 state.add_clips(&id, vec![clip]);
                 state.commit_batch();
+                if deferred {
+                    state.conform_when_estimated(&clip_id);
+                }
             }
         };
     }
@@ -102,7 +102,7 @@ impl UITimeline {
             for (track_index, track) in state.tracks().enumerate() {
                 if min_index <= track_index && track_index <= max_index {
                     for clip in track.clips.iter() {
-                        let end = clip.end(state.bpm());
+                        let end = clip.end(state.tempo());
                         if end >= min_pos && clip.position < max_pos {
                             self.selected_clips.clip_ids.push(clip.id.clone());
                         }
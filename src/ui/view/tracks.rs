@@ -1,8 +1,11 @@
-use egui::{Color32, FontId, Rect, Sense, Ui, pos2, vec2};
-use egui_phosphor::fill::PLUS;
+use egui::{Align2, Color32, FontId, Rect, Sense, Stroke, StrokeKind, Ui, pos2, vec2};
+use egui_phosphor::fill::{PIANO_KEYS, PLUGS, PLUS, SQUARES_FOUR};
 
 use crate::{
-    core::{state::ToniqueProjectState, track::TrackCore},
+    core::{
+        state::ToniqueProjectState,
+        track::{TAKE_LANE_HEIGHT, TrackCore, TrackReferenceCore},
+    },
     ui::{
         font::PHOSPHOR_REGULAR,
         panels::{central_panel::SCROLLBAR_WIDTH, left_panel::DragPayload},
@@ -96,8 +99,8 @@ impl UITracks {
 
                 // Skip if track is entirely outside the visible vertical range
                 if track_bottom < view_top || y > view_bottom {
-                    y += track.height + HANDLE_HEIGHT;
-                    ui.add_space(track.height + HANDLE_HEIGHT);
+                    y += track.full_height() + HANDLE_HEIGHT;
+                    ui.add_space(track.full_height() + HANDLE_HEIGHT);
                     continue;
                 }
 
@@ -121,8 +124,11 @@ impl UITracks {
                 {
                     state.add_effect(&track.id, id, 0);
                 }
+                if track.lanes_height > 0. {
+                    self.take_lanes_ui(ui, &track);
+                }
 
-                y += track.height + HANDLE_HEIGHT;
+                y += track.full_height() + HANDLE_HEIGHT;
             }
             if let Some(track) = dragged_track
                 && let Some(pos) = ui.input(|i| i.pointer.hover_pos())
@@ -161,6 +167,49 @@ impl UITracks {
         });
     }
 
+    /// Headers of the take lanes shown under a track
+    fn take_lanes_ui(&self, ui: &mut Ui, track: &TrackReferenceCore) {
+        let (rect, _) = ui.allocate_exact_size(
+            vec2(ui.available_width(), track.lanes_height),
+            Sense::hover(),
+        );
+        let painter = ui.painter_at(rect);
+        for (index, take) in track.takes.takes.iter().enumerate() {
+            let lane = Rect::from_min_size(
+                pos2(rect.left(), rect.top() + index as f32 * TAKE_LANE_HEIGHT),
+                vec2(rect.width(), TAKE_LANE_HEIGHT),
+            );
+            let comped = track.takes.comp.iter().any(|s| s.take == take.id);
+            painter.rect(
+                lane,
+                0.,
+                Color32::from_gray(32),
+                Stroke::new(0.5, Color32::from_gray(60)),
+                StrokeKind::Inside,
+            );
+            painter.rect_filled(
+                Rect::from_min_size(lane.min + vec2(2., 2.), vec2(4., lane.height() - 4.)),
+                0.,
+                if comped {
+                    track.color
+                } else {
+                    Color32::from_gray(80)
+                },
+            );
+            painter.text(
+                lane.left_center() + vec2(12., 0.),
+                Align2::LEFT_CENTER,
+                format!("Take {}", index + 1),
+                FontId::new(9., egui::FontFamily::Proportional),
+                if comped {
+                    Color32::WHITE
+                } else {
+                    Color32::from_gray(140)
+                },
+            );
+        }
+    }
+
     fn context_menu_ui(&self, ui: &mut Ui, state: &mut ToniqueProjectState) {
         if ui
             .add(ContextMenuButton::new(PLUS, "Add audio track"))
@@ -169,5 +218,26 @@ impl UITracks {
             state.add_track(TrackCore::new());
             ui.close();
         }
+        if ui
+            .add(ContextMenuButton::new(PIANO_KEYS, "Add MIDI track"))
+            .clicked()
+        {
+            state.add_track(TrackCore::midi());
+            ui.close();
+        }
+        if ui
+            .add(ContextMenuButton::new(SQUARES_FOUR, "Add drum rack track"))
+            .clicked()
+        {
+            state.add_track(TrackCore::drum_rack());
+            ui.close();
+        }
+        if ui
+            .add(ContextMenuButton::new(PLUGS, "Add external MIDI track"))
+            .clicked()
+        {
+            state.add_track(TrackCore::external());
+            ui.close();
+        }
     }
 }
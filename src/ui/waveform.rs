@@ -1,22 +1,45 @@
-use std::sync::RwLockReadGuard;
-
-use egui::{Color32, Rect, Shape};
+use crate::core::{
+    clip::ClipCore,
+    tempo::{TempoCurve, TempoMap},
+};
+use egui::{Color32, Rect, Shape, pos2};
+use std::ops::Range;
 
 const MAX_SEGMENT_SIZE: usize = 15;
+/// Pieces painted for a clip played over tempo ramps
+const RAMP_PIECES: usize = 32;
 
 #[derive(Clone)]
-pub struct UIWaveform {}
+pub struct UIWaveform {
+    gain: f32,
+    reversed: bool,
+}
 
 impl UIWaveform {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            gain: 1.,
+            reversed: false,
+        }
+    }
+
+    /// Scale the drawn samples by a linear gain
+    pub fn gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Draw the samples from right to left
+    pub fn reversed(mut self, reversed: bool) -> Self {
+        self.reversed = reversed;
+        self
     }
 
     pub fn paint(
         &self,
         shapes: &mut Vec<Shape>,
         rect: Rect,
-        data: RwLockReadGuard<'_, (Vec<f32>, Vec<f32>)>,
+        data: &(Vec<f32>, Vec<f32>),
         start_ratio: f32,
         end_ratio: f32,
         num_samples: u64,
@@ -69,6 +92,12 @@ impl UIWaveform {
                 i += step;
             }
 
+            min_left = (min_left * self.gain).clamp(-1., 1.);
+            max_left = (max_left * self.gain).clamp(-1., 1.);
+            min_right = (min_right * self.gain).clamp(-1., 1.);
+            max_right = (max_right * self.gain).clamp(-1., 1.);
+            let x = if self.reversed { width - 1 - x } else { x };
+
             let points = if is_stereo {
                 [
                     egui::pos2(
@@ -112,4 +141,87 @@ impl UIWaveform {
         }
         shapes.extend(waveform_shapes);
     }
+
+    /// Paint the waveform of `clip` between `beats` from its start, with one piece per warp
+    /// or tempo segment so the waveform follows the warp markers and the tempo changes.
+    pub fn paint_clip(
+        &self,
+        shapes: &mut Vec<Shape>,
+        rect: Rect,
+        clip: &ClipCore,
+        tempo: &TempoMap,
+        beats: Range<f32>,
+        color: Color32,
+    ) {
+        let (Some(duration), Some(num_samples)) = (clip.audio.duration, clip.audio.num_samples)
+        else {
+            return;
+        };
+        let Ok(data) = clip.audio.data.read() else {
+            return;
+        };
+        if beats.end <= beats.start {
+            return;
+        }
+        let duration = duration.as_secs_f32();
+        let waveform = self.clone().gain(clip.amplitude()).reversed(clip.reverse);
+
+        let mut bounds = vec![beats.start];
+        if clip.warp.enabled {
+            bounds.extend(
+                clip.warp
+                    .markers
+                    .iter()
+                    .map(|marker| clip.beats_at(marker.source, tempo))
+                    .filter(|beat| beats.contains(beat) && *beat > beats.start),
+            );
+        } else {
+            bounds.extend(
+                tempo
+                    .points()
+                    .iter()
+                    .map(|point| point.beat - clip.position)
+                    .filter(|beat| beats.contains(beat) && *beat > beats.start),
+            );
+            if tempo
+                .points()
+                .iter()
+                .any(|point| point.curve == TempoCurve::Ramp)
+            {
+                let step = (beats.end - beats.start) / RAMP_PIECES as f32;
+                bounds.extend((1..RAMP_PIECES).map(|i| beats.start + i as f32 * step));
+            }
+        }
+        bounds.sort_by(f32::total_cmp);
+        bounds.push(beats.end);
+
+        let pixels_per_beat = rect.width() / (beats.end - beats.start);
+        for piece in bounds.windows(2) {
+            let piece_rect = Rect::from_min_max(
+                pos2(
+                    rect.left() + (piece[0] - beats.start) * pixels_per_beat,
+                    rect.top(),
+                ),
+                pos2(
+                    rect.left() + (piece[1] - beats.start) * pixels_per_beat,
+                    rect.bottom(),
+                ),
+            );
+            let mut start_ratio = clip.source_at(piece[0], tempo) / duration;
+            let mut end_ratio = clip.source_at(piece[1], tempo) / duration;
+            if clip.reverse {
+                (start_ratio, end_ratio) = (end_ratio, start_ratio);
+            }
+            waveform.paint(
+                shapes,
+                piece_rect,
+                &data,
+                start_ratio,
+                end_ratio,
+                num_samples,
+                !clip.audio.layout.is_mono(),
+                color,
+            );
+        }
+    }
 }
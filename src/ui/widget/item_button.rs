@@ -4,6 +4,8 @@ use crate::ui::theme::PRIMARY_COLOR;
 
 pub struct ItemButton {
     text: String,
+    /// Text aligned on the right
+    detail: Option<String>,
     selected: bool,
 }
 
@@ -11,10 +13,16 @@ impl ItemButton {
     pub fn new(text: impl ToString) -> Self {
         Self {
             text: text.to_string(),
+            detail: None,
             selected: false,
         }
     }
 
+    pub fn detail(mut self, text: impl ToString) -> Self {
+        self.detail = Some(text.to_string());
+        self
+    }
+
     pub fn selected(mut self, val: bool) -> Self {
         self.selected = val;
         self
@@ -42,9 +50,9 @@ impl Widget for ItemButton {
         let mut font_id = egui::TextStyle::Button.resolve(ui.style());
         font_id.size = 12.;
 
-        let galley = ui
-            .painter()
-            .layout_no_wrap(self.text.to_string(), font_id, Color32::WHITE);
+        let galley =
+            ui.painter()
+                .layout_no_wrap(self.text.to_string(), font_id.clone(), Color32::WHITE);
 
         painter.galley(
             response.rect.left_top() + Vec2::new(6.0, 1.0),
@@ -52,6 +60,21 @@ impl Widget for ItemButton {
             Color32::WHITE,
         );
 
+        if let Some(detail) = self.detail {
+            font_id.size = 10.;
+            let galley = ui
+                .painter()
+                .layout_no_wrap(detail, font_id, Color32::from_gray(140));
+            let pos = response.rect.right_center()
+                - Vec2::new(galley.size().x + 6.0, galley.size().y / 2.);
+            painter.rect_filled(
+                egui::Rect::from_min_size(pos, galley.size()).expand(2.),
+                0.,
+                bg_color,
+            );
+            painter.galley(pos, galley, Color32::from_gray(140));
+        }
+
         response
     }
 }
@@ -2,12 +2,14 @@ use std::fs::File;
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
 use symphonia::core::audio::{AudioBufferRef, Signal};
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::formats::FormatOptions;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, Decoder, DecoderOptions};
+use symphonia::core::formats::{FormatOptions, FormatReader, Track};
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 
+use crate::analysis::ChannelLayout;
+
 const CHUNK_SIZE: usize = 88200;
 
 fn normalize_buffer(audio_buf: AudioBufferRef, sample_buffer: &mut Vec<f32>, channel: usize) {
@@ -79,14 +81,27 @@ fn normalize_buffer(audio_buf: AudioBufferRef, sample_buffer: &mut Vec<f32>, cha
     }
 }
 
-pub fn load_audio(
-    path: String,
-    shared_data: Arc<RwLock<(Vec<f32>, Vec<f32>)>>,
-    ready: Arc<RwLock<bool>>,
-) -> Result<(), String> {
-    let start = Instant::now();
+/// Track of a file read as its audio: the default track when it can be decoded,
+/// else the first track with a codec
+pub fn audio_track(format: &dyn FormatReader) -> Option<&Track> {
+    format
+        .default_track()
+        .filter(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .or_else(|| {
+            format
+                .tracks()
+                .iter()
+                .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        })
+}
+
+/// Reader, decoder and decoded track of a file
+type OpenedAudio = (Box<dyn FormatReader>, Box<dyn Decoder>, Track);
+
+/// Open the audio track of the file at `path`
+fn open_audio(path: &str) -> Result<OpenedAudio, String> {
     // Open the audio file
-    let file = File::open(&path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
     // Probe the file to detect format
     let hint = Hint::new();
@@ -98,25 +113,100 @@ pub fn load_audio(
             &MetadataOptions::default(),
         )
         .map_err(|e| format!("Failed to probe file: {}", e))?;
-    let mut format = probed.format;
-
-    // Extract the track ID before the loop to avoid borrowing conflicts
-    let track_id = {
-        let track = format
-            .tracks()
-            .iter()
-            .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-            .ok_or("No valid audio tracks found")?;
-        track.id
-    };
+    let format = probed.format;
+
+    // Cloned to avoid borrowing the reader while decoding
+    let track = audio_track(format.as_ref())
+        .ok_or("No valid audio tracks found")?
+        .clone();
 
     // Create a decoder for the track
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&format.tracks()[0].codec_params, &DecoderOptions::default())
+    let decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
         .map_err(|e| format!("Failed to create decoder: {}", e))?;
+    Ok((format, decoder, track))
+}
+
+/// Append the decoded packet folded down to stereo
+fn append_stereo(
+    audio_buf: AudioBufferRef,
+    buffer_0: &mut Vec<f32>,
+    buffer_1: &mut Vec<f32>,
+    channel_buffer: &mut Vec<f32>,
+) {
+    // Fold every source channel down to stereo
+    let layout = ChannelLayout::from_channels(audio_buf.spec().channels);
+    let offset = buffer_0.len();
+    buffer_0.resize(offset + audio_buf.frames(), 0.);
+    buffer_1.resize(offset + audio_buf.frames(), 0.);
+    for (channel, [left, right]) in layout.downmix_matrix().into_iter().enumerate() {
+        if left == 0. && right == 0. {
+            continue;
+        }
+        channel_buffer.clear();
+        normalize_buffer(audio_buf.clone(), channel_buffer, channel);
+        for (i, sample) in channel_buffer.iter().enumerate() {
+            buffer_0[offset + i] += sample * left;
+            buffer_1[offset + i] += sample * right;
+        }
+    }
+}
+
+/// Start of an audio file decoded in stereo
+pub struct Excerpt {
+    pub data: (Vec<f32>, Vec<f32>),
+    pub sample_rate: u32,
+    /// Frames of the whole file when known
+    pub num_samples: Option<u64>,
+}
 
+/// Decode at most `seconds` from the start of the file at `path`
+pub fn load_excerpt(path: &str, seconds: f32) -> Result<Excerpt, String> {
+    let (mut format, mut decoder, track) = open_audio(path)?;
+    let track_id = track.id;
+    let num_samples = track.codec_params.n_frames;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or("Missing sample rate")?;
+    let max_frames = (seconds * sample_rate as f32) as usize;
     let mut buffer_0 = Vec::new();
     let mut buffer_1 = Vec::new();
+    let mut channel_buffer = Vec::new();
+    while buffer_0.len() < max_frames
+        && let Ok(packet) = format.next_packet()
+    {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(audio_buf) => {
+                append_stereo(audio_buf, &mut buffer_0, &mut buffer_1, &mut channel_buffer)
+            }
+            Err(e) => eprintln!("Error decoding audio packet: {}", e),
+        }
+    }
+    buffer_0.truncate(max_frames);
+    buffer_1.truncate(max_frames);
+    Ok(Excerpt {
+        data: (buffer_0, buffer_1),
+        sample_rate,
+        num_samples,
+    })
+}
+
+pub fn load_audio(
+    path: String,
+    shared_data: Arc<RwLock<(Vec<f32>, Vec<f32>)>>,
+    ready: Arc<RwLock<bool>>,
+) -> Result<(), String> {
+    let start = Instant::now();
+    let (mut format, mut decoder, track) = open_audio(&path)?;
+    let track_id = track.id;
+
+    let mut buffer_0 = Vec::new();
+    let mut buffer_1 = Vec::new();
+    let mut channel_buffer = Vec::new();
 
     // Decode the audio packets
     while let Ok(packet) = format.next_packet() {
@@ -127,13 +217,7 @@ pub fn load_audio(
         // Decode the packet
         match decoder.decode(&packet) {
             Ok(audio_buf) => {
-                let audio_buf_copy = audio_buf.clone();
-                // Process the decoded audio buffer on channel 0
-                normalize_buffer(audio_buf, &mut buffer_0, 0);
-                // Process the decoded audio buffer on channel 1
-                if audio_buf_copy.spec().channels.count() > 1 {
-                    normalize_buffer(audio_buf_copy, &mut buffer_1, 1);
-                }
+                append_stereo(audio_buf, &mut buffer_0, &mut buffer_1, &mut channel_buffer);
                 // Append data chunk by chunk
                 if buffer_0.len() > CHUNK_SIZE || buffer_1.len() > CHUNK_SIZE {
                     if let Ok(mut data) = shared_data.write() {